@@ -0,0 +1,316 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use crate::pdf::{AttachmentsByHash, PdfMetadata};
+
+// Zotero's connector server, the same one the browser extensions talk to
+const ZOTERO_CONNECTOR: &str = "127.0.0.1:23119";
+const ZOTERO_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CitationFormat {
+    BibTex,
+    Ris,
+}
+
+impl CitationFormat {
+    pub fn from_str(name: &str) -> Option<Self> {
+        match name {
+            "bibtex" => Some(Self::BibTex),
+            "ris" => Some(Self::Ris),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::BibTex => "bib",
+            Self::Ris => "ris",
+        }
+    }
+
+    pub fn format(self, documents: &[PdfMetadata], attachments: &AttachmentsByHash) -> String {
+        match self {
+            Self::BibTex => to_bibtex(documents, attachments),
+            Self::Ris => to_ris(documents, attachments),
+        }
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|value| !value.is_empty())
+}
+
+/// PDF dates look like "D:20190412093000+02'00'"
+fn year(metadata: &PdfMetadata) -> Option<&str> {
+    let date = non_empty(&metadata.creation_date)?;
+    let date = date.strip_prefix("D:").unwrap_or(date);
+    let year = date.get(..4)?;
+    year.chars().all(|c| c.is_ascii_digit()).then_some(year)
+}
+
+/// PDF authors are free text, usually separated by semicolons or "and". A
+/// comma is left alone, it's as likely to be in "Knuth, Donald" as between
+/// two names.
+fn authors(metadata: &PdfMetadata) -> Vec<String> {
+    let Some(author) = non_empty(&metadata.author) else { return Vec::new(); };
+    let separator = if author.contains(';') { ";" } else { " and " };
+    author.split(separator)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn keywords(metadata: &PdfMetadata) -> Vec<String> {
+    non_empty(&metadata.keywords).unwrap_or("")
+        .split([',', ';'])
+        .map(str::trim)
+        .filter(|keyword| !keyword.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn bibtex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '{' | '}' | '&' | '%' | '$' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '\n' | '\r' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// e.g. "knuth1984literate", made unique by appending letters
+fn bibtex_key(metadata: &PdfMetadata, used: &mut Vec<String>) -> String {
+    let word = |text: &str| -> String {
+        text.split(|c: char| !c.is_alphanumeric())
+            .find(|word| word.len() > 3)
+            .or_else(|| text.split(|c: char| !c.is_alphanumeric()).find(|word| !word.is_empty()))
+            .unwrap_or("")
+            .to_lowercase()
+    };
+    let author = authors(metadata).first()
+        // Last name, whether written "Donald Knuth" or "Knuth, Donald"
+        .map(|name| match name.split_once(',') {
+            Some((last, _)) => word(last),
+            None => word(name.split_whitespace().last().unwrap_or(name)),
+        })
+        .unwrap_or_default();
+    let mut key = format!("{}{}{}", author, year(metadata).unwrap_or(""), word(&metadata.display_title()));
+    if key.is_empty() { key = "document".to_string(); }
+
+    let base = key.clone();
+    let mut suffix = 0;
+    while used.contains(&key) {
+        key = format!("{}{}", base, letters(suffix));
+        suffix += 1;
+    }
+    used.push(key.clone());
+    key
+}
+
+// a to z, then aa, ab and on like spreadsheet columns
+fn letters(mut index: usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push(b'a' + (index % 26) as u8);
+        if index < 26 { break; }
+        index = index / 26 - 1;
+    }
+    letters.iter().rev().map(|&c| c as char).collect()
+}
+
+fn attached<'a>(metadata: &PdfMetadata, attachments: &'a AttachmentsByHash) -> &'a [String] {
+    attachments.get(&metadata.hash).map(Vec::as_slice).unwrap_or(&[])
+}
+
+pub fn to_bibtex(documents: &[PdfMetadata], attachments: &AttachmentsByHash) -> String {
+    let mut used = Vec::new();
+    let mut output = String::new();
+    for metadata in documents {
+        let mut fields = vec![("title", metadata.display_title())];
+        let authors = authors(metadata);
+        if !authors.is_empty() { fields.push(("author", authors.join(" and "))); }
+        if let Some(year) = year(metadata) { fields.push(("year", year.to_string())); }
+        if let Some(subject) = non_empty(&metadata.subject) { fields.push(("abstract", subject.to_string())); }
+        let keywords = keywords(metadata);
+        if !keywords.is_empty() { fields.push(("keywords", keywords.join(", "))); }
+        if metadata.page_count > 0 { fields.push(("pagetotal", metadata.page_count.to_string())); }
+        let mut fields: Vec<(&str, String)> = fields.into_iter()
+            .map(|(name, value)| (name, bibtex_escape(&value)))
+            .collect();
+        // Zotero and JabRef read several files separated by semicolons, and
+        // take the paths as they are apart from that separator. Braces are
+        // escaped too so a path can't end the field early.
+        let files: Vec<String> = std::iter::once(metadata.path.as_str())
+            .chain(attached(metadata, attachments).iter().map(String::as_str))
+            .map(|path| path.replace(';', "\\;").replace('{', "\\{").replace('}', "\\}"))
+            .collect();
+        fields.push(("file", files.join(";")));
+
+        output.push_str(&format!("@misc{{{},\n", bibtex_key(metadata, &mut used)));
+        for (name, value) in fields {
+            output.push_str(&format!("  {} = {{{}}},\n", name, value));
+        }
+        output.push_str("}\n\n");
+    }
+    output
+}
+
+pub fn to_ris(documents: &[PdfMetadata], attachments: &AttachmentsByHash) -> String {
+    let mut output = String::new();
+    let mut tag = |name: &str, value: &str| {
+        output.push_str(&format!("{}  - {}\r\n", name, value.replace(['\r', '\n'], " ")));
+    };
+    for metadata in documents {
+        tag("TY", "GEN");
+        tag("TI", &metadata.display_title());
+        for author in authors(metadata) { tag("AU", &author); }
+        if let Some(year) = year(metadata) { tag("PY", year); }
+        if let Some(subject) = non_empty(&metadata.subject) { tag("AB", subject); }
+        for keyword in keywords(metadata) { tag("KW", &keyword); }
+        let file_name = Path::new(&metadata.path).file_name().map(|name| name.to_string_lossy().to_string());
+        if let Some(file_name) = file_name { tag("N1", &file_name); }
+        tag("L1", &format!("file://{}", metadata.path));
+        for path in attached(metadata, attachments) { tag("L1", &format!("file://{}", path)); }
+        tag("ER", "");
+    }
+    output
+}
+
+/// Hand the documents to a running Zotero, which imports them into the
+/// currently selected collection
+pub fn send_to_zotero(documents: &[PdfMetadata], attachments: &AttachmentsByHash) -> Result<()> {
+    let body = to_ris(documents, attachments);
+    let mut stream = TcpStream::connect(ZOTERO_CONNECTOR)
+        .context("Could not reach Zotero, is it running?")?;
+    stream.set_read_timeout(Some(ZOTERO_TIMEOUT))?;
+    stream.set_write_timeout(Some(ZOTERO_TIMEOUT))?;
+    let request = format!(
+        "POST /connector/import HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Type: application/x-research-info-systems\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        ZOTERO_CONNECTOR,
+        body.len(),
+        body,
+    );
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status = response.lines().next().unwrap_or("");
+    let code = status.split_whitespace().nth(1).unwrap_or("");
+    if !code.starts_with('2') {
+        bail!("Zotero rejected the import: {}", status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(path: &str, title: &str, author: Option<&str>, creation_date: Option<&str>) -> PdfMetadata {
+        PdfMetadata {
+            title: Some(title.to_string()),
+            author: author.map(str::to_string),
+            creation_date: creation_date.map(str::to_string),
+            page_count: 0,
+            ..PdfMetadata::for_test("0123456789abcdef", path)
+        }
+    }
+
+    fn authors_of(author: &str) -> Vec<String> {
+        authors(&document("/books/a.pdf", "A Title", Some(author), None))
+    }
+
+    #[test]
+    fn authors_split_on_semicolons_and_and() {
+        assert_eq!(authors_of("Donald Knuth"), ["Donald Knuth"]);
+        assert_eq!(authors_of("Knuth, Donald"), ["Knuth, Donald"]);
+        assert_eq!(authors_of("Knuth, Donald; Lamport, Leslie"), ["Knuth, Donald", "Lamport, Leslie"]);
+        assert_eq!(authors_of("Donald Knuth and Leslie Lamport"), ["Donald Knuth", "Leslie Lamport"]);
+        assert_eq!(authors_of(" ; "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn bibtex_key_from_last_name_year_and_title() {
+        let mut used = Vec::new();
+        let first = document("/books/a.pdf", "Literate Programming", Some("Knuth, Donald"), Some("D:19840101000000"));
+        let second = document("/books/b.pdf", "Literate Programming", Some("Donald E. Knuth"), Some("1984"));
+        assert_eq!(bibtex_key(&first, &mut used), "knuth1984literate");
+        assert_eq!(bibtex_key(&second, &mut used), "knuth1984literatea");
+        assert_eq!(bibtex_key(&document("/books/c.pdf", "", None, None), &mut used), "c");
+    }
+
+    #[test]
+    fn bibtex_keys_stay_unique_past_z() {
+        let document = document("/books/a.pdf", "Literate Programming", Some("Donald Knuth"), Some("1984"));
+        let mut used = Vec::new();
+        let keys: Vec<String> = (0..60).map(|_| bibtex_key(&document, &mut used)).collect();
+        assert_eq!(keys[26], "knuth1984literatez");
+        assert_eq!(keys[27], "knuth1984literateaa");
+        assert_eq!(keys[28], "knuth1984literateab");
+        let unique: std::collections::HashSet<&String> = keys.iter().collect();
+        assert_eq!(unique.len(), keys.len());
+    }
+
+    #[test]
+    fn bibtex_escapes_special_characters() {
+        assert_eq!(bibtex_escape("50% of R&D_costs {sic}"), "50\\% of R\\&D\\_costs \\{sic\\}");
+        assert_eq!(bibtex_escape("a\\b ~c^d\ne"), "a\\textbackslash{}b \\textasciitilde{}c\\textasciicircum{}d e");
+    }
+
+    #[test]
+    fn bibtex_entry() {
+        let documents = [document("/books/50% off; R&D.pdf", "Tea & Biscuits", Some("Knuth, Donald"), Some("D:1999"))];
+        let attachments = AttachmentsByHash::from([("0123456789abcdef".to_string(), vec!["/notes/n.txt".to_string()])]);
+        assert_eq!(to_bibtex(&documents, &attachments), "\
+@misc{knuth1999biscuits,
+  title = {Tea \\& Biscuits},
+  author = {Knuth, Donald},
+  year = {1999},
+  file = {/books/50% off\\; R&D.pdf;/notes/n.txt},
+}
+
+");
+    }
+
+    #[test]
+    fn bibtex_braces_in_paths_stay_balanced() {
+        let documents = [
+            document("/books/{draft}/a}.pdf", "A", None, None),
+            document("/books/b.pdf", "B", None, None),
+        ];
+        let bibtex = to_bibtex(&documents, &AttachmentsByHash::new());
+        assert!(bibtex.contains("  file = {/books/\\{draft\\}/a\\}.pdf},\n"));
+        let unescaped = bibtex.replace("\\{", "").replace("\\}", "");
+        assert_eq!(unescaped.matches('{').count(), unescaped.matches('}').count());
+        assert!(bibtex.contains("@misc{b,\n"));
+    }
+
+    #[test]
+    fn ris_entry() {
+        let documents = [document("/books/a b.pdf", "Line\nbreak", Some("Knuth, Donald and Lamport, Leslie"), None)];
+        assert_eq!(
+            to_ris(&documents, &AttachmentsByHash::new()),
+            "TY  - GEN\r\nTI  - Line break\r\nAU  - Knuth, Donald\r\nAU  - Lamport, Leslie\r\n\
+             N1  - a b.pdf\r\nL1  - file:///books/a b.pdf\r\nER  - \r\n"
+        );
+    }
+}
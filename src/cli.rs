@@ -0,0 +1,445 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use gtk::glib;
+
+use crate::collections::CollectionTree;
+use crate::config::Config;
+use crate::pdf::{cache_db_path, AccessToken, MetadataField, PdfCache, PdfMetadata};
+use crate::selector::{is_glob, is_hash, Filter};
+use crate::server;
+use crate::snapshot::{Change, Snapshot};
+use crate::utils::compute_full_hash;
+
+const USAGE: &str = "\
+Usage:
+  shelf                                  start the library window
+  shelf [--search <query>] [--shelf <name>] [--refresh] [<files…>]
+                                         start with a search, a collection or volume shown
+                                         or a scan running, in the open window if there is one
+  shelf list <documents…>                print the selected documents
+  shelf tag add <tag> <documents…>       tag documents
+  shelf tag remove <tag> <documents…>    untag documents
+  shelf tag list <documents…>            show the tags of documents
+  shelf set [--title <text>] [--author <text>] [--subject <text>] [--keywords <text>] <documents…>
+                                         overwrite metadata, an empty value clears it
+  shelf export --to <directory> <documents…>
+                                         copy documents into a directory, attachments
+                                         go into a folder named after each document
+  shelf snapshot <file.db>               save a copy of the library database
+  shelf diff <old.db> [<new.db>]         compare two snapshots, or a snapshot with the library
+                                         one tab separated line per added, removed, moved
+                                         or changed document
+  shelf token add <name> [--collection <name>]… [--no-download]
+                                         give someone read-only access to the share server,
+                                         limited to some collections or to browsing
+  shelf token list                       show access tokens and their links
+  shelf token remove <name>              revoke an access token
+
+Add --verbose to any of them to log debug messages to stderr, they always
+go to the log files in ~/.cache/shelf/logs.
+
+Documents are paths, quoted globs matched against the library (e.g. '~/Papers/**/*.pdf')
+or hashes as shown by `shelf list --hashes`. Add --filter to narrow them down, or to pick
+from the whole library when no documents are given:
+  --filter 'tag:thesis author:smith'     terms are tag, collection, author, title, keyword,
+                                         lang, field, path and hash
+  --filter 'lang:bn field:course=cs301'  lang is a language code or name, field:key=value
+                                         matches a custom field and field:key any document
+                                         that has one
+
+Documents have to be scanned by Shelf before they can be changed here.";
+
+/// Run a command line subcommand. Returns None when `args` aren't one, so
+/// the window should start instead.
+pub fn run(args: &[String]) -> Option<glib::ExitCode> {
+    let result = match args.first().map(String::as_str) {
+        Some("list") => list(&args[1..]),
+        Some("tag") => tag(&args[1..]),
+        Some("set") => set(&args[1..]),
+        Some("export") => export(&args[1..]),
+        Some("snapshot") => snapshot(&args[1..]),
+        Some("diff") => diff(&args[1..]),
+        Some("token") => token(&args[1..]),
+        Some("help") => {
+            println!("{}", USAGE);
+            Ok(true)
+        }
+        _ => return None,
+    };
+    Some(match result {
+        Ok(true) => glib::ExitCode::SUCCESS,
+        Ok(false) => glib::ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("shelf: {:#}", e);
+            eprintln!("\n{}", USAGE);
+            glib::ExitCode::FAILURE
+        }
+    })
+}
+
+/// Options of a subcommand and the documents it works on
+#[derive(Default)]
+struct Args {
+    options: Vec<(String, String)>,
+    switches: Vec<String>,
+    documents: Vec<String>,
+}
+
+impl Args {
+    /// `options` take a value, as `--name value` or `--name=value`
+    fn parse(args: &[String], options: &[&str], switches: &[&str]) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.documents.extend(args.by_ref().cloned());
+                break;
+            }
+            if !arg.starts_with("--") {
+                parsed.documents.push(arg.clone());
+                continue;
+            }
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if switches.contains(&name) && inline_value.is_none() {
+                parsed.switches.push(name.to_string());
+            } else if name == "--filter" || options.contains(&name) {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => args.next().cloned().with_context(|| format!("{} needs a value", name))?,
+                };
+                parsed.options.push((name.to_string(), value));
+            } else {
+                bail!("Unknown option {}", name);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(other, _)| other == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Every value of an option that can be given more than once
+    fn all_options(&self, name: &str) -> Vec<&str> {
+        self.options.iter().filter(|(other, _)| other == name).map(|(_, value)| value.as_str()).collect()
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|other| other == name)
+    }
+}
+
+fn open_cache() -> Result<PdfCache> {
+    let low_memory = Config::load().map(|config| config.low_memory_mode).unwrap_or(false);
+    PdfCache::new(low_memory).context("Failed to open the library cache")
+}
+
+/// Look a document up the way the scan stored it
+fn resolve(cache: &PdfCache, path: &str) -> Result<PdfMetadata> {
+    let mut candidates = Vec::new();
+    if let Ok(canonical) = std::fs::canonicalize(path) { candidates.push(canonical); }
+    if let Ok(absolute) = std::path::absolute(path) { candidates.push(absolute); }
+    for candidate in candidates {
+        if let Some(metadata) = cache.get_by_path(&candidate.to_string_lossy())? {
+            return Ok(metadata);
+        }
+    }
+    if !Path::new(path).exists() { bail!("{}: no such file", path); }
+    bail!("{} is not in the library, let Shelf scan it first", path)
+}
+
+/// Documents picked by paths, globs, hashes and `--filter`, in library order.
+/// Documents that can't be found are reported and left out.
+fn select(cache: &PdfCache, args: &Args) -> Result<(Vec<PdfMetadata>, bool)> {
+    let filter = Filter::parse(args.option("--filter").unwrap_or(""))?;
+    if args.documents.is_empty() && filter.is_empty() { bail!("No documents given"); }
+
+    let library = cache.all_metadata()?;
+    let tags = cache.all_tags()?;
+    let collections = CollectionTree::load(cache)?.names_by_hash();
+    let fields = cache.all_custom_fields()?;
+    let mut ok = true;
+    let mut selected: Vec<PdfMetadata> = if args.documents.is_empty() {
+        library.clone()
+    } else {
+        let mut selected: Vec<PdfMetadata> = Vec::new();
+        for document in &args.documents {
+            let found: Vec<PdfMetadata> = if Path::new(document).exists() || !(is_glob(document) || is_hash(document)) {
+                match resolve(cache, document) {
+                    Ok(metadata) => vec![metadata],
+                    Err(e) => {
+                        eprintln!("shelf: {:#}", e);
+                        ok = false;
+                        continue;
+                    }
+                }
+            } else if is_hash(document) {
+                library.iter().filter(|pdf| pdf.hash.starts_with(document.as_str())).cloned().collect()
+            } else {
+                let expanded = shellexpand::tilde(document).to_string();
+                let absolute = std::path::absolute(&expanded).unwrap_or_else(|_| PathBuf::from(&expanded));
+                let pattern = glob::Pattern::new(&absolute.to_string_lossy())?;
+                library.iter().filter(|pdf| pattern.matches(&pdf.path)).cloned().collect()
+            };
+            if found.is_empty() {
+                eprintln!("shelf: nothing in the library matches {}", document);
+                ok = false;
+            }
+            for metadata in found {
+                if !selected.iter().any(|other| other.hash == metadata.hash) {
+                    selected.push(metadata);
+                }
+            }
+        }
+        selected
+    };
+    selected.retain(|metadata| filter.matches(
+        metadata,
+        tags.get(&metadata.hash).map(Vec::as_slice).unwrap_or(&[]),
+        collections.get(&metadata.hash).map(Vec::as_slice).unwrap_or(&[]),
+        fields.get(&metadata.hash).map(Vec::as_slice).unwrap_or(&[]),
+    ));
+    Ok((selected, ok))
+}
+
+/// Apply `f` to every selected document, reporting failures as they come.
+/// Returns false if anything failed.
+fn for_each_document(args: &Args, mut f: impl FnMut(&PdfCache, &PdfMetadata) -> Result<()>) -> Result<bool> {
+    let cache = open_cache()?;
+    let (selected, mut ok) = select(&cache, args)?;
+    for metadata in &selected {
+        if let Err(e) = f(&cache, metadata) {
+            eprintln!("shelf: {}: {:#}", metadata.path, e);
+            ok = false;
+        }
+    }
+    Ok(ok)
+}
+
+fn list(args: &[String]) -> Result<bool> {
+    let args = Args::parse(args, &[], &["--hashes"])?;
+    let hashes = args.switch("--hashes");
+    for_each_document(&args, |_, metadata| {
+        if hashes {
+            println!("{}  {}", &metadata.hash[..16.min(metadata.hash.len())], metadata.path);
+        } else {
+            println!("{}", metadata.path);
+        }
+        Ok(())
+    })
+}
+
+fn tag(args: &[String]) -> Result<bool> {
+    match args.first().map(String::as_str) {
+        Some(action @ ("add" | "remove")) => {
+            let Some(tag) = args.get(1).map(|tag| tag.trim().to_lowercase()).filter(|tag| !tag.is_empty()) else {
+                bail!("No tag given");
+            };
+            let args = Args::parse(&args[2..], &[], &[])?;
+            for_each_document(&args, |cache, metadata| {
+                let changed = if action == "add" {
+                    cache.add_tag(&metadata.hash, &tag)?
+                } else {
+                    cache.remove_tag(&metadata.hash, &tag)?
+                };
+                if changed { println!("{}: {} {}", metadata.path, action, tag); }
+                Ok(())
+            })
+        }
+        Some("list") => {
+            let args = Args::parse(&args[1..], &[], &[])?;
+            for_each_document(&args, |cache, metadata| {
+                println!("{}: {}", metadata.path, cache.get_tags(&metadata.hash)?.join(", "));
+                Ok(())
+            })
+        }
+        Some(action) => bail!("Unknown tag action {}", action),
+        None => bail!("Missing tag action"),
+    }
+}
+
+const SET_OPTIONS: [(&str, MetadataField); 4] = [
+    ("--title", MetadataField::Title),
+    ("--author", MetadataField::Author),
+    ("--subject", MetadataField::Subject),
+    ("--keywords", MetadataField::Keywords),
+];
+
+fn set(args: &[String]) -> Result<bool> {
+    let names = SET_OPTIONS.map(|(name, _)| name);
+    let args = Args::parse(args, &names, &[])?;
+    let fields: Vec<(MetadataField, Option<String>)> = SET_OPTIONS.iter()
+        .filter_map(|(name, field)| args.option(name).map(|value| {
+            (*field, Some(value.trim().to_string()).filter(|value| !value.is_empty()))
+        }))
+        .collect();
+    if fields.is_empty() { bail!("Nothing to set"); }
+
+    for_each_document(&args, |cache, metadata| {
+        for (field, value) in &fields {
+            cache.set_field(&metadata.hash, *field, value.as_deref())?;
+        }
+        println!("{}: updated", metadata.path);
+        Ok(())
+    })
+}
+
+fn export(args: &[String]) -> Result<bool> {
+    let args = Args::parse(args, &["--to"], &[])?;
+    let target = PathBuf::from(shellexpand::tilde(args.option("--to").context("--to is required")?).to_string());
+    std::fs::create_dir_all(&target)
+        .with_context(|| format!("Failed to create {}", target.display()))?;
+
+    for_each_document(&args, |cache, metadata| {
+        let source = Path::new(&metadata.path);
+        let name = source.file_name().context("Document has no file name")?;
+        // Exported before, when the export is run again
+        let is_copy = |path: &Path| path.metadata().is_ok_and(|file| file.len() == metadata.file_size)
+            && compute_full_hash(path).is_ok_and(|hash| hash == metadata.hash);
+        let mut destination = target.join(name);
+        // Another document with the same file name is already there
+        if destination.exists() && !is_copy(&destination) {
+            let stem = source.file_stem().unwrap_or(name).to_string_lossy();
+            let short_hash = &metadata.hash[..8.min(metadata.hash.len())];
+            destination = target.join(match source.extension() {
+                Some(extension) => format!("{}-{}.{}", stem, short_hash, extension.to_string_lossy()),
+                None => format!("{}-{}", stem, short_hash),
+            });
+        }
+        if destination.exists() && is_copy(&destination) {
+            println!("{}: already in {}", metadata.path, destination.display());
+        } else {
+            std::fs::copy(source, &destination)?;
+            println!("{} -> {}", metadata.path, destination.display());
+        }
+
+        let attachments = cache.get_attachments(&metadata.hash)?;
+        if attachments.is_empty() { return Ok(()); }
+        let folder = destination.with_extension("files");
+        std::fs::create_dir_all(&folder)
+            .with_context(|| format!("Failed to create {}", folder.display()))?;
+        for attachment in attachments {
+            let attachment = Path::new(&attachment);
+            let Some(name) = attachment.file_name() else { continue; };
+            let copied = folder.join(name);
+            match std::fs::copy(attachment, &copied) {
+                Ok(_) => println!("{} -> {}", attachment.display(), copied.display()),
+                // A missing attachment shouldn't fail the document
+                Err(e) => eprintln!("shelf: {}: {}", attachment.display(), e),
+            }
+        }
+        Ok(())
+    })
+}
+
+fn snapshot(args: &[String]) -> Result<bool> {
+    let [path] = args else { bail!("Expected one snapshot file"); };
+    open_cache()?.snapshot(Path::new(path))?;
+    println!("Saved the library to {}", path);
+    Ok(true)
+}
+
+fn diff(args: &[String]) -> Result<bool> {
+    let (old, new) = match args {
+        [old] => (PathBuf::from(old), cache_db_path()),
+        [old, new] => (PathBuf::from(old), PathBuf::from(new)),
+        _ => bail!("Expected one or two snapshot files"),
+    };
+    // Make sure queued writes of a running scan aren't missed
+    if args.len() == 1 { open_cache()?.flush()?; }
+    let old = Snapshot::open(&old)?;
+    let new = Snapshot::open(&new)?;
+
+    let changes = old.diff(&new);
+    let mut counts = [0usize; 4];
+    for change in &changes {
+        let index = match change {
+            Change::Added { .. } => 0,
+            Change::Removed { .. } => 1,
+            Change::Moved { .. } => 2,
+            Change::Changed { .. } => 3,
+        };
+        counts[index] += 1;
+        println!("{}", change);
+    }
+    // On stderr so the output stays easy to parse
+    eprintln!(
+        "{} → {} documents: {} added, {} removed, {} moved, {} changes",
+        old.len(), new.len(), counts[0], counts[1], counts[2], counts[3]
+    );
+    Ok(true)
+}
+
+fn token(args: &[String]) -> Result<bool> {
+    let port = Config::load().unwrap_or_default().share_port;
+    match args.first().map(String::as_str) {
+        Some("add") => {
+            let args = Args::parse(&args[1..], &["--collection"], &["--no-download"])?;
+            let [name] = args.documents.as_slice() else { bail!("Expected one token name"); };
+            let name = name.trim();
+            if name.is_empty() { bail!("Empty token name"); }
+            let cache = open_cache()?;
+            if cache.access_tokens()?.iter().any(|access| access.name == name) {
+                bail!("There already is a token named {}", name);
+            }
+
+            let tree = CollectionTree::load(&cache)?;
+            let names = args.all_options("--collection");
+            let collections = if names.is_empty() {
+                None
+            } else {
+                let mut ids = Vec::new();
+                for wanted in names {
+                    let found: Vec<i64> = tree.ordered().iter()
+                        .filter(|(collection, _)| collection.name.eq_ignore_ascii_case(wanted.trim()))
+                        .map(|(collection, _)| collection.id)
+                        .collect();
+                    match found.as_slice() {
+                        [id] => ids.push(*id),
+                        [] => bail!("No collection named {}", wanted),
+                        _ => bail!("More than one collection is named {}", wanted),
+                    }
+                }
+                Some(ids)
+            };
+            let access = AccessToken {
+                token: server::new_token(),
+                name: name.to_string(),
+                collections,
+                download: !args.switch("--no-download"),
+            };
+            cache.store_access_token(&access)?;
+            println!("{}", server::share_url(&access.token, port));
+            eprintln!("Shelf serves it while the window is open, restart Shelf if it's running");
+            Ok(true)
+        }
+        Some("list") => {
+            let cache = open_cache()?;
+            let tree = CollectionTree::load(&cache)?;
+            for access in cache.access_tokens()? {
+                let scope = match &access.collections {
+                    None => "whole library".to_string(),
+                    Some(ids) => ids.iter()
+                        .map(|id| tree.get(*id).map(|c| c.name.clone()).unwrap_or_else(|| "(deleted)".to_string()))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                };
+                let download = if access.download { "download" } else { "browse only" };
+                println!("{}\t{}\t{}\t{}", access.name, scope, download, server::share_url(&access.token, port));
+            }
+            Ok(true)
+        }
+        Some("remove") => {
+            let [name] = &args[1..] else { bail!("Expected one token name"); };
+            if !open_cache()?.remove_access_token(name)? { bail!("No token named {}", name); }
+            println!("Revoked {}", name);
+            Ok(true)
+        }
+        Some(action) => bail!("Unknown token action {}", action),
+        None => bail!("Missing token action"),
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use crate::pdf::{Collection, PdfCache};
+
+/// Collections with what they contain, loaded from the cache in one go
+#[derive(Debug, Clone, Default)]
+pub struct CollectionTree {
+    collections: Vec<Collection>,
+    // Documents directly in each collection
+    items: HashMap<i64, HashSet<String>>,
+}
+
+impl CollectionTree {
+    pub fn load(cache: &PdfCache) -> Result<Self> {
+        let collections = cache.collections()?;
+        let mut items: HashMap<i64, HashSet<String>> = HashMap::new();
+        for (id, hash) in cache.collection_items()? {
+            items.entry(id).or_default().insert(hash);
+        }
+        Ok(Self { collections, items })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collections.is_empty()
+    }
+
+    pub fn get(&self, id: i64) -> Option<&Collection> {
+        self.collections.iter().find(|collection| collection.id == id)
+    }
+
+    fn children(&self, parent: Option<i64>) -> impl Iterator<Item = &Collection> {
+        self.collections.iter().filter(move |collection| {
+            // Collections whose parent is gone show up at the top level
+            collection.parent == parent
+                || (parent.is_none() && collection.parent.is_some_and(|id| self.get(id).is_none()))
+        })
+    }
+
+    /// Every collection with its depth, parents before their children
+    pub fn ordered(&self) -> Vec<(&Collection, usize)> {
+        fn visit<'a>(tree: &'a CollectionTree, parent: Option<i64>, depth: usize, out: &mut Vec<(&'a Collection, usize)>) {
+            for collection in tree.children(parent) {
+                out.push((collection, depth));
+                visit(tree, Some(collection.id), depth + 1, out);
+            }
+        }
+        let mut ordered = Vec::with_capacity(self.collections.len());
+        visit(self, None, 0, &mut ordered);
+        ordered
+    }
+
+    /// `id` and every collection below it
+    pub fn subtree(&self, id: i64) -> Vec<i64> {
+        let mut ids = vec![id];
+        let mut index = 0;
+        while index < ids.len() {
+            let parent = ids[index];
+            for child in self.collections.iter().filter(|c| c.parent == Some(parent)) {
+                if !ids.contains(&child.id) { ids.push(child.id); }
+            }
+            index += 1;
+        }
+        ids
+    }
+
+    /// Documents in the collection or any of its sub-collections
+    pub fn documents(&self, id: i64) -> HashSet<String> {
+        self.subtree(id).iter()
+            .filter_map(|id| self.items.get(id))
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    pub fn contains(&self, id: i64, hash: &str) -> bool {
+        self.items.get(&id).is_some_and(|items| items.contains(hash))
+    }
+
+    /// Whether `parent` can take `id` without making it its own ancestor
+    pub fn can_move(&self, id: i64, parent: Option<i64>) -> bool {
+        parent.is_none_or(|parent| !self.subtree(id).contains(&parent))
+    }
+
+    /// Names of the collections each document is in, including the ones
+    /// above them, lowercased for matching
+    pub fn names_by_hash(&self) -> HashMap<String, Vec<String>> {
+        let mut names: HashMap<String, Vec<String>> = HashMap::new();
+        for (id, hashes) in &self.items {
+            let mut ancestor = self.get(*id);
+            let mut visited = Vec::new();
+            while let Some(collection) = ancestor && !visited.contains(&collection.id) {
+                visited.push(collection.id);
+                ancestor = collection.parent.and_then(|parent| self.get(parent));
+            }
+            for hash in hashes {
+                let entry = names.entry(hash.clone()).or_default();
+                for id in &visited {
+                    let name = self.get(*id).unwrap().name.to_lowercase();
+                    if !entry.contains(&name) { entry.push(name); }
+                }
+            }
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(collections: &[(i64, &str, Option<i64>)], items: &[(i64, &str)]) -> CollectionTree {
+        let mut tree = CollectionTree {
+            collections: collections.iter()
+                .map(|&(id, name, parent)| Collection { id, name: name.to_string(), parent })
+                .collect(),
+            items: HashMap::new(),
+        };
+        for &(id, hash) in items {
+            tree.items.entry(id).or_default().insert(hash.to_string());
+        }
+        tree
+    }
+
+    #[test]
+    fn ordered_puts_parents_before_children() {
+        let tree = tree(&[(3, "Papers", Some(1)), (1, "Work", None), (2, "Fiction", None), (4, "2019", Some(3))], &[]);
+        let ordered: Vec<_> = tree.ordered().into_iter().map(|(c, depth)| (c.id, depth)).collect();
+        assert_eq!(ordered, vec![(1, 0), (3, 1), (4, 2), (2, 0)]);
+    }
+
+    #[test]
+    fn orphans_show_up_at_the_top() {
+        let tree = tree(&[(1, "Work", None), (2, "Lost", Some(9))], &[]);
+        let ordered: Vec<_> = tree.ordered().into_iter().map(|(c, depth)| (c.id, depth)).collect();
+        assert_eq!(ordered, vec![(1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn documents_include_sub_collections() {
+        let tree = tree(
+            &[(1, "Work", None), (2, "Papers", Some(1)), (3, "2019", Some(2)), (4, "Fiction", None)],
+            &[(1, "a"), (3, "b"), (4, "c")],
+        );
+        let mut subtree = tree.subtree(1);
+        subtree.sort();
+        assert_eq!(subtree, vec![1, 2, 3]);
+        assert_eq!(tree.documents(1), HashSet::from(["a".to_string(), "b".to_string()]));
+        assert_eq!(tree.documents(2), HashSet::from(["b".to_string()]));
+        assert!(tree.contains(1, "a"));
+        assert!(!tree.contains(1, "b"));
+    }
+
+    #[test]
+    fn can_move_refuses_cycles() {
+        let tree = tree(&[(1, "Work", None), (2, "Papers", Some(1)), (3, "2019", Some(2)), (4, "Fiction", None)], &[]);
+        assert!(!tree.can_move(1, Some(1)));
+        assert!(!tree.can_move(1, Some(3)));
+        assert!(tree.can_move(3, Some(1)));
+        assert!(tree.can_move(1, Some(4)));
+        assert!(tree.can_move(3, None));
+    }
+
+    #[test]
+    fn cycles_already_in_the_database_terminate() {
+        let tree = tree(&[(1, "A", Some(2)), (2, "B", Some(1))], &[(1, "x")]);
+        let mut subtree = tree.subtree(1);
+        subtree.sort();
+        assert_eq!(subtree, vec![1, 2]);
+        assert_eq!(tree.documents(2), HashSet::from(["x".to_string()]));
+        let mut names = tree.names_by_hash().remove("x").unwrap();
+        names.sort();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn names_include_ancestors_once() {
+        let tree = tree(&[(1, "Work", None), (2, "Papers", Some(1))], &[(1, "a"), (2, "a"), (2, "b")]);
+        let names = tree.names_by_hash();
+        let mut a = names["a"].clone();
+        a.sort();
+        assert_eq!(a, vec!["papers", "work"]);
+        let mut b = names["b"].clone();
+        b.sort();
+        assert_eq!(b, vec!["papers", "work"]);
+    }
+}
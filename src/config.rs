@@ -1,34 +1,211 @@
 #![allow(dead_code)]
 
-use std::{fs, path::PathBuf};
+use std::{collections::BTreeMap, fs, path::PathBuf};
 use anyhow::{Context, Ok};
 use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::gsettings;
+use crate::utils::WalkOptions;
+
+/// Where preferences are kept. With GSettings only this stays in config.toml.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Toml,
+    Gsettings,
+}
+
+/// Light or dark style, or following the desktop
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+/// What scans do with scan directories on network filesystems
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NetworkMounts {
+    #[default]
+    Full,
+    // Metadata is read, covers and snippets aren't
+    MetadataOnly,
+    // Left out of full scans, what's cached for them stays
+    Skip,
+}
+
+/// How new covers are encoded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CoverFormat {
+    #[default]
+    Jpeg,
+    // About half the size of JPEG at the same quality
+    Webp,
+}
+
+impl CoverFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Webp => "webp",
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
+    pub backend: Backend,
     #[serde(default)]
     pub scan_dirs: Vec<PathBuf>,
     #[serde(default = "default_pdf_viewer_command")]
     pub pdf_viewer_command: String,
+    #[serde(default = "default_true")]
+    pub show_cover_badges: bool,
+    #[serde(default)]
+    pub use_internal_reader: bool,
+    // Trade speed for a smaller footprint on old laptops and SBCs
+    #[serde(default)]
+    pub low_memory_mode: bool,
+    // Status bar modules left to right: path, selection, scan, sync
+    #[serde(default = "default_status_bar_modules")]
+    pub status_bar_modules: Vec<String>,
+    // Documents opened with Shelf from outside the scan directories
+    #[serde(default)]
+    pub extra_files: Vec<PathBuf>,
+    // Where shared collections are served, see server.rs
+    #[serde(default = "default_share_address")]
+    pub share_address: String,
+    #[serde(default = "default_share_port")]
+    pub share_port: u16,
+    // Covers and page thumbnails are trimmed to this by the daily maintenance,
+    // thumbnails first, then the covers shown the longest ago
+    #[serde(default = "default_cover_cache_limit_mb")]
+    pub cover_cache_limit_mb: u64,
+    // Whether the daily maintenance deletes the covers of documents gone
+    // from the library, they can still be deleted from the settings
+    #[serde(default = "default_true")]
+    pub remove_unused_covers: bool,
+    // Changing it renders every cover again on the next scan
+    #[serde(default)]
+    pub cover_format: CoverFormat,
+    // 1 to 100, for JPEG and WebP alike
+    #[serde(default = "default_cover_quality")]
+    pub cover_quality: u8,
+    // Covers are rendered at up to this times 72 DPI, from 0.25 to 4.
+    // Changing it or the quality also renders every cover again.
+    #[serde(default = "default_cover_render_scale")]
+    pub cover_render_scale: f32,
+    // Look the covers of books up on Open Library and Google Books after a
+    // scan, by ISBN or title and author. Sends those to both.
+    #[serde(default)]
+    pub fetch_online_covers: bool,
+    // Fill in the missing title, author, year and subjects of books with an
+    // ISBN from Open Library and Google Books after a scan, and of papers
+    // with an arXiv ID from arXiv along with their abstract
+    #[serde(default)]
+    pub fetch_online_metadata: bool,
+    // Rules like "{author} - {title} ({year})" filling in missing metadata
+    // from file names when files are read, the first that matches wins,
+    // see filename_patterns.rs
+    #[serde(default)]
+    pub filename_patterns: Vec<String>,
+    // Shortcuts replacing the defaults, action name to accelerators, see ui/shortcuts.rs
+    #[serde(default)]
+    pub keybindings: BTreeMap<String, Vec<String>>,
+    // h/j/k/l, gg, G and o in the grid
+    #[serde(default)]
+    pub vim_navigation: bool,
+    #[serde(default)]
+    pub theme: Theme,
+    // Pick up PDFs added to or deleted from the scan directories as it happens
+    #[serde(default = "default_true")]
+    pub watch_scan_dirs: bool,
+    // Threads a scan runs on, 0 is one per core
+    #[serde(default)]
+    pub scan_threads: usize,
+    // Covers rendered at once, MuPDF takes a lot of memory per page. 0 is
+    // one per scan thread
+    #[serde(default)]
+    pub max_concurrent_renders: usize,
+    // A new file taking longer than this is skipped, 0 waits for ever
+    #[serde(default = "default_extraction_timeout_secs")]
+    pub extraction_timeout_secs: u64,
+    // Larger files are skipped, 0 is no limit
+    #[serde(default)]
+    pub max_file_size_mb: u64,
+    // Files whose first page is wider or taller than this many pixels at
+    // 72 DPI are skipped, 0 is no limit
+    #[serde(default)]
+    pub max_render_dimension: u32,
+    // Scan the folders and PDFs symbolic links point to
+    #[serde(default = "default_true")]
+    pub follow_symlinks: bool,
+    // Leave out files and folders whose names start with a dot
+    #[serde(default = "default_true")]
+    pub skip_hidden: bool,
+    #[serde(default)]
+    pub network_mounts: NetworkMounts,
 }
 
 fn default_pdf_viewer_command() -> String { "zathura %".to_string() }
+fn default_true() -> bool { true }
+fn default_share_address() -> String { "0.0.0.0".to_string() }
+fn default_share_port() -> u16 { 8765 }
+fn default_cover_cache_limit_mb() -> u64 { 512 }
+fn default_cover_quality() -> u8 { 75 }
+fn default_cover_render_scale() -> f32 { 1.0 }
+fn default_extraction_timeout_secs() -> u64 { 60 }
+fn default_status_bar_modules() -> Vec<String> {
+    ["path", "selection", "scan", "sync"].map(String::from).to_vec()
+}
 
 impl Default for Config {
     fn default() -> Self {
         Self { 
+            backend: Backend::Toml,
             scan_dirs: Vec::new(),
-            pdf_viewer_command: "zathura %".to_string()
+            pdf_viewer_command: "zathura %".to_string(),
+            show_cover_badges: true,
+            use_internal_reader: false,
+            low_memory_mode: false,
+            status_bar_modules: default_status_bar_modules(),
+            extra_files: Vec::new(),
+            share_address: default_share_address(),
+            share_port: default_share_port(),
+            cover_cache_limit_mb: default_cover_cache_limit_mb(),
+            remove_unused_covers: true,
+            cover_format: CoverFormat::Jpeg,
+            cover_quality: default_cover_quality(),
+            cover_render_scale: default_cover_render_scale(),
+            fetch_online_covers: false,
+            fetch_online_metadata: false,
+            filename_patterns: Vec::new(),
+            keybindings: BTreeMap::new(),
+            vim_navigation: false,
+            theme: Theme::System,
+            watch_scan_dirs: true,
+            scan_threads: 0,
+            max_concurrent_renders: 0,
+            extraction_timeout_secs: default_extraction_timeout_secs(),
+            max_file_size_mb: 0,
+            max_render_dimension: 0,
+            follow_symlinks: true,
+            skip_hidden: true,
+            network_mounts: NetworkMounts::Full,
         }
     }
 }
 
 impl Config {
-    fn config_path() -> anyhow::Result<PathBuf> {
-        let config_path = dirs::home_dir()
-            .context("No home directory found! Set HOME environment variable")?
-            .join(".shelf").join("config.toml");
-        Ok(config_path)
+    pub fn config_path() -> anyhow::Result<PathBuf> {
+        Ok(crate::paths::config_dir()?.join("config.toml"))
     }
 
     pub fn load() -> anyhow::Result<Self> {
@@ -45,6 +222,13 @@ impl Config {
 
         let contents = fs::read_to_string(&config_path)?;
         let mut config = toml::from_str::<Config>(&contents)?;
+        if config.backend == Backend::Gsettings {
+            if gsettings::is_available() {
+                config = gsettings::load()?;
+            } else {
+                warn!("backend = \"gsettings\" but the org.galib.shelf schema isn't installed, using config.toml");
+            }
+        }
         config.scan_dirs = config.scan_dirs.iter()
             .map(|p| {
                 let s = p.to_str().unwrap();
@@ -53,20 +237,29 @@ impl Config {
             })
             .collect();
         
-        if toml::to_string_pretty(&config)? != contents { config.save()?; }
+        if config.backend == Backend::Toml && toml::to_string_pretty(&config)? != contents { config.save()?; }
         Ok(config)
     }
 
     pub fn save(&self) -> anyhow::Result<()>{
-        println!("Saving config.toml ...");
+        debug!("Saving config.toml ...");
         let config_path = Self::config_path()?;
         
         let app_data_dir = config_path.parent().context("Error getting config path")?;
         if !app_data_dir.exists() { fs::create_dir_all(&app_data_dir)?; }
 
+        if self.backend == Backend::Gsettings && gsettings::is_available() {
+            gsettings::save(self)?;
+            fs::write(&config_path, "backend = \"gsettings\"\n")?;
+            return Ok(());
+        }
         let contents = toml::to_string_pretty(self)?;
         fs::write(&config_path, contents)?;
         
         Ok(())
     }
+
+    pub fn walk_options(&self) -> WalkOptions {
+        WalkOptions { follow_symlinks: self.follow_symlinks, skip_hidden: self.skip_hidden }
+    }
 }
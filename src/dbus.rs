@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use gtk::glib;
+use gtk::gio;
+use gtk::prelude::*;
+use tracing::warn;
+
+use crate::ui::window::ShelfWindow;
+
+const INTERFACE: &str = "org.galib.shelf";
+
+const INTROSPECTION: &str = r#"
+<node>
+  <interface name="org.galib.shelf">
+    <!-- Documents matching a filter like `shelf --filter`, as (hash, title, path) -->
+    <method name="Search">
+      <arg type="s" name="query" direction="in"/>
+      <arg type="a(sss)" name="documents" direction="out"/>
+    </method>
+    <!-- Open a document by hash, or by the start of one -->
+    <method name="Open">
+      <arg type="s" name="hash" direction="in"/>
+    </method>
+    <!-- False if a scan is already running -->
+    <method name="Rescan">
+      <arg type="b" name="started" direction="out"/>
+    </method>
+    <signal name="ScanCompleted">
+      <arg type="u" name="documents"/>
+      <arg type="d" name="seconds"/>
+    </signal>
+  </interface>
+</node>
+"#;
+
+fn library_window(app: &gtk::Application) -> Option<ShelfWindow> {
+    app.windows().into_iter().find_map(|window| window.downcast::<ShelfWindow>().ok())
+}
+
+/// Export the library interface next to the ones GApplication puts on its
+/// object path, e.g. `/org/galib/shelf` on the session bus
+pub fn register(app: &gtk::Application) -> Result<()> {
+    let (Some(connection), Some(object_path)) = (app.dbus_connection(), app.dbus_object_path()) else {
+        // Not on a bus, e.g. a non-unique instance
+        return Ok(());
+    };
+    let node = gio::DBusNodeInfo::for_xml(INTROSPECTION)?;
+    let interface = node.lookup_interface(INTERFACE).context("Missing D-Bus interface")?;
+    connection
+        .register_object(&object_path, &interface)
+        .method_call(glib::clone!(
+            #[weak] app,
+            move |_, _, _, _, method, params, invocation| {
+                let Some(window) = library_window(&app) else {
+                    invocation.return_dbus_error("org.galib.shelf.Error.NoWindow", "The library window isn't open");
+                    return;
+                };
+                match method {
+                    "Search" => {
+                        let (query,) = params.get::<(String,)>().unwrap_or_default();
+                        match window.search_documents(&query) {
+                            Ok(documents) => {
+                                let documents: Vec<(String, String, String)> = documents.into_iter()
+                                    .map(|metadata| (metadata.hash.clone(), metadata.display_title(), metadata.path))
+                                    .collect();
+                                invocation.return_value(Some(&(documents,).to_variant()));
+                            }
+                            Err(e) => invocation.return_dbus_error("org.galib.shelf.Error.InvalidQuery", &format!("{:#}", e)),
+                        }
+                    }
+                    "Open" => {
+                        let (hash,) = params.get::<(String,)>().unwrap_or_default();
+                        if window.open_hash(&hash) {
+                            invocation.return_value(None);
+                        } else {
+                            invocation.return_dbus_error("org.galib.shelf.Error.NotFound", &format!("No document with hash {}", hash));
+                        }
+                    }
+                    "Rescan" => invocation.return_value(Some(&(window.rescan(),).to_variant())),
+                    _ => invocation.return_dbus_error("org.freedesktop.DBus.Error.UnknownMethod", &format!("Unknown method {}", method)),
+                }
+            }
+        ))
+        .build()?;
+
+    // Forward scans of every library window to the bus
+    app.connect_window_added(move |_, window| {
+        let Some(window) = window.downcast_ref::<ShelfWindow>() else { return; };
+        window.connect_closure("scan-complete", false, glib::closure_local!(
+            #[strong] connection,
+            #[strong] object_path,
+            move |_: ShelfWindow, found: u32, seconds: f64| {
+                if let Err(e) = connection.emit_signal(None, &object_path, INTERFACE, "ScanCompleted", Some(&(found, seconds).to_variant())) {
+                    warn!("Failed to emit ScanCompleted: {}", e);
+                }
+            }
+        ));
+    });
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::pdf::{PdfCache, PdfMetadata};
+
+// Shorter titles are too generic to group by, e.g. "Notes" or "Slides"
+const MIN_WORK_TITLE: usize = 8;
+
+static EDITION_MARKERS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    [
+        r"\b(\d+(st|nd|rd|th)|first|second|third|fourth|fifth|sixth|seventh|eighth|ninth|tenth|revised|updated|expanded|international|global)\s+(edition|ed\.?)(\W|$)",
+        r"\b(edition|ed\.)\s*\d+\b",
+        r"\bv(ersion)?\s*\d+(\.\d+)*\b",
+        r"\b(19|20)\d{2}\b",
+        r"\b(draft|preprint|final|arxiv)\b",
+    ]
+    .iter()
+    .map(|pattern| Regex::new(pattern).unwrap())
+    .collect()
+});
+
+/// Documents grouped into works, loaded from the cache in one go
+#[derive(Debug, Clone, Default)]
+pub struct Editions {
+    preferred: HashMap<i64, String>,
+    work_of: HashMap<String, i64>,
+}
+
+impl Editions {
+    pub fn load(cache: &PdfCache) -> Result<Self> {
+        let preferred = cache.works()?.into_iter().collect();
+        let work_of = cache.work_editions()?.into_iter().map(|(id, hash)| (hash, id)).collect();
+        Ok(Self { preferred, work_of })
+    }
+
+    pub fn is_edition(&self, hash: &str) -> bool {
+        self.work_of.contains_key(hash)
+    }
+
+    pub fn is_preferred(&self, hash: &str) -> bool {
+        self.work_of.get(hash).is_some_and(|id| self.preferred.get(id).is_some_and(|preferred| preferred == hash))
+    }
+
+    /// The other editions of the document's work
+    pub fn others(&self, hash: &str) -> Vec<&str> {
+        let Some(work) = self.work_of.get(hash) else { return Vec::new(); };
+        let mut others: Vec<&str> = self.work_of.iter()
+            .filter(|(other, id)| *id == work && other.as_str() != hash)
+            .map(|(other, _)| other.as_str())
+            .collect();
+        others.sort_unstable();
+        others
+    }
+
+    /// Editions the grid leaves out. A work whose preferred edition isn't in
+    /// the library shows one of the others instead.
+    pub fn hidden(&self, library: &HashSet<&str>) -> HashSet<String> {
+        let mut shown: HashMap<i64, &str> = HashMap::new();
+        for (id, preferred) in &self.preferred {
+            if library.contains(preferred.as_str()) { shown.insert(*id, preferred); }
+        }
+        let mut fallbacks: Vec<(&i64, &str)> = self.work_of.iter()
+            .filter(|(hash, id)| !shown.contains_key(id) && library.contains(hash.as_str()))
+            .map(|(hash, id)| (id, hash.as_str()))
+            .collect();
+        fallbacks.sort_unstable();
+        for (id, hash) in fallbacks {
+            shown.entry(*id).or_insert(hash);
+        }
+
+        self.work_of.iter()
+            .filter(|(hash, id)| shown.get(id).is_some_and(|shown| shown != hash))
+            .map(|(hash, _)| hash.clone())
+            .collect()
+    }
+
+    /// The documents without the editions hidden among them
+    pub fn without_hidden<T>(&self, documents: Vec<T>, hash: impl Fn(&T) -> &str) -> Vec<T> {
+        let hidden = self.hidden(&documents.iter().map(&hash).collect());
+        documents.into_iter().filter(|document| !hidden.contains(hash(document))).collect()
+    }
+}
+
+/// A title with edition and version markers, years and punctuation taken
+/// out, so editions of the same work compare equal
+pub fn work_title(title: &str) -> String {
+    let mut title = title.to_lowercase();
+    for marker in EDITION_MARKERS.iter() {
+        title = marker.replace_all(&title, " ").to_string();
+    }
+    title.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+/// Documents that aren't editions of anything yet but share a title with
+/// others, largest groups first
+pub fn suggestions<'a>(library: &'a [PdfMetadata], editions: &Editions) -> Vec<Vec<&'a PdfMetadata>> {
+    let mut groups: HashMap<String, Vec<&PdfMetadata>> = HashMap::new();
+    let mut seen: HashSet<&str> = HashSet::new();
+    for metadata in library {
+        // Copies of one file share a hash, they're not editions
+        if editions.is_edition(&metadata.hash) || !seen.insert(&metadata.hash) { continue; }
+        let title = work_title(&metadata.display_title());
+        if title.len() < MIN_WORK_TITLE { continue; }
+        groups.entry(title).or_default().push(metadata);
+    }
+    let mut suggestions: Vec<Vec<&PdfMetadata>> = groups.into_values().filter(|group| group.len() > 1).collect();
+    suggestions.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a[0].display_title().cmp(&b[0].display_title())));
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Work 1 prefers "a2" over "a1" and "a3", work 2 prefers "b1" over "b2"
+    fn editions() -> Editions {
+        Editions {
+            preferred: HashMap::from([(1, "a2".to_string()), (2, "b1".to_string())]),
+            work_of: [("a1", 1), ("a2", 1), ("a3", 1), ("b1", 2), ("b2", 2)].into_iter()
+                .map(|(hash, id)| (hash.to_string(), id))
+                .collect(),
+        }
+    }
+
+    // Those in the library, the others are never shown anyway
+    fn hidden(library: &[&str]) -> Vec<String> {
+        let mut hidden: Vec<String> = editions().hidden(&library.iter().copied().collect()).into_iter()
+            .filter(|hash| library.contains(&hash.as_str()))
+            .collect();
+        hidden.sort_unstable();
+        hidden
+    }
+
+    fn document(hash: &str, title: &str) -> PdfMetadata {
+        PdfMetadata { title: Some(title.to_string()), ..PdfMetadata::for_test(hash, &format!("/books/{}.pdf", hash)) }
+    }
+
+    #[test]
+    fn preferred_edition_shown() {
+        assert_eq!(hidden(&["a1", "a2", "a3", "b1", "b2", "c"]), ["a1", "a3", "b2"]);
+        assert!(editions().is_preferred("a2"));
+        assert!(!editions().is_preferred("a1"));
+        assert_eq!(editions().others("a1"), ["a2", "a3"]);
+    }
+
+    #[test]
+    fn another_edition_shown_without_the_preferred_one() {
+        assert_eq!(hidden(&["a1", "a3"]), ["a3"]);
+        assert_eq!(hidden(&["a3"]), Vec::<String>::new());
+        assert_eq!(hidden(&["b2"]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn without_hidden_keeps_documents_in_order() {
+        let documents = vec![("c", 5), ("a3", 4), ("b2", 3), ("a1", 2), ("b1", 1)];
+        assert_eq!(editions().without_hidden(documents, |(hash, _)| *hash), [("c", 5), ("a1", 2), ("b1", 1)]);
+    }
+
+    #[test]
+    fn work_title_drops_edition_markers() {
+        assert_eq!(work_title("Operating Systems: Three Easy Pieces (2nd Edition)"), "operating systems three easy pieces");
+        assert_eq!(work_title("Operating Systems - Three Easy Pieces, v1.10"), "operating systems three easy pieces");
+        assert_eq!(work_title("Compilers, Revised Edition 2006"), "compilers");
+        assert_eq!(work_title("Attention Is All You Need (arXiv preprint)"), "attention is all you need");
+    }
+
+    #[test]
+    fn suggestions_group_by_work_title() {
+        let library = [
+            document("x1", "Structure and Interpretation of Computer Programs"),
+            document("x2", "Structure and Interpretation of Computer Programs, 2nd ed."),
+            document("x2", "Structure and Interpretation of Computer Programs, 2nd ed."),
+            document("y1", "Notes"),
+            document("y2", "Notes (draft)"),
+            document("a1", "Linear Algebra Done Right"),
+            document("z1", "Linear Algebra Done Right, 3rd edition"),
+        ];
+        let suggestions = suggestions(&library, &editions());
+        let hashes: Vec<Vec<&str>> = suggestions.iter()
+            .map(|group| group.iter().map(|document| document.hash.as_str()).collect())
+            .collect();
+        // Copies aren't editions, titles this short are too generic and
+        // documents already grouped aren't suggested again
+        assert_eq!(hashes, [["x1", "x2"]]);
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use tracing::warn;
+
+use crate::pdf::{MetadataField, PdfMetadata};
+
+// The placeholders a pattern can have, besides {*} for text to skip
+const PLACEHOLDERS: [(&str, MetadataField); 5] = [
+    ("title", MetadataField::Title),
+    ("author", MetadataField::Author),
+    ("year", MetadataField::CreationDate),
+    ("subject", MetadataField::Subject),
+    ("keywords", MetadataField::Keywords),
+];
+
+/// A rule like `{author} - {title} ({year})` for reading metadata from the
+/// name of a well-named file. It has to match the whole name, without the
+/// extension and ignoring case; spaces in it also match underscores.
+#[derive(Debug, Clone)]
+pub struct FilenamePattern {
+    regex: Regex,
+    fields: Vec<MetadataField>,
+}
+
+impl FilenamePattern {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let mut expression = String::from("(?i)^");
+        let mut fields = Vec::new();
+        let mut rest = pattern.trim();
+        while !rest.is_empty() {
+            let Some(start) = rest.find('{') else {
+                expression.push_str(&literal(rest));
+                break;
+            };
+            expression.push_str(&literal(&rest[..start]));
+            let end = rest[start..].find('}').with_context(|| format!("{{ at {} isn't closed", start + 1))? + start;
+            let name = rest[start + 1..end].trim().to_lowercase();
+            if name == "*" {
+                expression.push_str(".*?");
+            } else {
+                let Some((_, field)) = PLACEHOLDERS.iter().find(|(placeholder, _)| *placeholder == name) else {
+                    bail!("Unknown placeholder {{{}}}, use {}", name, placeholder_list());
+                };
+                if fields.contains(field) { bail!("{{{}}} is in the pattern twice", name); }
+                expression.push_str(if *field == MetadataField::CreationDate { r"(\d{4})" } else { "(.+?)" });
+                fields.push(*field);
+            }
+            rest = &rest[end + 1..];
+        }
+        if fields.is_empty() { bail!("The pattern has no placeholders, use {}", placeholder_list()); }
+        expression.push('$');
+        Ok(Self { regex: Regex::new(&expression)?, fields })
+    }
+
+    /// The fields read from the name of `path`, None if it doesn't match.
+    /// A year becomes a creation date.
+    pub fn read(&self, path: &Path) -> Option<Vec<(MetadataField, String)>> {
+        let stem = path.file_stem()?.to_string_lossy();
+        let captures = self.regex.captures(&stem)?;
+        let values = self.fields.iter().zip(captures.iter().skip(1))
+            .filter_map(|(field, capture)| {
+                let value = capture?.as_str().replace('_', " ");
+                let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                if value.is_empty() { return None; }
+                Some((*field, if *field == MetadataField::CreationDate { format!("D:{}", value) } else { value }))
+            })
+            .collect();
+        Some(values)
+    }
+}
+
+fn literal(text: &str) -> String {
+    text.split(' ').map(regex::escape).collect::<Vec<_>>().join(r"[\s_]+")
+}
+
+fn placeholder_list() -> String {
+    PLACEHOLDERS.iter().map(|(name, _)| format!("{{{}}}", name)).chain(["{*}".to_string()]).collect::<Vec<_>>().join(", ")
+}
+
+/// The patterns of the config that parse, in order, warning about the rest
+pub fn compile(patterns: &[String]) -> Vec<FilenamePattern> {
+    patterns.iter()
+        .filter(|pattern| !pattern.trim().is_empty())
+        .filter_map(|pattern| FilenamePattern::parse(pattern)
+            .inspect_err(|e| warn!("Ignoring the file name pattern {:?}: {:#}", pattern, e))
+            .ok())
+        .collect()
+}
+
+/// Whether reading a field from the file name would fill it in, a junk
+/// title counts as missing
+fn is_missing(metadata: &PdfMetadata, field: MetadataField) -> bool {
+    match field {
+        MetadataField::Title => metadata.usable_title().is_none(),
+        field => field.get(metadata).is_none_or(|value| value.trim().is_empty()),
+    }
+}
+
+/// Fill in the missing fields of `metadata` from the first of `patterns`
+/// its file name matches, returning the fields that were filled in
+pub fn fill_in(metadata: &mut PdfMetadata, patterns: &[FilenamePattern]) -> Vec<MetadataField> {
+    let path = Path::new(&metadata.path).to_path_buf();
+    let Some(values) = patterns.iter().find_map(|pattern| pattern.read(&path)) else { return Vec::new(); };
+    let mut filled = Vec::new();
+    for (field, value) in values {
+        if !is_missing(metadata, field) { continue; }
+        field.set(metadata, Some(value));
+        filled.push(field);
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(pattern: &str, name: &str) -> Option<Vec<(MetadataField, String)>> {
+        FilenamePattern::parse(pattern).unwrap().read(Path::new(name))
+    }
+
+    #[test]
+    fn reads_fields_from_the_whole_name() {
+        assert_eq!(
+            read("{author} - {title} ({year})", "/books/Le Guin - The Dispossessed (1974).pdf"),
+            Some(vec![
+                (MetadataField::Author, "Le Guin".to_string()),
+                (MetadataField::Title, "The Dispossessed".to_string()),
+                (MetadataField::CreationDate, "D:1974".to_string()),
+            ])
+        );
+        assert_eq!(read("{author} - {title} ({year})", "Le Guin - The Dispossessed.pdf"), None);
+        assert_eq!(read("{title} ({year})", "The Dispossessed (197x).pdf"), None);
+    }
+
+    #[test]
+    fn regex_characters_in_the_pattern_are_literal() {
+        let pattern = "[{year}] {title} (v1.0+)";
+        assert!(read(pattern, "[2001] Notes (v1.0+).pdf").is_some());
+        assert!(read(pattern, "[2001] Notes (v1x0+).pdf").is_none());
+        assert!(read(pattern, "2001 Notes v1.0.pdf").is_none());
+        assert!(read("{title}.$^|*?\\", "Notes.$^|*?\\.pdf").is_some());
+    }
+
+    #[test]
+    fn spaces_match_underscores_and_case_is_ignored() {
+        assert_eq!(
+            read("{author} - {title}", "le_guin_-_the_dispossessed.pdf"),
+            Some(vec![
+                (MetadataField::Author, "le guin".to_string()),
+                (MetadataField::Title, "the dispossessed".to_string()),
+            ])
+        );
+        assert!(read("Paper {title}", "PAPER_Notes.pdf").is_some());
+    }
+
+    #[test]
+    fn wildcard_skips_text() {
+        assert_eq!(
+            read("{*} - {title}", "scan 0042 - Notes.pdf"),
+            Some(vec![(MetadataField::Title, "Notes".to_string())])
+        );
+    }
+
+    #[test]
+    fn bad_patterns_are_refused() {
+        assert!(FilenamePattern::parse("{title").is_err());
+        assert!(FilenamePattern::parse("{publisher} - {title}").is_err());
+        assert!(FilenamePattern::parse("{title} {TITLE}").is_err());
+        assert!(FilenamePattern::parse("just text").is_err());
+        assert!(FilenamePattern::parse("{*}").is_err());
+        assert_eq!(compile(&["{title".to_string(), "".to_string(), "{title}".to_string()]).len(), 1);
+    }
+
+    #[test]
+    fn fill_in_keeps_fields_already_set() {
+        let mut metadata = PdfMetadata {
+            title: Some("The Dispossessed".to_string()),
+            ..PdfMetadata::for_test("h", "/books/Le Guin - The Dispossessed.pdf")
+        };
+        let patterns = compile(&["{author} - {title}".to_string()]);
+        assert_eq!(fill_in(&mut metadata, &patterns), vec![MetadataField::Author]);
+        assert_eq!(metadata.author.as_deref(), Some("Le Guin"));
+        assert_eq!(metadata.title.as_deref(), Some("The Dispossessed"));
+    }
+}
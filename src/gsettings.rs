@@ -0,0 +1,131 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+use gtk::gio;
+use gtk::glib;
+use gtk::prelude::*;
+
+use crate::config::Config;
+
+const SCHEMA_ID: &str = "org.galib.shelf";
+
+/// None when the schema isn't installed, `gio::Settings::new` would abort
+fn settings() -> Option<gio::Settings> {
+    gio::SettingsSchemaSource::default()?.lookup(SCHEMA_ID, true)?;
+    Some(gio::Settings::new(SCHEMA_ID))
+}
+
+pub fn is_available() -> bool {
+    settings().is_some()
+}
+
+// Keys are the config.toml names with dashes, e.g. scan-dirs for scan_dirs
+fn field_name(key: &str) -> String {
+    key.replace('-', "_")
+}
+
+fn to_toml(value: &glib::Variant) -> Option<toml::Value> {
+    use toml::Value;
+    Some(match value.type_().as_str() {
+        "b" => Value::Boolean(value.get::<bool>()?),
+        "s" => Value::String(value.get::<String>()?),
+        "as" => Value::Array(value.get::<Vec<String>>()?.into_iter().map(Value::String).collect()),
+        "q" => Value::Integer(value.get::<u16>()?.into()),
+        "u" => Value::Integer(value.get::<u32>()?.into()),
+        "t" => Value::Integer(i64::try_from(value.get::<u64>()?).ok()?),
+        "i" => Value::Integer(value.get::<i32>()?.into()),
+        "x" => Value::Integer(value.get::<i64>()?),
+        "d" => Value::Float(value.get::<f64>()?),
+        "a{sas}" => Value::Table(value.get::<HashMap<String, Vec<String>>>()?.into_iter()
+            .map(|(key, items)| (key, Value::Array(items.into_iter().map(Value::String).collect())))
+            .collect()),
+        _ => return None,
+    })
+}
+
+fn to_variant(value: &toml::Value, value_type: &glib::VariantTy) -> Option<glib::Variant> {
+    use toml::Value;
+    Some(match (value_type.as_str(), value) {
+        ("b", Value::Boolean(value)) => value.to_variant(),
+        ("s", Value::String(value)) => value.to_variant(),
+        ("as", Value::Array(items)) => items.iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<Vec<String>>>()?
+            .to_variant(),
+        ("q", Value::Integer(value)) => u16::try_from(*value).ok()?.to_variant(),
+        ("u", Value::Integer(value)) => u32::try_from(*value).ok()?.to_variant(),
+        ("t", Value::Integer(value)) => u64::try_from(*value).ok()?.to_variant(),
+        ("i", Value::Integer(value)) => i32::try_from(*value).ok()?.to_variant(),
+        ("x", Value::Integer(value)) => value.to_variant(),
+        ("d", Value::Float(value)) => value.to_variant(),
+        ("d", Value::Integer(value)) => (*value as f64).to_variant(),
+        ("a{sas}", Value::Table(table)) => table.iter()
+            .map(|(key, items)| Some((key.clone(), items.as_array()?.iter()
+                .map(|item| item.as_str().map(str::to_string))
+                .collect::<Option<Vec<String>>>()?)))
+            .collect::<Option<HashMap<String, Vec<String>>>>()?
+            .to_variant(),
+        _ => return None,
+    })
+}
+
+/// Every key of the schema, fields it doesn't have get their usual defaults
+pub fn load() -> Result<Config> {
+    let settings = settings().context("The org.galib.shelf GSettings schema isn't installed")?;
+    let schema = settings.settings_schema().context("Failed to read the GSettings schema")?;
+    let mut table = toml::Table::new();
+    for key in schema.list_keys() {
+        let value = settings.value(&key);
+        let value = to_toml(&value).with_context(|| format!("Unsupported type {} for {}", value.type_(), key))?;
+        table.insert(field_name(&key), value);
+    }
+    table.insert("backend".to_string(), toml::Value::String("gsettings".to_string()));
+    Ok(table.try_into()?)
+}
+
+/// Write the keys that changed, in one go so a watcher reloads once
+pub fn save(config: &Config) -> Result<()> {
+    let settings = settings().context("The org.galib.shelf GSettings schema isn't installed")?;
+    let schema = settings.settings_schema().context("Failed to read the GSettings schema")?;
+    let toml::Value::Table(table) = toml::Value::try_from(config)? else { bail!("Config isn't a table"); };
+
+    settings.delay();
+    for key in schema.list_keys() {
+        let Some(value) = table.get(&field_name(&key)) else { continue; };
+        let variant = to_variant(value, &schema.key(&key).value_type())
+            .with_context(|| format!("{} doesn't fit the type of {}", value, key))?;
+        if settings.value(&key) != variant {
+            settings.set_value(&key, &variant)?;
+        }
+    }
+    settings.apply();
+    // The command line exits right after saving
+    gio::Settings::sync();
+    Ok(())
+}
+
+/// Call `changed` once per batch of changes, e.g. from `gsettings set` or
+/// a dconf profile. The returned object has to be kept alive.
+pub fn watch(changed: impl Fn() + 'static) -> Option<gio::Settings> {
+    let settings = settings()?;
+    let pending = Rc::new(Cell::new(false));
+    let changed = Rc::new(changed);
+    settings.connect_changed(None, move |_, _| {
+        if pending.replace(true) { return; }
+        glib::idle_add_local_once(glib::clone!(
+            #[strong] pending,
+            #[strong] changed,
+            move || {
+                pending.set(false);
+                changed();
+            }
+        ));
+    });
+    // Only keys that have been read are reported
+    if let Some(schema) = settings.settings_schema() {
+        for key in schema.list_keys() { settings.value(&key); }
+    }
+    Some(settings)
+}
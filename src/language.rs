@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+// Languages told apart by their script alone. Japanese mixes kana with Han
+// characters, see detect.
+const SCRIPTS: &[(RangeInclusive<char>, &str)] = &[
+    ('\u{0980}'..='\u{09FF}', "bn"),
+    ('\u{0900}'..='\u{097F}', "hi"),
+    ('\u{0A80}'..='\u{0AFF}', "gu"),
+    ('\u{0A00}'..='\u{0A7F}', "pa"),
+    ('\u{0B80}'..='\u{0BFF}', "ta"),
+    ('\u{0C00}'..='\u{0C7F}', "te"),
+    ('\u{0600}'..='\u{06FF}', "ar"),
+    ('\u{0590}'..='\u{05FF}', "he"),
+    ('\u{0370}'..='\u{03FF}', "el"),
+    ('\u{0400}'..='\u{04FF}', "ru"),
+    ('\u{0E00}'..='\u{0E7F}', "th"),
+    ('\u{3040}'..='\u{30FF}', "ja"),
+    ('\u{AC00}'..='\u{D7AF}', "ko"),
+    ('\u{4E00}'..='\u{9FFF}', "zh"),
+];
+
+// The commonest short words of languages in the Latin script
+const STOPWORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "of", "to", "in", "is", "that", "for", "with", "this", "are", "as", "on", "by", "it"]),
+    ("de", &["der", "die", "und", "das", "ist", "nicht", "mit", "den", "von", "zu", "ein", "eine", "sich", "auf", "des"]),
+    ("fr", &["le", "la", "les", "et", "des", "est", "une", "dans", "que", "pour", "pas", "du", "sur", "au", "qui"]),
+    ("es", &["el", "los", "las", "y", "del", "que", "en", "una", "por", "con", "para", "es", "se", "como", "al"]),
+    ("it", &["il", "di", "che", "e", "della", "per", "non", "gli", "una", "sono", "del", "le", "nel", "alla", "come"]),
+    ("pt", &["o", "os", "e", "do", "da", "que", "em", "um", "uma", "para", "com", "não", "dos", "das", "no"]),
+    ("nl", &["de", "het", "een", "en", "van", "is", "dat", "niet", "op", "te", "zijn", "voor", "met", "die", "ook"]),
+];
+
+// Too little text to go by, e.g. a cover page with a title alone
+const MIN_LETTERS: usize = 40;
+const MIN_STOPWORDS: usize = 5;
+
+/// The ISO 639-1 code of the language `text` is most likely in, by its
+/// script and, for the Latin script, the share of common words
+pub fn detect(text: &str) -> Option<&'static str> {
+    let mut scripts: HashMap<&'static str, usize> = HashMap::new();
+    let mut latin = 0;
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        match SCRIPTS.iter().find(|(range, _)| range.contains(&c)) {
+            Some((_, code)) => *scripts.entry(*code).or_default() += 1,
+            None if c.is_ascii_alphabetic() || ('\u{00C0}'..='\u{024F}').contains(&c) => latin += 1,
+            None => {}
+        }
+    }
+    let (script, count) = scripts.iter().max_by_key(|(_, count)| **count).map_or(("", 0), |(code, count)| (*code, *count));
+    if count.max(latin) < MIN_LETTERS { return None; }
+    if count > latin {
+        let kana = scripts.get("ja").copied().unwrap_or_default();
+        // Even a little kana among the Han characters is Japanese
+        return Some(if script == "zh" && kana * 10 >= count { "ja" } else { script });
+    }
+
+    let words: Vec<String> = text.split(|c: char| !c.is_alphabetic()).filter(|word| !word.is_empty()).map(str::to_lowercase).collect();
+    STOPWORDS.iter()
+        .map(|(code, stopwords)| (*code, words.iter().filter(|word| stopwords.contains(&word.as_str())).count()))
+        .filter(|(_, hits)| *hits >= MIN_STOPWORDS)
+        .max_by_key(|(_, hits)| *hits)
+        .map(|(code, _)| code)
+}
+
+/// The English name of a language detect returns, or the code itself
+pub fn name(code: &str) -> &str {
+    match code {
+        "en" => "English",
+        "de" => "German",
+        "fr" => "French",
+        "es" => "Spanish",
+        "it" => "Italian",
+        "pt" => "Portuguese",
+        "nl" => "Dutch",
+        "bn" => "Bengali",
+        "hi" => "Hindi",
+        "gu" => "Gujarati",
+        "pa" => "Punjabi",
+        "ta" => "Tamil",
+        "te" => "Telugu",
+        "ar" => "Arabic",
+        "he" => "Hebrew",
+        "el" => "Greek",
+        "ru" => "Russian",
+        "th" => "Thai",
+        "ja" => "Japanese",
+        "ko" => "Korean",
+        "zh" => "Chinese",
+        code => code,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latin_languages_by_common_words() {
+        assert_eq!(detect("The history of the city is told in this book, with maps and letters that are kept in the archive."), Some("en"));
+        assert_eq!(detect("Die Geschichte der Stadt ist in diesem Buch erzählt, und die Karten sind nicht von der Stadt."), Some("de"));
+        assert_eq!(detect("Le livre raconte l'histoire de la ville et des gens qui sont dans les archives pour la mémoire."), Some("fr"));
+    }
+
+    #[test]
+    fn other_scripts_by_their_letters() {
+        assert_eq!(detect(&"Ιστορία της πόλης ".repeat(4)), Some("el"));
+        assert_eq!(detect(&"История города в книге ".repeat(4)), Some("ru"));
+        assert_eq!(detect(&"城市的历史和文化研究".repeat(5)), Some("zh"));
+        assert_eq!(detect(&"都市の歴史と文化の研究".repeat(5)), Some("ja"));
+    }
+
+    #[test]
+    fn too_little_text_is_unknown() {
+        assert_eq!(detect("The Dispossessed"), None);
+        assert_eq!(detect("12 34 56 78 90 -- 12 34 56 78 90 -- 12 34 56 78 90 -- 12 34 56 78 90"), None);
+        // Enough letters but none of the common words
+        assert_eq!(detect("Xylophones zigzag quickly beyond wharves; jovial gnomes vex bumpy dwarfs."), None);
+    }
+
+    #[test]
+    fn names_fall_back_to_the_code() {
+        assert_eq!(name("pt"), "Portuguese");
+        assert_eq!(name("sv"), "sv");
+    }
+}
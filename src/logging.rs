@@ -0,0 +1,88 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
+
+use crate::paths;
+
+const LOG_PREFIX: &str = "shelf";
+const LOG_SUFFIX: &str = "log";
+// A new file every day, a week of them is kept
+const MAX_LOG_FILES: usize = 7;
+// How much of the log "Copy Debug Log" copies, from its end
+const COPIED_LOG_BYTES: u64 = 64 * 1024;
+
+/// `$XDG_CACHE_HOME/shelf/logs`
+pub fn log_dir() -> Result<PathBuf> {
+    Ok(paths::cache_dir()?.join("logs"))
+}
+
+/// Log to stderr and to a file under `log_dir`. Stderr gets info and up,
+/// or debug with `verbose`, unless `RUST_LOG` says otherwise, the file
+/// always gets debug. Keep the guard until exiting, it writes out the
+/// rest of the file.
+pub fn init(verbose: bool) -> Option<WorkerGuard> {
+    let level = if verbose { LevelFilter::DEBUG } else { LevelFilter::INFO };
+    let stderr = fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_target(false)
+        .with_filter(EnvFilter::builder().with_default_directive(level.into()).from_env_lossy());
+
+    let appender = log_dir().and_then(|dir| {
+        std::fs::create_dir_all(&dir)?;
+        Builder::new()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(LOG_PREFIX)
+            .filename_suffix(LOG_SUFFIX)
+            .max_log_files(MAX_LOG_FILES)
+            .build(&dir)
+            .context("Failed to create the log file")
+    });
+    let (file, guard) = match appender {
+        Ok(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let file = fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_filter(LevelFilter::DEBUG);
+            (Some(file), Some(guard))
+        }
+        Err(e) => {
+            eprintln!("Logging to stderr only: {:#}", e);
+            (None, None)
+        }
+    };
+
+    tracing_subscriber::registry().with(stderr).with(file).init();
+    guard
+}
+
+/// The end of the newest log file, to paste into a bug report
+pub fn recent_log() -> Result<String> {
+    let dir = log_dir()?;
+    let newest = std::fs::read_dir(&dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(LOG_PREFIX)))
+        .max_by_key(|path| path.metadata().and_then(|metadata| metadata.modified()).ok())
+        .with_context(|| format!("No log files in {}", dir.display()))?;
+
+    let mut file = std::fs::File::open(&newest)?;
+    let size = file.metadata()?.len();
+    file.seek(SeekFrom::Start(size.saturating_sub(COPIED_LOG_BYTES)))?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let text = String::from_utf8_lossy(&bytes);
+    // Cut off the partial first line
+    let text = if size > COPIED_LOG_BYTES {
+        text.split_once('\n').map_or(&*text, |(_, rest)| rest)
+    } else {
+        &text
+    };
+    Ok(text.to_string())
+}
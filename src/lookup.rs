@@ -0,0 +1,487 @@
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use image::DynamicImage;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::pdf::{save_custom_cover, MetadataField, PdfCache, PdfMetadata};
+
+const TIMEOUT: Duration = Duration::from_secs(20);
+const USER_AGENT: &str = concat!("Shelf/", env!("CARGO_PKG_VERSION"), " (https://github.com/galib45/shelf)");
+// Covers are a few hundred KB, anything much larger isn't one
+const MAX_COVER_BYTES: u64 = 10 * 1024 * 1024;
+// "Image not available" placeholders are smaller than this
+const MIN_COVER_SIZE: u32 = 100;
+// Subjects beyond these are usually shelving minutiae
+const MAX_SUBJECTS: usize = 5;
+// Open Library allows 100 cover requests by ISBN per 5 minutes, arXiv asks
+// for a request every 3 seconds
+pub const LOOKUP_INTERVAL: Duration = Duration::from_secs(3);
+
+static AGENT: LazyLock<ureq::Agent> = LazyLock::new(|| {
+    ureq::Agent::config_builder().timeout_global(Some(TIMEOUT)).build().into()
+});
+
+// ISBN-13 or ISBN-10, with or without hyphens or spaces between the groups
+static ISBN_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(?:97[89][- ]?)?\d(?:[- ]?\d){8}[- ]?[\dX]\b").unwrap()
+});
+
+// "arXiv:2301.01234v2" as printed in the margin of preprints, IDs before
+// 2007 look like hep-th/9901001
+static ARXIV_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\barxiv:\s*(\d{4}\.\d{4,5}|[a-z][a-z-]*(?:\.[a-z]{2})?/\d{7})(?:v\d+)?\b").unwrap()
+});
+
+// A file name made of the ID, as arXiv names downloads, or containing it
+static ARXIV_NAME_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|[^\d.])(\d{4}\.\d{4,5})(?:v\d+)?(?:[^\d.]|$)").unwrap()
+});
+
+// Fields of an arXiv API entry, author names are the only <name>s
+static ATOM_FIELD_PATTERN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<(id|title|summary|published|name)>(.*?)</(?:id|title|summary|published|name)>").unwrap()
+});
+
+/// Years and months that exist, with four digit numbers until 2014 and
+/// five since
+fn is_arxiv_id(id: &str) -> bool {
+    if id.contains('/') { return true; }
+    let Some((yymm, number)) = id.split_once('.') else { return false; };
+    let month: u32 = yymm[2..].parse().unwrap_or(0);
+    (1..=12).contains(&month) && number.len() == if yymm >= "1501" { 5 } else { 4 }
+}
+
+/// The first arXiv ID in `text`, without its version
+pub fn find_arxiv_id(text: &str) -> Option<String> {
+    ARXIV_PATTERN.captures_iter(text)
+        .map(|captures| captures[1].to_string())
+        .find(|id| is_arxiv_id(id))
+}
+
+/// The arXiv ID in the name of the file, e.g. 2301.01234v2.pdf
+pub fn arxiv_id_in_name(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    find_arxiv_id(stem).or_else(|| {
+        ARXIV_NAME_PATTERN.captures_iter(stem)
+            .map(|captures| captures[1].to_string())
+            .find(|id| is_arxiv_id(id))
+    })
+}
+
+/// The arXiv ID found while scanning, or else in the file name or metadata
+pub fn arxiv_of(metadata: &PdfMetadata) -> Option<String> {
+    metadata.arxiv_id.clone()
+        .or_else(|| arxiv_id_in_name(Path::new(&metadata.path)))
+        .or_else(|| {
+            [&metadata.keywords, &metadata.subject, &metadata.snippet].into_iter()
+                .flatten()
+                .find_map(|text| find_arxiv_id(text))
+        })
+}
+
+/// The first valid ISBN in `text`, as ISBN-13
+pub fn find_isbn(text: &str) -> Option<String> {
+    ISBN_PATTERN.find_iter(text)
+        .map(|found| found.as_str().chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_uppercase())
+        .find_map(|digits| match digits.len() {
+            13 if isbn13_checksum(&digits[..12]) == digits.as_bytes()[12] => Some(digits),
+            10 if isbn10_checksum(&digits[..9]) == digits.as_bytes()[9] => {
+                let isbn = format!("978{}", &digits[..9]);
+                let check = isbn13_checksum(&isbn) as char;
+                Some(format!("{}{}", isbn, check))
+            }
+            _ => None,
+        })
+}
+
+// Check digits as ASCII, a digit or X
+fn isbn13_checksum(digits: &str) -> u8 {
+    let sum: u32 = digits.bytes().enumerate()
+        .map(|(index, digit)| (digit - b'0') as u32 * if index % 2 == 0 { 1 } else { 3 })
+        .sum();
+    b'0' + ((10 - sum % 10) % 10) as u8
+}
+
+fn isbn10_checksum(digits: &str) -> u8 {
+    let sum: u32 = digits.bytes().enumerate()
+        .map(|(index, digit)| (digit - b'0') as u32 * (10 - index as u32))
+        .sum();
+    match (11 - sum % 11) % 11 {
+        10 => b'X',
+        check => b'0' + check as u8,
+    }
+}
+
+/// The ISBN found in the document's opening pages, or else its metadata.
+/// Documents cached before ISBNs were looked for only have the latter.
+pub fn isbn_of(metadata: &PdfMetadata) -> Option<String> {
+    metadata.isbn.clone().or_else(|| {
+        [&metadata.keywords, &metadata.subject, &metadata.title, &metadata.snippet].into_iter()
+            .flatten()
+            .find_map(|text| find_isbn(text))
+    })
+}
+
+/// What a cover is looked up by. Without an ISBN only documents whose own
+/// metadata has a title and an author, file names match too many books.
+enum Query {
+    Isbn(String),
+    TitleAuthor(String, String),
+}
+
+impl Query {
+    fn of(metadata: &PdfMetadata) -> Option<Self> {
+        if let Some(isbn) = isbn_of(metadata) { return Some(Self::Isbn(isbn)); }
+        let title = metadata.title.as_deref().map(str::trim).filter(|title| !title.is_empty())?;
+        let author = metadata.author.as_deref().map(str::trim).filter(|author| !author.is_empty())?;
+        Some(Self::TitleAuthor(title.to_string(), author.to_string()))
+    }
+}
+
+/// Whether there's anything to look a cover up by
+pub fn can_look_up(metadata: &PdfMetadata) -> bool {
+    Query::of(metadata).is_some()
+}
+
+/// The body of the response, None if there's nothing at the URL
+fn get(request: ureq::RequestBuilder<ureq::typestate::WithoutBody>) -> Result<Option<Vec<u8>>> {
+    let mut response = match request.header("User-Agent", USER_AGENT).call() {
+        Ok(response) => response,
+        Err(ureq::Error::StatusCode(404)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Some(response.body_mut().with_config().limit(MAX_COVER_BYTES).read_to_vec()?))
+}
+
+/// None for placeholders and anything that isn't an image
+fn decode_cover(bytes: &[u8]) -> Option<DynamicImage> {
+    let image = image::load_from_memory(bytes).ok()?;
+    (image.width().min(image.height()) >= MIN_COVER_SIZE).then_some(image)
+}
+
+#[derive(Deserialize)]
+struct OpenLibrarySearch {
+    #[serde(default)]
+    docs: Vec<OpenLibraryDoc>,
+}
+
+#[derive(Deserialize)]
+struct OpenLibraryDoc {
+    cover_i: Option<i64>,
+}
+
+fn open_library_cover(query: &Query) -> Result<Option<DynamicImage>> {
+    let url = match query {
+        Query::Isbn(isbn) => format!("https://covers.openlibrary.org/b/isbn/{}-L.jpg?default=false", isbn),
+        Query::TitleAuthor(title, author) => {
+            let request = AGENT.get("https://openlibrary.org/search.json")
+                .query("title", title)
+                .query("author", author)
+                .query("fields", "cover_i")
+                .query("limit", "1");
+            let Some(body) = get(request)? else { return Ok(None); };
+            let search: OpenLibrarySearch = serde_json::from_slice(&body).context("Unexpected answer from Open Library")?;
+            let Some(id) = search.docs.into_iter().find_map(|doc| doc.cover_i) else { return Ok(None); };
+            format!("https://covers.openlibrary.org/b/id/{}-L.jpg?default=false", id)
+        }
+    };
+    Ok(get(AGENT.get(url))?.and_then(|body| decode_cover(&body)))
+}
+
+#[derive(Deserialize)]
+struct GoogleBooksSearch {
+    #[serde(default)]
+    items: Vec<GoogleBooksVolume>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleBooksVolume {
+    volume_info: GoogleBooksInfo,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleBooksInfo {
+    image_links: Option<GoogleBooksImages>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleBooksImages {
+    thumbnail: Option<String>,
+    small_thumbnail: Option<String>,
+}
+
+fn google_books_cover(query: &Query) -> Result<Option<DynamicImage>> {
+    let q = match query {
+        Query::Isbn(isbn) => format!("isbn:{}", isbn),
+        Query::TitleAuthor(title, author) => format!("intitle:{} inauthor:{}", title, author),
+    };
+    let request = AGENT.get("https://www.googleapis.com/books/v1/volumes").query("q", &q).query("maxResults", "1");
+    let Some(body) = get(request)? else { return Ok(None); };
+    let search: GoogleBooksSearch = serde_json::from_slice(&body).context("Unexpected answer from Google Books")?;
+    let url = search.items.into_iter()
+        .filter_map(|volume| volume.volume_info.image_links)
+        .find_map(|images| images.thumbnail.or(images.small_thumbnail));
+    let Some(url) = url else { return Ok(None); };
+    // Links come as plain http
+    let url = url.replacen("http://", "https://", 1).replace("&edge=curl", "");
+    Ok(get(AGENT.get(url))?.and_then(|body| decode_cover(&body)))
+}
+
+/// A cover for `metadata` from Open Library, or Google Books if it has
+/// none. None if there's nothing to look it up by or neither has one.
+pub fn fetch_cover(metadata: &PdfMetadata) -> Result<Option<DynamicImage>> {
+    let Some(query) = Query::of(metadata) else { return Ok(None); };
+    if let Some(cover) = open_library_cover(&query)? { return Ok(Some(cover)); }
+    google_books_cover(&query)
+}
+
+/// Look a cover up online and make it the document's custom cover, so
+/// rescans keep it. Returns its name, None if none was found. Either way
+/// the lookup is remembered and not repeated automatically.
+pub fn fetch_online_cover(metadata: &PdfMetadata, cache: &PdfCache) -> Result<Option<String>> {
+    let cover = fetch_cover(metadata)?;
+    cache.record_cover_lookup(&metadata.hash)?;
+    cover.map(|cover| save_custom_cover(&metadata.hash, &cover, cache)).transpose()
+}
+
+/// What arXiv, Open Library or Google Books know about a document
+#[derive(Debug, Default)]
+struct BookDetails {
+    title: Option<String>,
+    authors: Vec<String>,
+    // The digits of a PDF date, a year or down to the day
+    date: Option<String>,
+    subjects: Vec<String>,
+    summary: Option<String>,
+}
+
+impl BookDetails {
+    fn value(&self, field: MetadataField) -> Option<String> {
+        let joined = |values: &[String]| (!values.is_empty()).then(|| values.join(", "));
+        match field {
+            MetadataField::Title => self.title.clone(),
+            MetadataField::Author => joined(&self.authors),
+            MetadataField::Subject => joined(&self.subjects[..self.subjects.len().min(MAX_SUBJECTS)]),
+            MetadataField::Keywords => None,
+            // As a PDF date, see citations.rs
+            MetadataField::CreationDate => self.date.as_ref().map(|date| format!("D:{}", date)),
+            MetadataField::Abstract => self.summary.clone(),
+        }
+    }
+}
+
+// Filled in when they're empty, keywords are left to the document
+const LOOKED_UP_FIELDS: [MetadataField; 5] = [
+    MetadataField::Title,
+    MetadataField::Author,
+    MetadataField::Subject,
+    MetadataField::CreationDate,
+    MetadataField::Abstract,
+];
+
+fn blank(value: Option<&str>) -> bool {
+    value.is_none_or(|value| value.trim().is_empty())
+}
+
+// Only arXiv has abstracts
+fn missing_fields(metadata: &PdfMetadata, arxiv_id: Option<&str>) -> Vec<MetadataField> {
+    LOOKED_UP_FIELDS.into_iter()
+        .filter(|field| *field != MetadataField::Abstract || arxiv_id.is_some())
+        // A junk title, e.g. "untitled", is as good as none
+        .filter(|field| match field {
+            MetadataField::Title => metadata.usable_title().is_none(),
+            field => blank(field.get(metadata)),
+        })
+        .collect()
+}
+
+/// Whether a lookup could fill something in, it has an ISBN or an arXiv
+/// ID and a field that's empty
+pub fn has_missing_details(metadata: &PdfMetadata) -> bool {
+    let arxiv_id = arxiv_of(metadata);
+    (arxiv_id.is_some() || isbn_of(metadata).is_some()) && !missing_fields(metadata, arxiv_id.as_deref()).is_empty()
+}
+
+fn unescape_xml(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+fn arxiv_details(id: &str) -> Result<Option<BookDetails>> {
+    let request = AGENT.get("https://export.arxiv.org/api/query")
+        .query("id_list", id)
+        .query("max_results", "1");
+    let Some(body) = get(request)? else { return Ok(None); };
+    let body = String::from_utf8_lossy(&body);
+    let Some(entry) = body.split_once("<entry>").and_then(|(_, entry)| entry.split_once("</entry>")).map(|(entry, _)| entry) else {
+        return Ok(None);
+    };
+    let mut details = BookDetails::default();
+    for captures in ATOM_FIELD_PATTERN.captures_iter(entry) {
+        let value = unescape_xml(&captures[2]);
+        match &captures[1] {
+            // Unknown IDs come back as an entry describing the error
+            "id" if value.contains("/api/errors") => return Ok(None),
+            "title" => details.title = Some(value),
+            "summary" => details.summary = Some(value),
+            // 2023-01-03T18:00:00Z
+            "published" => details.date = value.get(..10).map(|date| date.replace('-', "")),
+            "name" => details.authors.push(value),
+            _ => {}
+        }
+    }
+    Ok(details.title.is_some().then_some(details))
+}
+
+#[derive(Deserialize)]
+struct OpenLibraryBook {
+    title: Option<String>,
+    #[serde(default)]
+    author_name: Vec<String>,
+    first_publish_year: Option<i32>,
+    #[serde(default)]
+    subject: Vec<String>,
+}
+
+fn open_library_details(isbn: &str) -> Result<Option<BookDetails>> {
+    let request = AGENT.get("https://openlibrary.org/search.json")
+        .query("isbn", isbn)
+        .query("fields", "title,author_name,first_publish_year,subject")
+        .query("limit", "1");
+    let Some(body) = get(request)? else { return Ok(None); };
+    #[derive(Deserialize)]
+    struct Search {
+        #[serde(default)]
+        docs: Vec<OpenLibraryBook>,
+    }
+    let search: Search = serde_json::from_slice(&body).context("Unexpected answer from Open Library")?;
+    Ok(search.docs.into_iter().next().map(|book| BookDetails {
+        title: book.title,
+        authors: book.author_name,
+        date: book.first_publish_year.map(|year| year.to_string()),
+        subjects: book.subject,
+        summary: None,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleBooksDetails {
+    title: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    // "2011", "2011-05" or "2011-05-17"
+    published_date: Option<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+}
+
+fn google_books_details(isbn: &str) -> Result<Option<BookDetails>> {
+    let request = AGENT.get("https://www.googleapis.com/books/v1/volumes")
+        .query("q", format!("isbn:{}", isbn))
+        .query("maxResults", "1");
+    let Some(body) = get(request)? else { return Ok(None); };
+    #[derive(Deserialize)]
+    struct Volume {
+        #[serde(rename = "volumeInfo")]
+        volume_info: GoogleBooksDetails,
+    }
+    #[derive(Deserialize)]
+    struct Search {
+        #[serde(default)]
+        items: Vec<Volume>,
+    }
+    let search: Search = serde_json::from_slice(&body).context("Unexpected answer from Google Books")?;
+    Ok(search.items.into_iter().next().map(|volume| {
+        let book = volume.volume_info;
+        BookDetails {
+            title: book.title,
+            authors: book.authors,
+            date: book.published_date.and_then(|date| date.get(..4).map(str::to_string))
+                .filter(|year| year.chars().all(|c| c.is_ascii_digit())),
+            subjects: book.categories,
+            summary: None,
+        }
+    }))
+}
+
+type DetailsLookup = fn(&str) -> Result<Option<BookDetails>>;
+
+/// Look the document up by its arXiv ID or ISBN and fill in the fields it
+/// has empty, from arXiv, then Open Library and then Google Books for
+/// what's still missing. Every change is recorded with its source so it can
+/// be reviewed, and the lookup isn't repeated automatically. Returns the
+/// fields filled in.
+pub fn fill_in_metadata(metadata: &PdfMetadata, cache: &PdfCache) -> Result<Vec<MetadataField>> {
+    let (arxiv_id, isbn) = (arxiv_of(metadata), isbn_of(metadata));
+    if arxiv_id.is_none() && isbn.is_none() { return Ok(Vec::new()); }
+    let mut missing = missing_fields(metadata, arxiv_id.as_deref());
+    let mut filled = Vec::new();
+    let sources: [(Option<&String>, &str, DetailsLookup); 3] = [
+        (arxiv_id.as_ref(), "arXiv", arxiv_details),
+        (isbn.as_ref(), "Open Library", open_library_details),
+        (isbn.as_ref(), "Google Books", google_books_details),
+    ];
+    for (key, source, details) in sources {
+        let Some(key) = key else { continue; };
+        if missing.is_empty() { break; }
+        let Some(details) = details(key)? else { continue; };
+        missing.retain(|field| {
+            let Some(value) = details.value(*field).filter(|value| !value.trim().is_empty()) else { return true; };
+            match cache.apply_metadata_change(&metadata.hash, *field, value.trim(), source) {
+                Ok(true) => filled.push(*field),
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Failed to store the {} of {}: {:#}", field.label(), metadata.path, e),
+            }
+            false
+        });
+    }
+    cache.record_metadata_lookup(&metadata.hash)?;
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isbn13() {
+        assert_eq!(find_isbn("ISBN 9780306406157").as_deref(), Some("9780306406157"));
+        assert_eq!(isbn13_checksum("978030640615"), b'7');
+    }
+
+    #[test]
+    fn isbn10_with_x_becomes_isbn13() {
+        assert_eq!(isbn10_checksum("080442957"), b'X');
+        assert_eq!(find_isbn("ISBN 0-8044-2957-X").as_deref(), Some("9780804429573"));
+        assert_eq!(find_isbn("isbn 080442957x").as_deref(), Some("9780804429573"));
+    }
+
+    #[test]
+    fn separated_isbns() {
+        assert_eq!(find_isbn("ISBN 978-0-306-40615-7").as_deref(), Some("9780306406157"));
+        assert_eq!(find_isbn("ISBN 978 0 306 40615 7").as_deref(), Some("9780306406157"));
+        assert_eq!(find_isbn("ISBN 0-306-40615-2, paperback").as_deref(), Some("9780306406157"));
+    }
+
+    #[test]
+    fn bad_checksum_rejected() {
+        assert_eq!(find_isbn("ISBN 978-0-306-40615-8"), None);
+        assert_eq!(find_isbn("ISBN 0-306-40615-3"), None);
+        // The next valid one is still found
+        assert_eq!(find_isbn("0306406153 or 0306406152").as_deref(), Some("9780306406157"));
+    }
+
+    #[test]
+    fn digits_inside_a_longer_number_ignored() {
+        assert_eq!(find_isbn("Order no. 1203064061520"), None);
+        assert_eq!(find_isbn("Tracking 99030640615266"), None);
+    }
+}
@@ -2,13 +2,34 @@ mod pdf;
 mod utils;
 mod ui;
 mod config;
+mod volumes;
+mod review;
+mod state;
+mod citations;
+mod cli;
+mod collections;
+mod selector;
+mod snapshot;
+mod server;
+mod dbus;
+mod editions;
+mod maintenance;
+mod paths;
+mod portal;
+mod gsettings;
+mod logging;
+mod shelfignore;
+mod lookup;
+mod filename_patterns;
+mod language;
 
-use std::sync::Arc;
-use std::sync::RwLock;
+use std::fs::{File, TryLockError};
+use std::sync::{Arc, OnceLock, RwLock};
 
 use gtk::prelude::*;
 use gtk::glib;
 use gtk::gio;
+use tracing::{error, info, warn};
 
 use crate::config::Config;
 use crate::ui::window::ShelfWindow;
@@ -16,14 +37,140 @@ use crate::ui::window::ShelfWindow;
 const APP_ID: &str = "org.galib.shelf";
 
 fn main() -> glib::ExitCode {
+    let verbose = std::env::args().any(|arg| arg == "--verbose" || arg == "-v");
+    let _log_guard = logging::init(verbose);
+    if let Err(e) = paths::migrate_legacy_dir() {
+        warn!("Failed to move ~/.shelf to the XDG directories: {:#}", e);
+    }
+    if let Err(e) = paths::migrate_database(pdf::is_custom_cover) {
+        warn!("Failed to move the database out of the cache directory: {:#}", e);
+    }
+    let args: Vec<String> = std::env::args().skip(1).filter(|arg| arg != "--verbose" && arg != "-v").collect();
+    if let Some(exit_code) = cli::run(&args) {
+        return exit_code;
+    }
+
     gio::resources_register_include!("compiled.gresource").expect("Failed to register resource");
-    let app = gtk::Application::builder().application_id(APP_ID).build();
-    app.connect_activate(app_main);
+    let app = gtk::Application::builder()
+        .application_id(APP_ID)
+        .flags(gio::ApplicationFlags::HANDLES_OPEN | gio::ApplicationFlags::HANDLES_COMMAND_LINE)
+        .build();
+    app.add_main_option("search", glib::Char::from(b's'), glib::OptionFlags::NONE, glib::OptionArg::String, "Start with this search, e.g. \"tag:thesis\"", Some("QUERY"));
+    app.add_main_option("shelf", glib::Char::from(0), glib::OptionFlags::NONE, glib::OptionArg::String, "Show this collection or volume", Some("NAME"));
+    app.add_main_option("refresh", glib::Char::from(b'r'), glib::OptionFlags::NONE, glib::OptionArg::None, "Scan the library folders right away", None);
+    // Read before anything is logged, see above
+    app.add_main_option("verbose", glib::Char::from(b'v'), glib::OptionFlags::NONE, glib::OptionArg::None, "Log debug messages to stderr too", None);
+    app.connect_startup(|app| {
+        if let Err(e) = adw::init() {
+            error!("Failed to initialize libadwaita: {}", e);
+        }
+        load_css();
+        ui::theme::load_user_css();
+        add_actions(app);
+        if let Err(e) = dbus::register(app) {
+            error!("Failed to export the D-Bus interface: {:#}", e);
+        }
+    });
+    // A second launch only reaches these in the first instance, which gets
+    // the files and presents its window
+    app.connect_activate(|app| {
+        if lock_instance(app) { main_window(app); }
+    });
+    // Options of every launch end up here, in the first instance
+    app.connect_command_line(|app, command_line| {
+        let files: Vec<gio::File> = command_line.arguments().iter().skip(1)
+            .map(|arg| command_line.create_file_for_arg(arg))
+            .collect();
+        if files.is_empty() { app.activate(); } else { app.open(&files, ""); }
+        let Some(window) = app.windows().into_iter().find_map(|window| window.downcast::<ShelfWindow>().ok()) else {
+            return glib::ExitCode::FAILURE;
+        };
+
+        let options = command_line.options_dict();
+        if let Ok(Some(query)) = options.lookup::<String>("search") {
+            window.search(&query);
+        }
+        if let Ok(Some(name)) = options.lookup::<String>("shelf")
+            && !window.show_shelf(&name) {
+            warn!("No collection or volume named {}", name);
+        }
+        if options.contains("refresh") && !window.rescan() {
+            info!("A scan is already running");
+        }
+        glib::ExitCode::SUCCESS
+    });
+    app.connect_open(|app, files, _| {
+        if !lock_instance(app) { return; }
+        let window = main_window(app);
+        for path in files.iter().filter_map(|file| file.path()) {
+            ActionGroupExt::activate_action(&window, "open-file", Some(&path.to_string_lossy().to_variant()));
+        }
+    });
     app.run()
 }
 
-fn app_main(app: &gtk::Application) {
+fn load_css() {
+    let provider = gtk::CssProvider::new();
+    provider.load_from_resource("/org/galib/shelf/style.css");
+    gtk::style_context_add_provider_for_display(
+        &gtk::gdk::Display::default().expect("Could not connect to a display"),
+        &provider,
+        gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+    );
+}
+
+/// Without a session bus GApplication can't find the instance already
+/// running, so a lock file next to the cache keeps a second one out
+fn lock_instance(app: &gtk::Application) -> bool {
+    static LOCK: OnceLock<File> = OnceLock::new();
+    if app.dbus_connection().is_some() || LOCK.get().is_some() { return true; }
+    let Ok(dir) = paths::cache_dir() else { return true; };
+    let file = std::fs::create_dir_all(&dir).and_then(|()| File::create(dir.join("instance.lock")));
+    let file = match file {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Failed to create the instance lock: {}", e);
+            return true;
+        }
+    };
+    match file.try_lock() {
+        Ok(()) => {
+            LOCK.set(file).ok();
+            true
+        }
+        Err(TryLockError::WouldBlock) => {
+            warn!("Shelf is already running, without a session bus it can't be asked to open anything");
+            false
+        }
+        Err(TryLockError::Error(e)) => {
+            warn!("Failed to lock the instance: {}", e);
+            true
+        }
+    }
+}
+
+/// App-wide actions, the same from every window
+fn add_actions(app: &gtk::Application) {
+    let about = gio::ActionEntry::builder("about")
+        .activate(|app: &gtk::Application, _, _| ui::about::present(app.active_window().as_ref()))
+        .build();
+    // Closing the windows saves their state, the app exits after the last one
+    let quit = gio::ActionEntry::builder("quit")
+        .activate(|app: &gtk::Application, _, _| {
+            for window in app.windows() { window.close(); }
+        })
+        .build();
+    app.add_action_entries([about, quit]);
+}
+
+/// The library window, created on first use
+fn main_window(app: &gtk::Application) -> ShelfWindow {
+    if let Some(window) = app.windows().into_iter().find_map(|window| window.downcast::<ShelfWindow>().ok()) {
+        window.present();
+        return window;
+    }
     let config = Arc::new(RwLock::new(Config::load().unwrap()));
     let window = ShelfWindow::new(app, config.clone()); 
     window.present();
+    window
 }
@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Result;
+
+use crate::pdf::{cache_db_path, cover_of_file, covers_dir, custom_covers_dir, page_thumbnails_dir, PdfCache, PdfMetadata};
+use crate::utils::{human_readable_file_size, unix_now};
+
+const MAINTENANCE_INTERVAL: i64 = 24 * 60 * 60;
+
+/// What a maintenance run did
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub removed_covers: usize,
+    pub removed_thumbnails: usize,
+    // Covers of documents in the library, rendered again when shown
+    pub evicted_covers: usize,
+    pub freed_bytes: u64,
+    pub database_bytes: (u64, u64),
+    // Covers and page thumbnails left afterwards
+    pub cover_bytes: u64,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "removed {} unused covers and {} page thumbnails, evicted {} covers ({}), database {} -> {}, covers now {}",
+            self.removed_covers,
+            self.removed_thumbnails,
+            self.evicted_covers,
+            human_readable_file_size(self.freed_bytes),
+            human_readable_file_size(self.database_bytes.0),
+            human_readable_file_size(self.database_bytes.1),
+            human_readable_file_size(self.cover_bytes),
+        )
+    }
+}
+
+/// Forget the documents a scan flagged as missing whose files are still
+/// gone, their covers go with the next run. Only asked for from the
+/// settings, never done on its own. Returns the hashes forgotten.
+pub fn prune_missing(cache: &PdfCache) -> Result<Vec<String>> {
+    // A file only counts as gone if its folder is still there, otherwise
+    // it's probably on a drive that isn't plugged in
+    let gone: Vec<String> = cache.all_metadata()?.into_iter()
+        .filter(|pdf| {
+            let path = Path::new(&pdf.path);
+            pdf.missing && !path.exists() && path.parent().is_some_and(Path::is_dir)
+        })
+        .map(|pdf| pdf.hash)
+        .collect();
+    cache.remove_metadata(&gone)?;
+    cache.log_maintenance(&format!("pruned {} missing documents", gone.len()))?;
+    Ok(gone)
+}
+
+/// Whether a day has passed since the last run
+pub fn due(cache: &PdfCache) -> Result<bool> {
+    Ok(cache.last_maintenance()?.is_none_or(|last| unix_now() - last >= MAINTENANCE_INTERVAL))
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+/// Files directly in `dir` with their size and modification time
+fn files_in(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new(); };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            metadata.is_file().then(|| (entry.path(), metadata.len(), metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH)))
+        })
+        .collect()
+}
+
+/// Covers in the covers directories, in every size, whose document isn't in
+/// the library any more are deleted unless `keep`. The rest of those in the
+/// cache is returned, custom covers at full size don't count towards its size.
+fn sweep_covers(library: &[PdfMetadata], keep: bool, summary: &mut Summary) -> Vec<(PathBuf, u64, SystemTime)> {
+    let covers: HashSet<&str> = library.iter().filter_map(|pdf| pdf.cover_path.as_deref()).collect();
+    let custom_dir = custom_covers_dir();
+    let mut cover_files = files_in(&covers_dir());
+    cover_files.extend(files_in(&custom_dir));
+    cover_files.retain(|(path, size, _)| {
+        let name = path.file_name().map(|name| cover_of_file(&name.to_string_lossy())).unwrap_or_default();
+        if keep || covers.contains(name.as_str()) { return true; }
+        if fs::remove_file(path).is_ok() {
+            summary.removed_covers += 1;
+            summary.freed_bytes += size;
+        }
+        false
+    });
+    cover_files.retain(|(path, _, _)| !path.starts_with(&custom_dir));
+    cover_files
+}
+
+/// Page thumbnails, oldest first, named <hash prefix>-<page>.jpg, and
+/// whether their document is gone from the library
+fn thumbnails(library: &[PdfMetadata]) -> Vec<(PathBuf, u64, bool)> {
+    let short_hashes: HashSet<&str> = library.iter().filter_map(|pdf| pdf.hash.get(..16)).collect();
+    let mut thumbnails = files_in(&page_thumbnails_dir());
+    thumbnails.sort_by_key(|(_, _, modified)| *modified);
+    thumbnails.into_iter()
+        .map(|(path, size, _)| {
+            let name = path.file_stem().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            let orphaned = name.split_once('-').is_none_or(|(hash, _)| !short_hashes.contains(hash));
+            (path, size, orphaned)
+        })
+        .collect()
+}
+
+/// Delete the covers and page thumbnails of documents that aren't in the
+/// library any more, without trimming or vacuuming anything else. Must not
+/// run during a scan, whose new covers would look unused.
+pub fn remove_unused_covers(cache: &PdfCache) -> Result<Summary> {
+    let mut summary = Summary::default();
+    let library = cache.all_metadata()?;
+    let mut total: u64 = sweep_covers(&library, false, &mut summary).iter().map(|(_, size, _)| size).sum();
+    for (path, size, orphaned) in thumbnails(&library) {
+        if orphaned && fs::remove_file(&path).is_ok() {
+            summary.removed_thumbnails += 1;
+            summary.freed_bytes += size;
+        } else {
+            total += size;
+        }
+    }
+    summary.cover_bytes = total;
+
+    cache.log_maintenance(&format!(
+        "removed {} unused covers and {} page thumbnails ({})",
+        summary.removed_covers,
+        summary.removed_thumbnails,
+        human_readable_file_size(summary.freed_bytes),
+    ))?;
+    Ok(summary)
+}
+
+/// Vacuum the database and trim the covers to `cover_limit` bytes, deleting
+/// those of documents gone from the library first if `remove_unused`. Must
+/// not run during a scan, whose new covers would look unused. Page
+/// thumbnails go first, then the covers shown the longest ago, both are
+/// rendered again when needed. Covers of documents that are missing, and
+/// custom covers, are kept.
+pub fn run(cache: &PdfCache, cover_limit: u64, remove_unused: bool) -> Result<Summary> {
+    let mut summary = Summary::default();
+
+    let library = cache.all_metadata()?;
+    let mut cover_files = sweep_covers(&library, !remove_unused, &mut summary);
+    let thumbnails = thumbnails(&library);
+    let mut total: u64 = cover_files.iter().map(|(_, size, _)| size)
+        .chain(thumbnails.iter().map(|(_, size, _)| size))
+        .sum();
+    for (path, size, orphaned) in &thumbnails {
+        if ((*orphaned && remove_unused) || total > cover_limit) && fs::remove_file(path).is_ok() {
+            summary.removed_thumbnails += 1;
+            summary.freed_bytes += size;
+            total -= size;
+        }
+    }
+    // Still too large without thumbnails, covers that weren't shown for
+    // the longest go next, see cover_loader.rs. Ones whose file can't be
+    // reached right now couldn't be rendered again. The smaller sizes of
+    // custom covers are made again from the full one, which isn't here.
+    let unreachable: HashSet<&str> = library.iter()
+        .filter(|pdf| pdf.missing || !Path::new(&pdf.path).exists())
+        .filter_map(|pdf| pdf.cover_path.as_deref())
+        .collect();
+    cover_files.sort_by_key(|(_, _, modified)| *modified);
+    for (path, size, _) in &cover_files {
+        if total <= cover_limit { break; }
+        let name = path.file_name().map(|name| cover_of_file(&name.to_string_lossy())).unwrap_or_default();
+        if unreachable.contains(name.as_str()) { continue; }
+        if fs::remove_file(path).is_ok() {
+            summary.evicted_covers += 1;
+            summary.freed_bytes += size;
+            total -= size;
+        }
+    }
+    summary.cover_bytes = total;
+
+    let db = cache_db_path();
+    let before = file_size(&db);
+    cache.vacuum()?;
+    summary.database_bytes = (before, file_size(&db));
+
+    cache.log_maintenance(&summary.to_string())?;
+    Ok(summary)
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+const APP_DIR: &str = "shelf";
+
+// Where everything lived before the XDG directories, with the directory
+// each entry belongs in now
+const LEGACY_DIR: &str = ".shelf";
+const LEGACY_ENTRIES: [(&str, Kind); 7] = [
+    ("config.toml", Kind::Config),
+    ("state.toml", Kind::Data),
+    ("session.toml", Kind::Data),
+    ("pdf_cache.db", Kind::Data),
+    ("pdf_cache.db-wal", Kind::Data),
+    ("pdf_cache.db-shm", Kind::Data),
+    ("covers", Kind::Cache),
+];
+
+// The database was kept in the cache directory before it was found to hold
+// user data, see migrate_database
+const DATABASE_FILES: [&str; 3] = ["pdf_cache.db", "pdf_cache.db-wal", "pdf_cache.db-shm"];
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Config,
+    Cache,
+    Data,
+}
+
+fn app_dir(base: Option<PathBuf>) -> Result<PathBuf> {
+    Ok(base.context("No home directory found! Set HOME environment variable")?.join(APP_DIR))
+}
+
+/// `$XDG_CONFIG_HOME/shelf`, for config.toml
+pub fn config_dir() -> Result<PathBuf> {
+    app_dir(dirs::config_dir())
+}
+
+/// `$XDG_CACHE_HOME/shelf`, for rendered covers, page thumbnails and logs,
+/// which can all be rendered or written again
+pub fn cache_dir() -> Result<PathBuf> {
+    app_dir(dirs::cache_dir())
+}
+
+/// `$XDG_DATA_HOME/shelf`, for the database, custom covers, window state and
+/// the saved session. The database holds tags, collections, notes, reading
+/// progress and edited metadata that no scan could bring back.
+pub fn data_dir() -> Result<PathBuf> {
+    app_dir(dirs::data_dir())
+}
+
+/// Rename, or copy and delete when `to` is on another file system
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_ok() { return Ok(()); }
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            move_path(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        fs::remove_dir(from)?;
+    } else {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Move the database and custom covers out of the cache directory, where
+/// earlier versions kept them. Custom covers are told apart by `is_custom`,
+/// their smaller sizes stay behind and are rendered again when missing.
+pub fn migrate_database(is_custom: impl Fn(&str) -> bool) -> Result<()> {
+    let (cache, data) = (cache_dir()?, data_dir()?);
+    if !data.join(DATABASE_FILES[0]).exists() {
+        for name in DATABASE_FILES {
+            let (from, to) = (cache.join(name), data.join(name));
+            if !from.exists() { continue; }
+            fs::create_dir_all(&data)?;
+            move_path(&from, &to).with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))?;
+            info!("Moved {} to {}", from.display(), to.display());
+        }
+    }
+
+    let Ok(covers) = fs::read_dir(cache.join("covers")) else { return Ok(()); };
+    for entry in covers.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !is_custom(&name) { continue; }
+        let to = data.join("covers").join(&name);
+        if to.exists() { continue; }
+        fs::create_dir_all(data.join("covers"))?;
+        move_path(&entry.path(), &to).with_context(|| format!("Failed to move {} to {}", entry.path().display(), to.display()))?;
+    }
+    Ok(())
+}
+
+/// Move files from `~/.shelf` to the XDG directories. Runs before anything
+/// else reads them, entries that already exist in the new place are left alone.
+pub fn migrate_legacy_dir() -> Result<()> {
+    let Some(legacy) = dirs::home_dir().map(|home| home.join(LEGACY_DIR)) else { return Ok(()); };
+    if !legacy.is_dir() { return Ok(()); }
+
+    for (name, kind) in LEGACY_ENTRIES {
+        let from = legacy.join(name);
+        if !from.exists() { continue; }
+        let dir = match kind {
+            Kind::Config => config_dir()?,
+            Kind::Cache => cache_dir()?,
+            Kind::Data => data_dir()?,
+        };
+        let to = dir.join(name);
+        if to.exists() {
+            warn!("Not moving {}, {} already exists", from.display(), to.display());
+            continue;
+        }
+        fs::create_dir_all(&dir)?;
+        move_path(&from, &to).with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))?;
+        info!("Moved {} to {}", from.display(), to.display());
+    }
+
+    // The old instance lock goes with the directory, unless something else is in it
+    let _ = fs::remove_file(legacy.join("instance.lock"));
+    if fs::read_dir(&legacy)?.next().is_none() {
+        fs::remove_dir(&legacy)?;
+    }
+    Ok(())
+}
@@ -1,26 +1,146 @@
 #![allow(dead_code)]
 
-use std::{fs::create_dir_all, path::{Path, PathBuf}, time::Duration};
+use std::{collections::{HashMap, HashSet}, fs::create_dir_all, path::{Path, PathBuf}, sync::{Arc, Condvar, LazyLock, Mutex, mpsc::RecvTimeoutError}, time::{Duration, Instant}};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use image::codecs::jpeg::JpegEncoder;
 use image::RgbImage;
-use mupdf::{Document, Matrix, MetadataName};
+use mupdf::{Document, Matrix, MetadataName, TextBlockType, TextPageOptions};
+use mupdf::pdf::{PdfDocument, PdfObject, PdfPage, PdfWriteOptions};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::params;
+use rusqlite::{params, TransactionBehavior};
+use tracing::{debug, error, info, warn};
+use crate::config::CoverFormat;
+use crate::filename_patterns::{self, FilenamePattern};
+use crate::language;
+use crate::lookup;
+use crate::paths;
 use crate::utils::*;
 
+/// Sent by a scan, see `ScanReporter` for how often
 #[derive(Debug, Clone)]
 pub enum ScanProgress {
-    Found(PathBuf),
+    // PDFs found so far, while looking for them
+    Found(usize),
+    // The file most recently started on
     Processing(PathBuf),
-    Extracted(String, PdfMetadata),
+    // Documents done and documents found, once discovery is over
+    Progress(usize, usize),
+    // Extracted since the last message
+    Extracted(Vec<PdfMetadata>),
     DuplicateDetected(PathBuf, PathBuf),
     Error(PathBuf, String),
     Complete(Vec<PdfMetadata>, Duration),
 }
 
+// Progress is coalesced into a message this often, one per file floods the
+// main loop on large scans
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+// Messages the main loop hasn't got to yet, the scan waits when it's full
+pub const PROGRESS_CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Default)]
+struct ReporterState {
+    last_sent: Option<Instant>,
+    found: usize,
+    processed: usize,
+    total: Option<usize>,
+    processing: Option<PathBuf>,
+    extracted: Vec<PdfMetadata>,
+}
+
+/// Collects the progress of a scan from all of its threads and passes it
+/// on at most every `PROGRESS_INTERVAL`, errors and the result right away
+pub struct ScanReporter {
+    tx: async_channel::Sender<ScanProgress>,
+    state: Mutex<ReporterState>,
+}
+
+impl ScanReporter {
+    pub fn new(tx: async_channel::Sender<ScanProgress>) -> Self {
+        Self { tx, state: Mutex::default() }
+    }
+
+    pub fn sender(&self) -> &async_channel::Sender<ScanProgress> {
+        &self.tx
+    }
+
+    pub fn found(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.found += 1;
+        self.send_throttled(&mut state);
+    }
+
+    /// Discovery is over, `total` documents are to be processed
+    pub fn discovered(&self, total: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.total = Some(total);
+        self.send_pending(&mut state);
+    }
+
+    pub fn processing(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        state.processing = Some(path.to_path_buf());
+        self.send_throttled(&mut state);
+    }
+
+    pub fn extracted(&self, metadata: PdfMetadata) {
+        let mut state = self.state.lock().unwrap();
+        state.processed += 1;
+        state.extracted.push(metadata);
+        self.send_throttled(&mut state);
+    }
+
+    /// Left alone, e.g. quarantined, but done all the same
+    pub fn skipped(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.processed += 1;
+        self.send_throttled(&mut state);
+    }
+
+    pub fn failed(&self, path: &Path, error: String) {
+        let mut state = self.state.lock().unwrap();
+        state.processed += 1;
+        self.send_pending(&mut state);
+        let _ = self.tx.send_blocking(ScanProgress::Error(path.to_path_buf(), error));
+    }
+
+    /// Pass on what's collected so far
+    pub fn flush(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.send_pending(&mut state);
+    }
+
+    /// Pass on what's collected so far, then `message`
+    pub fn send(&self, message: ScanProgress) {
+        let mut state = self.state.lock().unwrap();
+        self.send_pending(&mut state);
+        let _ = self.tx.send_blocking(message);
+    }
+
+    fn send_throttled(&self, state: &mut ReporterState) {
+        if state.last_sent.is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL) { return; }
+        self.send_pending(state);
+    }
+
+    fn send_pending(&self, state: &mut ReporterState) {
+        state.last_sent = Some(Instant::now());
+        if let Some(path) = state.processing.take() {
+            let _ = self.tx.send_blocking(ScanProgress::Processing(path));
+        }
+        if !state.extracted.is_empty() {
+            let _ = self.tx.send_blocking(ScanProgress::Extracted(std::mem::take(&mut state.extracted)));
+        }
+        let _ = self.tx.send_blocking(match state.total {
+            Some(total) => ScanProgress::Progress(state.processed, total),
+            None => ScanProgress::Found(state.found),
+        });
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(test, derive(Default))]
 pub struct PdfMetadata {
     pub hash: String,
     pub partial_hash: String,
@@ -36,27 +156,754 @@ pub struct PdfMetadata {
     pub page_count: u32,
     pub cover_path: Option<String>,
     pub file_size: u64,
+    // Opening words of the text, None until extracted and empty for scans without text
+    #[serde(default)]
+    pub snippet: Option<String>,
+    // Modification time of the file when it was last read, files with the
+    // same size and mtime are taken as unchanged
+    #[serde(default)]
+    pub mtime: Option<i64>,
+    // Not found by the last scan, kept until pruned from the settings
+    #[serde(default)]
+    pub missing: bool,
+    // Found in the text of the first few pages, as ISBN-13
+    #[serde(default)]
+    pub isbn: Option<String>,
+    // Found in the file name or on the first page, without the version
+    #[serde(default)]
+    pub arxiv_id: Option<String>,
+    // Looked up on arXiv, kept when the document is read again
+    #[serde(default)]
+    pub abstract_text: Option<String>,
+    // The largest text on the first page, shown instead of a missing or
+    // junk title, see title_is_heuristic
+    #[serde(default)]
+    pub heuristic_title: Option<String>,
+    // ISO 639-1 code guessed from the snippet, see language.rs
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+impl PdfMetadata {
+    /// The year of the creation date, which PDFs write like
+    /// "D:20190412093000+02'00'"
+    pub fn year(&self) -> Option<i32> {
+        let date = self.creation_date.as_deref()?.trim_start();
+        let year = date.strip_prefix("D:").unwrap_or(date).get(..4)?;
+        if !year.chars().all(|c| c.is_ascii_digit()) { return None; }
+        year.parse().ok().filter(|year| *year > 0)
+    }
+
+    /// The document title, falling back to the largest text on the first
+    /// page and then the file name
+    pub fn display_title(&self) -> String {
+        self.usable_title()
+            .or(self.heuristic_title.as_deref())
+            .map(str::to_string)
+            .or_else(|| Path::new(&self.path).file_stem().map(|s| s.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "(untitled)".to_string())
+    }
+
+    /// Whether display_title is a guess from the first page rather than
+    /// the title in the file
+    pub fn title_is_heuristic(&self) -> bool {
+        self.usable_title().is_none() && self.heuristic_title.is_some()
+    }
+
+    /// The title in the file, unless it's empty or junk
+    pub fn usable_title(&self) -> Option<&str> {
+        self.title.as_deref().map(str::trim).filter(|title| !is_junk_title(title))
+    }
+}
+
+// What word processors and converters put in the Title of files nobody
+// gave a title
+const JUNK_TITLES: &[&str] = &["untitled", "untitled document", "document", "title", "no title", "slide 1"];
+const JUNK_TITLE_PREFIXES: &[&str] = &["microsoft word - ", "microsoft powerpoint - ", "microsoft excel - "];
+const JUNK_TITLE_EXTENSIONS: &[&str] = &[".doc", ".docx", ".odt", ".rtf", ".tex", ".dvi", ".ps", ".pdf", ".indd", ".qxd", ".txt"];
+
+/// An empty title, a placeholder, or a file name with its extension or
+/// the prefix of the program it was converted from
+fn is_junk_title(title: &str) -> bool {
+    let lower = title.trim().to_lowercase();
+    !lower.chars().any(char::is_alphanumeric)
+        || JUNK_TITLES.contains(&lower.as_str())
+        || JUNK_TITLE_PREFIXES.iter().any(|prefix| lower.starts_with(prefix))
+        || JUNK_TITLE_EXTENSIONS.iter().any(|extension| lower.ends_with(extension))
+}
+
+#[cfg(test)]
+impl PdfMetadata {
+    /// A one-page document with nothing but a hash and a path
+    pub fn for_test(hash: &str, path: &str) -> Self {
+        Self {
+            hash: hash.to_string(),
+            partial_hash: hash.to_string(),
+            path: path.to_string(),
+            page_count: 1,
+            file_size: 1,
+            ..Default::default()
+        }
+    }
+}
+
+/// A note or markup annotation found in a document
+#[derive(Debug, Clone)]
+pub struct Annotation {
+    // Zero-based
+    pub page: u32,
+    // PDF annotation subtype, e.g. "Highlight"
+    pub kind: String,
+    pub author: Option<String>,
+    pub contents: Option<String>,
+}
+
+impl Annotation {
+    pub fn kind_label(&self) -> &str {
+        match self.kind.as_str() {
+            "Text" => "Note",
+            "FreeText" => "Text box",
+            "StrikeOut" => "Strikeout",
+            "Ink" => "Drawing",
+            kind => kind,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    pub parent: Option<i64>,
+}
+
+/// A named key to the share server. Every token is read-only, the scope
+/// only limits what it can see.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessToken {
+    pub token: String,
+    pub name: String,
+    // None for the whole library
+    pub collections: Option<Vec<i64>>,
+    // Without it only the listing and covers are served
+    pub download: bool,
+}
+
+/// Metadata fields that can be set by hand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetadataField {
+    Title,
+    Author,
+    Subject,
+    Keywords,
+    CreationDate,
+    Abstract,
+}
+
+impl MetadataField {
+    pub const ALL: [Self; 6] = [Self::Title, Self::Author, Self::Subject, Self::Keywords, Self::CreationDate, Self::Abstract];
+
+    pub fn column(self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Author => "author",
+            Self::Subject => "subject",
+            Self::Keywords => "keywords",
+            Self::CreationDate => "creation_date",
+            Self::Abstract => "abstract",
+        }
+    }
+
+    pub fn from_column(column: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.column() == column)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Title => "Title",
+            Self::Author => "Author",
+            Self::Subject => "Subject",
+            Self::Keywords => "Keywords",
+            Self::CreationDate => "Date",
+            Self::Abstract => "Abstract",
+        }
+    }
+
+    pub fn get(self, metadata: &PdfMetadata) -> Option<&str> {
+        match self {
+            Self::Title => metadata.title.as_deref(),
+            Self::Author => metadata.author.as_deref(),
+            Self::Subject => metadata.subject.as_deref(),
+            Self::Keywords => metadata.keywords.as_deref(),
+            Self::CreationDate => metadata.creation_date.as_deref(),
+            Self::Abstract => metadata.abstract_text.as_deref(),
+        }
+    }
+
+    /// Its key in the Info dictionary of a PDF, the abstract has none
+    fn info_key(self) -> Option<&'static str> {
+        match self {
+            Self::Title => Some("Title"),
+            Self::Author => Some("Author"),
+            Self::Subject => Some("Subject"),
+            Self::Keywords => Some("Keywords"),
+            Self::CreationDate => Some("CreationDate"),
+            Self::Abstract => None,
+        }
+    }
+
+    pub fn set(self, metadata: &mut PdfMetadata, value: Option<String>) {
+        match self {
+            Self::Title => metadata.title = value,
+            Self::Author => metadata.author = value,
+            Self::Subject => metadata.subject = value,
+            Self::Keywords => metadata.keywords = value,
+            Self::CreationDate => metadata.creation_date = value,
+            Self::Abstract => metadata.abstract_text = value,
+        }
+    }
+}
+
+/// A field filled in by an online lookup, see `lookup::fill_in_metadata`.
+/// Kept as a record of where the value came from until it's reverted.
+#[derive(Debug, Clone)]
+pub struct MetadataChange {
+    pub hash: String,
+    pub field: MetadataField,
+    pub value: String,
+    pub previous: Option<String>,
+    // e.g. "Open Library"
+    pub source: String,
+    pub changed_at: i64,
+    pub reviewed: bool,
+}
+
+/// A file extraction keeps failing on, as recorded in `failed_files`
+#[derive(Debug, Clone)]
+pub struct FailedFile {
+    pub path: String,
+    pub error: String,
+    pub attempts: u32,
+    pub last_attempt: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bookmark {
+    // Zero-based
+    pub page: u32,
+    pub label: String,
+}
+
+// Columns read into a PdfMetadata, in the order metadata_from_row expects
+const METADATA_COLUMNS: &str = "hash, partial_hash, path, title, author, subject, keywords, creator, producer, \
+    creation_date, modification_date, page_count, cover_path, file_size, snippet, mtime, isbn, \
+    arxiv_id, abstract, heuristic_title, language";
+
+fn metadata_from_row(row: &rusqlite::Row) -> rusqlite::Result<PdfMetadata> {
+    Ok(PdfMetadata {
+        hash: row.get(0)?,
+        partial_hash: row.get(1)?,
+        path: row.get(2)?,
+        title: row.get(3)?,
+        author: row.get(4)?,
+        subject: row.get(5)?,
+        keywords: row.get(6)?,
+        creator: row.get(7)?,
+        producer: row.get(8)?,
+        creation_date: row.get(9)?,
+        modification_date: row.get(10)?,
+        page_count: row.get(11)?,
+        cover_path: row.get(12)?,
+        file_size: row.get(13)?,
+        snippet: row.get(14)?,
+        mtime: row.get(15)?,
+        missing: false,
+        isbn: row.get(16)?,
+        arxiv_id: row.get(17)?,
+        abstract_text: row.get(18)?,
+        heuristic_title: row.get(19)?,
+        // Empty when there was text but no telling its language
+        language: row.get::<_, Option<String>>(20)?.filter(|code| !code.is_empty()),
+    })
+}
+
+// Changes to the tables above, applied once each in order. The number
+// applied so far is kept in the database's user_version.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE pdf_metadata ADD COLUMN snippet TEXT",
+    "ALTER TABLE pdf_metadata ADD COLUMN mtime INTEGER",
+    "ALTER TABLE pdf_metadata ADD COLUMN isbn TEXT",
+    "ALTER TABLE pdf_metadata ADD COLUMN arxiv_id TEXT",
+    "ALTER TABLE pdf_metadata ADD COLUMN abstract TEXT",
+    "ALTER TABLE pdf_metadata ADD COLUMN heuristic_title TEXT",
+    "ALTER TABLE pdf_metadata ADD COLUMN language TEXT",
+    // Same as PdfMetadata::year, indexed for counting by decade
+    "ALTER TABLE pdf_metadata ADD COLUMN year INTEGER GENERATED ALWAYS AS (CASE
+        WHEN ltrim(creation_date) GLOB 'D:[0-9][0-9][0-9][0-9]*' THEN CAST(substr(ltrim(creation_date), 3, 4) AS INTEGER)
+        WHEN ltrim(creation_date) GLOB '[0-9][0-9][0-9][0-9]*' THEN CAST(substr(ltrim(creation_date), 1, 4) AS INTEGER)
+    END) VIRTUAL;
+    CREATE INDEX IF NOT EXISTS idx_year ON pdf_metadata(year)",
+    // Documents already in the library when additions started being recorded
+    // were dated by when they were last seen, which made them all count as
+    // added that year. Nothing tells those rows apart now, from here on such
+    // documents have no date.
+    "CREATE TABLE library_additions_new (hash TEXT PRIMARY KEY, added_at INTEGER);
+    INSERT INTO library_additions_new SELECT hash, added_at FROM library_additions;
+    DROP TABLE library_additions;
+    ALTER TABLE library_additions_new RENAME TO library_additions;
+    INSERT OR IGNORE INTO library_additions (hash, added_at) SELECT hash, NULL FROM pdf_metadata",
+    // A row per page turn added up without end, one per page and day is
+    // enough for the reports
+    "CREATE TABLE page_views_new (
+        hash TEXT NOT NULL,
+        page INTEGER NOT NULL,
+        day INTEGER NOT NULL,
+        viewed_at INTEGER NOT NULL,
+        views INTEGER NOT NULL,
+        PRIMARY KEY (hash, page, day)
+    );
+    INSERT INTO page_views_new (hash, page, day, viewed_at, views)
+        SELECT hash, page, viewed_at / 86400, MIN(viewed_at), COUNT(*) FROM page_views GROUP BY hash, page, viewed_at / 86400;
+    DROP TABLE page_views;
+    ALTER TABLE page_views_new RENAME TO page_views;
+    CREATE INDEX IF NOT EXISTS idx_page_views_viewed_at ON page_views(viewed_at)",
+];
+
+/// Guess the language of documents read before languages were, from their
+/// cached snippet
+fn detect_languages(conn: &rusqlite::Connection) -> Result<()> {
+    let mut stmt = conn.prepare("SELECT hash, snippet FROM pdf_metadata WHERE language IS NULL AND snippet <> ''")?;
+    let snippets = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    if snippets.is_empty() { return Ok(()); }
+    let mut update = conn.prepare("UPDATE pdf_metadata SET language = ?2 WHERE hash = ?1")?;
+    for (hash, snippet) in &snippets {
+        update.execute(params![hash, language::detect(snippet).unwrap_or("")])?;
+    }
+    info!("Detected the language of {} documents", snippets.len());
+    Ok(())
+}
+
+fn migrate(conn: &mut rusqlite::Connection) -> Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)
+            .with_context(|| format!("Failed to apply database migration {}", index + 1))?;
+        tx.execute_batch(&format!("PRAGMA user_version = {}", index + 1))?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+// Tags of each document, sorted
+pub type TagsByHash = HashMap<String, Vec<String>>;
+/// Custom fields of every document that has any, by hash, see
+/// `PdfCache::custom_fields`
+pub type CustomFieldsByHash = HashMap<String, Vec<(String, String)>>;
+// Paths of the files attached to each document
+pub type AttachmentsByHash = HashMap<String, Vec<String>>;
+
+fn has_table(conn: &rusqlite::Connection, table: &str) -> Result<bool> {
+    let count: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+/// Documents and tags of a cache database, read without changing it. Works
+/// on databases from older versions too, missing columns read as empty.
+pub fn read_snapshot(db_path: &Path) -> Result<(Vec<PdfMetadata>, TagsByHash)> {
+    if !db_path.exists() { anyhow::bail!("{}: no such file", db_path.display()); }
+    let conn = rusqlite::Connection::open_with_flags(db_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    if !has_table(&conn, "pdf_metadata")? {
+        anyhow::bail!("{} is not a Shelf library", db_path.display());
+    }
+
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info('pdf_metadata')")?;
+    let existing: Vec<String> = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+    let columns: Vec<String> = METADATA_COLUMNS.split(',')
+        .map(str::trim)
+        .map(|column| if existing.iter().any(|other| other == column) { column.to_string() } else { format!("NULL AS {}", column) })
+        .collect();
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM pdf_metadata ORDER BY path", columns.join(", ")))?;
+    let documents = stmt.query_map([], metadata_from_row)?.collect::<Result<Vec<_>, _>>()?;
+
+    let mut tags = TagsByHash::new();
+    if has_table(&conn, "tags")? {
+        let mut stmt = conn.prepare("SELECT hash, tag FROM tags ORDER BY tag")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        for row in rows {
+            let (hash, tag) = row?;
+            tags.entry(hash).or_default().push(tag);
+        }
+    }
+
+    Ok((documents, tags))
+}
+
+// How much of the text is kept for the preview pane
+const SNIPPET_WORDS: usize = 500;
+// Don't dig further than this for text, covers and blank pages come first
+const SNIPPET_MAX_PAGES: i32 = 3;
+
+/// The first few hundred words of the document's text
+pub fn extract_snippet(document: &Document) -> Result<String> {
+    let mut words: Vec<String> = Vec::new();
+    for page_no in 0..document.page_count()?.min(SNIPPET_MAX_PAGES) {
+        let text = document.load_page(page_no)?.to_text()?;
+        words.extend(text.split_whitespace().take(SNIPPET_WORDS - words.len()).map(str::to_string));
+        if words.len() >= SNIPPET_WORDS { break; }
+    }
+    Ok(words.join(" "))
+}
+
+// The copyright page, where books print their ISBN, is rarely further in
+const ISBN_MAX_PAGES: i32 = 6;
+
+// More lines in the largest size than a title runs to, the page is body
+// text in one size
+const TITLE_MAX_LINES: usize = 4;
+const TITLE_MAX_LENGTH: usize = 200;
+
+/// The text in the largest font on `page`, the title on most title pages.
+/// Lines of that size following each other are joined, for titles set over
+/// two or three lines.
+fn largest_text_on(page: &mupdf::Page) -> Result<Option<String>> {
+    let text_page = page.to_text_page(TextPageOptions::empty())?;
+    let mut lines: Vec<(f32, String)> = Vec::new();
+    for block in text_page.blocks().filter(|block| block.r#type() == TextBlockType::Text) {
+        for line in block.lines() {
+            let (mut text, mut size) = (String::new(), 0.0f32);
+            for ch in line.chars() {
+                let Some(c) = ch.char() else { continue; };
+                text.push(c);
+                if !c.is_whitespace() { size = size.max(ch.size()); }
+            }
+            // Page numbers, rules and drop caps aren't titles
+            if text.chars().filter(|c| c.is_alphabetic()).count() < 3 { continue; }
+            lines.push((size, text.split_whitespace().collect::<Vec<_>>().join(" ")));
+        }
+    }
+    let Some(largest) = lines.iter().map(|(size, _)| *size).reduce(f32::max) else { return Ok(None); };
+    let run: Vec<&str> = lines.iter()
+        .skip_while(|(size, _)| largest - size > 0.5)
+        .take_while(|(size, _)| largest - size <= 0.5)
+        .map(|(_, text)| text.as_str())
+        .collect();
+    if run.len() > TITLE_MAX_LINES { return Ok(None); }
+    let title = run.join(" ");
+    Ok((title.chars().count() <= TITLE_MAX_LENGTH).then_some(title))
+}
+
+/// The first ISBN in the text of the opening pages
+fn find_isbn_in(document: &Document) -> Result<Option<String>> {
+    for page_no in 0..document.page_count()?.min(ISBN_MAX_PAGES) {
+        let text = document.load_page(page_no)?.to_text()?;
+        if let Some(isbn) = lookup::find_isbn(&text) { return Ok(Some(isbn)); }
+    }
+    Ok(None)
+}
+
+pub fn covers_dir() -> PathBuf {
+    paths::cache_dir().unwrap().join("covers")
+}
+
+/// Custom covers at full size, kept with the database since they can't be
+/// rendered again. Their smaller sizes are in `covers_dir`.
+pub fn custom_covers_dir() -> PathBuf {
+    paths::data_dir().unwrap().join("covers")
+}
+
+pub fn cache_db_path() -> PathBuf {
+    paths::data_dir().unwrap().join("pdf_cache.db")
+}
+
+/// Whether `file` is a custom cover at full size, named by save_custom_cover
+pub fn is_custom_cover(file: &str) -> bool {
+    file.contains(CUSTOM_COVER_MARKER) && cover_of_file(file) == file
+}
+
+/// Where cover `name` is saved at full size
+pub fn full_cover_file(name: &str) -> PathBuf {
+    if is_custom_cover(name) { custom_covers_dir().join(name) } else { covers_dir().join(name) }
+}
+
+/// Page thumbnails, rendered on demand and kept alongside the covers
+pub fn page_thumbnails_dir() -> PathBuf {
+    covers_dir().join("pages")
+}
+
+// Rendered at twice the width they're shown at, for HiDPI screens
+const PAGE_THUMBNAIL_WIDTH: f32 = 192.0;
+// Covers are rendered no larger than this, whatever the page size, and
+// scaled down to COVER_SAVED_SIZE, twice what the grid shows, when saved
+const COVER_RENDER_SIZE: f32 = 1024.0;
+const COVER_SAVED_SIZE: u32 = 512;
+// Smaller copies of each cover saved next to it, so the grid doesn't decode
+// a 512px JPEG for every 128px item
+pub const COVER_SIZES: [u32; 3] = [128, 256, COVER_SAVED_SIZE];
+// Custom covers are named <hash prefix>_custom<time set>.<extension>
+const CUSTOM_COVER_MARKER: &str = "_custom";
+
+/// The file in the covers directory with cover `name` at `size`, the
+/// largest size is `name` itself, e.g. abcd.jpg and abcd-128.jpg
+pub fn cover_file(name: &str, size: u32) -> String {
+    if size >= COVER_SAVED_SIZE { return name.to_string(); }
+    match name.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}-{}.{}", stem, size, extension),
+        None => name.to_string(),
+    }
+}
+
+/// The cover a file in the covers directory is a size of, see `cover_file`
+pub fn cover_of_file(file: &str) -> String {
+    let sized = file.rsplit_once('.')
+        .and_then(|(stem, extension)| Some((stem.rsplit_once('-')?, extension)))
+        .filter(|((_, size), _)| COVER_SIZES.iter().any(|known| known.to_string() == *size));
+    match sized {
+        Some(((stem, _), extension)) => format!("{}.{}", stem, extension),
+        None => file.to_string(),
+    }
+}
+
+/// How new covers are rendered and saved, from the config
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoverEncoding {
+    pub format: CoverFormat,
+    // 1 to 100
+    pub quality: u8,
+    // Times 72 DPI, COVER_RENDER_SIZE grows with it
+    pub render_scale: f32,
+}
+
+const DEFAULT_COVER_ENCODING: CoverEncoding = CoverEncoding { format: CoverFormat::Jpeg, quality: 75, render_scale: 1.0 };
+
+// Covers are also saved outside of scans, e.g. when an evicted one is shown
+static COVER_ENCODING: Mutex<CoverEncoding> = Mutex::new(DEFAULT_COVER_ENCODING);
+
+pub fn set_cover_encoding(encoding: CoverEncoding) {
+    *COVER_ENCODING.lock().unwrap() = CoverEncoding {
+        quality: encoding.quality.clamp(1, 100),
+        render_scale: encoding.render_scale.clamp(0.25, 4.0),
+        ..encoding
+    };
+}
+
+/// The name a new cover of the document with `hash` is saved under. Covers
+/// rendered or saved differently from the defaults say how in their name,
+/// so a scan can tell which ones are out of date.
+fn new_cover_name(hash: &str) -> String {
+    let encoding = *COVER_ENCODING.lock().unwrap();
+    let extension = encoding.format.extension();
+    if encoding.quality == DEFAULT_COVER_ENCODING.quality && encoding.render_scale == DEFAULT_COVER_ENCODING.render_scale {
+        return format!("{}.{}", &hash[..16], extension);
+    }
+    format!("{}_s{}q{}.{}", &hash[..16], (encoding.render_scale * 100.0).round() as u32, encoding.quality, extension)
+}
+
+/// Encode `image` to `path`, as WebP if that's its extension and JPEG otherwise
+pub fn save_cover_image(image: &RgbImage, path: &Path) -> Result<()> {
+    let quality = COVER_ENCODING.lock().unwrap().quality;
+    if path.extension().is_some_and(|extension| extension == "webp") {
+        let (width, height) = image.dimensions();
+        let encoded = webp::Encoder::from_rgb(image.as_raw(), width, height).encode(quality as f32);
+        std::fs::write(path, &*encoded)?;
+    } else {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        image.write_with_encoder(JpegEncoder::new_with_quality(file, quality))?;
+    }
+    Ok(())
+}
+
+/// The smallest saved size that isn't blurry at `pixels`
+pub fn cover_size_for(pixels: u32) -> u32 {
+    COVER_SIZES.into_iter().find(|size| *size >= pixels).unwrap_or(COVER_SAVED_SIZE)
+}
+
+/// Save `image`, at most COVER_SAVED_SIZE, as cover `name` and its smaller sizes
+fn save_cover(image: &RgbImage, name: &str) -> Result<()> {
+    let dir = covers_dir();
+    save_cover_image(image, &full_cover_file(name))?;
+    let (width, height) = image.dimensions();
+    for size in COVER_SIZES.into_iter().filter(|size| *size < COVER_SAVED_SIZE) {
+        let scale = (size as f32 / width.max(height).max(1) as f32).min(1.0);
+        let scaled_width = ((width as f32 * scale).round() as u32).max(1);
+        let scaled_height = ((height as f32 * scale).round() as u32).max(1);
+        save_cover_image(&image::imageops::thumbnail(image, scaled_width, scaled_height), &dir.join(cover_file(name, size)))?;
+    }
+    Ok(())
+}
+
+/// Render a small image of page `page_no`, reusing the one from an earlier call
+pub fn page_thumbnail(document: &Document, hash: &str, page_no: i32) -> Result<PathBuf> {
+    let thumbnail_path = page_thumbnails_dir().join(format!("{}-{}.jpg", &hash[..16], page_no));
+    if thumbnail_path.exists() { return Ok(thumbnail_path); }
+
+    let page = document.load_page(page_no)?;
+    let bounds = page.bounds()?;
+    let scale = PAGE_THUMBNAIL_WIDTH / bounds.width().max(1.0);
+    let pixmap = page.to_pixmap(&Matrix::new_scale(scale, scale), &mupdf::Colorspace::device_rgb(), false, true)?;
+    let image = RgbImage::from_raw(pixmap.width(), pixmap.height(), pixmap.samples().to_vec())
+        .context("Failed to create image from pixmap")?;
+    image.save(&thumbnail_path)?;
+    Ok(thumbnail_path)
+}
+
+// SQLite page cache limit in low-memory mode, in KiB
+const LOW_MEMORY_SQLITE_CACHE_KIB: i64 = 1024;
+const LOW_MEMORY_POOL_SIZE: u32 = 2;
+// Extracted metadata is committed this many rows at a time
+const WRITE_BATCH_SIZE: usize = 32;
+// How long a connection waits for another one's write to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+// Failed extractions of an unchanged file before scans skip it
+pub const QUARANTINE_AFTER: u32 = 3;
+
+// A document seen again at `path`, but not read
+struct Touch {
+    hash: String,
+    seen: u64,
+    path: String,
+    mtime: Option<i64>,
 }
 
 pub struct PdfCache {
     pool: Pool<SqliteConnectionManager>,
     // conn: Connection,
     cache_dir: PathBuf,
+    // Metadata rows not written yet, with the time they were seen
+    pending: Mutex<Vec<(PdfMetadata, u64)>>,
+    // Unchanged documents seen again, only their last_seen and the location
+    // they were seen at need writing
+    touched: Mutex<Vec<Touch>>,
+    renders: Arc<RenderSlots>,
+    limits: Mutex<ExtractionLimits>,
+}
+
+/// What a single new file may take before it's skipped, None is no limit
+#[derive(Clone, Default)]
+pub struct ExtractionLimits {
+    pub timeout: Option<Duration>,
+    pub max_file_size: Option<u64>,
+    // Width or height of the rendered cover, in pixels
+    pub max_render_dimension: Option<u32>,
+    // Documents opened at once, 0 is no limit
+    pub max_concurrent_renders: usize,
+    // Folders whose files only have their metadata read, without a cover
+    // or snippet, e.g. ones on slow network mounts
+    pub metadata_only: Vec<PathBuf>,
+    // Filling in what the file doesn't have from its name
+    pub filename_patterns: Vec<FilenamePattern>,
+}
+
+#[derive(Default)]
+struct RenderSlotsState {
+    // 0 is no limit
+    limit: usize,
+    busy: usize,
+    // Held by reads that were given up on, see read_document_with_timeout
+    hung: usize,
+}
+
+/// Every render slot is held by a read MuPDF never came back from, so no
+/// more files can be read until Shelf is restarted. Not the file's fault.
+#[derive(Debug)]
+pub struct StuckReads(usize);
+
+impl std::fmt::Display for StuckReads {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MuPDF is stuck on {} other files, restart Shelf to read more", self.0)
+    }
+}
+
+impl std::error::Error for StuckReads {}
+
+/// Caps how many documents MuPDF has open for rendering at once, each can
+/// take hundreds of MB on scanned books
+#[derive(Default)]
+struct RenderSlots {
+    state: Mutex<RenderSlotsState>,
+    freed: Condvar,
+}
+
+// Owns its slots, so it can go along with a read that outlives its caller
+struct RenderSlot(Arc<RenderSlots>);
+
+// Cover pixmaps in memory at once, whatever the number of scan threads
+const PIXMAPS_IN_FLIGHT: usize = 4;
+static PIXMAP_SLOTS: LazyLock<Arc<RenderSlots>> = LazyLock::new(|| Arc::new(RenderSlots::new(PIXMAPS_IN_FLIGHT)));
+
+impl RenderSlots {
+    const fn new(limit: usize) -> Self {
+        Self { state: Mutex::new(RenderSlotsState { limit, busy: 0, hung: 0 }), freed: Condvar::new() }
+    }
+
+    fn set_limit(&self, limit: usize) {
+        self.state.lock().unwrap().limit = limit;
+        self.freed.notify_all();
+    }
+
+    fn acquire(self: &Arc<Self>) -> Result<RenderSlot, StuckReads> {
+        let mut state = self.state.lock().unwrap();
+        while state.limit > 0 && state.busy >= state.limit {
+            // None of them is ever coming back
+            if state.hung >= state.limit { return Err(StuckReads(state.hung)); }
+            state = self.freed.wait(state).unwrap();
+        }
+        state.busy += 1;
+        Ok(RenderSlot(Arc::clone(self)))
+    }
+
+    fn set_hung(&self, hung: bool) {
+        let mut state = self.state.lock().unwrap();
+        if hung { state.hung += 1; } else { state.hung -= 1; }
+        self.freed.notify_all();
+    }
+}
+
+impl Drop for RenderSlot {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().busy -= 1;
+        self.0.freed.notify_one();
+    }
 }
 
 impl PdfCache {
-    pub fn new() -> Result<Self> {
-        let cache_dir = dirs::home_dir().unwrap().join(".shelf");
+    pub fn new(low_memory: bool) -> Result<Self> {
+        let cache_dir = paths::cache_dir()?;
         
         create_dir_all(&cache_dir)?;
-        create_dir_all(cache_dir.join("covers"))?;
+        create_dir_all(covers_dir())?;
+        create_dir_all(custom_covers_dir())?;
+        create_dir_all(page_thumbnails_dir())?;
         
-        let db_path = cache_dir.join("pdf_cache.db");
-        let manager = SqliteConnectionManager::file(&db_path);
-        let pool = Pool::new(manager)?;
+        let db_path = cache_db_path();
+        let manager = SqliteConnectionManager::file(&db_path).with_init(move |conn| {
+            // Scan threads write while the window reads, a writer that finds
+            // the database locked waits its turn instead of failing
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+            conn.pragma_update(None, "synchronous", "NORMAL")?;
+            // Take the write lock up front, a read turned write can't wait for it
+            conn.set_transaction_behavior(TransactionBehavior::Immediate);
+            if low_memory {
+                conn.execute_batch(&format!("PRAGMA cache_size = -{};", LOW_MEMORY_SQLITE_CACHE_KIB))?;
+            }
+            Ok(())
+        });
+        let pool = if low_memory {
+            Pool::builder().max_size(LOW_MEMORY_POOL_SIZE).build(manager)?
+        } else {
+            Pool::new(manager)?
+        };
 
         {
-            let conn = pool.get()?;
+            let mut conn = pool.get()?;
+            // Readers don't wait for writers, kept in the database file
+            conn.pragma_update(None, "journal_mode", "WAL")?;
             conn.execute(
                 "CREATE TABLE IF NOT EXISTS pdf_metadata (
                     hash TEXT PRIMARY KEY,
@@ -87,103 +934,1897 @@ impl PdfCache {
                 "CREATE INDEX IF NOT EXISTS idx_path ON pdf_metadata(path)",
                 [],
             )?;
+
+            // Every path a document was found at, copies share its row above
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS locations (
+                    path TEXT PRIMARY KEY,
+                    hash TEXT NOT NULL,
+                    mtime INTEGER
+                )",
+                [],
+            )?;
+            conn.execute(
+                "CREATE INDEX IF NOT EXISTS idx_locations_hash ON locations(hash)",
+                [],
+            )?;
+
+            // Last page viewed in the built-in reader, keyed by content hash
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS reading_progress (
+                    hash TEXT PRIMARY KEY,
+                    page INTEGER NOT NULL,
+                    updated_at INTEGER NOT NULL
+                )",
+                [],
+            )?;
+
+            // Per-document reader preferences
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS reader_preferences (
+                    hash TEXT PRIMARY KEY,
+                    render_mode TEXT NOT NULL
+                )",
+                [],
+            )?;
+
+            // Pages viewed in the reader, for reading history reports. One row
+            // per page and UTC day, with when it was first viewed that day
+            // and how often.
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS page_views (
+                    hash TEXT NOT NULL,
+                    page INTEGER NOT NULL,
+                    day INTEGER NOT NULL,
+                    viewed_at INTEGER NOT NULL,
+                    views INTEGER NOT NULL,
+                    PRIMARY KEY (hash, page, day)
+                )",
+                [],
+            )?;
+
+            conn.execute(
+                "CREATE INDEX IF NOT EXISTS idx_page_views_viewed_at ON page_views(viewed_at)",
+                [],
+            )?;
+
+            // pdf_metadata rows are replaced on every rescan, so the date a
+            // document first showed up is kept separately. NULL for documents
+            // that were there before additions were recorded.
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS library_additions (
+                    hash TEXT PRIMARY KEY,
+                    added_at INTEGER
+                )",
+                [],
+            )?;
+
+            // Covers picked by the user, rescans keep them instead of
+            // rendering the first page
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS custom_covers (
+                    hash TEXT PRIMARY KEY,
+                    cover TEXT NOT NULL
+                )",
+                [],
+            )?;
+
+            // Documents whose cover was looked up online, found or not, so
+            // it's only tried once unless asked for
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS cover_lookups (
+                    hash TEXT PRIMARY KEY,
+                    looked_up_at INTEGER NOT NULL
+                )",
+                [],
+            )?;
+
+            // Fields filled in by online lookups with what they replaced,
+            // for review, and the documents that were looked up
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS metadata_changes (
+                    hash TEXT NOT NULL,
+                    field TEXT NOT NULL,
+                    value TEXT,
+                    previous TEXT,
+                    source TEXT NOT NULL,
+                    changed_at INTEGER NOT NULL,
+                    reviewed INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY (hash, field)
+                )",
+                [],
+            )?;
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS metadata_lookups (
+                    hash TEXT PRIMARY KEY,
+                    looked_up_at INTEGER NOT NULL
+                )",
+                [],
+            )?;
+            // Fields edited by hand, they win over what's read from the file.
+            // The value is NULL for a cleared field, original is what the
+            // file has, put back when the edit is undone.
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS metadata_overrides (
+                    hash TEXT NOT NULL,
+                    field TEXT NOT NULL,
+                    value TEXT,
+                    original TEXT,
+                    PRIMARY KEY (hash, field)
+                )",
+                [],
+            )?;
+            // Fields the user made up, like "Course: CS301"
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS custom_fields (
+                    hash TEXT NOT NULL,
+                    key TEXT NOT NULL COLLATE NOCASE,
+                    value TEXT NOT NULL,
+                    PRIMARY KEY (hash, key)
+                )",
+                [],
+            )?;
+
+            migrate(&mut conn)?;
+            detect_languages(&conn)?;
+
+            conn.execute(
+                "INSERT OR IGNORE INTO locations (path, hash, mtime)
+                SELECT path, hash, mtime FROM pdf_metadata",
+                [],
+            )?;
+
+            // Annotations are extracted on demand, annotation_scans tells a
+            // document without any apart from one that hasn't been looked at
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS annotations (
+                    hash TEXT NOT NULL,
+                    page INTEGER NOT NULL,
+                    kind TEXT NOT NULL,
+                    author TEXT,
+                    contents TEXT
+                )",
+                [],
+            )?;
+
+            conn.execute(
+                "CREATE INDEX IF NOT EXISTS idx_annotations_hash ON annotations(hash)",
+                [],
+            )?;
+
+            // Every time a document was opened, in any viewer
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS document_opens (
+                    hash TEXT NOT NULL,
+                    opened_at INTEGER NOT NULL
+                )",
+                [],
+            )?;
+
+            // One bookmark per page and document
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS bookmarks (
+                    hash TEXT NOT NULL,
+                    page INTEGER NOT NULL,
+                    label TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    PRIMARY KEY (hash, page)
+                )",
+                [],
+            )?;
+
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS annotation_scans (
+                    hash TEXT PRIMARY KEY,
+                    scanned_at INTEGER NOT NULL
+                )",
+                [],
+            )?;
+
+            // Collections nest through parent_id, NULL for top level ones
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS collections (
+                    id INTEGER PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    parent_id INTEGER
+                )",
+                [],
+            )?;
+
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS collection_items (
+                    collection_id INTEGER NOT NULL,
+                    hash TEXT NOT NULL,
+                    PRIMARY KEY (collection_id, hash)
+                )",
+                [],
+            )?;
+
+            // Collections published by the share server, one secret token each
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS collection_shares (
+                    token TEXT PRIMARY KEY,
+                    collection_id INTEGER NOT NULL,
+                    created_at INTEGER NOT NULL
+                )",
+                [],
+            )?;
+
+            // Tokens for people rather than single collections
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS access_tokens (
+                    token TEXT PRIMARY KEY,
+                    name TEXT NOT NULL UNIQUE,
+                    whole_library INTEGER NOT NULL,
+                    download INTEGER NOT NULL,
+                    created_at INTEGER NOT NULL
+                )",
+                [],
+            )?;
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS access_token_collections (
+                    token TEXT NOT NULL,
+                    collection_id INTEGER NOT NULL,
+                    PRIMARY KEY (token, collection_id)
+                )",
+                [],
+            )?;
+
+            // Documents maintenance found missing, and since when
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS missing_files (
+                    hash TEXT PRIMARY KEY,
+                    missing_since INTEGER NOT NULL
+                )",
+                [],
+            )?;
+
+            // Files extraction failed on, skipped once it's failed often enough
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS failed_files (
+                    path TEXT PRIMARY KEY,
+                    file_size INTEGER NOT NULL,
+                    mtime INTEGER NOT NULL,
+                    error TEXT NOT NULL,
+                    attempts INTEGER NOT NULL,
+                    last_attempt INTEGER NOT NULL
+                )",
+                [],
+            )?;
+
+            // When the idle maintenance ran and what it did
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS maintenance_runs (
+                    ran_at INTEGER NOT NULL,
+                    summary TEXT NOT NULL
+                )",
+                [],
+            )?;
+
+            // Editions of the same work, the grid only shows the preferred one
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS works (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    preferred TEXT NOT NULL
+                )",
+                [],
+            )?;
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS work_editions (
+                    hash TEXT PRIMARY KEY,
+                    work_id INTEGER NOT NULL
+                )",
+                [],
+            )?;
+
+            // Related files that belong to a document, e.g. errata or an audiobook
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS attachments (
+                    hash TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    added_at INTEGER NOT NULL,
+                    PRIMARY KEY (hash, path)
+                )",
+                [],
+            )?;
+
+            // User tags, kept apart from the keywords stored in the file
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS tags (
+                    hash TEXT NOT NULL,
+                    tag TEXT NOT NULL,
+                    PRIMARY KEY (hash, tag)
+                )",
+                [],
+            )?;
+        }
+        
+        Ok(Self { pool, cache_dir, pending: Mutex::default(), touched: Mutex::default(),
+            renders: Arc::default(), limits: Mutex::default() })
+    }
+
+    /// Applies to files read from now on
+    pub fn set_extraction_limits(&self, limits: ExtractionLimits) {
+        self.renders.set_limit(limits.max_concurrent_renders);
+        *self.limits.lock().unwrap() = limits;
+    }
+
+    fn is_metadata_only(&self, path: &Path) -> bool {
+        self.limits.lock().unwrap().metadata_only.iter().any(|dir| path.starts_with(dir))
+    }
+    
+    /// Queued rows `matches` picks, the latest of each document, so a file
+    /// found twice before the batch is written is still seen as a copy
+    fn pending_rows(&self, matches: impl Fn(&PdfMetadata) -> bool) -> Vec<PdfMetadata> {
+        let pending = self.pending.lock().unwrap();
+        let mut rows: Vec<PdfMetadata> = Vec::new();
+        for (metadata, _) in pending.iter().rev() {
+            if matches(metadata) && !rows.iter().any(|row| row.hash == metadata.hash) {
+                rows.push(metadata.clone());
+            }
+        }
+        rows
+    }
+
+    pub fn get_by_partial_hash(&self, partial_hash: &str, file_size: u64) -> Result<Vec<PdfMetadata>> {
+        let mut results = self.pending_rows(|metadata| metadata.partial_hash == partial_hash && metadata.file_size == file_size);
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM pdf_metadata WHERE partial_hash = ?1 AND file_size = ?2",
+            METADATA_COLUMNS
+        ))?;
+        
+        for metadata in stmt.query_map(params![partial_hash, file_size], metadata_from_row)? {
+            let metadata = metadata?;
+            if !results.iter().any(|queued| queued.hash == metadata.hash) { results.push(metadata); }
+        }
+
+        Ok(results)
+    }
+    
+    pub fn get_metadata(&self, hash: &str) -> Result<Option<PdfMetadata>> {
+        if let Some(queued) = self.pending_rows(|metadata| metadata.hash == hash).pop() {
+            return Ok(Some(queued));
+        }
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM pdf_metadata WHERE hash = ?1",
+            METADATA_COLUMNS
+        ))?;
+        
+        let result = stmt.query_row(params![hash], metadata_from_row);
+        
+        match result {
+            Ok(metadata) => Ok(Some(metadata)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+    
+    /// Queue a metadata row, rows are written in batches so call `flush`
+    /// once a scan is over
+    pub fn store_metadata(&self, metadata: &PdfMetadata) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let full = {
+            let mut pending = self.pending.lock().unwrap();
+            pending.push((metadata.clone(), now));
+            pending.len() >= WRITE_BATCH_SIZE
+        };
+        if full { self.flush()?; }
+
+        Ok(())
+    }
+
+    /// Queue a new last_seen for a document whose file hasn't changed, found
+    /// at `path`, which may be a copy
+    pub fn touch(&self, hash: &str, path: &str, mtime: Option<i64>) -> Result<()> {
+        let full = {
+            let mut touched = self.touched.lock().unwrap();
+            touched.push(Touch { hash: hash.to_string(), seen: unix_now() as u64, path: path.to_string(), mtime });
+            // Cheap rows, a lot more of them fit in a batch
+            touched.len() >= WRITE_BATCH_SIZE * 32
+        };
+        if full { self.flush()?; }
+
+        Ok(())
+    }
+
+    /// Write a consistent copy of the whole database to `path`
+    pub fn snapshot(&self, path: &Path) -> Result<()> {
+        self.flush()?;
+        if path.exists() { anyhow::bail!("{} already exists", path.display()); }
+        let conn = self.pool.get()?;
+        conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])?;
+
+        Ok(())
+    }
+
+    /// Forget documents along with what was extracted from them. Tags,
+    /// collections and other things the user made are kept in case the
+    /// file comes back.
+    pub fn remove_metadata(&self, hashes: &[String]) -> Result<usize> {
+        self.flush()?;
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let mut removed = 0;
+        for hash in hashes {
+            removed += tx.execute("DELETE FROM pdf_metadata WHERE hash = ?1", params![hash])?;
+            tx.execute("DELETE FROM annotations WHERE hash = ?1", params![hash])?;
+            tx.execute("DELETE FROM annotation_scans WHERE hash = ?1", params![hash])?;
+            tx.execute("DELETE FROM missing_files WHERE hash = ?1", params![hash])?;
+            tx.execute("DELETE FROM locations WHERE hash = ?1", params![hash])?;
+        }
+        tx.commit()?;
+
+        Ok(removed)
+    }
+
+    /// Move what's kept about a document from `old` to `new`, the hash it
+    /// has after Shelf changed the file. The metadata is read again.
+    pub fn carry_over(&self, old: &str, new: &str) -> Result<()> {
+        self.flush()?;
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for table in CARRIED_OVER_TABLES {
+            tx.execute(&format!("UPDATE OR REPLACE {} SET hash = ?2 WHERE hash = ?1", table), params![old, new])?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// After a scan: flag the documents it found missing, keeping the time
+    /// of the ones already known to be, and unflag the ones it found
+    pub fn update_missing(&self, missing: &[String], found: &HashSet<String>) -> Result<()> {
+        let now = unix_now();
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare("INSERT OR IGNORE INTO missing_files (hash, missing_since) VALUES (?1, ?2)")?;
+            for hash in missing {
+                insert.execute(params![hash, now])?;
+            }
+            let mut delete = tx.prepare("DELETE FROM missing_files WHERE hash = ?1")?;
+            for hash in found {
+                delete.execute([hash])?;
+            }
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Hashes of the documents flagged as missing
+    pub fn missing_hashes(&self) -> Result<HashSet<String>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT hash FROM missing_files")?;
+        let hashes = stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(hashes)
+    }
+
+    /// Note documents whose files were just deleted, keeping the time of the
+    /// ones already known to be missing
+    pub fn mark_missing(&self, hashes: &[String]) -> Result<()> {
+        let now = unix_now();
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for hash in hashes {
+            tx.execute(
+                "INSERT OR IGNORE INTO missing_files (hash, missing_since) VALUES (?1, ?2)",
+                params![hash, now],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Count a failed extraction of `path`. A file that changed since its
+    /// last failure starts over.
+    pub fn record_failure(&self, path: &Path, error: &str) -> Result<u32> {
+        let (size, mtime) = file_stat(path)?;
+        let path = path.to_string_lossy();
+        let conn = self.pool.get()?;
+        let attempts = conn.query_row(
+            "INSERT INTO failed_files (path, file_size, mtime, error, attempts, last_attempt)
+            VALUES (?1, ?2, ?3, ?4, 1, ?5)
+            ON CONFLICT(path) DO UPDATE SET
+                attempts = CASE WHEN file_size = excluded.file_size AND mtime = excluded.mtime
+                    THEN attempts + 1 ELSE 1 END,
+                file_size = excluded.file_size,
+                mtime = excluded.mtime,
+                error = excluded.error,
+                last_attempt = excluded.last_attempt
+            RETURNING attempts",
+            params![path, size as i64, mtime, error, unix_now()],
+            |row| row.get(0),
+        )?;
+
+        Ok(attempts)
+    }
+
+    /// Size and mtime of every quarantined file when it last failed, by
+    /// path. Scans skip them while they're unchanged.
+    pub fn quarantined(&self) -> Result<HashMap<String, (u64, i64)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT path, file_size, mtime FROM failed_files WHERE attempts >= ?1")?;
+        let quarantined = stmt.query_map([QUARANTINE_AFTER], |row| {
+            Ok((row.get(0)?, (row.get::<_, i64>(1)? as u64, row.get(2)?)))
+        })?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(quarantined)
+    }
+
+    /// Every file that's failed at least once, most recent first
+    pub fn failed_files(&self) -> Result<Vec<FailedFile>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT path, error, attempts, last_attempt FROM failed_files ORDER BY last_attempt DESC"
+        )?;
+        let failed = stmt.query_map([], |row| Ok(FailedFile {
+            path: row.get(0)?,
+            error: row.get(1)?,
+            attempts: row.get(2)?,
+            last_attempt: row.get(3)?,
+        }))?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(failed)
+    }
+
+    /// Forget the failures of `path`, which the next scan tries again
+    pub fn clear_failure(&self, path: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute("DELETE FROM failed_files WHERE path = ?1", [path])?;
+
+        Ok(())
+    }
+
+    pub fn clear_failed_files(&self) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute("DELETE FROM failed_files", [])?;
+
+        Ok(())
+    }
+
+    pub fn vacuum(&self) -> Result<()> {
+        self.flush()?;
+        let conn = self.pool.get()?;
+        // The rewrite goes through the write-ahead log, which is emptied after
+        conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")?;
+
+        Ok(())
+    }
+
+    /// Unix time of the last maintenance run
+    pub fn last_maintenance(&self) -> Result<Option<i64>> {
+        let conn = self.pool.get()?;
+        let last = conn.query_row("SELECT MAX(ran_at) FROM maintenance_runs", [], |row| row.get(0))?;
+
+        Ok(last)
+    }
+
+    pub fn log_maintenance(&self, summary: &str) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO maintenance_runs (ran_at, summary) VALUES (?1, ?2)",
+            params![now, summary],
+        )?;
+
+        Ok(())
+    }
+
+    /// Every cached document, including ones no longer on disk, which are
+    /// flagged if a scan found them missing. A document found at several
+    /// paths is listed once for each.
+    pub fn all_metadata(&self) -> Result<Vec<PdfMetadata>> {
+        self.flush()?;
+        let missing = self.missing_hashes()?;
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM pdf_metadata ORDER BY path",
+            METADATA_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], metadata_from_row)?.collect::<Result<Vec<_>, _>>()?;
+        let mut copies: HashMap<String, Vec<(String, Option<i64>)>> = HashMap::new();
+        let mut stmt = conn.prepare(
+            "SELECT l.hash, l.path, l.mtime FROM locations l
+            JOIN pdf_metadata m ON m.hash = l.hash AND m.path != l.path
+            ORDER BY l.path"
+        )?;
+        for row in stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?)))? {
+            let (hash, path, mtime) = row?;
+            copies.entry(hash).or_default().push((path, mtime));
+        }
+
+        let mut results = Vec::with_capacity(rows.len());
+        for mut pdf in rows {
+            pdf.missing = missing.contains(&pdf.hash);
+            for (path, mtime) in copies.remove(&pdf.hash).unwrap_or_default() {
+                results.push(PdfMetadata { path, mtime, ..pdf.clone() });
+            }
+            results.push(pdf);
+        }
+        results.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(results)
+    }
+
+    /// The cached document at `path`, as it was seen by the last scan. A
+    /// copy comes with its own path and mtime.
+    pub fn get_by_path(&self, path: &str) -> Result<Option<PdfMetadata>> {
+        if let Some(queued) = self.pending_rows(|metadata| metadata.path == path).into_iter().next() {
+            return Ok(Some(queued));
+        }
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM pdf_metadata WHERE path = ?1",
+            METADATA_COLUMNS
+        ))?;
+
+        match stmt.query_row(params![path], metadata_from_row) {
+            Ok(metadata) => return Ok(Some(metadata)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let location = conn.query_row(
+            "SELECT hash, mtime FROM locations WHERE path = ?1",
+            params![path],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<i64>>(1)?)),
+        );
+        let (hash, mtime) = match location {
+            Ok(location) => location,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM pdf_metadata WHERE hash = ?1",
+            METADATA_COLUMNS
+        ))?;
+        match stmt.query_row(params![hash], metadata_from_row) {
+            Ok(metadata) => Ok(Some(PdfMetadata { path: path.to_string(), mtime, ..metadata })),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Every path a document was found at, its own first
+    pub fn locations(&self, hash: &str) -> Result<Vec<String>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT l.path FROM locations l LEFT JOIN pdf_metadata m ON m.path = l.path
+            WHERE l.hash = ?1 ORDER BY m.path IS NULL, l.path"
+        )?;
+        let paths = stmt.query_map([hash], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(paths)
+    }
+
+    /// Forget copies that aren't there any more. The path a document's row
+    /// has is kept, it goes with the row.
+    pub fn forget_locations(&self, paths: &[String]) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "DELETE FROM locations WHERE path = ?1 AND NOT EXISTS (SELECT 1 FROM pdf_metadata WHERE path = ?1)"
+            )?;
+            for path in paths {
+                stmt.execute([path])?;
+            }
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// The custom cover of the document with `hash`, if it has one
+    pub fn custom_cover(&self, hash: &str) -> Result<Option<String>> {
+        let conn = self.pool.get()?;
+        let result = conn.query_row("SELECT cover FROM custom_covers WHERE hash = ?1", [hash], |row| row.get(0));
+
+        match result {
+            Ok(cover) => Ok(Some(cover)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Custom covers by the hash of their document
+    pub fn custom_covers(&self) -> Result<HashMap<String, String>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT hash, cover FROM custom_covers")?;
+        let covers = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(covers)
+    }
+
+    /// Make `cover`, already saved, the cover of the document with `hash`
+    pub fn store_custom_cover(&self, hash: &str, cover: &str) -> Result<()> {
+        self.flush()?;
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute("INSERT OR REPLACE INTO custom_covers (hash, cover) VALUES (?1, ?2)", params![hash, cover])?;
+        tx.execute("UPDATE pdf_metadata SET cover_path = ?2 WHERE hash = ?1", params![hash, cover])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn record_cover_lookup(&self, hash: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO cover_lookups (hash, looked_up_at) VALUES (?1, ?2)",
+            params![hash, unix_now()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Hashes of the documents whose cover was looked up online
+    pub fn cover_lookups(&self) -> Result<HashSet<String>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT hash FROM cover_lookups")?;
+        let hashes = stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(hashes)
+    }
+
+    /// Let rescans render the cover of the document with `hash` again
+    pub fn remove_custom_cover(&self, hash: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute("DELETE FROM custom_covers WHERE hash = ?1", [hash])?;
+
+        Ok(())
+    }
+
+    /// Edit a field by hand, the value stays through rescans until the
+    /// override is removed
+    pub fn set_field(&self, hash: &str, field: MetadataField, value: Option<&str>) -> Result<()> {
+        self.flush()?;
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        // The first edit remembers what the field was
+        tx.execute(
+            &format!(
+                "INSERT INTO metadata_overrides (hash, field, value, original)
+                SELECT ?1, ?2, ?3, {} FROM pdf_metadata WHERE hash = ?1
+                ON CONFLICT (hash, field) DO UPDATE SET value = excluded.value",
+                field.column(),
+            ),
+            params![hash, field.column(), value],
+        )?;
+        tx.execute(
+            &format!("UPDATE pdf_metadata SET {} = ?2 WHERE hash = ?1", field.column()),
+            params![hash, value],
+        )?;
+        // Set by hand, a lookup's value is no longer there to revert
+        tx.execute("DELETE FROM metadata_changes WHERE hash = ?1 AND field = ?2", params![hash, field.column()])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// The fields of the document edited by hand, with what the file has
+    pub fn overrides(&self, hash: &str) -> Result<HashMap<MetadataField, Option<String>>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT field, original FROM metadata_overrides WHERE hash = ?1")?;
+        let rows = stmt.query_map([hash], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?;
+        let mut overrides = HashMap::new();
+        for row in rows {
+            let (column, original) = row?;
+            let Some(field) = MetadataField::from_column(&column) else { continue; };
+            overrides.insert(field, original);
+        }
+
+        Ok(overrides)
+    }
+
+    /// Put the edits made by hand over what was just read from the file,
+    /// which is kept to undo them
+    fn apply_overrides(&self, metadata: &mut PdfMetadata) -> Result<()> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT field, value FROM metadata_overrides WHERE hash = ?1")?;
+        let rows = stmt.query_map([&metadata.hash], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)))?;
+        let overrides = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        for (column, value) in overrides {
+            let Some(field) = MetadataField::from_column(&column) else { continue; };
+            conn.execute(
+                "UPDATE metadata_overrides SET original = ?3 WHERE hash = ?1 AND field = ?2",
+                params![metadata.hash, column, field.get(metadata)],
+            )?;
+            field.set(metadata, value);
+        }
+
+        Ok(())
+    }
+
+    /// Let go of the edits once the file has them, see `save_metadata_into_file`
+    fn clear_overrides(&self, hash: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute("DELETE FROM metadata_overrides WHERE hash = ?1", [hash])?;
+
+        Ok(())
+    }
+
+    /// Keep a looked up abstract for a document read again
+    fn store_abstract(&self, hash: &str, text: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute("UPDATE pdf_metadata SET abstract = ?2 WHERE hash = ?1", params![hash, text])?;
+
+        Ok(())
+    }
+
+    /// Undo the edit of a field, back to what the file has
+    pub fn remove_override(&self, hash: &str, field: MetadataField) -> Result<()> {
+        self.flush()?;
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            &format!(
+                "UPDATE pdf_metadata SET {} = (SELECT original FROM metadata_overrides WHERE hash = ?1 AND field = ?2)
+                WHERE hash = ?1 AND EXISTS (SELECT 1 FROM metadata_overrides WHERE hash = ?1 AND field = ?2)",
+                field.column(),
+            ),
+            params![hash, field.column()],
+        )?;
+        tx.execute("DELETE FROM metadata_overrides WHERE hash = ?1 AND field = ?2", params![hash, field.column()])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Fill in a field from an online lookup, remembering what it was.
+    /// False if the field was edited by hand, it's left as it is.
+    pub fn apply_metadata_change(&self, hash: &str, field: MetadataField, value: &str, source: &str) -> Result<bool> {
+        self.flush()?;
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let overridden: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM metadata_overrides WHERE hash = ?1 AND field = ?2)",
+            params![hash, field.column()],
+            |row| row.get(0),
+        )?;
+        if overridden { return Ok(false); }
+        let previous: Option<String> = tx.query_row(
+            &format!("SELECT {} FROM pdf_metadata WHERE hash = ?1", field.column()),
+            [hash],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            &format!("UPDATE pdf_metadata SET {} = ?2 WHERE hash = ?1", field.column()),
+            params![hash, value],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO metadata_changes (hash, field, value, previous, source, changed_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![hash, field.column(), value, previous, source, unix_now()],
+        )?;
+        tx.commit()?;
+
+        Ok(true)
+    }
+
+    /// Changes made by online lookups to documents still in the library,
+    /// the ones not reviewed yet first, newest first
+    pub fn metadata_changes(&self) -> Result<Vec<MetadataChange>> {
+        self.query_metadata_changes("", params![])
+    }
+
+    pub fn metadata_changes_of(&self, hash: &str) -> Result<Vec<MetadataChange>> {
+        self.query_metadata_changes("AND c.hash = ?1", params![hash])
+    }
+
+    fn query_metadata_changes<P: rusqlite::Params>(&self, condition: &str, params: P) -> Result<Vec<MetadataChange>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT c.hash, c.field, c.value, c.previous, c.source, c.changed_at, c.reviewed
+            FROM metadata_changes c JOIN pdf_metadata m ON m.hash = c.hash
+            WHERE 1 {} ORDER BY c.reviewed, c.changed_at DESC, c.hash, c.field",
+            condition,
+        ))?;
+        let rows = stmt.query_map(params, |row| Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+            row.get(6)?,
+        )))?;
+        let mut changes = Vec::new();
+        for row in rows {
+            let (hash, column, value, previous, source, changed_at, reviewed) = row?;
+            let Some(field) = MetadataField::from_column(&column) else { continue; };
+            changes.push(MetadataChange { hash, field, value, previous, source, changed_at, reviewed });
+        }
+
+        Ok(changes)
+    }
+
+    /// Keep a looked up value, it stays listed as where the value came from
+    pub fn keep_metadata_change(&self, hash: &str, field: MetadataField) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "UPDATE metadata_changes SET reviewed = 1 WHERE hash = ?1 AND field = ?2",
+            params![hash, field.column()],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn keep_metadata_changes(&self) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute("UPDATE metadata_changes SET reviewed = 1", [])?;
+
+        Ok(())
+    }
+
+    /// Put back what a lookup replaced. The document stays looked up, so
+    /// the value isn't filled in again.
+    pub fn revert_metadata_change(&self, hash: &str, field: MetadataField) -> Result<()> {
+        self.flush()?;
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            &format!(
+                "UPDATE pdf_metadata SET {} = (SELECT previous FROM metadata_changes WHERE hash = ?1 AND field = ?2)
+                WHERE hash = ?1 AND EXISTS (SELECT 1 FROM metadata_changes WHERE hash = ?1 AND field = ?2)",
+                field.column(),
+            ),
+            params![hash, field.column()],
+        )?;
+        tx.execute("DELETE FROM metadata_changes WHERE hash = ?1 AND field = ?2", params![hash, field.column()])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn record_metadata_lookup(&self, hash: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata_lookups (hash, looked_up_at) VALUES (?1, ?2)",
+            params![hash, unix_now()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Hashes of the documents whose metadata was looked up online
+    pub fn metadata_lookups(&self) -> Result<HashSet<String>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT hash FROM metadata_lookups")?;
+        let hashes = stmt.query_map([], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+
+        Ok(hashes)
+    }
+
+    /// Fill in the snippet of a document cached before snippets were extracted
+    pub fn store_snippet(&self, hash: &str, snippet: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "UPDATE pdf_metadata SET snippet = ?2 WHERE hash = ?1",
+            params![hash, snippet],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn pending_writes(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Write every queued metadata row in a single transaction
+    pub fn flush(&self) -> Result<()> {
+        let touched = std::mem::take(&mut *self.touched.lock().unwrap());
+        if !touched.is_empty() && let Err(e) = self.write_last_seen(&touched) {
+            self.touched.lock().unwrap().extend(touched);
+            return Err(e);
+        }
+
+        // Held while writing, so lookups find the rows in the queue or in
+        // the database, never in neither. Kept for the next attempt on errors.
+        let mut pending = self.pending.lock().unwrap();
+        if pending.is_empty() { return Ok(()); }
+        self.write_metadata(&pending)?;
+        pending.clear();
+
+        Ok(())
+    }
+
+    fn write_last_seen(&self, touched: &[Touch]) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare("UPDATE pdf_metadata SET last_seen = ?2 WHERE hash = ?1")?;
+            let mut locate = tx.prepare("INSERT OR REPLACE INTO locations (path, hash, mtime) VALUES (?1, ?2, ?3)")?;
+            for touch in touched {
+                stmt.execute(params![touch.hash, touch.seen])?;
+                locate.execute(params![touch.path, touch.hash, touch.mtime])?;
+            }
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn write_metadata(&self, batch: &[(PdfMetadata, u64)]) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for (metadata, seen) in batch {
+            tx.execute(
+                "INSERT OR REPLACE INTO pdf_metadata 
+                (hash, partial_hash, path, title, author, subject, keywords, creator, producer, 
+                 creation_date, modification_date, page_count, cover_path, file_size, last_seen, snippet, mtime, isbn,
+                 arxiv_id, abstract, heuristic_title, language)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19,
+                    COALESCE(?20, (SELECT abstract FROM pdf_metadata WHERE hash = ?1)), ?21, ?22)",
+                params![
+                    metadata.hash,
+                    metadata.partial_hash,
+                    metadata.path,
+                    metadata.title,
+                    metadata.author,
+                    metadata.subject,
+                    metadata.keywords,
+                    metadata.creator,
+                    metadata.producer,
+                    metadata.creation_date,
+                    metadata.modification_date,
+                    metadata.page_count,
+                    metadata.cover_path,
+                    metadata.file_size,
+                    seen,
+                    metadata.snippet,
+                    metadata.mtime,
+                    metadata.isbn,
+                    metadata.arxiv_id,
+                    metadata.abstract_text,
+                    metadata.heuristic_title,
+                    // Tried and not told, detect_languages needn't try again
+                    metadata.language.as_deref().or(metadata.snippet.is_some().then_some("")),
+                ],
+            )?;
+
+            tx.execute(
+                "INSERT OR IGNORE INTO library_additions (hash, added_at) VALUES (?1, ?2)",
+                params![metadata.hash, seen],
+            )?;
+            tx.execute(
+                "INSERT OR REPLACE INTO locations (path, hash, mtime) VALUES (?1, ?2, ?3)",
+                params![metadata.path, metadata.hash, metadata.mtime],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn get_last_page(&self, hash: &str) -> Result<Option<u32>> {
+        let conn = self.pool.get()?;
+        let result = conn.query_row(
+            "SELECT page FROM reading_progress WHERE hash = ?1",
+            params![hash],
+            |row| row.get(0),
+        );
+
+        match result {
+            Ok(page) => Ok(Some(page)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn store_last_page(&self, hash: &str, page: u32) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO reading_progress (hash, page, updated_at) VALUES (?1, ?2, ?3)",
+            params![hash, page, now],
+        )?;
+        conn.execute(
+            "INSERT INTO page_views (hash, page, day, viewed_at, views) VALUES (?1, ?2, ?3 / 86400, ?3, 1)
+            ON CONFLICT (hash, page, day) DO UPDATE SET views = views + 1",
+            params![hash, page, now],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_render_mode(&self, hash: &str) -> Result<Option<String>> {
+        let conn = self.pool.get()?;
+        let result = conn.query_row(
+            "SELECT render_mode FROM reader_preferences WHERE hash = ?1",
+            params![hash],
+            |row| row.get(0),
+        );
+
+        match result {
+            Ok(mode) => Ok(Some(mode)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn store_render_mode(&self, hash: &str, render_mode: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO reader_preferences (hash, render_mode) VALUES (?1, ?2)",
+            params![hash, render_mode],
+        )?;
+
+        Ok(())
+    }
+
+    /// Last viewed page of every document opened in the reader
+    pub fn all_last_pages(&self) -> Result<HashMap<String, u32>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT hash, page FROM reading_progress")?;
+        let results = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        results.collect::<Result<HashMap<_, _>, _>>().map_err(Into::into)
+    }
+
+    /// Distinct (hash, page) pairs viewed between the two unix timestamps
+    pub fn pages_viewed_between(&self, start: i64, end: i64) -> Result<Vec<(String, u32)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT hash, page FROM page_views WHERE viewed_at >= ?1 AND viewed_at < ?2"
+        )?;
+        let results = stmt.query_map(params![start, end], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        results.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Annotations of a document in page order, None if they haven't been extracted yet
+    pub fn get_annotations(&self, hash: &str) -> Result<Option<Vec<Annotation>>> {
+        let conn = self.pool.get()?;
+        let scanned = conn.query_row(
+            "SELECT 1 FROM annotation_scans WHERE hash = ?1",
+            params![hash],
+            |_| Ok(()),
+        );
+        match scanned {
+            Ok(()) => {}
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT page, kind, author, contents FROM annotations WHERE hash = ?1 ORDER BY page, rowid"
+        )?;
+        let results = stmt.query_map(params![hash], |row| {
+            Ok(Annotation {
+                page: row.get(0)?,
+                kind: row.get(1)?,
+                author: row.get(2)?,
+                contents: row.get(3)?,
+            })
+        })?;
+
+        results.collect::<Result<Vec<_>, _>>().map(Some).map_err(Into::into)
+    }
+
+    pub fn store_annotations(&self, hash: &str, annotations: &[Annotation]) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM annotations WHERE hash = ?1", params![hash])?;
+        for annotation in annotations {
+            tx.execute(
+                "INSERT INTO annotations (hash, page, kind, author, contents) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![hash, annotation.page, annotation.kind, annotation.author, annotation.contents],
+            )?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO annotation_scans (hash, scanned_at) VALUES (?1, ?2)",
+            params![hash, now],
+        )?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn record_open(&self, hash: &str) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO document_opens (hash, opened_at) VALUES (?1, ?2)",
+            params![hash, now],
+        )?;
+
+        Ok(())
+    }
+
+    /// How many times each document has been opened
+    pub fn open_counts(&self) -> Result<HashMap<String, u32>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT hash, COUNT(*) FROM document_opens GROUP BY hash")?;
+        let results = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        results.collect::<Result<HashMap<_, _>, _>>().map_err(Into::into)
+    }
+
+    /// Bookmarks of a document in page order
+    pub fn get_bookmarks(&self, hash: &str) -> Result<Vec<Bookmark>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT page, label FROM bookmarks WHERE hash = ?1 ORDER BY page"
+        )?;
+        let results = stmt.query_map(params![hash], |row| {
+            Ok(Bookmark { page: row.get(0)?, label: row.get(1)? })
+        })?;
+
+        results.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Bookmark `page`, relabelling the bookmark if it already has one
+    pub fn store_bookmark(&self, hash: &str, page: u32, label: &str) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO bookmarks (hash, page, label, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![hash, page, label, now],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn remove_bookmark(&self, hash: &str, page: u32) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "DELETE FROM bookmarks WHERE hash = ?1 AND page = ?2",
+            params![hash, page],
+        )?;
+
+        Ok(())
+    }
+
+    /// Every work with its preferred edition
+    pub fn works(&self) -> Result<Vec<(i64, String)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT id, preferred FROM works")?;
+        let results = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        results.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    pub fn work_editions(&self) -> Result<Vec<(i64, String)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT work_id, hash FROM work_editions")?;
+        let results = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        results.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Make the documents editions of one work. Works some of them already
+    /// belong to are merged, keeping the first one's preferred edition.
+    pub fn link_editions(&self, hashes: &[String]) -> Result<i64> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let mut existing: Vec<i64> = Vec::new();
+        for hash in hashes {
+            match tx.query_row("SELECT work_id FROM work_editions WHERE hash = ?1", params![hash], |row| row.get(0)) {
+                Ok(id) => if !existing.contains(&id) { existing.push(id); },
+                Err(rusqlite::Error::QueryReturnedNoRows) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let work_id = match existing.first() {
+            Some(id) => *id,
+            None => {
+                let Some(preferred) = hashes.first() else { anyhow::bail!("No editions given"); };
+                tx.execute("INSERT INTO works (preferred) VALUES (?1)", params![preferred])?;
+                tx.last_insert_rowid()
+            }
+        };
+        for other in existing.iter().skip(1) {
+            tx.execute("UPDATE work_editions SET work_id = ?1 WHERE work_id = ?2", params![work_id, other])?;
+            tx.execute("DELETE FROM works WHERE id = ?1", params![other])?;
+        }
+        for hash in hashes {
+            tx.execute(
+                "INSERT OR REPLACE INTO work_editions (hash, work_id) VALUES (?1, ?2)",
+                params![hash, work_id],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(work_id)
+    }
+
+    pub fn set_preferred_edition(&self, hash: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "UPDATE works SET preferred = ?1 WHERE id = (SELECT work_id FROM work_editions WHERE hash = ?1)",
+            params![hash],
+        )?;
+
+        Ok(())
+    }
+
+    /// Take a document out of its work. A work left with a single edition
+    /// is dissolved.
+    pub fn unlink_edition(&self, hash: &str) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let work_id: i64 = match tx.query_row("SELECT work_id FROM work_editions WHERE hash = ?1", params![hash], |row| row.get(0)) {
+            Ok(id) => id,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        tx.execute("DELETE FROM work_editions WHERE hash = ?1", params![hash])?;
+        let remaining: Vec<String> = {
+            let mut stmt = tx.prepare("SELECT hash FROM work_editions WHERE work_id = ?1 ORDER BY hash")?;
+            stmt.query_map(params![work_id], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?
+        };
+        if remaining.len() < 2 {
+            tx.execute("DELETE FROM work_editions WHERE work_id = ?1", params![work_id])?;
+            tx.execute("DELETE FROM works WHERE id = ?1", params![work_id])?;
+        } else {
+            tx.execute(
+                "UPDATE works SET preferred = ?1 WHERE id = ?2 AND preferred = ?3",
+                params![remaining[0], work_id, hash],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Paths of the files attached to a document, in the order they were added
+    pub fn get_attachments(&self, hash: &str) -> Result<Vec<String>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT path FROM attachments WHERE hash = ?1 ORDER BY added_at, path")?;
+        let results = stmt.query_map(params![hash], |row| row.get(0))?;
+
+        results.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Attachments of every document that has any, by hash
+    pub fn all_attachments(&self) -> Result<AttachmentsByHash> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT hash, path FROM attachments ORDER BY added_at, path")?;
+        let mut attachments = AttachmentsByHash::new();
+        for row in stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))? {
+            let (hash, path) = row?;
+            attachments.entry(hash).or_default().push(path);
+        }
+
+        Ok(attachments)
+    }
+
+    /// Returns false if the file was already attached
+    pub fn add_attachment(&self, hash: &str, path: &str) -> Result<bool> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let conn = self.pool.get()?;
+        let added = conn.execute(
+            "INSERT OR IGNORE INTO attachments (hash, path, added_at) VALUES (?1, ?2, ?3)",
+            params![hash, path, now],
+        )?;
+
+        Ok(added > 0)
+    }
+
+    pub fn remove_attachment(&self, hash: &str, path: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "DELETE FROM attachments WHERE hash = ?1 AND path = ?2",
+            params![hash, path],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns false if the document already had the tag
+    pub fn add_tag(&self, hash: &str, tag: &str) -> Result<bool> {
+        let conn = self.pool.get()?;
+        let added = conn.execute(
+            "INSERT OR IGNORE INTO tags (hash, tag) VALUES (?1, ?2)",
+            params![hash, tag],
+        )?;
+
+        Ok(added > 0)
+    }
+
+    /// Returns false if the document didn't have the tag
+    pub fn remove_tag(&self, hash: &str, tag: &str) -> Result<bool> {
+        let conn = self.pool.get()?;
+        let removed = conn.execute(
+            "DELETE FROM tags WHERE hash = ?1 AND tag = ?2",
+            params![hash, tag],
+        )?;
+
+        Ok(removed > 0)
+    }
+
+    pub fn get_tags(&self, hash: &str) -> Result<Vec<String>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT tag FROM tags WHERE hash = ?1 ORDER BY tag")?;
+        let results = stmt.query_map(params![hash], |row| row.get(0))?;
+
+        results.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Tags of every tagged document, by hash
+    pub fn all_tags(&self) -> Result<TagsByHash> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT hash, tag FROM tags ORDER BY tag")?;
+        let mut tags = TagsByHash::new();
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        for row in rows {
+            let (hash, tag) = row?;
+            tags.entry(hash).or_default().push(tag);
+        }
+
+        Ok(tags)
+    }
+
+    /// The custom fields of a document as key and value, by key
+    pub fn custom_fields(&self, hash: &str) -> Result<Vec<(String, String)>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT key, value FROM custom_fields WHERE hash = ?1 ORDER BY key")?;
+        let results = stmt.query_map(params![hash], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        results.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    pub fn all_custom_fields(&self) -> Result<CustomFieldsByHash> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT hash, key, value FROM custom_fields ORDER BY key")?;
+        let mut fields = CustomFieldsByHash::new();
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?)))?;
+        for row in rows {
+            let (hash, key, value) = row?;
+            fields.entry(hash).or_default().push((key, value));
+        }
+
+        Ok(fields)
+    }
+
+    /// Replace the custom fields of a document. Keys differing only in
+    /// case are the same field, the last one wins.
+    pub fn set_custom_fields(&self, hash: &str, fields: &[(String, String)]) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM custom_fields WHERE hash = ?1", params![hash])?;
+        for (key, value) in fields {
+            tx.execute(
+                "INSERT OR REPLACE INTO custom_fields (hash, key, value) VALUES (?1, ?2, ?3)",
+                params![hash, key, value],
+            )?;
         }
-        
-        Ok(Self { pool, cache_dir })
+        tx.commit()?;
+
+        Ok(())
     }
-    
-    pub fn get_by_partial_hash(&self, partial_hash: &str, file_size: u64) -> Result<Vec<PdfMetadata>> {
+
+    pub fn collections(&self) -> Result<Vec<Collection>> {
         let conn = self.pool.get()?;
-        let mut stmt = conn.prepare(
-            "SELECT * FROM pdf_metadata WHERE partial_hash = ?1 AND file_size = ?2"
-        )?;
-        
-        let results = stmt.query_map(params![partial_hash, file_size], |row| {
-            Ok(PdfMetadata {
-                hash: row.get(0)?,
-                partial_hash: row.get(1)?,
-                path: row.get(2)?,
-                title: row.get(3)?,
-                author: row.get(4)?,
-                subject: row.get(5)?,
-                keywords: row.get(6)?,
-                creator: row.get(7)?,
-                producer: row.get(8)?,
-                creation_date: row.get(9)?,
-                modification_date: row.get(10)?,
-                page_count: row.get(11)?,
-                cover_path: row.get(12)?,
-                file_size: row.get(13)?,
-            })
+        let mut stmt = conn.prepare("SELECT id, name, parent_id FROM collections ORDER BY name COLLATE NOCASE")?;
+        let results = stmt.query_map([], |row| {
+            Ok(Collection { id: row.get(0)?, name: row.get(1)?, parent: row.get(2)? })
         })?;
-        
+
         results.collect::<Result<Vec<_>, _>>().map_err(Into::into)
     }
-    
-    pub fn get_metadata(&self, hash: &str) -> Result<Option<PdfMetadata>> {
+
+    /// Documents directly in each collection, not counting sub-collections
+    pub fn collection_items(&self) -> Result<Vec<(i64, String)>> {
         let conn = self.pool.get()?;
-        let mut stmt = conn.prepare(
-            "SELECT * FROM pdf_metadata WHERE hash = ?1"
+        let mut stmt = conn.prepare("SELECT collection_id, hash FROM collection_items")?;
+        let results = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        results.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    pub fn create_collection(&self, name: &str, parent: Option<i64>) -> Result<i64> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "INSERT INTO collections (name, parent_id) VALUES (?1, ?2)",
+            params![name, parent],
         )?;
-        
-        let result = stmt.query_row(params![hash], |row| {
-            Ok(PdfMetadata {
-                hash: row.get(0)?,
-                partial_hash: row.get(1)?,
-                path: row.get(2)?,
-                title: row.get(3)?,
-                author: row.get(4)?,
-                subject: row.get(5)?,
-                keywords: row.get(6)?,
-                creator: row.get(7)?,
-                producer: row.get(8)?,
-                creation_date: row.get(9)?,
-                modification_date: row.get(10)?,
-                page_count: row.get(11)?,
-                cover_path: row.get(12)?,
-                file_size: row.get(13)?,
-            })
-        });
-        
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn rename_collection(&self, id: i64, name: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute("UPDATE collections SET name = ?2 WHERE id = ?1", params![id, name])?;
+
+        Ok(())
+    }
+
+    /// Move a collection under `parent`, or to the top level. Callers make
+    /// sure this doesn't create a cycle.
+    pub fn move_collection(&self, id: i64, parent: Option<i64>) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute("UPDATE collections SET parent_id = ?2 WHERE id = ?1", params![id, parent])?;
+
+        Ok(())
+    }
+
+    /// Delete collections along with what they contain, documents themselves
+    /// stay in the library
+    pub fn delete_collections(&self, ids: &[i64]) -> Result<()> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        for id in ids {
+            tx.execute("DELETE FROM collection_shares WHERE collection_id = ?1", params![id])?;
+            tx.execute("DELETE FROM access_token_collections WHERE collection_id = ?1", params![id])?;
+            tx.execute("DELETE FROM collection_items WHERE collection_id = ?1", params![id])?;
+            tx.execute("DELETE FROM collections WHERE id = ?1", params![id])?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn share_token(&self, collection_id: i64) -> Result<Option<String>> {
+        let conn = self.pool.get()?;
+        let result = conn.query_row(
+            "SELECT token FROM collection_shares WHERE collection_id = ?1",
+            params![collection_id],
+            |row| row.get(0),
+        );
+
         match result {
-            Ok(metadata) => Ok(Some(metadata)),
+            Ok(token) => Ok(Some(token)),
             Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
             Err(e) => Err(e.into()),
         }
     }
-    
-    pub fn store_metadata(&self, metadata: &PdfMetadata) -> Result<()> {
+
+    pub fn store_share(&self, token: &str, collection_id: i64) -> Result<()> {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)?
             .as_secs();
-        
+
         let conn = self.pool.get()?;
         conn.execute(
-            "INSERT OR REPLACE INTO pdf_metadata 
-            (hash, partial_hash, path, title, author, subject, keywords, creator, producer, 
-             creation_date, modification_date, page_count, cover_path, file_size, last_seen)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
-            params![
-                metadata.hash,
-                metadata.partial_hash,
-                metadata.path,
-                metadata.title,
-                metadata.author,
-                metadata.subject,
-                metadata.keywords,
-                metadata.creator,
-                metadata.producer,
-                metadata.creation_date,
-                metadata.modification_date,
-                metadata.page_count,
-                metadata.cover_path,
-                metadata.file_size,
-                now,
-            ],
+            "INSERT INTO collection_shares (token, collection_id, created_at) VALUES (?1, ?2, ?3)",
+            params![token, collection_id, now],
         )?;
-        
+
+        Ok(())
+    }
+
+    pub fn remove_share(&self, collection_id: i64) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute("DELETE FROM collection_shares WHERE collection_id = ?1", params![collection_id])?;
+
+        Ok(())
+    }
+
+    /// The collection published under `token`
+    pub fn shared_collection(&self, token: &str) -> Result<Option<i64>> {
+        let conn = self.pool.get()?;
+        let result = conn.query_row(
+            "SELECT collection_id FROM collection_shares WHERE token = ?1",
+            params![token],
+            |row| row.get(0),
+        );
+
+        match result {
+            Ok(id) => Ok(Some(id)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether anything is shared, by collection or by access token
+    pub fn has_shares(&self) -> Result<bool> {
+        let conn = self.pool.get()?;
+        let count: u32 = conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM collection_shares) + (SELECT COUNT(*) FROM access_tokens)",
+            [],
+            |row| row.get(0),
+        )?;
+
+        Ok(count > 0)
+    }
+
+    pub fn access_tokens(&self) -> Result<Vec<AccessToken>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT token, name, whole_library, download FROM access_tokens ORDER BY name")?;
+        let tokens = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, bool>(2)?, row.get::<_, bool>(3)?))
+        })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare("SELECT collection_id FROM access_token_collections WHERE token = ?1 ORDER BY collection_id")?;
+        let mut access_tokens = Vec::with_capacity(tokens.len());
+        for (token, name, whole_library, download) in tokens {
+            let collections = if whole_library {
+                None
+            } else {
+                Some(stmt.query_map(params![token], |row| row.get(0))?.collect::<rusqlite::Result<Vec<i64>>>()?)
+            };
+            access_tokens.push(AccessToken { token, name, collections, download });
+        }
+
+        Ok(access_tokens)
+    }
+
+    pub fn access_token(&self, token: &str) -> Result<Option<AccessToken>> {
+        Ok(self.access_tokens()?.into_iter().find(|access| access.token == token))
+    }
+
+    pub fn store_access_token(&self, access: &AccessToken) -> Result<()> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO access_tokens (token, name, whole_library, download, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![access.token, access.name, access.collections.is_none(), access.download, now],
+        )?;
+        for id in access.collections.iter().flatten() {
+            tx.execute(
+                "INSERT OR IGNORE INTO access_token_collections (token, collection_id) VALUES (?1, ?2)",
+                params![access.token, id],
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Revoke a token by its name or its value. Returns false if there was
+    /// no such token.
+    pub fn remove_access_token(&self, name_or_token: &str) -> Result<bool> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let token: Option<String> = match tx.query_row(
+            "SELECT token FROM access_tokens WHERE name = ?1 OR token = ?1",
+            params![name_or_token],
+            |row| row.get(0),
+        ) {
+            Ok(token) => Some(token),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let Some(token) = token else { return Ok(false); };
+        tx.execute("DELETE FROM access_token_collections WHERE token = ?1", params![token])?;
+        tx.execute("DELETE FROM access_tokens WHERE token = ?1", params![token])?;
+        tx.commit()?;
+
+        Ok(true)
+    }
+
+    /// Returns false if the document was already in the collection
+    pub fn add_to_collection(&self, id: i64, hash: &str) -> Result<bool> {
+        let conn = self.pool.get()?;
+        let added = conn.execute(
+            "INSERT OR IGNORE INTO collection_items (collection_id, hash) VALUES (?1, ?2)",
+            params![id, hash],
+        )?;
+
+        Ok(added > 0)
+    }
+
+    pub fn remove_from_collection(&self, id: i64, hash: &str) -> Result<()> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "DELETE FROM collection_items WHERE collection_id = ?1 AND hash = ?2",
+            params![id, hash],
+        )?;
+
         Ok(())
     }
+
+    /// Hashes of documents first seen between the two unix timestamps,
+    /// without those there before additions were recorded
+    pub fn added_between(&self, start: i64, end: i64) -> Result<Vec<String>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT hash FROM library_additions WHERE added_at >= ?1 AND added_at < ?2"
+        )?;
+        let results = stmt.query_map(params![start, end], |row| row.get(0))?;
+
+        results.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+}
+
+impl Drop for PdfCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            error!("Failed to write cached metadata: {}", e);
+        }
+    }
+}
+
+// What the user did with a document rather than what was read from it.
+// Annotations and the rest are read again.
+const CARRIED_OVER_TABLES: [&str; 16] = [
+    "reading_progress", "reader_preferences", "page_views", "library_additions", "document_opens",
+    "bookmarks", "tags", "collection_items", "work_editions", "attachments", "custom_covers",
+    "cover_lookups", "metadata_changes", "metadata_lookups", "metadata_overrides", "custom_fields",
+];
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// `date` as a PDF date, "D:YYYYMMDDHHmmSS" or as much of it as is known.
+/// Takes dates already in that form, ISO 8601 ones like "2019-05-12" or
+/// "2019-05-12T09:30:00", a bare year, and ones with the month written out
+/// like "12 May 2019" or "May 2019". None for anything else, including
+/// "05/12/2019", which could be either day first or month first.
+fn pdf_date(date: &str) -> Option<String> {
+    if let Some(digits) = date.strip_prefix("D:") {
+        let digits = digits.get(..digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len()))?;
+        return (digits.len() >= 4 && digits.len() % 2 == 0).then(|| date.to_string());
+    }
+
+    // Runs of digits and of letters, "12T09" is three of them
+    let mut tokens: Vec<&str> = Vec::new();
+    let mut start = None;
+    for (index, c) in date.char_indices().chain(std::iter::once((date.len(), ' '))) {
+        let kind = if c.is_ascii_digit() { 1 } else if c.is_alphabetic() { 2 } else { 0 };
+        if let Some((from, previous)) = start && previous != kind {
+            tokens.push(&date[from..index]);
+            start = None;
+        }
+        if kind != 0 && start.is_none() { start = Some((index, kind)); }
+    }
+    let (numbers, words): (Vec<&str>, Vec<&str>) = tokens.iter().partition(|token| token.chars().all(|c| c.is_ascii_digit()));
+    let month_name = words.iter()
+        .filter(|word| word.len() >= 3)
+        .find_map(|token| MONTHS.iter().position(|month| token.to_lowercase().starts_with(month)));
+
+    // Year, month, day, hour, minute and second, as far as known
+    let parts: Vec<u32> = match month_name {
+        Some(month) => {
+            let year = numbers.iter().find(|number| number.len() == 4)?;
+            let days: Vec<&str> = numbers.iter().copied().filter(|number| number.len() <= 2).collect();
+            if numbers.len() > 2 || days.len() > 1 { return None; }
+            let mut parts = vec![year.parse().ok()?, month as u32 + 1];
+            if let Some(day) = days.first() { parts.push(day.parse().ok()?); }
+            parts
+        }
+        None if numbers.first().is_some_and(|year| year.len() == 4) && words.iter().all(|word| matches!(*word, "T" | "Z")) => {
+            numbers.iter().take(6).map(|number| number.parse().ok()).collect::<Option<_>>()?
+        }
+        None => return None,
+    };
+    let ranges = [1..=9999, 1..=12, 1..=31, 0..=23, 0..=59, 0..=59];
+    if parts.iter().zip(ranges).any(|(part, range)| !range.contains(part)) { return None; }
+    let mut pdf = format!("D:{:04}", parts[0]);
+    for part in &parts[1..] {
+        pdf.push_str(&format!("{:02}", part));
+    }
+    Some(pdf)
+}
+
+/// Put `fields` into the Info dictionary of the PDF at `path`, None removes
+/// one. Appended to the file when MuPDF can, like PDF editors do, and
+/// otherwise written out in full next to it and moved over it.
+fn write_info(path: &Path, fields: &[(MetadataField, Option<String>)]) -> Result<()> {
+    let name = path.to_str().context("Path is not valid UTF-8")?;
+    let mut document = PdfDocument::open(name)?;
+    let mut trailer = document.trailer()?;
+    let mut info = match trailer.get_dict("Info")? {
+        Some(info) => info,
+        None => {
+            let info = document.add_object(&document.new_dict()?)?;
+            trailer.dict_put("Info", info.try_clone()?)?;
+            info
+        }
+    };
+    for (field, value) in fields {
+        let Some(key) = field.info_key() else { continue; };
+        match value.as_deref().map(str::trim).filter(|value| !value.is_empty()) {
+            // Readers drop or misread dates not written the PDF way
+            Some(value) if *field == MetadataField::CreationDate => {
+                let date = pdf_date(value).with_context(|| format!("\"{}\" isn't a date that can be saved into the file", value))?;
+                info.dict_put(key, PdfObject::new_string(&date)?)?
+            }
+            Some(value) => info.dict_put(key, PdfObject::new_string(value)?)?,
+            None => info.dict_delete(key)?,
+        }
+    }
+
+    let mut options = PdfWriteOptions::default();
+    if document.can_be_saved_incrementally() {
+        options.set_incremental(true);
+        document.save_with_options(name, options)?;
+        return Ok(());
+    }
+    // Hidden, so the scan and the folder watcher leave it alone
+    let file_name = path.file_name().context("Path has no file name")?.to_string_lossy();
+    let temporary = path.with_file_name(format!(".{}.shelf-save", file_name));
+    let temporary_name = temporary.to_str().context("Path is not valid UTF-8")?;
+    let saved = document.save_with_options(temporary_name, options).map_err(anyhow::Error::from)
+        .and_then(|()| Ok(std::fs::set_permissions(&temporary, std::fs::metadata(path)?.permissions())?))
+        .and_then(|()| Ok(std::fs::rename(&temporary, path)?));
+    if saved.is_err() { let _ = std::fs::remove_file(&temporary); }
+    saved.with_context(|| format!("Failed to save {}", path.display()))
+}
+
+/// Write the title, author, subject, keywords and date of the document,
+/// as Shelf shows them, into its file so they go wherever it goes, then read
+/// it again. What's kept by hash moves to the file's new hash, and the
+/// edits made by hand are let go, the file has them now.
+pub fn save_metadata_into_file(metadata: &PdfMetadata, cache: &PdfCache) -> Result<PdfMetadata> {
+    let path = Path::new(&metadata.path);
+    let copies: Vec<String> = cache.locations(&metadata.hash)?.into_iter().filter(|copy| *copy != metadata.path).collect();
+    if let Some(copy) = copies.first() {
+        bail!("The same file is also at {}, saving into one copy would tell them apart", copy);
+    }
+    let fields: Vec<(MetadataField, Option<String>)> = MetadataField::ALL.into_iter()
+        .map(|field| (field, field.get(metadata).map(str::to_string)))
+        .collect();
+    write_info(path, &fields)?;
+
+    let hash = compute_full_hash(path)?;
+    if hash != metadata.hash { cache.carry_over(&metadata.hash, &hash)?; }
+    cache.clear_overrides(&hash)?;
+    let mut updated = reextract_pdf_metadata(path, cache)?;
+    if let Some(text) = &metadata.abstract_text {
+        cache.store_abstract(&updated.hash, text)?;
+        updated.abstract_text = Some(text.clone());
+    }
+    if updated.hash != metadata.hash { cache.remove_metadata(std::slice::from_ref(&metadata.hash))?; }
+    cache.flush()?;
+    Ok(updated)
+}
+
+// Links, form fields and the popups attached to notes aren't anything a reader wrote
+const SKIPPED_ANNOTATIONS: [&str; 3] = ["Link", "Widget", "Popup"];
+
+/// Read the notes and markup annotations stored in a PDF
+pub fn extract_annotations(path: &Path) -> Result<Vec<Annotation>> {
+    let path = path.to_str().context("Path is not valid UTF-8")?;
+    let document = PdfDocument::open(path)?;
+
+    let mut annotations = Vec::new();
+    for page_no in 0..document.page_count()? {
+        let page = PdfPage::try_from(document.load_page(page_no)?)?;
+        let Some(annots) = page.object().get_dict("Annots")? else { continue; };
+        for i in 0..annots.len()? {
+            let Some(annot) = annots.get_array(i as i32)? else { continue; };
+            let Some(subtype) = annot.get_dict("Subtype")? else { continue; };
+            let kind = String::from_utf8_lossy(subtype.as_name()?).into_owned();
+            if SKIPPED_ANNOTATIONS.contains(&kind.as_str()) { continue; }
+
+            let text = |key: &str| -> Result<Option<String>> {
+                let Some(value) = annot.get_dict(key)? else { return Ok(None); };
+                let value = value.as_string()?.trim();
+                Ok((!value.is_empty()).then(|| value.to_string()))
+            };
+            annotations.push(Annotation {
+                page: page_no as u32,
+                kind,
+                author: text("T")?,
+                contents: text("Contents")?,
+            });
+        }
+    }
+
+    Ok(annotations)
 }
 
 /// Compute partial hash from:
@@ -197,6 +2838,25 @@ pub fn extract_pdf_metadata(
     cache: &PdfCache,
     tx: &async_channel::Sender<ScanProgress>,
 ) -> Result<PdfMetadata> {
+    // Step 0: Same size and mtime as last time, nothing needs reading
+    let (size, mtime) = file_stat(path)?;
+    if let Some(cached) = cache.get_by_path(&path.to_string_lossy())?
+        && cached.mtime == Some(mtime)
+        && cached.file_size == size {
+        // Only the metadata was read, e.g. while it was on a network mount
+        if cached.cover_path.is_none() && cached.page_count > 0 && !cache.is_metadata_only(path) {
+            return read_pdf_metadata(path, cached.partial_hash, size, mtime, cache);
+        }
+        // Rendered with an older render scale, quality or format
+        if cached.cover_path.as_ref().is_some_and(|name| *name != new_cover_name(&cached.hash))
+            && !cache.is_metadata_only(path)
+            && cache.custom_cover(&cached.hash)?.is_none() {
+            return rerender_cover(cached, cache);
+        }
+        cache.touch(&cached.hash, &cached.path, cached.mtime)?;
+        return Ok(cached);
+    }
+
     // Step 1: Compute fast partial hash
     let (partial_hash, file_size) = compute_partial_hash(path)?;
     
@@ -211,94 +2871,405 @@ pub fn extract_pdf_metadata(
             let full_hash = compute_full_hash(path)?;
             for cached in cached_matches {
                 if cached.hash == full_hash {
-                    // Exact match found - update path if changed
-                    if cached.path != path.to_string_lossy() {
-                        let _ = tx.send_blocking(ScanProgress::DuplicateDetected(
-                            PathBuf::from(&cached.path),
-                            path.to_path_buf(),
-                        ));
-                    }
-                    
-                    // Return cached metadata with updated path
-                    let mut updated = cached.clone();
-                    updated.path = path.to_string_lossy().to_string();
-                    cache.store_metadata(&updated)?;
-                    return Ok(updated);
+                    return found_again(cached, path, mtime, cache, tx);
                 }
             }
         } else {
-            return Ok(first_hit); 
+            return found_again(first_hit, path, mtime, cache, tx);
         }
     }
     
-    println!("New file detected - {}", path.display());
-    // Step 4: No cache hit - extract metadata from PDF
-    let document = Document::open(path).unwrap();
-    let page_count = document.page_count().unwrap() as u32;
+    debug!("New file detected - {}", path.display());
+    read_pdf_metadata(path, partial_hash, file_size, mtime, cache)
+}
+
+/// A cached document found at `path`, whose file has the same contents. A
+/// copy becomes another location of it, a file that moved takes its row along.
+fn found_again(
+    cached: PdfMetadata,
+    path: &Path,
+    mtime: i64,
+    cache: &PdfCache,
+    tx: &async_channel::Sender<ScanProgress>,
+) -> Result<PdfMetadata> {
+    let path_str = path.to_string_lossy().to_string();
+    if cached.path == path_str {
+        if cached.mtime == Some(mtime) {
+            return Ok(cached);
+        }
+        // Cached before mtimes were, or touched without changing
+        let updated = PdfMetadata { mtime: Some(mtime), ..cached };
+        cache.store_metadata(&updated)?;
+        return Ok(updated);
+    }
+
+    if Path::new(&cached.path).exists() {
+        let _ = tx.send_blocking(ScanProgress::DuplicateDetected(
+            PathBuf::from(&cached.path),
+            path.to_path_buf(),
+        ));
+        cache.touch(&cached.hash, &path_str, Some(mtime))?;
+        return Ok(PdfMetadata { path: path_str, mtime: Some(mtime), ..cached });
+    }
+
+    let updated = PdfMetadata { path: path_str, mtime: Some(mtime), ..cached };
+    cache.store_metadata(&updated)?;
+    Ok(updated)
+}
+
+/// Read everything from the file again, whatever is cached for it, e.g.
+/// after it was replaced in place or its embedded metadata was fixed
+pub fn reextract_pdf_metadata(path: &Path, cache: &PdfCache) -> Result<PdfMetadata> {
+    let (_, mtime) = file_stat(path)?;
+    let (partial_hash, file_size) = compute_partial_hash(path)?;
+    read_pdf_metadata(path, partial_hash, file_size, mtime, cache)
+}
+
+/// What MuPDF reads from a document for the cache
+struct DocumentContents {
+    page_count: u32,
+    title: Option<String>,
+    author: Option<String>,
+    subject: Option<String>,
+    keywords: Option<String>,
+    creator: Option<String>,
+    producer: Option<String>,
+    creation_date: Option<String>,
+    modification_date: Option<String>,
+    snippet: Option<String>,
+    isbn: Option<String>,
+    arxiv_id: Option<String>,
+    heuristic_title: Option<String>,
+    cover: Option<RgbImage>,
+}
+
+/// The first page as it's saved in the covers directory
+fn render_cover(page: &mupdf::Page) -> Result<RgbImage> {
+    let bounds = page.bounds()?;
+    // Never larger than render_scale times 72 DPI, scanned books can have
+    // pages of several metres
+    let render_scale = COVER_ENCODING.lock().unwrap().render_scale;
+    let scale = (COVER_RENDER_SIZE * render_scale / bounds.width().max(bounds.height()).max(1.0)).min(render_scale);
+    let matrix = Matrix::new_scale(scale, scale);
+
+    // Render page to pixmap and convert it to an image, callers hold a
+    // PIXMAP_SLOTS slot
+    let image = {
+        let pixmap = page.to_pixmap(&matrix, &mupdf::Colorspace::device_rgb(), false, true)?;
+        RgbImage::from_raw(pixmap.width(), pixmap.height(), pixmap.samples().to_vec())
+            .context("Failed to create image from pixmap")?
+    };
+    Ok(fit_cover(image))
+}
+
+/// Scale `image` down to at most COVER_SAVED_SIZE
+fn fit_cover(image: RgbImage) -> RgbImage {
+    let (width, height) = image.dimensions();
+    let saved_scale = (COVER_SAVED_SIZE as f32 / width.max(height).max(1) as f32).min(1.0);
+    if saved_scale < 1.0 {
+        let saved_width = ((width as f32 * saved_scale).round() as u32).max(1);
+        let saved_height = ((height as f32 * saved_scale).round() as u32).max(1);
+        image::imageops::thumbnail(&image, saved_width, saved_height)
+    } else {
+        image
+    }
+}
+
+/// Make the image at `image` the cover of the document with `hash`, saved
+/// like a rendered one. Returns the name of the new cover.
+pub fn set_custom_cover(hash: &str, image: &Path, cache: &PdfCache) -> Result<String> {
+    let decoded = image::open(image).with_context(|| format!("Failed to read {}", image.display()))?;
+    save_custom_cover(hash, &decoded, cache)
+}
+
+/// Save `image` as the custom cover of the document with `hash`
+pub fn save_custom_cover(hash: &str, image: &image::DynamicImage, cache: &PdfCache) -> Result<String> {
+    let extension = COVER_ENCODING.lock().unwrap().format.extension();
+    // Named after when it was set, so a cover replaced again isn't served
+    // from a texture cache
+    let name = format!("{}{}{}.{}", &hash[..16], CUSTOM_COVER_MARKER, unix_now(), extension);
+    save_cover(&fit_cover(image.to_rgb8()), &name)?;
+    cache.store_custom_cover(hash, &name)?;
+    Ok(name)
+}
+
+/// Go back to the first page as the cover of `metadata`, rendered again.
+/// The custom cover is left to maintenance.
+pub fn remove_custom_cover(metadata: PdfMetadata, cache: &PdfCache) -> Result<PdfMetadata> {
+    cache.remove_custom_cover(&metadata.hash)?;
+    let updated = rerender_cover(metadata, cache)?;
+    cache.flush()?;
+    Ok(updated)
+}
+
+/// Render the cover of an unchanged document again, under the name the
+/// current settings give it. The old one is left to maintenance.
+pub fn rerender_cover(metadata: PdfMetadata, cache: &PdfCache) -> Result<PdfMetadata> {
+    let name = new_cover_name(&metadata.hash);
+    save_cover(&render_cover_of(&metadata.path, cache)?, &name)?;
+    let updated = PdfMetadata { cover_path: Some(name), ..metadata };
+    cache.store_metadata(&updated)?;
+    Ok(updated)
+}
+
+/// Render the cover of a document again after maintenance evicted it, under
+/// the name its row already has
+pub fn restore_cover(metadata: &PdfMetadata, cache: &PdfCache) -> Result<()> {
+    let name = metadata.cover_path.as_deref().context("The document has no cover")?;
+    if cache.custom_cover(&metadata.hash)?.as_deref() == Some(name) {
+        bail!("The custom cover was deleted, the first page would replace it");
+    }
+    save_cover(&render_cover_of(&metadata.path, cache)?, name)
+}
+
+fn render_cover_of(path: &str, cache: &PdfCache) -> Result<RgbImage> {
+    let _render_slot = cache.renders.acquire()?;
+    let _pixmap_slot = PIXMAP_SLOTS.acquire()?;
+    let document = Document::open(path)?;
+    let page = document.load_page(0)?;
+    render_cover(&page)
+}
+
+fn read_document(path: &Path, max_render_dimension: Option<u32>, metadata_only: bool) -> Result<DocumentContents> {
+    let document = Document::open(path)?;
+    let page_count = document.page_count()? as u32;
     // let format = document.metadata(MetadataName::Format).ok();
     // let encryption = document.metadata(MetadataName::Encryption).ok();
-    let author = document.metadata(MetadataName::Author).ok();
+    // Pages are what's slow to fetch from a network mount
+    let snippet = (!metadata_only).then(|| extract_snippet(&document).unwrap_or_else(|e| {
+        warn!("Failed to extract text from {}: {}", path.display(), e);
+        String::new()
+    }));
+
+    let isbn = if metadata_only { None } else {
+        find_isbn_in(&document).unwrap_or_else(|e| {
+            warn!("Failed to look for an ISBN in {}: {}", path.display(), e);
+            None
+        })
+    };
+    // arXiv prints it in the margin of the first page
+    let arxiv_id = if metadata_only || page_count == 0 { None } else {
+        document.load_page(0).and_then(|page| page.to_text()).map(|text| lookup::find_arxiv_id(&text)).unwrap_or_else(|e| {
+            warn!("Failed to look for an arXiv ID in {}: {}", path.display(), e);
+            None
+        })
+    };
+
     let title = document.metadata(MetadataName::Title).ok();
-    let producer = document.metadata(MetadataName::Producer).ok();
-    let creator = document.metadata(MetadataName::Creator).ok();
-    let creation_date = document.metadata(MetadataName::CreationDate).ok();
-    let modification_date = document.metadata(MetadataName::ModDate).ok();
-    let subject = document.metadata(MetadataName::Subject).ok();
-    let keywords = document.metadata(MetadataName::Keywords).ok();
-    
-    // Compute full hash now (we need it for unique identification)
-    let full_hash = compute_full_hash(path)?;
-    
+    let junk_title = title.as_deref().is_none_or(is_junk_title);
+    let heuristic_title = if metadata_only || page_count == 0 || !junk_title { None } else {
+        document.load_page(0).map_err(anyhow::Error::from).and_then(|page| largest_text_on(&page)).unwrap_or_else(|e| {
+            warn!("Failed to look for a title on the first page of {}: {}", path.display(), e);
+            None
+        })
+    };
+
     // Step 5: Extract cover image
-    let cover_path = if page_count > 0 {
+    let cover = if page_count > 0 && !metadata_only {
         let page = document.load_page(0)?;
         
-        // Calculate scale from DPI (default PDF is 72 DPI)
-        let scale = 1.0;
-        let matrix = Matrix::new_scale(scale, scale);
-        
-        // Render page to pixmap
-        let pixmap = page.to_pixmap(&matrix, &mupdf::Colorspace::device_rgb(), false, true)?;
-        
-        // Convert to image and save
-        let width = pixmap.width() as u32;
-        let height = pixmap.height() as u32;
-        let samples = pixmap.samples();
-        
-        let image = RgbImage::from_raw(width, height, samples.to_vec())
-            .context("Failed to create image from pixmap")?;
-         
-        let cover_filename = format!("{}.jpg", &full_hash[..16]);
-        let cover_full_path = cache.cache_dir.join("covers").join(&cover_filename);
-        
-        image.save(&cover_full_path)?;
-        Some(cover_filename)
+        // At 72 DPI, the size of the page in points
+        let bounds = page.bounds()?;
+        if let Some(max) = max_render_dimension
+            && (bounds.width() > max as f32 || bounds.height() > max as f32) {
+            bail!("The first page is {:.0}x{:.0} px, larger than max_render_dimension", bounds.width(), bounds.height());
+        }
+
+        Some(render_cover(&page)?)
     } else {
         None
     };
+
+    Ok(DocumentContents {
+        page_count,
+        title,
+        author: document.metadata(MetadataName::Author).ok(),
+        subject: document.metadata(MetadataName::Subject).ok(),
+        keywords: document.metadata(MetadataName::Keywords).ok(),
+        creator: document.metadata(MetadataName::Creator).ok(),
+        producer: document.metadata(MetadataName::Producer).ok(),
+        creation_date: document.metadata(MetadataName::CreationDate).ok(),
+        modification_date: document.metadata(MetadataName::ModDate).ok(),
+        snippet,
+        isbn,
+        arxiv_id,
+        heuristic_title,
+        cover,
+    })
+}
+
+// Whether a read finished or was given up on first, decided under one lock
+#[derive(Default)]
+struct ReadState {
+    done: bool,
+    abandoned: bool,
+}
+
+/// Read the document under its render and pixmap slots, on a thread of its
+/// own so a file MuPDF gets stuck on can be given up on. The thread can't be
+/// stopped: it keeps its slots until MuPDF returns, counted as hung so that
+/// later reads don't wait on them forever.
+fn read_document_with_timeout(path: &Path, limits: &ExtractionLimits, metadata_only: bool, slots: &Arc<RenderSlots>) -> Result<DocumentContents> {
+    let max_render_dimension = limits.max_render_dimension;
+    // Taken before the clock starts, waiting for them isn't the file's fault
+    let render_slot = slots.acquire()?;
+    let pixmap_slot = if metadata_only { None } else { Some(PIXMAP_SLOTS.acquire()?) };
+    let Some(timeout) = limits.timeout else {
+        return read_document(path, max_render_dimension, metadata_only);
+    };
+    let (tx, rx) = std::sync::mpsc::channel();
+    let state = Arc::new(Mutex::new(ReadState::default()));
+    let owned_path = path.to_path_buf();
+    let thread_state = state.clone();
+    std::thread::Builder::new()
+        .name("shelf-extract".to_string())
+        .spawn(move || {
+            let contents = read_document(&owned_path, max_render_dimension, metadata_only);
+            let mut state = thread_state.lock().unwrap();
+            state.done = true;
+            if state.abandoned {
+                for slot in std::iter::once(&render_slot).chain(pixmap_slot.as_ref()) {
+                    slot.0.set_hung(false);
+                }
+            }
+            drop(state);
+            drop((render_slot, pixmap_slot));
+            let _ = tx.send(contents);
+        })?;
+    match rx.recv_timeout(timeout) {
+        Ok(contents) => contents,
+        Err(RecvTimeoutError::Timeout) => {
+            let mut state = state.lock().unwrap();
+            // Done just now, the result is on its way
+            if state.done {
+                drop(state);
+                return rx.recv().unwrap_or_else(|_| bail!("MuPDF crashed reading the file"));
+            }
+            state.abandoned = true;
+            slots.set_hung(true);
+            if !metadata_only { PIXMAP_SLOTS.set_hung(true); }
+            bail!("Gave up after {}", human_readable_duration(timeout))
+        }
+        Err(RecvTimeoutError::Disconnected) => bail!("MuPDF crashed reading the file"),
+    }
+}
+
+fn read_pdf_metadata(path: &Path, partial_hash: String, file_size: u64, mtime: i64, cache: &PdfCache) -> Result<PdfMetadata> {
+    // Step 4: No cache hit - extract metadata from PDF
+    let limits = cache.limits.lock().unwrap().clone();
+    if let Some(max) = limits.max_file_size
+        && file_size > max {
+        bail!("{} is larger than max_file_size_mb", human_readable_file_size(file_size));
+    }
+    let metadata_only = cache.is_metadata_only(path);
+    let contents = read_document_with_timeout(path, &limits, metadata_only, &cache.renders)?;
+
+    // Compute full hash now (we need it for unique identification)
+    let full_hash = compute_full_hash(path)?;
+
+    // A custom cover wins over the first page, unless its file was deleted
+    // while the document was gone from the library
+    let custom_cover = cache.custom_cover(&full_hash)?.filter(|name| full_cover_file(name).exists());
+    let cover_path = match contents.cover {
+        _ if custom_cover.is_some() => custom_cover,
+        Some(image) => {
+            let cover_filename = new_cover_name(&full_hash);
+            save_cover(&image, &cover_filename)?;
+            Some(cover_filename)
+        }
+        None => None,
+    };
     
-    let metadata = PdfMetadata {
+    let language = contents.snippet.as_deref().and_then(language::detect).map(str::to_string);
+    let mut metadata = PdfMetadata {
         hash: full_hash,
         partial_hash,
         path: path.to_string_lossy().to_string(),
-        title,
-        author,
-        subject,
-        keywords,
-        creator,
-        producer,
-        creation_date,
-        modification_date,
-        page_count,
+        title: contents.title,
+        author: contents.author,
+        subject: contents.subject,
+        keywords: contents.keywords,
+        creator: contents.creator,
+        producer: contents.producer,
+        creation_date: contents.creation_date,
+        modification_date: contents.modification_date,
+        page_count: contents.page_count,
         cover_path,
         file_size,
+        snippet: contents.snippet,
+        mtime: Some(mtime),
+        missing: false,
+        isbn: contents.isbn,
+        arxiv_id: contents.arxiv_id.or_else(|| lookup::arxiv_id_in_name(path)),
+        abstract_text: None,
+        heuristic_title: contents.heuristic_title,
+        language,
     };
-    
+    let filled = filename_patterns::fill_in(&mut metadata, &limits.filename_patterns);
+    if !filled.is_empty() { debug!("Read {:?} from the name of {}", filled, path.display()); }
+    cache.apply_overrides(&mut metadata)?;
+
     // Step 6: Store in cache
-    println!("storing cache");
+    debug!("storing cache");
     cache.store_metadata(&metadata)?;
+    cache.clear_failure(&metadata.path)?;
     
     Ok(metadata)
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pdf_dates_kept() {
+        assert_eq!(pdf_date("D:20190412093000+02'00'").as_deref(), Some("D:20190412093000+02'00'"));
+        assert_eq!(pdf_date("D:2019").as_deref(), Some("D:2019"));
+        assert_eq!(pdf_date("D:201"), None);
+    }
+
+    #[test]
+    fn iso_dates_and_years() {
+        assert_eq!(pdf_date("2019").as_deref(), Some("D:2019"));
+        assert_eq!(pdf_date("2019-05").as_deref(), Some("D:201905"));
+        assert_eq!(pdf_date("2019-05-12").as_deref(), Some("D:20190512"));
+        assert_eq!(pdf_date("2019-05-12T09:30:00Z").as_deref(), Some("D:20190512093000"));
+        assert_eq!(pdf_date("2019-13-01"), None);
+    }
+
+    #[test]
+    fn written_out_months() {
+        assert_eq!(pdf_date("12 May 2019").as_deref(), Some("D:20190512"));
+        assert_eq!(pdf_date("September 3, 1998").as_deref(), Some("D:19980903"));
+        assert_eq!(pdf_date("Mar 2001").as_deref(), Some("D:200103"));
+    }
+
+    #[test]
+    fn ambiguous_or_unknown_dates_refused() {
+        assert_eq!(pdf_date("05/12/2019"), None);
+        assert_eq!(pdf_date("last spring"), None);
+        assert_eq!(pdf_date("May"), None);
+    }
+
+    #[test]
+    fn titles_like_file_names_are_junk() {
+        let title = |title: &str| PdfMetadata { title: Some(title.to_string()), ..PdfMetadata::for_test("h", "/books/report.pdf") };
+        assert_eq!(title("report.docx").usable_title(), None);
+        assert_eq!(title("Microsoft Word - Report").usable_title(), None);
+        assert_eq!(title("Untitled").usable_title(), None);
+        assert_eq!(title(" - ").usable_title(), None);
+        assert_eq!(title(" A Report ").usable_title(), Some("A Report"));
+    }
+
+    #[test]
+    fn title_matching_the_file_name_kept() {
+        let document = PdfMetadata {
+            title: Some("The Dispossessed".to_string()),
+            ..PdfMetadata::for_test("h", "/books/The Dispossessed.pdf")
+        };
+        assert_eq!(document.usable_title(), Some("The Dispossessed"));
+        assert!(!document.title_is_heuristic());
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use gtk::gio;
+use gtk::prelude::*;
+
+// Set by xdg-desktop-portal on the files it exports, as user.document-portal.host-path
+const HOST_PATH_ATTRIBUTE: &str = "xattr::document-portal.host-path";
+
+static SANDBOXED: LazyLock<bool> = LazyLock::new(|| Path::new("/.flatpak-info").exists());
+
+/// Running inside a Flatpak, where only folders picked in a file dialog can
+/// be read and they show up under the document portal
+pub fn is_sandboxed() -> bool {
+    *SANDBOXED
+}
+
+/// Whether `path` is a folder or file the document portal exported, e.g.
+/// `/run/user/1000/doc/1a2b3c/Papers`
+pub fn is_portal_path(path: &Path) -> bool {
+    dirs::runtime_dir().is_some_and(|runtime| path.starts_with(runtime.join("doc")))
+}
+
+/// Where an exported path is outside the sandbox, for showing to people; the
+/// path itself is what has to be opened
+pub fn host_path(path: &Path) -> PathBuf {
+    if !is_portal_path(path) { return path.to_path_buf(); }
+    gio::File::for_path(path)
+        .query_info(HOST_PATH_ATTRIBUTE, gio::FileQueryInfoFlags::NONE, None::<&gio::Cancellable>)
+        .ok()
+        .and_then(|info| info.attribute_as_string(HOST_PATH_ATTRIBUTE))
+        .map(|host| PathBuf::from(host.as_str()))
+        .unwrap_or_else(|| path.to_path_buf())
+}
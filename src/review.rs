@@ -0,0 +1,159 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use gtk::glib;
+
+use crate::pdf::{PdfCache, PdfMetadata, TagsByHash};
+
+const TOP_COUNT: usize = 5;
+
+/// Reading summary for one calendar year, in local time
+#[derive(Debug, Clone, Default)]
+pub struct YearReview {
+    pub year: i32,
+    pub added: usize,
+    pub opened: usize,
+    pub pages_read: usize,
+    // Titles of documents whose last page was reached during the year
+    pub finished: Vec<String>,
+    pub top_authors: Vec<(String, usize)>,
+    pub top_tags: Vec<(String, usize)>,
+}
+
+fn year_start(year: i32) -> Result<i64> {
+    let date = glib::DateTime::from_local(year, 1, 1, 0, 0, 0.0)
+        .context("Invalid year")?;
+    Ok(date.to_unix())
+}
+
+pub fn current_year() -> i32 {
+    glib::DateTime::now_local().map(|now| now.year()).unwrap_or(1970)
+}
+
+/// The tags given to a document, or for one without any its keywords, the
+/// closest thing PDFs have to tags
+fn tags(metadata: &PdfMetadata, all_tags: &TagsByHash) -> Vec<String> {
+    if let Some(tags) = all_tags.get(&metadata.hash).filter(|tags| !tags.is_empty()) {
+        return tags.clone();
+    }
+    metadata.keywords.as_deref().unwrap_or("")
+        .split([',', ';'])
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+fn top(counts: HashMap<String, usize>) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(TOP_COUNT);
+    counts
+}
+
+pub fn year_review(cache: &PdfCache, library: &[PdfMetadata], year: i32) -> Result<YearReview> {
+    let start = year_start(year)?;
+    let end = year_start(year + 1)?;
+
+    let by_hash: HashMap<&str, &PdfMetadata> = library.iter()
+        .map(|pdf| (pdf.hash.as_str(), pdf))
+        .collect();
+
+    let viewed = cache.pages_viewed_between(start, end)?;
+    let mut opened: Vec<&str> = Vec::new();
+    let mut finished: HashSet<&str> = HashSet::new();
+    for (hash, page) in &viewed {
+        if !opened.contains(&hash.as_str()) {
+            opened.push(hash);
+        }
+        if let Some(pdf) = by_hash.get(hash.as_str()) && page + 1 >= pdf.page_count {
+            finished.insert(hash);
+        }
+    }
+
+    let all_tags = cache.all_tags()?;
+    let mut authors: HashMap<String, usize> = HashMap::new();
+    let mut tag_counts: HashMap<String, usize> = HashMap::new();
+    for pdf in opened.iter().filter_map(|hash| by_hash.get(hash)) {
+        if let Some(author) = pdf.author.as_deref().map(str::trim).filter(|a| !a.is_empty()) {
+            *authors.entry(author.to_string()).or_default() += 1;
+        }
+        for tag in tags(pdf, &all_tags) {
+            *tag_counts.entry(tag).or_default() += 1;
+        }
+    }
+
+    let mut finished: Vec<String> = finished.iter()
+        .filter_map(|hash| by_hash.get(hash))
+        .map(|pdf| pdf.display_title())
+        .collect();
+    finished.sort_unstable();
+
+    Ok(YearReview {
+        year,
+        added: cache.added_between(start, end)?.len(),
+        opened: opened.len(),
+        pages_read: viewed.len(),
+        finished,
+        top_authors: top(authors),
+        top_tags: top(tag_counts),
+    })
+}
+
+impl YearReview {
+    /// A standalone HTML page with the same content as the review window
+    pub fn to_html(&self) -> String {
+        let escape = |text: &str| glib::markup_escape_text(text).to_string();
+        let list = |items: &[(String, usize)]| -> String {
+            if items.is_empty() { return "<p class=\"empty\">Nothing yet</p>".to_string(); }
+            let rows: String = items.iter()
+                .map(|(name, count)| format!("<li>{} <span>{}</span></li>", escape(name), count))
+                .collect();
+            format!("<ol>{}</ol>", rows)
+        };
+        let finished: String = if self.finished.is_empty() {
+            "<p class=\"empty\">Nothing yet</p>".to_string()
+        } else {
+            let rows: String = self.finished.iter().map(|title| format!("<li>{}</li>", escape(title))).collect();
+            format!("<ul>{}</ul>", rows)
+        };
+
+        format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{year} in Review</title>
+<style>
+body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; color: #222; }}
+.stats {{ display: flex; gap: 2em; }}
+.stat b {{ display: block; font-size: 2em; }}
+li span, .empty {{ color: #777; }}
+</style>
+</head>
+<body>
+<h1>{year} in Review</h1>
+<div class="stats">
+<div class="stat"><b>{added}</b>added</div>
+<div class="stat"><b>{opened}</b>opened</div>
+<div class="stat"><b>{finished_count}</b>finished</div>
+<div class="stat"><b>{pages}</b>pages read</div>
+</div>
+<h2>Top Authors</h2>
+{authors}
+<h2>Top Tags</h2>
+{tags}
+<h2>Finished</h2>
+{finished}
+</body>
+</html>
+"#,
+            year = self.year,
+            added = self.added,
+            opened = self.opened,
+            finished_count = self.finished.len(),
+            pages = self.pages_read,
+            authors = list(&self.top_authors),
+            tags = list(&self.top_tags),
+            finished = finished,
+        )
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{bail, Result};
+use glob::Pattern;
+
+use crate::language;
+use crate::pdf::PdfMetadata;
+
+#[derive(Debug, Clone)]
+enum Term {
+    Tag(String),
+    // Matches sub-collections too
+    Collection(String),
+    Author(String),
+    Title(String),
+    Keyword(String),
+    Path(Pattern),
+    Hash(String),
+    // ISO 639-1 code or English name
+    Language(String),
+    // A custom field by key, with a value or just there
+    Field(String, Option<String>),
+    // Anywhere in the title, author, path, abstract or custom fields
+    Text(String),
+}
+
+/// A space separated list of `key:value` terms that all have to match, e.g.
+/// `tag:thesis author:"van rossum" path:~/Papers/**`. Values are case
+/// insensitive substrings, except `path` which is a glob, `hash` which is a
+/// prefix, `lang` which is a language code or name and `tag` and
+/// `collection` which have to match whole names. `field:course=cs301`
+/// matches a custom field, `field:course` any document that has one.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    terms: Vec<Term>,
+}
+
+fn contains(haystack: &Option<String>, needle: &str) -> bool {
+    haystack.as_deref().is_some_and(|haystack| haystack.to_lowercase().contains(needle))
+}
+
+impl Filter {
+    pub fn parse(filter: &str) -> Result<Self> {
+        let Some(words) = shlex::split(filter) else {
+            bail!("Unbalanced quotes in filter: {}", filter);
+        };
+        let mut terms = Vec::new();
+        for word in words {
+            let term = match word.split_once(':') {
+                Some((key, value)) => {
+                    let value = value.trim().to_lowercase();
+                    if value.is_empty() { bail!("Empty value for {}:", key); }
+                    match key {
+                        "tag" => Term::Tag(value),
+                        "collection" => Term::Collection(value),
+                        "author" => Term::Author(value),
+                        "title" => Term::Title(value),
+                        "keyword" | "keywords" => Term::Keyword(value),
+                        "hash" => Term::Hash(value),
+                        "lang" | "language" => Term::Language(value),
+                        "field" => match value.split_once('=') {
+                            Some((key, value)) => Term::Field(key.trim().to_string(), Some(value.trim().to_string())),
+                            None => Term::Field(value, None),
+                        },
+                        "path" => {
+                            let expanded = shellexpand::tilde(word.split_once(':').unwrap().1).to_string();
+                            Term::Path(Pattern::new(&expanded)?)
+                        }
+                        key => bail!("Unknown filter {}:, use tag, collection, author, title, keyword, lang, field, path or hash", key),
+                    }
+                }
+                None => Term::Text(word.to_lowercase()),
+            };
+            terms.push(term);
+        }
+        Ok(Self { terms })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// `collections` are the lowercased names of the collections the
+    /// document is in, and of their parents. `fields` are its custom fields.
+    pub fn matches(&self, metadata: &PdfMetadata, tags: &[String], collections: &[String], fields: &[(String, String)]) -> bool {
+        self.terms.iter().all(|term| match term {
+            Term::Tag(tag) => tags.iter().any(|other| other == tag),
+            Term::Collection(name) => collections.iter().any(|other| other == name),
+            Term::Author(author) => contains(&metadata.author, author),
+            Term::Title(title) => metadata.display_title().to_lowercase().contains(title),
+            Term::Keyword(keyword) => contains(&metadata.keywords, keyword),
+            Term::Path(pattern) => pattern.matches(&metadata.path),
+            Term::Hash(prefix) => metadata.hash.starts_with(prefix.as_str()),
+            Term::Language(language) => metadata.language.as_deref()
+                .is_some_and(|code| code == language || language::name(code).to_lowercase() == *language),
+            Term::Field(key, value) => fields.iter()
+                .any(|(other, other_value)| other.to_lowercase() == *key
+                    && value.as_ref().is_none_or(|value| other_value.to_lowercase().contains(value.as_str()))),
+            Term::Text(text) => {
+                metadata.display_title().to_lowercase().contains(text)
+                    || contains(&metadata.author, text)
+                    || metadata.path.to_lowercase().contains(text)
+                    || contains(&metadata.abstract_text, text)
+                    || fields.iter().any(|(_, value)| value.to_lowercase().contains(text))
+            }
+        })
+    }
+}
+
+/// Glob characters in a command line argument the shell didn't expand
+pub fn is_glob(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+/// Looks like a (possibly shortened) document hash
+pub fn is_hash(arg: &str) -> bool {
+    arg.len() >= 8 && arg.chars().all(|c| c.is_ascii_hexdigit())
+}
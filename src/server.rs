@@ -0,0 +1,376 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::path::Path;
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use gtk::glib;
+use image::codecs::jpeg::JpegEncoder;
+use tracing::{error, info, warn};
+
+use crate::collections::CollectionTree;
+use crate::pdf::{full_cover_file, PdfCache, PdfMetadata};
+
+// Requests are tiny, anything slower than this is a stuck client
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REQUEST_LINE: usize = 4096;
+// Connections served at once, a few more wait their turn and the rest are
+// turned away, so a busy network can't spawn threads without end
+const WORKERS: usize = 4;
+const QUEUED_CONNECTIONS: usize = 16;
+// A resolved token is reused this long, so an e-reader fetching every cover
+// in a feed doesn't load the collections again for each. Revoking a link
+// takes effect after at most this.
+const SCOPE_TTL: Duration = Duration::from_secs(10);
+
+static STARTED: OnceLock<u16> = OnceLock::new();
+
+/// A fresh secret for a share link
+pub fn new_token() -> String {
+    rand::random::<[u8; 16]>().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+struct Server {
+    cache: Arc<PdfCache>,
+    scopes: Mutex<HashMap<String, (Instant, Arc<Scope>)>>,
+}
+
+/// Serve shared collections in the background, unless already serving.
+/// Only collections with a share token and what access tokens are scoped to
+/// are reachable, each under `/share/<token>/` as a web page and
+/// `/share/<token>/opds` as an OPDS feed. Nothing is ever written.
+pub fn ensure_running(cache: Arc<PdfCache>, address: &str, port: u16) -> Result<()> {
+    if STARTED.get().is_some() { return Ok(()); }
+    let listener = TcpListener::bind((address, port))
+        .with_context(|| format!("Failed to listen on {}:{}", address, port))?;
+    let port = listener.local_addr()?.port();
+    if STARTED.set(port).is_err() { return Ok(()); }
+    info!("Sharing collections on port {}", port);
+
+    let server = Arc::new(Server { cache, scopes: Mutex::default() });
+    let (sender, receiver) = mpsc::sync_channel::<TcpStream>(QUEUED_CONNECTIONS);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..WORKERS {
+        let (server, receiver) = (server.clone(), receiver.clone());
+        std::thread::spawn(move || loop {
+            let Ok(stream) = receiver.lock().unwrap().recv() else { return; };
+            if let Err(e) = handle(&server, stream) {
+                error!("Share server: {:#}", e);
+            }
+        });
+    }
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue; };
+            if let Err(TrySendError::Full(mut stream)) = sender.try_send(stream) {
+                warn!("Share server: too many connections, turning one away");
+                let _ = stream.set_write_timeout(Some(CLIENT_TIMEOUT));
+                let _ = stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// An address other devices on the network can probably reach the share at.
+/// `port` is used when the server isn't running in this process.
+pub fn share_url(token: &str, port: u16) -> String {
+    // Connecting a UDP socket sends nothing, it only picks the outgoing interface
+    let host = UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| socket.connect("192.0.2.1:9").map(|()| socket))
+        .and_then(|socket| socket.local_addr())
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|_| glib::host_name().to_string());
+    let port = STARTED.get().copied().unwrap_or(port);
+    format!("http://{}:{}/share/{}/", host, port, token)
+}
+
+enum Body {
+    Bytes(Vec<u8>),
+    // Streamed rather than read into memory, with its length
+    File(File, u64),
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Body,
+}
+
+impl Response {
+    fn text(status: &'static str, body: &str) -> Self {
+        Self { status, content_type: "text/plain; charset=utf-8", body: Body::Bytes(body.as_bytes().to_vec()) }
+    }
+
+    fn not_found() -> Self {
+        Self::text("404 Not Found", "Not found\n")
+    }
+}
+
+/// What a token gives access to
+struct Scope {
+    title: String,
+    // None for the whole library
+    hashes: Option<HashSet<String>>,
+    download: bool,
+}
+
+impl Scope {
+    fn allows(&self, hash: &str) -> bool {
+        self.hashes.as_ref().is_none_or(|hashes| hashes.contains(hash))
+    }
+
+    /// The documents it gives access to, by title
+    fn documents(&self, cache: &PdfCache) -> Result<Vec<PdfMetadata>> {
+        let mut documents: Vec<PdfMetadata> = match &self.hashes {
+            None => cache.all_metadata()?,
+            Some(hashes) => {
+                let mut documents = Vec::with_capacity(hashes.len());
+                for hash in hashes {
+                    if let Some(metadata) = cache.get_metadata(hash)? { documents.push(metadata); }
+                }
+                documents
+            }
+        };
+        documents.sort_by_key(|metadata| metadata.display_title().to_lowercase());
+        Ok(documents)
+    }
+}
+
+fn load_scope(cache: &PdfCache, token: &str) -> Result<Option<Scope>> {
+    let tree = CollectionTree::load(cache)?;
+    let (title, hashes, download) = if let Some(id) = cache.shared_collection(token)? {
+        let Some(collection) = tree.get(id) else { return Ok(None); };
+        (collection.name.clone(), Some(tree.documents(id)), true)
+    } else if let Some(access) = cache.access_token(token)? {
+        match &access.collections {
+            None => ("Library".to_string(), None, access.download),
+            Some(ids) => {
+                // Collections deleted since the token was made just drop out
+                let names: Vec<&str> = ids.iter().filter_map(|id| tree.get(*id)).map(|c| c.name.as_str()).collect();
+                let hashes: HashSet<String> = ids.iter().flat_map(|id| tree.documents(*id)).collect();
+                (names.join(", "), Some(hashes), access.download)
+            }
+        }
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(Scope { title, hashes, download }))
+}
+
+impl Server {
+    /// What `token` gives access to, resolved again once SCOPE_TTL is up
+    fn scope(&self, token: &str) -> Result<Option<Arc<Scope>>> {
+        let now = Instant::now();
+        {
+            let mut scopes = self.scopes.lock().unwrap();
+            scopes.retain(|_, (resolved, _)| now.duration_since(*resolved) < SCOPE_TTL);
+            if let Some((_, scope)) = scopes.get(token) { return Ok(Some(scope.clone())); }
+        }
+        let Some(scope) = load_scope(&self.cache, token)? else { return Ok(None); };
+        let scope = Arc::new(scope);
+        self.scopes.lock().unwrap().insert(token.to_string(), (now, scope.clone()));
+        Ok(Some(scope))
+    }
+}
+
+fn handle(server: &Server, mut stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.by_ref().take(MAX_REQUEST_LINE as u64).read_line(&mut request_line)?;
+    // Skip the headers, nothing in them matters here
+    let mut header = String::new();
+    while reader.by_ref().take(MAX_REQUEST_LINE as u64).read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("");
+    let response = if method != "GET" && method != "HEAD" {
+        Response::text("405 Method Not Allowed", "Only GET is supported\n")
+    } else {
+        route(server, target.split('?').next().unwrap_or(""))?
+    };
+
+    let length = match &response.body {
+        Body::Bytes(body) => body.len() as u64,
+        Body::File(_, length) => *length,
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status, response.content_type, length
+    )?;
+    if method == "HEAD" { return Ok(()); }
+    match response.body {
+        Body::Bytes(body) => stream.write_all(&body)?,
+        Body::File(file, length) => { io::copy(&mut file.take(length), &mut stream)?; }
+    }
+    Ok(())
+}
+
+fn route(server: &Server, path: &str) -> Result<Response> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let ["share", token, rest @ ..] = segments.as_slice() else { return Ok(Response::not_found()); };
+    let Some(scope) = server.scope(token)? else { return Ok(Response::not_found()); };
+
+    // Only the document asked for is looked up
+    let find = |name: &str, extension: &str| -> Result<Option<PdfMetadata>> {
+        let Some(hash) = name.strip_suffix(extension) else { return Ok(None); };
+        if !scope.allows(hash) { return Ok(None); }
+        server.cache.get_metadata(hash)
+    };
+    Ok(match rest {
+        [] => Response {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: Body::Bytes(html_page(&scope, &scope.documents(&server.cache)?, token).into_bytes()),
+        },
+        ["opds"] => Response {
+            status: "200 OK",
+            content_type: "application/atom+xml;profile=opds-catalog;kind=acquisition",
+            body: Body::Bytes(opds_feed(&scope, &scope.documents(&server.cache)?, token).into_bytes()),
+        },
+        [name] if name.ends_with(".pdf") => match find(name, ".pdf")? {
+            Some(_) if !scope.download => Response::text("403 Forbidden", "This link doesn't allow downloads\n"),
+            Some(metadata) => match File::open(&metadata.path).and_then(|file| Ok((file.metadata()?.len(), file))) {
+                Ok((length, file)) => Response { status: "200 OK", content_type: "application/pdf", body: Body::File(file, length) },
+                Err(_) => Response::not_found(),
+            },
+            None => Response::not_found(),
+        },
+        [name] if name.ends_with(".jpg") => {
+            let cover = find(name, ".jpg")?.and_then(|metadata| metadata.cover_path);
+            match cover.and_then(|cover| std::fs::read(full_cover_file(&cover)).ok().and_then(|body| as_jpeg(&cover, body))) {
+                Some(body) => Response { status: "200 OK", content_type: "image/jpeg", body: Body::Bytes(body) },
+                None => Response::not_found(),
+            }
+        }
+        _ => Response::not_found(),
+    })
+}
+
+/// Covers saved as WebP are sent as JPEG, which every OPDS reader can show
+fn as_jpeg(cover: &str, body: Vec<u8>) -> Option<Vec<u8>> {
+    if !cover.ends_with(".webp") { return Some(body); }
+    let image = image::load_from_memory(&body).ok()?.to_rgb8();
+    let mut jpeg = Vec::new();
+    image.write_with_encoder(JpegEncoder::new_with_quality(&mut jpeg, 90)).ok()?;
+    Some(jpeg)
+}
+
+fn escape(text: &str) -> String {
+    glib::markup_escape_text(text).to_string()
+}
+
+fn file_name(metadata: &PdfMetadata) -> String {
+    Path::new(&metadata.path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+fn html_page(scope: &Scope, documents: &[PdfMetadata], token: &str) -> String {
+    let rows: String = documents.iter().map(|metadata| {
+        let title = escape(&metadata.display_title());
+        let title = if scope.download {
+            format!(
+                "<a href=\"/share/{}/{}.pdf\" download=\"{}\">{}</a>",
+                token, metadata.hash, escape(&file_name(metadata)), title
+            )
+        } else {
+            title
+        };
+        format!("<li>{} <span>{}</span></li>\n", title, escape(metadata.author.as_deref().unwrap_or("")))
+    }).collect();
+    format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width">
+<title>{name}</title>
+<link rel="alternate" type="application/atom+xml;profile=opds-catalog" href="/share/{token}/opds">
+<style>
+body {{ font-family: sans-serif; max-width: 40em; margin: 2em auto; padding: 0 1em; color: #222; }}
+li {{ margin: 0.4em 0; }}
+span, p {{ color: #777; }}
+</style>
+</head>
+<body>
+<h1>{name}</h1>
+<p>{count} documents · <a href="/share/{token}/opds">OPDS feed</a> for e-readers</p>
+<ul>
+{rows}</ul>
+</body>
+</html>
+"#,
+        name = escape(&scope.title),
+        token = token,
+        count = documents.len(),
+        rows = rows,
+    )
+}
+
+fn opds_feed(scope: &Scope, documents: &[PdfMetadata], token: &str) -> String {
+    let updated = glib::DateTime::now_utc()
+        .and_then(|now| now.format_iso8601())
+        .map(|now| now.to_string())
+        .unwrap_or_default();
+    let entries: String = documents.iter().map(|metadata| {
+        let author = metadata.author.as_deref()
+            .filter(|author| !author.trim().is_empty())
+            .map(|author| format!("<author><name>{}</name></author>", escape(author)))
+            .unwrap_or_default();
+        let summary = metadata.subject.as_deref()
+            .filter(|subject| !subject.trim().is_empty())
+            .map(|subject| format!("<summary>{}</summary>", escape(subject)))
+            .unwrap_or_default();
+        let cover = if metadata.cover_path.is_some() {
+            format!(
+                "<link rel=\"http://opds-spec.org/image\" href=\"/share/{0}/{1}.jpg\" type=\"image/jpeg\"/>\
+                 <link rel=\"http://opds-spec.org/image/thumbnail\" href=\"/share/{0}/{1}.jpg\" type=\"image/jpeg\"/>",
+                token, metadata.hash
+            )
+        } else {
+            String::new()
+        };
+        let acquisition = if scope.download {
+            format!(
+                "<link rel=\"http://opds-spec.org/acquisition\" href=\"/share/{}/{}.pdf\" type=\"application/pdf\"/>",
+                token, metadata.hash
+            )
+        } else {
+            String::new()
+        };
+        format!(
+            "<entry><title>{title}</title><id>urn:shelf:{hash}</id><updated>{updated}</updated>{author}{summary}{acquisition}{cover}</entry>\n",
+            title = escape(&metadata.display_title()),
+            hash = metadata.hash,
+            updated = updated,
+            author = author,
+            summary = summary,
+            acquisition = acquisition,
+            cover = cover,
+        )
+    }).collect();
+    format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:opds="http://opds-spec.org/2010/catalog">
+<id>urn:shelf:share:{token}</id>
+<title>{name}</title>
+<updated>{updated}</updated>
+<link rel="self" href="/share/{token}/opds" type="application/atom+xml;profile=opds-catalog;kind=acquisition"/>
+<link rel="start" href="/share/{token}/opds" type="application/atom+xml;profile=opds-catalog;kind=acquisition"/>
+{entries}</feed>
+"#,
+        token = token,
+        name = escape(&scope.title),
+        updated = updated,
+        entries = entries,
+    )
+}
@@ -0,0 +1,158 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+use tracing::warn;
+
+pub const IGNORE_FILE: &str = ".shelfignore";
+
+// `*` and `?` stop at slashes like in git, `**` crosses them
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, Clone)]
+struct Rule {
+    pattern: Pattern,
+    negated: bool,
+    dirs_only: bool,
+}
+
+/// The `.shelfignore` of a scan directory, in gitignore syntax: a pattern
+/// per line, `#` for comments, `!` to take a file back in, a trailing `/`
+/// for folders only and a `/` anywhere else to anchor it to the directory.
+/// The last matching pattern wins.
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl IgnoreRules {
+    /// None if `root` has no `.shelfignore`
+    pub fn load(root: &Path) -> Option<Self> {
+        let path = root.join(IGNORE_FILE);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("Failed to read {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let rules = text.lines().enumerate()
+            .filter_map(|(number, line)| parse_rule(line).unwrap_or_else(|e| {
+                warn!("{}:{}: {}", path.display(), number + 1, e);
+                None
+            }))
+            .collect();
+        Some(Self { root: root.to_path_buf(), rules })
+    }
+
+    /// Whether `path`, somewhere under the scan directory, is left out
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else { return false; };
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.dirs_only && !is_dir { continue; }
+            if rule.pattern.matches_path_with(relative, MATCH_OPTIONS) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+fn parse_rule(line: &str) -> Result<Option<Rule>, glob::PatternError> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') { return Ok(None); }
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let (dirs_only, line) = match line.strip_suffix('/') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    // Without a slash it matches at any depth
+    let pattern = match line.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if line.contains('/') => line.to_string(),
+        None => format!("**/{}", line),
+    };
+    Ok(Some(Rule { pattern: Pattern::new(&pattern)?, negated, dirs_only }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(text: &str) -> IgnoreRules {
+        IgnoreRules {
+            root: PathBuf::from("/books"),
+            rules: text.lines().filter_map(|line| parse_rule(line).unwrap()).collect(),
+        }
+    }
+
+    fn ignored(rules: &IgnoreRules, path: &str) -> bool {
+        rules.is_ignored(&Path::new("/books").join(path), false)
+    }
+
+    #[test]
+    fn comments_and_blank_lines_skipped() {
+        let rules = rules("# drafts\n\n*.tmp.pdf\n");
+        assert_eq!(rules.rules.len(), 1);
+        assert!(ignored(&rules, "a.tmp.pdf"));
+        assert!(!ignored(&rules, "a.pdf"));
+    }
+
+    #[test]
+    fn unanchored_patterns_match_at_any_depth() {
+        let rules = rules("draft.pdf\n");
+        assert!(ignored(&rules, "draft.pdf"));
+        assert!(ignored(&rules, "papers/2019/draft.pdf"));
+        assert!(!ignored(&rules, "papers/draft.pdf.bak/final.pdf"));
+    }
+
+    #[test]
+    fn slashes_anchor_to_the_scan_directory() {
+        let rules = rules("/draft.pdf\npapers/*.pdf\n");
+        assert!(ignored(&rules, "draft.pdf"));
+        assert!(!ignored(&rules, "old/draft.pdf"));
+        assert!(ignored(&rules, "papers/a.pdf"));
+        // `*` stops at slashes
+        assert!(!ignored(&rules, "papers/2019/a.pdf"));
+        assert!(!ignored(&rules, "old/papers/a.pdf"));
+    }
+
+    #[test]
+    fn double_star_crosses_folders() {
+        let rules = rules("papers/**/*.pdf\n");
+        assert!(ignored(&rules, "papers/a.pdf"));
+        assert!(ignored(&rules, "papers/2019/spring/a.pdf"));
+    }
+
+    #[test]
+    fn negation_takes_files_back_and_last_match_wins() {
+        let rules = rules("*.pdf\n!keep*.pdf\nkeep-not.pdf\n");
+        assert!(ignored(&rules, "a.pdf"));
+        assert!(!ignored(&rules, "keep-me.pdf"));
+        assert!(!ignored(&rules, "sub/keep-me.pdf"));
+        assert!(ignored(&rules, "keep-not.pdf"));
+    }
+
+    #[test]
+    fn trailing_slash_matches_folders_only() {
+        let rules = rules("scans/\n");
+        assert!(rules.is_ignored(Path::new("/books/scans"), true));
+        assert!(rules.is_ignored(Path::new("/books/old/scans"), true));
+        assert!(!rules.is_ignored(Path::new("/books/scans"), false));
+    }
+
+    #[test]
+    fn paths_outside_the_root_never_ignored() {
+        assert!(!rules("*.pdf\n").is_ignored(Path::new("/elsewhere/a.pdf"), false));
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::pdf::{read_snapshot, PdfMetadata, TagsByHash};
+
+/// The documents of a library at one point, keyed by hash
+pub struct Snapshot {
+    documents: HashMap<String, PdfMetadata>,
+    tags: TagsByHash,
+}
+
+/// One difference between two snapshots, printed as a tab separated line
+/// starting with the kind of change and the document hash
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added { hash: String, path: String },
+    Removed { hash: String, path: String },
+    Moved { hash: String, from: String, to: String },
+    // Field name, old and new value
+    Changed { hash: String, path: String, field: &'static str, old: String, new: String },
+}
+
+impl Change {
+    fn hash(&self) -> &str {
+        match self {
+            Change::Added { hash, .. } | Change::Removed { hash, .. }
+            | Change::Moved { hash, .. } | Change::Changed { hash, .. } => hash,
+        }
+    }
+}
+
+// Keep every change on one line
+fn clean(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { hash, path } => write!(f, "added\t{}\t{}", hash, clean(path)),
+            Change::Removed { hash, path } => write!(f, "removed\t{}\t{}", hash, clean(path)),
+            Change::Moved { hash, from, to } => write!(f, "moved\t{}\t{}\t{}", hash, clean(from), clean(to)),
+            Change::Changed { hash, path, field, old, new } => {
+                write!(f, "changed\t{}\t{}\t{}\t{}\t{}", hash, clean(path), field, clean(old), clean(new))
+            }
+        }
+    }
+}
+
+fn fields(metadata: &PdfMetadata) -> [(&'static str, String); 6] {
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    [
+        ("title", text(&metadata.title)),
+        ("author", text(&metadata.author)),
+        ("subject", text(&metadata.subject)),
+        ("keywords", text(&metadata.keywords)),
+        ("pages", metadata.page_count.to_string()),
+        ("size", metadata.file_size.to_string()),
+    ]
+}
+
+impl Snapshot {
+    pub fn open(db_path: &Path) -> Result<Self> {
+        let (documents, tags) = read_snapshot(db_path)?;
+        let documents = documents.into_iter().map(|metadata| (metadata.hash.clone(), metadata)).collect();
+        Ok(Self { documents, tags })
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    /// What changed going from `self` to `newer`, ordered by path
+    pub fn diff(&self, newer: &Snapshot) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (hash, old) in &self.documents {
+            let Some(new) = newer.documents.get(hash) else {
+                changes.push(Change::Removed { hash: hash.clone(), path: old.path.clone() });
+                continue;
+            };
+            if old.path != new.path {
+                changes.push(Change::Moved { hash: hash.clone(), from: old.path.clone(), to: new.path.clone() });
+            }
+            for ((field, old_value), (_, new_value)) in fields(old).into_iter().zip(fields(new)) {
+                if old_value != new_value {
+                    changes.push(Change::Changed {
+                        hash: hash.clone(),
+                        path: new.path.clone(),
+                        field,
+                        old: old_value,
+                        new: new_value,
+                    });
+                }
+            }
+            let old_tags = self.tags.get(hash).map(|tags| tags.join(",")).unwrap_or_default();
+            let new_tags = newer.tags.get(hash).map(|tags| tags.join(",")).unwrap_or_default();
+            if old_tags != new_tags {
+                changes.push(Change::Changed {
+                    hash: hash.clone(),
+                    path: new.path.clone(),
+                    field: "tags",
+                    old: old_tags,
+                    new: new_tags,
+                });
+            }
+        }
+        for (hash, new) in &newer.documents {
+            if !self.documents.contains_key(hash) {
+                changes.push(Change::Added { hash: hash.clone(), path: new.path.clone() });
+            }
+        }
+
+        let path = |change: &Change| -> String {
+            let hash = change.hash();
+            newer.documents.get(hash).or_else(|| self.documents.get(hash)).map(|pdf| pdf.path.clone()).unwrap_or_default()
+        };
+        changes.sort_by_cached_key(|change| (path(change), change.hash().to_string()));
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(hash: &str, path: &str, title: &str) -> PdfMetadata {
+        PdfMetadata { title: Some(title.to_string()), ..PdfMetadata::for_test(hash, path) }
+    }
+
+    fn snapshot(documents: Vec<PdfMetadata>, tags: &[(&str, &[&str])]) -> Snapshot {
+        Snapshot {
+            documents: documents.into_iter().map(|metadata| (metadata.hash.clone(), metadata)).collect(),
+            tags: tags.iter().map(|(hash, tags)| (hash.to_string(), tags.iter().map(|tag| tag.to_string()).collect())).collect(),
+        }
+    }
+
+    #[test]
+    fn identical_snapshots_have_no_changes() {
+        let old = snapshot(vec![document("a", "/books/a.pdf", "A")], &[("a", &["x"])]);
+        let new = snapshot(vec![document("a", "/books/a.pdf", "A")], &[("a", &["x"])]);
+        assert!(old.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn added_removed_and_moved_by_path() {
+        let old = snapshot(vec![document("a", "/books/a.pdf", "A"), document("b", "/books/b.pdf", "B")], &[]);
+        let new = snapshot(vec![document("b", "/books/old/b.pdf", "B"), document("c", "/books/c.pdf", "C")], &[]);
+        assert_eq!(old.diff(&new), vec![
+            Change::Removed { hash: "a".to_string(), path: "/books/a.pdf".to_string() },
+            Change::Added { hash: "c".to_string(), path: "/books/c.pdf".to_string() },
+            Change::Moved { hash: "b".to_string(), from: "/books/b.pdf".to_string(), to: "/books/old/b.pdf".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn changed_fields_and_tags() {
+        let mut edited = document("a", "/books/a.pdf", "New title");
+        edited.page_count = 2;
+        let old = snapshot(vec![document("a", "/books/a.pdf", "Old title")], &[("a", &["x"])]);
+        let new = snapshot(vec![edited], &[("a", &["x", "y"])]);
+        let changes = old.diff(&new);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0], Change::Changed {
+            hash: "a".to_string(),
+            path: "/books/a.pdf".to_string(),
+            field: "title",
+            old: "Old title".to_string(),
+            new: "New title".to_string(),
+        });
+        assert_eq!(changes[1].to_string(), "changed\ta\t/books/a.pdf\tpages\t1\t2");
+        assert_eq!(changes[2].to_string(), "changed\ta\t/books/a.pdf\ttags\tx\tx,y");
+    }
+
+    #[test]
+    fn printed_changes_stay_on_one_line() {
+        let change = Change::Changed {
+            hash: "a".to_string(),
+            path: "/books/a\tb.pdf".to_string(),
+            field: "title",
+            old: "Two\nlines".to_string(),
+            new: String::new(),
+        };
+        assert_eq!(change.to_string(), "changed\ta\t/books/a b.pdf\ttitle\tTwo lines\t");
+    }
+}
@@ -0,0 +1,131 @@
+#![allow(dead_code)]
+
+use std::{fs, path::{Path, PathBuf}};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Window layout remembered between runs, unlike `Config` this isn't meant
+/// to be edited by hand
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    pub window_width: i32,
+    pub window_height: i32,
+    pub maximized: bool,
+    pub show_preview: bool,
+    // Grid order when not searching, "path" or "most-used"
+    pub sort: String,
+    // Documents a scan found missing are shown greyed out, or hidden
+    pub show_missing: bool,
+    // What the grid is showing, restored on the next start
+    pub search: String,
+    pub volume_filter: Option<PathBuf>,
+    pub collection_filter: Option<i64>,
+    pub language_filter: Option<String>,
+    pub author_filter: Option<String>,
+    // First and last year, both included
+    pub year_filter: Option<(i32, i32)>,
+    // How far down the grid was scrolled, as a fraction of its height
+    pub scroll_position: f64,
+    // Hash of the document last selected or opened, selected again after the first scan
+    pub last_document: Option<String>,
+}
+
+impl Default for UiState {
+    fn default() -> Self {
+        Self {
+            window_width: 800,
+            // -1 leaves the height to the content
+            window_height: -1,
+            maximized: false,
+            show_preview: true,
+            sort: "path".to_string(),
+            show_missing: true,
+            search: String::new(),
+            volume_filter: None,
+            collection_filter: None,
+            language_filter: None,
+            author_filter: None,
+            year_filter: None,
+            scroll_position: 0.0,
+            last_document: None,
+        }
+    }
+}
+
+impl UiState {
+    fn state_path() -> anyhow::Result<PathBuf> {
+        data_path("state.toml")
+    }
+
+    /// Missing or unreadable state just means starting with the defaults
+    pub fn load() -> Self {
+        let Ok(state_path) = Self::state_path() else { return Self::default(); };
+        let Ok(contents) = fs::read_to_string(&state_path) else { return Self::default(); };
+        toml::from_str(&contents).unwrap_or_else(|e| {
+            warn!("Ignoring invalid {}: {}", state_path.display(), e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        write_atomic(&Self::state_path()?, &toml::to_string_pretty(self)?)
+    }
+}
+
+/// What the user was looking at, written periodically while the app runs and
+/// removed on a clean exit. Finding one on launch means the last run crashed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub search: String,
+    pub volume_filter: Option<PathBuf>,
+    pub collection_filter: Option<i64>,
+    pub language_filter: Option<String>,
+    pub author_filter: Option<String>,
+    pub year_filter: Option<(i32, i32)>,
+    pub selected_path: Option<String>,
+    // Paths gathered in the workspace pane
+    pub workspace: Vec<String>,
+}
+
+impl Session {
+    fn session_path() -> anyhow::Result<PathBuf> {
+        data_path("session.toml")
+    }
+
+    /// The session left behind by a crashed run, if any
+    pub fn load() -> Option<Self> {
+        let session_path = Self::session_path().ok()?;
+        let contents = fs::read_to_string(&session_path).ok()?;
+        toml::from_str(&contents)
+            .inspect_err(|e| warn!("Ignoring invalid {}: {}", session_path.display(), e))
+            .ok()
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        write_atomic(&Self::session_path()?, &toml::to_string_pretty(self)?)
+    }
+
+    pub fn clear() -> anyhow::Result<()> {
+        let session_path = Self::session_path()?;
+        if session_path.exists() { fs::remove_file(&session_path)?; }
+        Ok(())
+    }
+}
+
+fn data_path(name: &str) -> anyhow::Result<PathBuf> {
+    Ok(crate::paths::data_dir()?.join(name))
+}
+
+// Write to a temporary file first so a crash can't leave half a file behind
+fn write_atomic(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let app_data_dir = path.parent().context("Error getting data path")?;
+    if !app_data_dir.exists() { fs::create_dir_all(app_data_dir)?; }
+
+    let tmp_path = path.with_extension("toml.tmp");
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use adw::prelude::*;
+
+const WEBSITE: &str = "https://github.com/galib45/shelf";
+
+/// The About window, on top of `parent` when there is one
+pub fn present(parent: Option<&gtk::Window>) {
+    let about = adw::AboutDialog::builder()
+        .application_name("Shelf")
+        .application_icon(crate::APP_ID)
+        .developer_name("galib45")
+        .version(env!("CARGO_PKG_VERSION"))
+        .comments("Browse and manage your PDF library")
+        .website(WEBSITE)
+        .issue_url(format!("{}/issues", WEBSITE))
+        // Unlicense isn't one of the licenses GTK knows
+        .license_type(gtk::License::Custom)
+        .license(include_str!("../../LICENSE"))
+        .developers(["galib45"])
+        .build();
+    about.add_acknowledgement_section(Some("Built on"), &["GTK https://gtk.org", "libadwaita https://gnome.pages.gitlab.gnome.org/libadwaita", "MuPDF https://mupdf.com"]);
+    about.present(parent);
+}
@@ -0,0 +1,23 @@
+use std::rc::Rc;
+
+/// More contrast was asked for, either through the GTK setting or by picking
+/// a high contrast theme
+pub fn prefers_high_contrast() -> bool {
+    let Some(settings) = gtk::Settings::default() else { return false; };
+    settings.gtk_interface_contrast() == gtk::InterfaceContrast::More
+        || settings.gtk_theme_name().is_some_and(|name| name.to_lowercase().contains("highcontrast"))
+}
+
+pub fn prefers_reduced_motion() -> bool {
+    gtk::Settings::default().is_some_and(|settings| !settings.is_gtk_enable_animations())
+}
+
+/// Call `f` whenever either preference may have changed
+pub fn connect_changed<F: Fn() + 'static>(f: F) {
+    let Some(settings) = gtk::Settings::default() else { return; };
+    let f = Rc::new(f);
+    let changed = move |_: &gtk::Settings| f();
+    settings.connect_gtk_interface_contrast_notify(changed.clone());
+    settings.connect_gtk_theme_name_notify(changed.clone());
+    settings.connect_gtk_enable_animations_notify(changed);
+}
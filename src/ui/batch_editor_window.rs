@@ -0,0 +1,189 @@
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+
+use crate::pdf::{MetadataField, PdfMetadata};
+use crate::ui::metadata_editor_window::EDITABLE_FIELDS;
+
+mod imp {
+    use std::cell::RefCell;
+    use std::sync::OnceLock;
+
+    use gtk::glib;
+    use gtk::glib::subclass::types::ObjectSubclass;
+    use gtk::glib::subclass::Signal;
+    use gtk::subclass::prelude::*;
+
+    use crate::pdf::PdfMetadata;
+
+    #[derive(Default, gtk::CompositeTemplate)]
+    #[template(resource = "/org/galib/shelf/ui/batch_editor_window.xml")]
+    pub struct ShelfBatchEditorWindow {
+        #[template_child]
+        pub field_dropdown: TemplateChild<gtk::DropDown>,
+        #[template_child]
+        pub value_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub summary_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub preview_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub cancel_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub apply_button: TemplateChild<gtk::Button>,
+
+        pub documents: RefCell<Vec<PdfMetadata>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ShelfBatchEditorWindow {
+        const NAME: &'static str = "ShelfBatchEditorWindow";
+        type Type = super::ShelfBatchEditorWindow;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ShelfBatchEditorWindow {
+        fn constructed(&self) {
+            self.parent_constructed();
+        }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            // The column of the field, see MetadataField::column, and its new
+            // value, empty to clear it. The documents to change are read with
+            // changed() before the dialog closes.
+            SIGNALS.get_or_init(|| vec![
+                Signal::builder("apply").param_types([glib::Type::STRING, glib::Type::STRING]).build(),
+            ])
+        }
+    }
+
+    impl WidgetImpl for ShelfBatchEditorWindow {}
+    impl WindowImpl for ShelfBatchEditorWindow {}
+}
+
+glib::wrapper! {
+    pub struct ShelfBatchEditorWindow(ObjectSubclass<imp::ShelfBatchEditorWindow>)
+        @extends gtk::Widget, gtk::Window,
+        @implements gio::ActionGroup, gio::ActionMap,
+                    gtk::Accessible, gtk::Buildable,
+                    gtk::ConstraintTarget, gtk::Native,
+                    gtk::Root, gtk::ShortcutManager;
+}
+
+impl ShelfBatchEditorWindow {
+    /// Sets one field of every document in `documents` to the same value,
+    /// listing what changes before it's applied
+    pub fn new(parent: &impl IsA<gtk::Window>, documents: Vec<PdfMetadata>) -> Self {
+        let obj: ShelfBatchEditorWindow = glib::Object::builder()
+            .property("transient-for", parent)
+            .build();
+        let imp = obj.imp();
+        let labels: Vec<&str> = EDITABLE_FIELDS.iter().map(|field| field.label()).collect();
+        imp.field_dropdown.set_model(Some(&gtk::StringList::new(&labels)));
+        imp.documents.replace(documents);
+        obj.set_default_widget(Some(&*imp.apply_button));
+
+        imp.field_dropdown.connect_selected_notify(glib::clone!(
+            #[weak] obj,
+            move |_| obj.refresh()
+        ));
+        imp.value_entry.connect_changed(glib::clone!(
+            #[weak] obj,
+            move |_| obj.refresh()
+        ));
+        imp.cancel_button.connect_clicked(glib::clone!(
+            #[weak] obj,
+            move |_| obj.close()
+        ));
+        imp.apply_button.connect_clicked(glib::clone!(
+            #[weak] obj,
+            move |_| {
+                let value = obj.value().unwrap_or_default();
+                obj.emit_by_name::<()>("apply", &[&obj.field().column(), &value]);
+                obj.close();
+            }
+        ));
+        obj.refresh();
+        obj
+    }
+
+    pub fn field(&self) -> MetadataField {
+        EDITABLE_FIELDS.get(self.imp().field_dropdown.selected() as usize).copied().unwrap_or(EDITABLE_FIELDS[0])
+    }
+
+    // None clears the field
+    fn value(&self) -> Option<String> {
+        Some(self.imp().value_entry.text().trim().to_string()).filter(|text| !text.is_empty())
+    }
+
+    /// The documents whose field the new value changes
+    pub fn changed(&self) -> Vec<PdfMetadata> {
+        let (field, value) = (self.field(), self.value());
+        self.imp().documents.borrow().iter()
+            .filter(|metadata| field.get(metadata).map(str::trim).filter(|old| !old.is_empty()) != value.as_deref())
+            .cloned()
+            .collect()
+    }
+
+    fn refresh(&self) {
+        let imp = self.imp();
+        let (field, value) = (self.field(), self.value());
+        let changed = self.changed().len();
+        let total = imp.documents.borrow().len();
+        imp.summary_label.set_label(&match changed {
+            0 => format!("None of the {} documents would change", total),
+            1 => format!("Changes the {} of 1 of {} documents", field.label().to_lowercase(), total),
+            _ => format!("Changes the {} of {} of {} documents", field.label().to_lowercase(), changed, total),
+        });
+        imp.apply_button.set_sensitive(changed > 0);
+
+        imp.preview_list.remove_all();
+        let new = value.as_deref().unwrap_or("empty");
+        for metadata in imp.documents.borrow().iter() {
+            let old = field.get(metadata).map(str::trim).filter(|old| !old.is_empty());
+            let change = if old == value.as_deref() {
+                "unchanged".to_string()
+            } else {
+                format!("{} → {}", old.unwrap_or("empty"), new)
+            };
+            imp.preview_list.append(&Self::preview_row(&metadata.display_title(), &change, old != value.as_deref()));
+        }
+    }
+
+    fn preview_row(title: &str, change: &str, changes: bool) -> gtk::Box {
+        let row = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(2)
+            .margin_start(12)
+            .margin_end(12)
+            .margin_top(6)
+            .margin_bottom(6)
+            .build();
+        let title_label = gtk::Label::builder()
+            .label(title)
+            .xalign(0.0)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .tooltip_text(title)
+            .build();
+        let change_label = gtk::Label::builder()
+            .label(change)
+            .xalign(0.0)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .tooltip_text(change)
+            .build();
+        change_label.add_css_class("caption");
+        if !changes { change_label.add_css_class("dim-label"); }
+        row.append(&title_label);
+        row.append(&change_label);
+        row
+    }
+}
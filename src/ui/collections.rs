@@ -0,0 +1,421 @@
+use std::collections::HashSet;
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::gio;
+use tracing::warn;
+
+use crate::collections::CollectionTree;
+use crate::server;
+use crate::ui::window::ShelfWindow;
+
+impl ShelfWindow {
+    pub(super) fn setup_collections_actions(&self, model: &gio::ListStore) {
+        let new_collection = gio::ActionEntry::builder("new-collection")
+            .parameter_type(Some(&i64::static_variant_type()))
+            .activate(|window: &Self, _, param| {
+                // -1 for a top level collection
+                let parent = param.and_then(|p| p.get::<i64>()).filter(|id| *id >= 0);
+                window.new_collection(parent);
+            })
+            .build();
+
+        let rename_collection = gio::ActionEntry::builder("rename-collection")
+            .parameter_type(Some(&i64::static_variant_type()))
+            .activate(|window: &Self, _, param| {
+                if let Some(id) = param.and_then(|p| p.get::<i64>()) {
+                    window.rename_collection(id);
+                }
+            })
+            .build();
+
+        let share_collection = gio::ActionEntry::builder("share-collection")
+            .parameter_type(Some(&i64::static_variant_type()))
+            .activate(|window: &Self, _, param| {
+                if let Some(id) = param.and_then(|p| p.get::<i64>()) {
+                    window.share_collection(id);
+                }
+            })
+            .build();
+
+        let delete_collection = gio::ActionEntry::builder("delete-collection")
+            .parameter_type(Some(&i64::static_variant_type()))
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, param| {
+                    if let Some(id) = param.and_then(|p| p.get::<i64>()) {
+                        window.delete_collection(&model, id);
+                    }
+                }
+            ))
+            .build();
+
+        let remove_from_collection = gio::ActionEntry::builder("remove-from-collection")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.remove_from_collection(&model)
+            ))
+            .build();
+
+        self.add_action_entries([new_collection, rename_collection, share_collection, delete_collection, remove_from_collection]);
+    }
+
+    pub(super) fn setup_collections(&self, model: gio::ListStore) {
+        let imp = self.imp();
+        imp.collection_list.connect_row_selected(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                // Rows are rebuilt whenever collections change
+                let Some(row) = row else { return; };
+                let imp = _self.imp();
+                let Some(id) = imp.collection_rows.borrow().get(row.index() as usize).copied() else { return; };
+                if imp.collection_filter.get() == Some(id) { return; }
+                imp.collection_filter.set(Some(id));
+                *imp.volume_filter.borrow_mut() = None;
+                imp.volume_list.unselect_all();
+                _self.update_collection_actions();
+                _self.populate_model(&model);
+            }
+        ));
+
+        // Dropping a collection on the heading moves it to the top level
+        let drop_target = gtk::DropTarget::new(i64::static_type(), gtk::gdk::DragAction::MOVE);
+        drop_target.connect_drop(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] false,
+            move |_, value, _, _| {
+                let Ok(id) = value.get::<i64>() else { return false; };
+                _self.move_collection(id, None)
+            }
+        ));
+        imp.collections_header.add_controller(drop_target);
+        self.refresh_collections();
+    }
+
+    /// Reload collections from the cache and rebuild the sidebar tree
+    pub(super) fn refresh_collections(&self) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return; };
+        let tree = CollectionTree::load(cache).unwrap_or_else(|e| {
+            warn!("Failed to load collections: {}", e);
+            CollectionTree::default()
+        });
+        if imp.collection_filter.get().is_some_and(|id| tree.get(id).is_none()) {
+            imp.collection_filter.set(None);
+        }
+
+        let library: HashSet<String> = imp.metadata_list.lock().unwrap().iter().map(|pdf| pdf.hash.clone()).collect();
+        imp.collection_list.remove_all();
+        let mut rows = Vec::new();
+        for (collection, depth) in tree.ordered() {
+            // Counts include everything in sub-collections
+            let count = tree.documents(collection.id).iter().filter(|hash| library.contains(*hash)).count();
+            imp.collection_list.append(&self.create_collection_row(collection.id, &collection.name, depth, count));
+            rows.push(collection.id);
+        }
+        let selected_index = imp.collection_filter.get().and_then(|id| rows.iter().position(|other| *other == id));
+        imp.collection_rows.replace(rows);
+        imp.collection_tree.replace(tree);
+        if let Some(index) = selected_index {
+            imp.collection_list.select_row(imp.collection_list.row_at_index(index as i32).as_ref());
+        }
+        self.update_collection_actions();
+    }
+
+    fn create_collection_row(&self, id: i64, name: &str, depth: usize, count: usize) -> gtk::Box {
+        let hbox = Self::create_volume_row("folder-symbolic", name, count);
+        hbox.set_margin_start(depth as i32 * 16);
+
+        let menu = gio::Menu::new();
+        for (label, action) in [
+            ("New Sub-collection…", "win.new-collection"),
+            ("Rename…", "win.rename-collection"),
+            ("Share…", "win.share-collection"),
+            ("Delete", "win.delete-collection"),
+        ] {
+            let item = gio::MenuItem::new(Some(label), None);
+            item.set_action_and_target_value(Some(action), Some(&id.to_variant()));
+            menu.append_item(&item);
+        }
+        let menu_button = gtk::MenuButton::builder()
+            .icon_name("view-more-symbolic")
+            .tooltip_text("Collection actions")
+            .menu_model(&menu)
+            .build();
+        menu_button.add_css_class("flat");
+        hbox.append(&menu_button);
+
+        // Drag a collection onto another one to nest it
+        let drag_source = gtk::DragSource::new();
+        drag_source.set_actions(gtk::gdk::DragAction::MOVE);
+        drag_source.connect_prepare(move |_, _, _| Some(gtk::gdk::ContentProvider::for_value(&id.to_value())));
+        hbox.add_controller(drag_source);
+
+        // Documents come as paths from the grid, collections as ids
+        let drop_target = gtk::DropTarget::new(glib::Type::INVALID, gtk::gdk::DragAction::COPY | gtk::gdk::DragAction::MOVE);
+        drop_target.set_types(&[String::static_type(), i64::static_type()]);
+        drop_target.connect_drop(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] false,
+            move |_, value, _, _| {
+                if let Ok(child) = value.get::<i64>() {
+                    _self.move_collection(child, Some(id))
+                } else if let Ok(path) = value.get::<String>() {
+                    _self.add_to_collection(id, &path)
+                } else {
+                    false
+                }
+            }
+        ));
+        hbox.add_controller(drop_target);
+        hbox
+    }
+
+    fn move_collection(&self, id: i64, parent: Option<i64>) -> bool {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return false; };
+        {
+            let tree = imp.collection_tree.borrow();
+            if tree.get(id).is_none_or(|collection| collection.parent == parent) || !tree.can_move(id, parent) {
+                return false;
+            }
+        }
+        if let Err(e) = cache.move_collection(id, parent) {
+            self.toast_error("Failed to move collection", e);
+            return false;
+        }
+        self.refresh_collections();
+        true
+    }
+
+    fn add_to_collection(&self, id: i64, path: &str) -> bool {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return false; };
+        let hash = imp.metadata_list.lock().unwrap().iter().find(|pdf| pdf.path == path).map(|pdf| pdf.hash.clone());
+        let Some(hash) = hash else { return false; };
+        match cache.add_to_collection(id, &hash) {
+            Ok(added) => {
+                if added { self.refresh_collections(); }
+                true
+            }
+            Err(e) => {
+                self.toast_error(&format!("Failed to add {} to collection", path), e);
+                false
+            }
+        }
+    }
+
+    pub(super) fn update_collection_actions(&self) {
+        if let Some(action) = self.lookup_action("remove-from-collection").and_downcast::<gio::SimpleAction>() {
+            action.set_enabled(self.imp().collection_filter.get().is_some());
+        }
+    }
+
+    /// Ask for a collection name, `on_done` gets it unless cancelled or empty
+    fn ask_collection_name(&self, title: &str, initial: &str, on_done: impl Fn(&str) + 'static) {
+        let dialog = gtk::Window::builder()
+            .title(title)
+            .modal(true)
+            .transient_for(self)
+            .resizable(false)
+            .default_width(320)
+            .build();
+        let vbox = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(12)
+            .margin_start(18)
+            .margin_end(18)
+            .margin_top(18)
+            .margin_bottom(18)
+            .build();
+        let entry = gtk::Entry::builder().text(initial).activates_default(true).build();
+        let buttons = gtk::Box::builder().spacing(6).halign(gtk::Align::End).build();
+        let cancel_button = gtk::Button::with_label("Cancel");
+        let ok_button = gtk::Button::with_label("Save");
+        ok_button.add_css_class("suggested-action");
+        buttons.append(&cancel_button);
+        buttons.append(&ok_button);
+        vbox.append(&entry);
+        vbox.append(&buttons);
+        dialog.set_child(Some(&vbox));
+        dialog.set_default_widget(Some(&ok_button));
+
+        cancel_button.connect_clicked(glib::clone!(
+            #[weak] dialog,
+            move |_| dialog.close()
+        ));
+        ok_button.connect_clicked(glib::clone!(
+            #[weak] dialog,
+            #[weak] entry,
+            move |_| {
+                let name = entry.text();
+                if !name.trim().is_empty() { on_done(name.trim()); }
+                dialog.close();
+            }
+        ));
+        dialog.present();
+    }
+
+    fn new_collection(&self, parent: Option<i64>) {
+        let title = if parent.is_some() { "New Sub-collection" } else { "New Collection" };
+        self.ask_collection_name(title, "", glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |name| {
+                let Some(cache) = _self.imp().cache.get() else { return; };
+                match cache.create_collection(name, parent) {
+                    Ok(_) => _self.refresh_collections(),
+                    Err(e) => _self.toast_error("Failed to create collection", e),
+                }
+            }
+        ));
+    }
+
+    fn rename_collection(&self, id: i64) {
+        let Some(name) = self.imp().collection_tree.borrow().get(id).map(|c| c.name.clone()) else { return; };
+        self.ask_collection_name("Rename Collection", &name, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |name| {
+                let Some(cache) = _self.imp().cache.get() else { return; };
+                match cache.rename_collection(id, name) {
+                    Ok(()) => _self.refresh_collections(),
+                    Err(e) => _self.toast_error("Failed to rename collection", e),
+                }
+            }
+        ));
+    }
+
+    pub(super) fn start_share_server(&self) -> anyhow::Result<()> {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get().cloned() else { anyhow::bail!("The library cache isn't available"); };
+        let (address, port) = {
+            let config = imp.config.get().unwrap().read().unwrap();
+            (config.share_address.clone(), config.share_port)
+        };
+        server::ensure_running(cache, &address, port)
+    }
+
+    /// Publish the collection under a private link, showing the link if it
+    /// already is
+    fn share_collection(&self, id: i64) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        let Some(name) = imp.collection_tree.borrow().get(id).map(|c| c.name.clone()) else { return; };
+        let token = match cache.share_token(id) {
+            Ok(Some(token)) => token,
+            Ok(None) => {
+                let token = server::new_token();
+                if let Err(e) = cache.store_share(&token, id) {
+                    self.toast_error("Failed to share collection", e);
+                    return;
+                }
+                token
+            }
+            Err(e) => {
+                self.toast_error("Failed to look up the share", e);
+                return;
+            }
+        };
+        if let Err(e) = self.start_share_server() {
+            self.show_error("Could not start sharing", &format!("{:#}", e));
+            return;
+        }
+
+        let url = server::share_url(&token, imp.config.get().unwrap().read().unwrap().share_port);
+        let dialog = gtk::AlertDialog::builder()
+            .message(format!("\u{201c}{}\u{201d} is shared", name))
+            .detail(format!(
+                "Anyone on your network with this link can browse and download the documents in this collection and its sub-collections, nothing else.\n\n{}\n\nE-readers can add it as an OPDS catalog at {}opds",
+                url, url
+            ))
+            .buttons(["Close", "Stop Sharing", "Copy Link"])
+            .cancel_button(0)
+            .default_button(2)
+            .build();
+        dialog.choose(Some(self), None::<&gio::Cancellable>, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |response| match response {
+                Ok(1) => match cache.remove_share(id) {
+                    Ok(()) => _self.toast(&format!("Stopped sharing {}", name)),
+                    Err(e) => _self.toast_error("Failed to stop sharing", e),
+                },
+                Ok(2) => _self.clipboard().set_text(&url),
+                _ => {}
+            }
+        ));
+    }
+
+    fn delete_collection(&self, model: &gio::ListStore, id: i64) {
+        let (name, ids) = {
+            let tree = self.imp().collection_tree.borrow();
+            let Some(collection) = tree.get(id) else { return; };
+            (collection.name.clone(), tree.subtree(id))
+        };
+        let detail = match ids.len() {
+            1 => "The documents stay in the library.".to_string(),
+            count => format!("Its {} sub-collections are deleted too. The documents stay in the library.", count - 1),
+        };
+        let dialog = gtk::AlertDialog::builder()
+            .message(format!("Delete \u{201c}{}\u{201d}?", name))
+            .detail(detail)
+            .buttons(["Cancel", "Delete"])
+            .cancel_button(0)
+            .default_button(0)
+            .build();
+        dialog.choose(Some(self), None::<&gio::Cancellable>, glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            move |response| {
+                if response != Ok(1) { return; }
+                let imp = _self.imp();
+                let Some(cache) = imp.cache.get() else { return; };
+                if let Err(e) = cache.delete_collections(&ids) {
+                    _self.toast_error("Failed to delete collection", e);
+                    return;
+                }
+                let showing_deleted = imp.collection_filter.get().is_some_and(|id| ids.contains(&id));
+                _self.refresh_collections();
+                if showing_deleted {
+                    imp.volume_list.select_row(imp.volume_list.row_at_index(0).as_ref());
+                    _self.populate_model(&model);
+                }
+            }
+        ));
+    }
+
+    /// Take the selected document out of the shown collection and the
+    /// collections below it
+    fn remove_from_collection(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        let Some(id) = imp.collection_filter.get() else { return; };
+        let Some(cache) = imp.cache.get() else { return; };
+        let Some(hash) = imp.selected.lock().unwrap().as_ref().map(|selected| selected.hash.clone()) else { return; };
+        // Only where it actually was comes back on undo
+        let tree = imp.collection_tree.borrow().clone();
+        let removed: Vec<i64> = tree.subtree(id).into_iter().filter(|id| tree.contains(*id, &hash)).collect();
+        for id in &removed {
+            if let Err(e) = cache.remove_from_collection(*id, &hash) {
+                self.toast_error("Failed to remove from collection", e);
+                return;
+            }
+        }
+        self.refresh_collections();
+        self.populate_model(model);
+        let name = tree.get(id).map(|collection| collection.name.clone()).unwrap_or_default();
+        self.toast_undo(&format!("Removed from {}", name), glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[weak] model,
+            move || {
+                let Some(cache) = _self.imp().cache.get() else { return; };
+                for id in &removed {
+                    if let Err(e) = cache.add_to_collection(*id, &hash) {
+                        _self.toast_error("Failed to add back to collection", e);
+                    }
+                }
+                _self.refresh_collections();
+                _self.populate_model(&model);
+            }
+        ));
+    }
+}
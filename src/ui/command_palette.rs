@@ -0,0 +1,208 @@
+use std::cell::RefCell;
+
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::{gdk, gio, glib};
+use tracing::warn;
+
+// Rows shown at once, the rest is a few more letters away
+const RESULT_LIMIT: usize = 50;
+
+/// Something the palette can run: an action of the window or application,
+/// with its parameter if it takes one
+#[derive(Clone)]
+pub struct Entry {
+    pub title: String,
+    pub subtitle: String,
+    pub action: String,
+    pub target: Option<glib::Variant>,
+}
+
+mod imp {
+    use super::*;
+    use gtk::glib::subclass::types::ObjectSubclass;
+    use gtk::subclass::prelude::*;
+
+    #[derive(Default, gtk::CompositeTemplate)]
+    #[template(resource = "/org/galib/shelf/ui/command_palette.xml")]
+    pub struct ShelfCommandPalette {
+        #[template_child]
+        pub entry: TemplateChild<gtk::SearchEntry>,
+        #[template_child]
+        pub scrolled_window: TemplateChild<gtk::ScrolledWindow>,
+        #[template_child]
+        pub results: TemplateChild<gtk::ListBox>,
+        pub commands: RefCell<Vec<Entry>>,
+        pub documents: RefCell<Vec<Entry>>,
+        // What the rows currently show, in order
+        pub shown: RefCell<Vec<Entry>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ShelfCommandPalette {
+        const NAME: &'static str = "ShelfCommandPalette";
+        type Type = super::ShelfCommandPalette;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ShelfCommandPalette {
+        fn constructed(&self) {
+            self.parent_constructed();
+        }
+    }
+
+    impl WidgetImpl for ShelfCommandPalette {}
+    impl WindowImpl for ShelfCommandPalette {}
+}
+
+glib::wrapper! {
+    pub struct ShelfCommandPalette(ObjectSubclass<imp::ShelfCommandPalette>)
+        @extends gtk::Widget, gtk::Window,
+        @implements gio::ActionGroup, gio::ActionMap,
+                    gtk::Accessible, gtk::Buildable,
+                    gtk::ConstraintTarget, gtk::Native,
+                    gtk::Root, gtk::ShortcutManager;
+}
+
+impl ShelfCommandPalette {
+    /// Runs the chosen entry on `parent`, commands are listed before documents
+    pub fn new(parent: &impl IsA<gtk::Window>, commands: Vec<Entry>, documents: Vec<Entry>) -> Self {
+        let obj: ShelfCommandPalette = glib::Object::builder()
+            .property("transient-for", parent)
+            .build();
+        let imp = obj.imp();
+        imp.commands.replace(commands);
+        imp.documents.replace(documents);
+        obj.setup();
+        obj.refresh();
+        obj
+    }
+
+    fn setup(&self) {
+        let imp = self.imp();
+        imp.entry.connect_search_changed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.refresh()
+        ));
+        imp.entry.connect_activate(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| {
+                if let Some(row) = _self.imp().results.selected_row() {
+                    _self.run(row.index());
+                }
+            }
+        ));
+        imp.entry.connect_stop_search(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.close()
+        ));
+        imp.results.connect_row_activated(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_, row| _self.run(row.index())
+        ));
+
+        // The focus stays in the entry, the arrow keys move through the rows
+        let key_controller = gtk::EventControllerKey::new();
+        key_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+        key_controller.connect_key_pressed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] glib::Propagation::Proceed,
+            move |_, key, _, _| match key {
+                gdk::Key::Down | gdk::Key::KP_Down => {
+                    _self.move_selection(1);
+                    glib::Propagation::Stop
+                }
+                gdk::Key::Up | gdk::Key::KP_Up => {
+                    _self.move_selection(-1);
+                    glib::Propagation::Stop
+                }
+                _ => glib::Propagation::Proceed,
+            }
+        ));
+        imp.entry.add_controller(key_controller);
+    }
+
+    /// Entries matching the query, best first
+    fn matches(&self, query: &str) -> Vec<Entry> {
+        let imp = self.imp();
+        let commands = imp.commands.borrow();
+        let documents = imp.documents.borrow();
+        if query.is_empty() {
+            return commands.iter().chain(documents.iter()).take(RESULT_LIMIT).cloned().collect();
+        }
+        let matcher = SkimMatcherV2::default();
+        let mut scored: Vec<(i64, &Entry)> = commands.iter().chain(documents.iter())
+            .filter_map(|entry| {
+                let searchable = format!("{} {}", entry.title, entry.subtitle);
+                matcher.fuzzy_match(&searchable, query).map(|score| (score, entry))
+            })
+            .collect();
+        // Stable, so commands stay ahead of documents scoring the same
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().take(RESULT_LIMIT).map(|(_, entry)| entry.clone()).collect()
+    }
+
+    fn refresh(&self) {
+        let imp = self.imp();
+        let shown = self.matches(imp.entry.text().trim());
+        imp.results.remove_all();
+        for entry in &shown {
+            let title = gtk::Label::builder()
+                .label(&entry.title)
+                .xalign(0.0)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .build();
+            let subtitle = gtk::Label::builder()
+                .label(&entry.subtitle)
+                .xalign(0.0)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .css_classes(["dim-label", "caption"])
+                .build();
+            let content = gtk::Box::new(gtk::Orientation::Vertical, 2);
+            content.append(&title);
+            if !entry.subtitle.is_empty() { content.append(&subtitle); }
+            imp.results.append(&content);
+        }
+        imp.shown.replace(shown);
+        imp.results.select_row(imp.results.row_at_index(0).as_ref());
+    }
+
+    fn move_selection(&self, step: i32) {
+        let imp = self.imp();
+        let count = imp.shown.borrow().len() as i32;
+        if count == 0 { return; }
+        let current = imp.results.selected_row().map_or(0, |row| row.index());
+        let Some(row) = imp.results.row_at_index((current + step).clamp(0, count - 1)) else { return; };
+        imp.results.select_row(Some(&row));
+        // Keep the row in view
+        let adjustment = imp.scrolled_window.vadjustment();
+        if let Some(bounds) = row.compute_bounds(&*imp.results) {
+            let (top, bottom) = (bounds.y() as f64, (bounds.y() + bounds.height()) as f64);
+            if top < adjustment.value() {
+                adjustment.set_value(top);
+            } else if bottom > adjustment.value() + adjustment.page_size() {
+                adjustment.set_value(bottom - adjustment.page_size());
+            }
+        }
+    }
+
+    fn run(&self, index: i32) {
+        let Some(entry) = self.imp().shown.borrow().get(index as usize).cloned() else { return; };
+        let parent = self.transient_for();
+        self.close();
+        let Some(parent) = parent else { return; };
+        if let Err(e) = WidgetExt::activate_action(&parent, &entry.action, entry.target.as_ref()) {
+            warn!("Failed to run {}: {}", entry.action, e);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::gio;
+use tracing::warn;
+
+use crate::pdf::PdfMetadata;
+use crate::ui::command_palette::{self, ShelfCommandPalette};
+use crate::ui::contact_sheet::render_contact_sheet;
+use crate::ui::models::PdfMetadataObject;
+use crate::ui::status_bar::StatusModule;
+use crate::ui::shortcuts;
+use crate::ui::usage_window::ShelfUsageWindow;
+use crate::ui::year_review_window::ShelfYearReviewWindow;
+use crate::ui::window::ShelfWindow;
+
+impl ShelfWindow {
+    /// Bind the shortcuts of the current `[keybindings]`
+    pub(super) fn load_shortcuts(&self) {
+        let Some(app) = self.application() else { return; };
+        shortcuts::apply(&app, &self.imp().config.get().unwrap().read().unwrap().keybindings);
+    }
+
+    /// Every enabled action and every document, in one searchable list
+    pub(super) fn show_command_palette(&self) {
+        let imp = self.imp();
+        let app = self.application();
+        let keybindings = imp.config.get().unwrap().read().unwrap().keybindings.clone();
+        let commands = shortcuts::resolve(&keybindings).into_iter()
+            .filter(|(binding, _)| binding.action != "win.command-palette")
+            .filter(|(binding, _)| {
+                let action = match binding.action.split_once('.') {
+                    Some(("win", name)) => self.lookup_action(name),
+                    Some(("app", name)) => app.as_ref().and_then(|app| app.lookup_action(name)),
+                    _ => None,
+                };
+                action.is_some_and(|action| action.is_enabled())
+            })
+            .map(|(binding, accels)| command_palette::Entry {
+                title: binding.title.to_string(),
+                subtitle: accels.iter()
+                    .filter_map(gtk::accelerator_parse)
+                    .map(|(key, modifiers)| gtk::accelerator_get_label(key, modifiers).to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                action: binding.action.to_string(),
+                target: None,
+            })
+            .collect();
+        let documents = imp.metadata_list.lock().unwrap().iter()
+            .map(|metadata| command_palette::Entry {
+                title: metadata.display_title(),
+                subtitle: metadata.author.clone().filter(|author| !author.trim().is_empty())
+                    .unwrap_or_else(|| metadata.path.clone()),
+                action: "win.open-path".to_string(),
+                target: Some(metadata.path.to_variant()),
+            })
+            .collect();
+        ShelfCommandPalette::new(self, commands, documents).present();
+    }
+
+    pub(super) fn show_shortcuts(&self) {
+        let keybindings = self.imp().config.get().unwrap().read().unwrap().keybindings.clone();
+        shortcuts::window(self, &keybindings).present();
+    }
+
+    pub(super) fn show_usage(&self) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return; };
+        let open_counts = match cache.open_counts() {
+            Ok(open_counts) => open_counts,
+            Err(e) => {
+                warn!("Failed to load open counts: {}", e);
+                return;
+            }
+        };
+        let library = imp.metadata_list.lock().unwrap().clone();
+        let usage = ShelfUsageWindow::new(&library, &open_counts, &imp.volumes.borrow());
+        usage.set_transient_for(Some(self));
+        usage.present();
+    }
+
+    pub(super) fn show_year_review(&self) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        let library = imp.metadata_list.lock().unwrap().clone();
+        let review = ShelfYearReviewWindow::new(cache, library);
+        review.set_transient_for(Some(self));
+        review.present();
+    }
+
+    /// Heading for exports describing what is currently shown in the grid
+    fn current_view_title(&self) -> String {
+        let imp = self.imp();
+        let volume_filter = imp.volume_filter.borrow();
+        let mut title = volume_filter.as_ref()
+            .and_then(|root| imp.volumes.borrow().iter().find(|v| &v.root == root).map(|v| v.name.clone()))
+            .or_else(|| imp.collection_filter.get()
+                .and_then(|id| imp.collection_tree.borrow().get(id).map(|c| c.name.clone())))
+            .unwrap_or_else(|| "My Shelf".to_string());
+        let query = imp.search_entry.text();
+        if !query.is_empty() {
+            title.push_str(&format!(" \u{2014} \"{}\"", query));
+        }
+        title
+    }
+
+    pub(super) fn export_contact_sheet(&self, model: &gio::ListStore) {
+        let items: Vec<PdfMetadata> = (0..model.n_items())
+            .filter_map(|i| model.item(i).and_downcast::<PdfMetadataObject>())
+            .filter_map(|obj| obj.metadata())
+            .collect();
+        if items.is_empty() {
+            self.set_status(StatusModule::Scan, "Nothing to export");
+            return;
+        }
+
+        let dialog = gtk::FileDialog::builder()
+            .title("Export Contact Sheet")
+            .initial_name("contact-sheet.png")
+            .build();
+        dialog.save(Some(self), None::<&gio::Cancellable>, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |result| {
+                let Ok(file) = result else { return; };
+                let Some(path) = file.path() else { return; };
+                let heading = _self.current_view_title();
+                match render_contact_sheet(&_self, &heading, &items)
+                    .and_then(|texture| texture.save_to_png(&path).map_err(Into::into)) {
+                    Ok(()) => _self.toast(&format!("Exported contact sheet to {}", path.display())),
+                    Err(e) => _self.toast_error("Failed to export contact sheet", e),
+                }
+            }
+        ));
+    }
+}
@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use gtk::prelude::*;
+use gtk::{gdk, graphene, gsk, pango};
+
+use crate::pdf::PdfMetadata;
+use crate::ui::cover_loader::load_full_cover;
+
+const MAX_ITEMS: usize = 120;
+const MAX_COLUMNS: usize = 10;
+const MARGIN: f32 = 32.0;
+const HEADER_HEIGHT: f32 = 56.0;
+const CELL_WIDTH: f32 = 180.0;
+const COVER_HEIGHT: f32 = 220.0;
+const CAPTION_HEIGHT: f32 = 44.0;
+const CELL_SPACING: f32 = 16.0;
+
+fn text_layout(widget: &impl IsA<gtk::Widget>, text: &str, font: &str, width: f32, lines: i32) -> pango::Layout {
+    let layout = widget.create_pango_layout(Some(text));
+    layout.set_font_description(Some(&pango::FontDescription::from_string(font)));
+    layout.set_width((width * pango::SCALE as f32) as i32);
+    layout.set_wrap(pango::WrapMode::WordChar);
+    layout.set_ellipsize(pango::EllipsizeMode::End);
+    // A negative height limits the layout to that many lines
+    layout.set_height(-lines);
+    layout
+}
+
+/// Render a grid of covers with their titles into a single image, using the
+/// renderer of `widget`'s window. The widget must be realized.
+pub fn render_contact_sheet(widget: &impl IsA<gtk::Widget>, heading: &str, items: &[PdfMetadata]) -> Result<gdk::Texture> {
+    let items = &items[..items.len().min(MAX_ITEMS)];
+    let columns = ((items.len() as f32).sqrt().ceil() as usize).clamp(1, MAX_COLUMNS);
+    let rows = items.len().div_ceil(columns).max(1);
+
+    let width = MARGIN * 2.0 + columns as f32 * CELL_WIDTH + (columns - 1) as f32 * CELL_SPACING;
+    let cell_height = COVER_HEIGHT + CAPTION_HEIGHT;
+    let height = MARGIN * 2.0 + HEADER_HEIGHT + rows as f32 * cell_height + (rows - 1) as f32 * CELL_SPACING;
+
+    let background = gdk::RGBA::new(0.98, 0.98, 0.97, 1.0);
+    let foreground = gdk::RGBA::new(0.13, 0.13, 0.13, 1.0);
+    let placeholder = gdk::RGBA::new(0.85, 0.85, 0.84, 1.0);
+
+    let snapshot = gtk::Snapshot::new();
+    snapshot.append_color(&background, &graphene::Rect::new(0.0, 0.0, width, height));
+
+    let header = text_layout(widget, heading, "Sans Bold 18", width - MARGIN * 2.0, 1);
+    snapshot.save();
+    snapshot.translate(&graphene::Point::new(MARGIN, MARGIN));
+    snapshot.append_layout(&header, &foreground);
+    snapshot.restore();
+
+    for (index, metadata) in items.iter().enumerate() {
+        let x = MARGIN + (index % columns) as f32 * (CELL_WIDTH + CELL_SPACING);
+        let y = MARGIN + HEADER_HEIGHT + (index / columns) as f32 * (cell_height + CELL_SPACING);
+
+        let texture = metadata.cover_path.as_deref().and_then(load_full_cover);
+        match texture {
+            Some(texture) => {
+                // Fit the cover inside its cell, keeping the aspect ratio
+                let scale = (CELL_WIDTH / texture.width() as f32).min(COVER_HEIGHT / texture.height() as f32);
+                let cover_width = texture.width() as f32 * scale;
+                let cover_height = texture.height() as f32 * scale;
+                let bounds = graphene::Rect::new(
+                    x + (CELL_WIDTH - cover_width) / 2.0,
+                    y + COVER_HEIGHT - cover_height,
+                    cover_width,
+                    cover_height,
+                );
+                snapshot.append_texture(&texture, &bounds);
+            }
+            None => {
+                let bounds = graphene::Rect::new(x + CELL_WIDTH * 0.1, y, CELL_WIDTH * 0.8, COVER_HEIGHT);
+                snapshot.append_color(&placeholder, &bounds);
+            }
+        }
+
+        let caption = text_layout(widget, &metadata.display_title(), "Sans 10", CELL_WIDTH, 2);
+        caption.set_alignment(pango::Alignment::Center);
+        snapshot.save();
+        snapshot.translate(&graphene::Point::new(x, y + COVER_HEIGHT + 6.0));
+        snapshot.append_layout(&caption, &foreground);
+        snapshot.restore();
+    }
+
+    let node: gsk::RenderNode = snapshot.to_node().context("Nothing to render")?;
+    let renderer = widget.native()
+        .and_then(|native| native.renderer())
+        .context("Widget has no renderer, is it realized?")?;
+    Ok(renderer.render_texture(&node, Some(&graphene::Rect::new(0.0, 0.0, width, height))))
+}
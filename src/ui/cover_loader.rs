@@ -0,0 +1,328 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, SystemTime};
+
+use gtk::prelude::*;
+use gtk::{gdk, glib};
+use tracing::warn;
+
+use crate::pdf::{cover_file, cover_size_for, covers_dir, full_cover_file, save_cover_image};
+use crate::ui::grid_item::ShelfGridItem;
+
+// The size grid_item.rs shows covers at, times the scale factor of the
+// screen it's on is the size decoded
+pub const DISPLAY_SIZE: u32 = 128;
+const CACHED_COVERS: usize = 300;
+const WORKERS: usize = 2;
+// Queued prefetches beyond this are dropped, they're too far behind the scroll position
+const MAX_PREFETCH_QUEUE: usize = 64;
+
+// A cover file's mtime is when it was last shown, maintenance evicts the
+// oldest first. Only bumped once in a while to spare the disk.
+const SHOWN_RESOLUTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+const LOW_MEMORY_MAX_SIZE: u32 = 128;
+const LOW_MEMORY_CACHED_COVERS: usize = 64;
+
+type MissingHandler = Box<dyn Fn(&str)>;
+
+#[derive(Default)]
+struct Queue {
+    // Cover file names, covers needed on screen go to the front
+    names: VecDeque<String>,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    ready: Condvar,
+    // One of the sizes in COVER_SIZES
+    size: AtomicU32,
+}
+
+/// Decodes covers on a small pool of background threads and keeps the most
+/// recent ones around as textures, so grid items can show them without
+/// touching the disk on bind
+pub struct CoverLoader {
+    shared: Arc<Shared>,
+    prefetch_enabled: bool,
+    capacity: usize,
+    max_size: u32,
+    textures: RefCell<HashMap<String, gdk::Texture>>,
+    // Least recently used first, a hit moves a cover to the back
+    order: RefCell<VecDeque<String>>,
+    // Queued or being decoded
+    requested: RefCell<HashSet<String>>,
+    waiters: RefCell<HashMap<String, Vec<glib::WeakRef<ShelfGridItem>>>>,
+    // Covers evicted from the disk, handed to `on_missing` to be rendered
+    // again. Their waiters wait for `reload`.
+    restoring: RefCell<HashSet<String>>,
+    on_missing: RefCell<Option<MissingHandler>>,
+}
+
+fn mark_shown(path: &Path) {
+    let now = SystemTime::now();
+    let stale = fs::metadata(path).and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() > SHOWN_RESOLUTION);
+    if stale && let Err(e) = fs::File::options().write(true).open(path).and_then(|file| file.set_modified(now)) {
+        warn!("Failed to update {}: {}", path.display(), e);
+    }
+}
+
+fn open_cover(path: &Path) -> Option<image::DynamicImage> {
+    match image::open(path) {
+        Ok(cover) => Some(cover),
+        Err(e) => {
+            if path.exists() {
+                warn!("Failed to load cover {}: {}", path.display(), e);
+            }
+            None
+        }
+    }
+}
+
+fn decode_cover(name: &str, size: u32) -> Option<gdk::Texture> {
+    let full_path = full_cover_file(name);
+    let file = cover_file(name, size);
+    let path = if file == name { full_path.clone() } else { covers_dir().join(file) };
+    let cover = match open_cover(&path) {
+        Some(cover) => cover,
+        // Saved before covers came in several sizes, or evicted on its own
+        None if path != full_path && !path.exists() => {
+            let cover = open_cover(&full_path)?.thumbnail(size, size);
+            if let Err(e) = save_cover_image(&cover.to_rgb8(), &path) {
+                warn!("Failed to save cover {}: {}", path.display(), e);
+            }
+            cover
+        }
+        None => return None,
+    };
+    mark_shown(&path);
+    Some(texture_of(cover.thumbnail(size, size)))
+}
+
+fn texture_of(cover: image::DynamicImage) -> gdk::Texture {
+    let cover = cover.to_rgb8();
+    let (width, height) = cover.dimensions();
+    let texture = gdk::MemoryTexture::new(
+        width as i32,
+        height as i32,
+        gdk::MemoryFormat::R8g8b8,
+        &glib::Bytes::from_owned(cover.into_raw()),
+        width as usize * 3,
+    );
+    texture.upcast()
+}
+
+/// Cover `name` at full size, decoded here rather than by GDK, which can't
+/// read WebP without an extra gdk-pixbuf loader
+pub fn load_full_cover(name: &str) -> Option<gdk::Texture> {
+    open_cover(&full_cover_file(name)).map(texture_of)
+}
+
+fn run_worker(shared: Arc<Shared>, tx: async_channel::Sender<(String, Option<gdk::Texture>)>) {
+    loop {
+        let name = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.closed { return; }
+                if let Some(name) = queue.names.pop_front() { break name; }
+                queue = shared.ready.wait(queue).unwrap();
+            }
+        };
+        let texture = decode_cover(&name, shared.size.load(Ordering::Relaxed));
+        if tx.send_blocking((name, texture)).is_err() { return; }
+    }
+}
+
+impl CoverLoader {
+    pub fn new(low_memory: bool) -> Rc<Self> {
+        let (max_size, capacity, workers) = if low_memory {
+            (LOW_MEMORY_MAX_SIZE, LOW_MEMORY_CACHED_COVERS, 1)
+        } else {
+            (u32::MAX, CACHED_COVERS, WORKERS)
+        };
+
+        // Until the window knows its screen, HiDPI is the safer guess
+        let size = cover_size_for(DISPLAY_SIZE * 2).min(max_size);
+        let shared = Arc::new(Shared { size: AtomicU32::new(size), ..Shared::default() });
+        let (tx, rx) = async_channel::unbounded();
+        for _ in 0..workers {
+            let shared = shared.clone();
+            let tx = tx.clone();
+            std::thread::spawn(move || run_worker(shared, tx));
+        }
+
+        let loader = Rc::new(Self {
+            shared,
+            prefetch_enabled: !low_memory,
+            capacity,
+            max_size,
+            textures: RefCell::default(),
+            order: RefCell::default(),
+            requested: RefCell::default(),
+            waiters: RefCell::default(),
+            restoring: RefCell::default(),
+            on_missing: RefCell::default(),
+        });
+
+        let weak = Rc::downgrade(&loader);
+        glib::spawn_future_local(async move {
+            while let Ok((name, texture)) = rx.recv().await {
+                let Some(loader) = weak.upgrade() else { break; };
+                loader.finish(name, texture);
+            }
+        });
+        loader
+    }
+
+    /// Decode covers at the smallest saved size that's sharp at `pixels`,
+    /// e.g. when the window moves to a screen with another scale factor
+    pub fn set_display_size(&self, pixels: u32) {
+        let size = cover_size_for(pixels).min(self.max_size);
+        if self.shared.size.swap(size, Ordering::Relaxed) == size { return; }
+        self.textures.borrow_mut().clear();
+        self.order.borrow_mut().clear();
+    }
+
+    pub fn cached(&self, name: &str) -> Option<gdk::Texture> {
+        let texture = self.textures.borrow().get(name).cloned()?;
+        let mut order = self.order.borrow_mut();
+        if order.back().is_none_or(|last| last != name)
+            && let Some(index) = order.iter().position(|other| other == name) {
+            let name = order.remove(index).unwrap();
+            order.push_back(name);
+        }
+        Some(texture)
+    }
+
+    /// Drop a cover that was rendered again, it's decoded afresh next time
+    pub fn forget(&self, name: &str) {
+        if self.textures.borrow_mut().remove(name).is_some() {
+            self.order.borrow_mut().retain(|other| other != name);
+        }
+    }
+
+    /// Call `on_missing` with the name of a cover whose file is gone, e.g.
+    /// evicted by maintenance. Only once per name, unless it's found again.
+    pub fn connect_missing(&self, on_missing: impl Fn(&str) + 'static) {
+        self.on_missing.replace(Some(Box::new(on_missing)));
+    }
+
+    /// Decode a cover again after its file was written, for the items
+    /// waiting for it
+    pub fn reload(&self, name: &str) {
+        self.forget(name);
+        if !self.waiters.borrow().contains_key(name) { return; }
+        let mut queue = self.shared.queue.lock().unwrap();
+        if self.requested.borrow_mut().insert(name.to_string()) {
+            queue.names.push_front(name.to_string());
+        }
+        drop(queue);
+        self.shared.ready.notify_one();
+    }
+
+    /// Load a cover needed on screen now, `item` gets it once it's decoded
+    pub fn request(&self, name: &str, item: &ShelfGridItem) {
+        self.waiters.borrow_mut().entry(name.to_string()).or_default().push(item.downgrade());
+        let mut queue = self.shared.queue.lock().unwrap();
+        if self.requested.borrow_mut().insert(name.to_string()) {
+            queue.names.push_front(name.to_string());
+        } else if let Some(index) = queue.names.iter().position(|queued| queued == name) {
+            // Already queued as a prefetch, move it up
+            let name = queue.names.remove(index).unwrap();
+            queue.names.push_front(name);
+        }
+        drop(queue);
+        self.shared.ready.notify_one();
+    }
+
+    /// `item` was unbound or rebound before its cover came in. The cover
+    /// stays queued like a prefetch, unless nothing else waits for it.
+    pub fn withdraw(&self, name: &str, item: &ShelfGridItem) {
+        let mut waiters = self.waiters.borrow_mut();
+        let Some(items) = waiters.get_mut(name) else { return; };
+        items.retain(|other| other.upgrade().is_some_and(|other| &other != item));
+        if items.is_empty() { waiters.remove(name); }
+    }
+
+    /// Queue covers that are likely to scroll into view soon
+    pub fn prefetch<I: IntoIterator<Item = String>>(&self, names: I) {
+        if !self.prefetch_enabled { return; }
+        let mut queue = self.shared.queue.lock().unwrap();
+        let mut requested = self.requested.borrow_mut();
+        for name in names {
+            if self.textures.borrow().contains_key(&name) || !requested.insert(name.clone()) { continue; }
+            queue.names.push_back(name);
+        }
+        // Trim the newest prefetches, covers waited for on screen stay
+        let waiters = self.waiters.borrow();
+        let mut excess = queue.names.len().saturating_sub(MAX_PREFETCH_QUEUE);
+        let mut index = queue.names.len();
+        while excess > 0 && index > 0 {
+            index -= 1;
+            if waiters.contains_key(&queue.names[index]) { continue; }
+            let name = queue.names.remove(index).unwrap();
+            requested.remove(&name);
+            excess -= 1;
+        }
+        drop(waiters);
+        drop(queue);
+        self.shared.ready.notify_all();
+    }
+
+    /// Drop queued prefetches, e.g. when the grid is refiltered and they no
+    /// longer match what's shown
+    pub fn cancel_prefetch(&self) {
+        let waiters = self.waiters.borrow();
+        let mut requested = self.requested.borrow_mut();
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.names.retain(|name| {
+            let wanted = waiters.contains_key(name);
+            if !wanted { requested.remove(name); }
+            wanted
+        });
+    }
+
+    fn finish(&self, name: String, texture: Option<gdk::Texture>) {
+        self.requested.borrow_mut().remove(&name);
+        if texture.is_none()
+            && self.waiters.borrow().contains_key(&name)
+            && !full_cover_file(&name).exists()
+            && let Some(on_missing) = self.on_missing.borrow().as_ref()
+            && self.restoring.borrow_mut().insert(name.clone()) {
+            on_missing(&name);
+            return;
+        }
+        if let Some(texture) = &texture {
+            self.restoring.borrow_mut().remove(&name);
+            let mut textures = self.textures.borrow_mut();
+            let mut order = self.order.borrow_mut();
+            if textures.insert(name.clone(), texture.clone()).is_none() {
+                order.push_back(name.clone());
+            }
+            while textures.len() > self.capacity {
+                let Some(oldest) = order.pop_front() else { break; };
+                textures.remove(&oldest);
+            }
+        }
+
+        let waiters = self.waiters.borrow_mut().remove(&name).unwrap_or_default();
+        for item in waiters.iter().filter_map(|item| item.upgrade()) {
+            item.show_cover(&name, texture.as_ref());
+        }
+    }
+}
+
+impl Drop for CoverLoader {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().closed = true;
+        self.shared.ready.notify_all();
+    }
+}
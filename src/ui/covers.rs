@@ -0,0 +1,309 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::gio;
+use tracing::{info, warn};
+
+use crate::lookup::{self, LOOKUP_INTERVAL};
+use crate::pdf::{remove_custom_cover, restore_cover, set_cover_encoding, set_custom_cover, CoverEncoding, PdfMetadata};
+use crate::ui::models::PdfMetadataObject;
+use crate::ui::status_bar::StatusModule;
+use crate::ui::window::ShelfWindow;
+
+// How many covers past the last bound item to decode ahead of scrolling
+const PREFETCH_AHEAD: u32 = 24;
+
+impl ShelfWindow {
+    pub(super) fn setup_covers_actions(&self, model: &gio::ListStore) {
+        let set_custom_cover = gio::ActionEntry::builder("set-custom-cover")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.choose_custom_cover(&model)
+            ))
+            .build();
+
+        let fetch_online_cover = gio::ActionEntry::builder("fetch-online-cover")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.fetch_online_cover(&model)
+            ))
+            .build();
+
+        let remove_custom_cover = gio::ActionEntry::builder("remove-custom-cover")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.change_cover(&model, None)
+            ))
+            .build();
+
+        self.add_action_entries([set_custom_cover, fetch_online_cover, remove_custom_cover]);
+    }
+
+    /// Render covers evicted by maintenance again when they're shown, one at
+    /// a time on a thread of their own
+    pub(super) fn restore_evicted_covers(&self) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        let (request_tx, request_rx) = async_channel::unbounded::<PdfMetadata>();
+        let (done_tx, done_rx) = async_channel::unbounded();
+        std::thread::spawn(move || {
+            while let Ok(metadata) = request_rx.recv_blocking() {
+                let name = metadata.cover_path.clone().unwrap_or_default();
+                let restored = restore_cover(&metadata, &cache)
+                    .inspect_err(|e| warn!("Failed to render the cover of {} again: {:#}", metadata.path, e))
+                    .is_ok();
+                if done_tx.send_blocking((name, restored)).is_err() { return; }
+            }
+        });
+        imp.cover_loader.get().unwrap().connect_missing(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |name| {
+                // Documents whose file is gone can't be rendered
+                let metadata = _self.imp().metadata_list.lock().unwrap().iter()
+                    .find(|pdf| !pdf.missing && pdf.cover_path.as_deref() == Some(name))
+                    .cloned();
+                if let Some(metadata) = metadata {
+                    let _ = request_tx.send_blocking(metadata);
+                }
+            }
+        ));
+        glib::spawn_future_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            async move {
+                while let Ok((name, restored)) = done_rx.recv().await {
+                    if restored { _self.imp().cover_loader.get().unwrap().reload(&name); }
+                }
+            }
+        ));
+    }
+
+    fn choose_custom_cover(&self, model: &gio::ListStore) {
+        if self.imp().selected.lock().unwrap().is_none() { return; }
+        let images = gtk::FileFilter::new();
+        images.set_name(Some("Images"));
+        // Whatever the image crate reads
+        for suffix in ["png", "jpg", "jpeg", "webp", "gif", "bmp", "tif", "tiff"] {
+            images.add_suffix(suffix);
+        }
+        let filters = gio::ListStore::new::<gtk::FileFilter>();
+        filters.append(&images);
+        let dialog = gtk::FileDialog::builder()
+            .title("Choose a Cover")
+            .accept_label("Use as Cover")
+            .filters(&filters)
+            .build();
+        dialog.open(Some(self), None::<&gio::Cancellable>, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[strong] model,
+            move |result| {
+                if let Some(path) = result.ok().and_then(|file| file.path()) {
+                    _self.change_cover(&model, Some(path));
+                }
+            }
+        ));
+    }
+
+    /// Make the image at `image` the cover of the selected document, or
+    /// render its first page again if None. Rescans keep a custom cover.
+    fn change_cover(&self, model: &gio::ListStore, image: Option<PathBuf>) {
+        let imp = self.imp();
+        let Some(selected) = imp.selected.lock().unwrap().clone() else { return; };
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        let (hash, old_cover) = (selected.hash.clone(), selected.cover_path.clone());
+        let (tx, rx) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            let changed = match image {
+                Some(image) => set_custom_cover(&selected.hash, &image, &cache),
+                None => remove_custom_cover(selected, &cache)
+                    .and_then(|updated| updated.cover_path.context("The first page couldn't be rendered")),
+            };
+            let _ = tx.send_blocking(changed.map_err(|e| format!("{:#}", e)));
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(changed) = rx.recv().await else { return; };
+                let cover = match changed {
+                    Ok(cover) => cover,
+                    Err(e) => {
+                        _self.toast_error("Failed to change the cover", e);
+                        return;
+                    }
+                };
+                _self.show_new_cover(&model, &hash, old_cover, cover);
+            }
+        ));
+    }
+
+    fn show_new_cover(&self, model: &gio::ListStore, hash: &str, old_cover: Option<String>, cover: String) {
+        let imp = self.imp();
+        let loader = imp.cover_loader.get().unwrap();
+        for name in old_cover.iter().chain([&cover]) {
+            loader.forget(name);
+        }
+        for pdf in imp.metadata_list.lock().unwrap().iter_mut().filter(|pdf| pdf.hash == hash) {
+            pdf.cover_path = Some(cover.clone());
+        }
+        if let Some(selected) = imp.selected.lock().unwrap().as_mut().filter(|selected| selected.hash == hash) {
+            selected.cover_path = Some(cover.clone());
+        }
+        self.refresh_model_in_place(model);
+        self.update_cover_actions();
+    }
+
+    /// Look the cover of the selected document up online, whether or not
+    /// `fetch_online_covers` is on
+    fn fetch_online_cover(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        let Some(selected) = imp.selected.lock().unwrap().clone() else { return; };
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        let title = selected.display_title();
+        if !lookup::can_look_up(&selected) {
+            self.toast(&format!("{} has no ISBN, title or author to look it up by", title));
+            return;
+        }
+        self.set_status(StatusModule::Sync, &format!("Looking up the cover of {}...", title));
+        let (tx, rx) = async_channel::bounded(1);
+        let (hash, old_cover) = (selected.hash.clone(), selected.cover_path.clone());
+        std::thread::spawn(move || {
+            let _ = tx.send_blocking(lookup::fetch_online_cover(&selected, &cache).map_err(|e| format!("{:#}", e)));
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(fetched) = rx.recv().await else { return; };
+                _self.update_sync_status();
+                match fetched {
+                    Ok(Some(cover)) => {
+                        _self.show_new_cover(&model, &hash, old_cover, cover);
+                        _self.toast(&format!("Found a cover for {}", title));
+                    }
+                    Ok(None) => _self.toast(&format!("No cover found online for {}", title)),
+                    Err(e) => _self.toast_error("Failed to look up the cover", e),
+                }
+            }
+        ));
+    }
+
+    /// With `fetch_online_covers` on, look up the covers of the books that
+    /// haven't been looked up yet, one at a time in the background. Waits
+    /// for a running scan, which calls it once it's done.
+    pub(super) fn fetch_online_covers(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        if !imp.config.get().unwrap().read().unwrap().fetch_online_covers || !imp.refresh_button.is_sensitive() { return; }
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        if imp.fetching_covers.replace(true) { return; }
+
+        let custom = cache.custom_covers().map(|covers| covers.into_keys().collect()).unwrap_or_else(|e| {
+            warn!("Failed to load custom covers: {:#}", e);
+            HashSet::new()
+        });
+        let mut skipped = cache.cover_lookups().unwrap_or_else(|e| {
+            warn!("Failed to load cover lookups: {:#}", e);
+            HashSet::new()
+        });
+        skipped.extend(custom);
+        let books: Vec<PdfMetadata> = imp.metadata_list.lock().unwrap().iter()
+            .filter(|pdf| !pdf.missing && lookup::can_look_up(pdf) && skipped.insert(pdf.hash.clone()))
+            .cloned()
+            .collect();
+        if books.is_empty() {
+            imp.fetching_covers.set(false);
+            return;
+        }
+        info!("Looking up {} covers online", books.len());
+        let (tx, rx) = async_channel::unbounded();
+        std::thread::spawn(move || {
+            let mut failures = 0;
+            for (index, book) in books.iter().enumerate() {
+                if index > 0 { std::thread::sleep(LOOKUP_INTERVAL); }
+                match lookup::fetch_online_cover(book, &cache) {
+                    Ok(cover) => {
+                        failures = 0;
+                        let Some(cover) = cover else { continue; };
+                        if tx.send_blocking((book.hash.clone(), book.cover_path.clone(), cover)).is_err() { return; }
+                    }
+                    Err(e) => {
+                        warn!("Failed to look up the cover of {}: {:#}", book.path, e);
+                        // Most likely offline, the rest is tried after the next scan
+                        failures += 1;
+                        if failures == 3 { return; }
+                    }
+                }
+            }
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            async move {
+                let mut found = 0;
+                while let Ok((hash, old_cover, cover)) = rx.recv().await {
+                    found += 1;
+                    _self.show_new_cover(&model, &hash, old_cover, cover);
+                }
+                info!("Found {} covers online", found);
+                _self.imp().fetching_covers.set(false);
+            }
+        ));
+    }
+
+    pub(super) fn update_cover_actions(&self) {
+        let imp = self.imp();
+        let hash = imp.selected.lock().unwrap().as_ref().map(|selected| selected.hash.clone());
+        let custom = hash.zip(imp.cache.get()).is_some_and(|(hash, cache)| {
+            cache.custom_cover(&hash).inspect_err(|e| warn!("Failed to load the custom cover: {}", e)).ok().flatten().is_some()
+        });
+        if let Some(action) = self.lookup_action("remove-custom-cover").and_downcast::<gio::SimpleAction>() {
+            action.set_enabled(custom);
+        }
+    }
+
+    /// An image dropped onto the preview becomes the cover of the selected document
+    pub(super) fn setup_cover_drop(&self, model: gio::ListStore) {
+        let drop_target = gtk::DropTarget::new(gtk::gdk::FileList::static_type(), gtk::gdk::DragAction::COPY);
+        drop_target.connect_drop(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] false,
+            move |_, value, _, _| {
+                let Ok(files) = value.get::<gtk::gdk::FileList>() else { return false; };
+                let Some(path) = files.files().first().and_then(|file| file.path()) else { return false; };
+                if _self.imp().selected.lock().unwrap().is_none() { return false; }
+                _self.change_cover(&model, Some(path));
+                true
+            }
+        ));
+        self.imp().preview_content.add_controller(drop_target);
+    }
+
+    pub(super) fn apply_cover_encoding(&self) {
+        let config = self.imp().config.get().unwrap().read().unwrap();
+        set_cover_encoding(CoverEncoding {
+            format: config.cover_format,
+            quality: config.cover_quality,
+            render_scale: config.cover_render_scale,
+        });
+    }
+
+    /// Queue the covers just past `position` in the direction the grid is
+    /// being scrolled. Items are bound at the edge of the view that's moving,
+    /// so this stays ahead of what's about to be shown.
+    pub(super) fn prefetch_covers(&self, model: &gio::ListStore, position: u32) {
+        let imp = self.imp();
+        let range = if imp.scrolling_up.get() {
+            position.saturating_sub(PREFETCH_AHEAD)..position
+        } else {
+            position + 1..(position + 1 + PREFETCH_AHEAD).min(model.n_items())
+        };
+        let names: Vec<String> = range
+            .filter_map(|i| model.item(i).and_downcast::<PdfMetadataObject>())
+            .filter_map(|object| object.metadata().and_then(|metadata| metadata.cover_path))
+            .collect();
+        imp.cover_loader.get().unwrap().prefetch(names);
+    }
+}
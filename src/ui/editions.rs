@@ -0,0 +1,216 @@
+use std::path::Path;
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::gio;
+use tracing::warn;
+
+use crate::editions::{suggestions, Editions};
+use crate::pdf::PdfMetadata;
+use crate::ui::window::ShelfWindow;
+
+impl ShelfWindow {
+    pub(super) fn setup_editions_actions(&self, model: &gio::ListStore) {
+        let group_editions = gio::ActionEntry::builder("group-editions")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| {
+                    let hashes: Vec<String> = window.workspace_documents().into_iter().map(|metadata| metadata.hash).collect();
+                    window.link_editions(&model, &hashes);
+                }
+            ))
+            .build();
+
+        let prefer_edition = gio::ActionEntry::builder("prefer-edition")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.prefer_edition(&model)
+            ))
+            .build();
+
+        let unlink_edition = gio::ActionEntry::builder("unlink-edition")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.unlink_edition(&model)
+            ))
+            .build();
+
+        let find_editions = gio::ActionEntry::builder("find-editions")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.find_editions(&model)
+            ))
+            .build();
+
+        self.add_action_entries([group_editions, prefer_edition, unlink_edition, find_editions]);
+    }
+
+    pub(super) fn refresh_editions(&self) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return; };
+        let editions = Editions::load(cache).unwrap_or_else(|e| {
+            warn!("Failed to load editions: {}", e);
+            Editions::default()
+        });
+        imp.editions.replace(editions);
+        self.update_edition_actions();
+        // Counted without the hidden editions
+        self.refresh_author_list();
+        self.refresh_year_list();
+    }
+
+    fn update_edition_actions(&self) {
+        let imp = self.imp();
+        let hash = imp.selected.lock().unwrap().as_ref().map(|selected| selected.hash.clone());
+        let editions = imp.editions.borrow();
+        let is_edition = hash.as_deref().is_some_and(|hash| editions.is_edition(hash));
+        let is_preferred = hash.as_deref().is_some_and(|hash| editions.is_preferred(hash));
+        for (name, enabled) in [("prefer-edition", is_edition && !is_preferred), ("unlink-edition", is_edition)] {
+            if let Some(action) = self.lookup_action(name).and_downcast::<gio::SimpleAction>() {
+                action.set_enabled(enabled);
+            }
+        }
+    }
+
+    pub(super) fn load_editions(&self, hash: &str) {
+        let imp = self.imp();
+        let others: Vec<PdfMetadata> = {
+            let editions = imp.editions.borrow();
+            let files = imp.metadata_list.lock().unwrap();
+            editions.others(hash).into_iter()
+                .filter_map(|other| files.iter().find(|pdf| pdf.hash == other).cloned())
+                .collect()
+        };
+
+        imp.editions_list.remove_all();
+        for metadata in &others {
+            let row = gtk::Box::builder().spacing(6).build();
+            let labels = gtk::Box::builder().orientation(gtk::Orientation::Vertical).hexpand(true).build();
+            let title = gtk::Label::builder()
+                .label(metadata.display_title())
+                .xalign(0.0)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .build();
+            let file_name = Path::new(&metadata.path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            let details = gtk::Label::builder()
+                .label(format!("{} · {} pages", file_name, metadata.page_count))
+                .xalign(0.0)
+                .ellipsize(gtk::pango::EllipsizeMode::Middle)
+                .build();
+            details.add_css_class("dim-label");
+            labels.append(&title);
+            labels.append(&details);
+            row.append(&labels);
+            if imp.editions.borrow().is_preferred(&metadata.hash) {
+                let preferred = gtk::Image::from_icon_name("starred-symbolic");
+                preferred.set_tooltip_text(Some("Preferred edition, shown in the grid"));
+                row.append(&preferred);
+            }
+            row.set_tooltip_text(Some(&metadata.path));
+            imp.editions_list.append(&row);
+        }
+
+        let any = !others.is_empty();
+        imp.editions_heading.set_visible(any);
+        imp.editions_list.set_visible(any);
+        imp.edition_rows.replace(others.into_iter().map(|metadata| metadata.path).collect());
+        self.update_edition_actions();
+    }
+
+    fn link_editions(&self, model: &gio::ListStore, hashes: &[String]) {
+        let Some(cache) = self.imp().cache.get() else { return; };
+        if hashes.len() < 2 { return; }
+        if let Err(e) = cache.link_editions(hashes) {
+            self.toast_error("Failed to group editions", e);
+            return;
+        }
+        self.refresh_editions();
+        self.populate_model(model);
+    }
+
+    /// Show the selected edition in the grid instead of the others
+    fn prefer_edition(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return; };
+        let Some(selected) = imp.selected.lock().unwrap().clone() else { return; };
+        if let Err(e) = cache.set_preferred_edition(&selected.hash) {
+            self.toast_error("Failed to prefer edition", e);
+            return;
+        }
+        self.refresh_editions();
+        imp.pending_selection.replace(Some(selected.path));
+        self.populate_model(model);
+    }
+
+    fn unlink_edition(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return; };
+        let Some(selected) = imp.selected.lock().unwrap().clone() else { return; };
+        if let Err(e) = cache.unlink_edition(&selected.hash) {
+            self.toast_error("Failed to unlink edition", e);
+            return;
+        }
+        self.refresh_editions();
+        imp.pending_selection.replace(Some(selected.path));
+        self.populate_model(model);
+    }
+
+    /// Documents that look like editions of each other, to group with a click
+    fn find_editions(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        let groups: Vec<Vec<PdfMetadata>> = {
+            let files = imp.metadata_list.lock().unwrap();
+            suggestions(&files, &imp.editions.borrow()).into_iter()
+                .map(|group| group.into_iter().cloned().collect())
+                .collect()
+        };
+        let dialog = gtk::Window::builder()
+            .title("Find Editions")
+            .transient_for(self)
+            .default_width(560)
+            .default_height(420)
+            .build();
+        let list = gtk::ListBox::builder().selection_mode(gtk::SelectionMode::None).build();
+        list.set_placeholder(Some(&gtk::Label::new(Some("No documents look like editions of each other"))));
+        for group in groups {
+            let row = gtk::Box::builder()
+                .spacing(12)
+                .margin_start(12)
+                .margin_end(12)
+                .margin_top(6)
+                .margin_bottom(6)
+                .build();
+            let labels = gtk::Box::builder().orientation(gtk::Orientation::Vertical).spacing(2).hexpand(true).build();
+            let title = gtk::Label::builder().label(group[0].display_title()).xalign(0.0).wrap(true).build();
+            title.add_css_class("heading");
+            labels.append(&title);
+            for metadata in &group {
+                let path = gtk::Label::builder()
+                    .label(&metadata.path)
+                    .xalign(0.0)
+                    .ellipsize(gtk::pango::EllipsizeMode::Middle)
+                    .tooltip_text(&metadata.path)
+                    .build();
+                path.add_css_class("dim-label");
+                labels.append(&path);
+            }
+            let group_button = gtk::Button::builder().label("Group").valign(gtk::Align::Center).build();
+            let hashes: Vec<String> = group.iter().map(|metadata| metadata.hash.clone()).collect();
+            group_button.connect_clicked(glib::clone!(
+                #[weak(rename_to = _self)] self,
+                #[weak] model,
+                #[weak] row,
+                move |_| {
+                    _self.link_editions(&model, &hashes);
+                    row.set_sensitive(false);
+                }
+            ));
+            row.append(&labels);
+            row.append(&group_button);
+            list.append(&row);
+        }
+        dialog.set_child(Some(&gtk::ScrolledWindow::builder().child(&list).build()));
+        dialog.present();
+    }
+}
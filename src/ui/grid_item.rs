@@ -1,9 +1,19 @@
-use gtk::glib;
+use std::path::Path;
+
+use gtk::{gdk, gio, glib};
+use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 
+use crate::config::Config;
+use crate::language;
+use crate::ui::accessibility::prefers_high_contrast;
+use crate::ui::cover_loader::CoverLoader;
 use crate::ui::models::PdfMetadataObject;
+use crate::utils::human_readable_file_size;
 
 mod imp {
+    use std::cell::{OnceCell, RefCell};
+
     use super::*; 
 
     #[derive(Default, gtk::CompositeTemplate)]
@@ -22,9 +32,86 @@ mod imp {
             <!-- </style> -->
             
             <child>
-              <object class="GtkImage" id="cover_image">
-                <property name="pixel-size">128</property>
+              <object class="GtkOverlay">
                 <property name="halign">center</property>
+                <child>
+                  <object class="GtkImage" id="cover_image">
+                    <property name="pixel-size">128</property>
+                    <property name="halign">center</property>
+                  </object>
+                </child>
+                <child type="overlay">
+                  <object class="GtkLabel" id="duplicate_badge">
+                    <property name="label">Duplicate</property>
+                    <property name="halign">start</property>
+                    <property name="valign">start</property>
+                    <property name="margin-start">4</property>
+                    <property name="margin-top">4</property>
+                    <property name="visible">false</property>
+                    <style>
+                      <class name="cover-badge"/>
+                      <class name="duplicate"/>
+                    </style>
+                  </object>
+                </child>
+                <child type="overlay">
+                  <object class="GtkLabel" id="continue_badge">
+                    <property name="halign">end</property>
+                    <property name="valign">start</property>
+                    <property name="margin-end">4</property>
+                    <property name="margin-top">4</property>
+                    <property name="visible">false</property>
+                    <style>
+                      <class name="cover-badge"/>
+                      <class name="continue"/>
+                    </style>
+                  </object>
+                </child>
+                <child type="overlay">
+                  <object class="GtkBox" id="badges">
+                    <property name="orientation">vertical</property>
+                    <property name="spacing">2</property>
+                    <property name="halign">end</property>
+                    <property name="valign">end</property>
+                    <property name="margin-end">4</property>
+                    <property name="margin-bottom">4</property>
+                    <child>
+                      <object class="GtkLabel" id="language_badge">
+                        <property name="halign">end</property>
+                        <style>
+                          <class name="cover-badge"/>
+                        </style>
+                      </object>
+                    </child>
+                    <child>
+                      <object class="GtkLabel" id="pages_badge">
+                        <property name="halign">end</property>
+                        <style>
+                          <class name="cover-badge"/>
+                        </style>
+                      </object>
+                    </child>
+                    <child>
+                      <object class="GtkLabel" id="size_badge">
+                        <property name="halign">end</property>
+                        <style>
+                          <class name="cover-badge"/>
+                        </style>
+                      </object>
+                    </child>
+                  </object>
+                </child>
+              </object>
+            </child>
+            <child>
+              <object class="GtkLabel" id="title_label">
+                <property name="wrap">true</property>
+                <property name="lines">2</property>
+                <property name="ellipsize">end</property>
+                <property name="justify">center</property>
+                <property name="max-width-chars">16</property>
+                <property name="margin-top">6</property>
+                <property name="visible">false</property>
               </object>
             </child>
             
@@ -34,6 +121,23 @@ mod imp {
     pub struct ShelfGridItem {
         #[template_child]
         pub cover_image: TemplateChild<gtk::Image>,
+        #[template_child]
+        pub badges: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub language_badge: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub pages_badge: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub size_badge: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub duplicate_badge: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub continue_badge: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub title_label: TemplateChild<gtk::Label>,
+
+        pub item: RefCell<Option<PdfMetadataObject>>,
+        pub context_menu: OnceCell<gtk::PopoverMenu>,
     }
 
     #[glib::object_subclass]
@@ -51,7 +155,35 @@ mod imp {
         }
     }
 
-    impl ObjectImpl for ShelfGridItem {}
+    impl ObjectImpl for ShelfGridItem {
+        fn constructed(&self) {
+            self.parent_constructed();
+            let obj = self.obj();
+            obj.add_css_class("grid-item");
+
+            let context_menu = gtk::PopoverMenu::from_model(None::<&gio::MenuModel>);
+            context_menu.set_parent(&*obj);
+            context_menu.set_has_arrow(false);
+            context_menu.set_halign(gtk::Align::Start);
+            self.context_menu.set(context_menu).unwrap();
+
+            let gesture = gtk::GestureClick::builder().button(gdk::BUTTON_SECONDARY).build();
+            gesture.connect_pressed(glib::clone!(
+                #[weak] obj,
+                move |gesture, _, x, y| {
+                    gesture.set_state(gtk::EventSequenceState::Claimed);
+                    obj.show_context_menu(x, y);
+                }
+            ));
+            obj.add_controller(gesture);
+        }
+
+        fn dispose(&self) {
+            if let Some(context_menu) = self.context_menu.get() {
+                context_menu.unparent();
+            }
+        }
+    }
     impl WidgetImpl for ShelfGridItem {}
     impl BoxImpl for ShelfGridItem {}
 }
@@ -67,19 +199,117 @@ impl ShelfGridItem {
         glib::Object::builder().build()
     }
 
-    pub fn bind(&self, pdf_metadata_object: &PdfMetadataObject) {
+    pub fn bind(&self, pdf_metadata_object: &PdfMetadataObject, config: &Config, covers: &CoverLoader) {
         let imp = self.imp();
+        self.unbind(covers);
+        imp.item.replace(Some(pdf_metadata_object.clone()));
+        imp.badges.set_visible(config.show_cover_badges);
+        imp.duplicate_badge.set_visible(!pdf_metadata_object.duplicates().is_empty());
+        let continue_page = pdf_metadata_object.continue_page();
+        imp.continue_badge.set_visible(continue_page.is_some());
+        if let Some(page) = continue_page {
+            imp.continue_badge.set_text(&format!("p. {}", page + 1));
+            imp.continue_badge.set_tooltip_text(Some(&format!("Continue reading from page {}", page + 1)));
+        }
         if let Some(metadata) = pdf_metadata_object.metadata() {
-            if let Some(cover_path) = metadata.cover_path {
-                let cover_path = dirs::home_dir().unwrap().join(".shelf").join("covers").join(cover_path);
-                if std::path::Path::new(&cover_path).exists() {
-                    imp.cover_image.set_from_file(Some(&cover_path));
-                } else {
+            // Covers alone say nothing to screen readers, and can be hard to
+            // tell apart with high contrast on
+            let title = metadata.display_title();
+            let mut name = match metadata.author.as_deref().map(str::trim).filter(|author| !author.is_empty()) {
+                Some(author) => format!("{}, by {}", title, author),
+                None => title.clone(),
+            };
+            if metadata.missing {
+                self.add_css_class("missing-document");
+                self.set_tooltip_text(Some(&format!("Missing, {} wasn't found by the last scan", metadata.path)));
+                name.push_str(" (missing)");
+            } else {
+                self.remove_css_class("missing-document");
+                self.set_tooltip_text(None);
+            }
+            self.update_property(&[gtk::accessible::Property::Label(&name)]);
+            imp.title_label.set_text(&title);
+            imp.title_label.set_visible(prefers_high_contrast());
+
+            imp.language_badge.set_visible(metadata.language.is_some());
+            if let Some(code) = metadata.language.as_deref() {
+                imp.language_badge.set_text(&code.to_uppercase());
+                imp.language_badge.set_tooltip_text(Some(language::name(code)));
+            }
+            imp.pages_badge.set_text(&format!("{} p", metadata.page_count));
+            imp.size_badge.set_text(&human_readable_file_size(metadata.file_size));
+
+            match metadata.cover_path.as_deref().map(|name| (name, covers.cached(name))) {
+                Some((_, Some(texture))) => imp.cover_image.set_paintable(Some(&texture)),
+                Some((name, None)) => {
                     imp.cover_image.set_icon_name(Some("x-office-document"));
+                    covers.request(name, self);
                 }
-            } else {
-                imp.cover_image.set_icon_name(Some("x-office-document"));
-            } 
+                None => imp.cover_image.set_icon_name(Some("x-office-document")),
+            }
         }
     } 
+
+    /// Let go of the document and its cover, e.g. when the item scrolls out
+    /// of view and waits in the factory's pool to be bound again
+    pub fn unbind(&self, covers: &CoverLoader) {
+        let imp = self.imp();
+        let Some(item) = imp.item.take() else { return; };
+        if let Some(name) = item.metadata().and_then(|metadata| metadata.cover_path) {
+            covers.withdraw(&name, self);
+        }
+        imp.cover_image.set_paintable(None::<&gdk::Paintable>);
+    }
+
+    /// Called by the cover loader once `name` is decoded, ignored if the item
+    /// has been rebound to another document in the meantime
+    pub fn show_cover(&self, name: &str, texture: Option<&gdk::Texture>) {
+        let imp = self.imp();
+        let current = imp.item.borrow().as_ref()
+            .and_then(|item| item.metadata())
+            .and_then(|metadata| metadata.cover_path);
+        if current.as_deref() != Some(name) { return; }
+        match texture {
+            Some(texture) => imp.cover_image.set_paintable(Some(texture)),
+            None => imp.cover_image.set_icon_name(Some("x-office-document")),
+        }
+    }
+
+    fn show_context_menu(&self, x: f64, y: f64) {
+        let imp = self.imp();
+        let Some(item) = imp.item.borrow().clone() else { return; };
+        let Some(metadata) = item.metadata() else { return; };
+
+        let menu = gio::Menu::new();
+        if let Some(page) = item.continue_page() {
+            let continue_item = gio::MenuItem::new(Some(&format!("Continue Reading (Page {})", page + 1)), None);
+            continue_item.set_action_and_target_value(Some("win.read-path"), Some(&metadata.path.to_variant()));
+            menu.append_item(&continue_item);
+        }
+        let open_item = gio::MenuItem::new(Some("Open"), None);
+        open_item.set_action_and_target_value(Some("win.open-path"), Some(&metadata.path.to_variant()));
+        menu.append_item(&open_item);
+        let read_item = gio::MenuItem::new(Some("Open in Built-in Reader"), None);
+        read_item.set_action_and_target_value(Some("win.read-path"), Some(&metadata.path.to_variant()));
+        menu.append_item(&read_item);
+
+        let duplicates = item.duplicates();
+        if !duplicates.is_empty() {
+            let copies = gio::Menu::new();
+            for path in &duplicates {
+                let label = Path::new(path).parent()
+                    .map(|dir| format!("Go to copy in {}", dir.display()))
+                    .unwrap_or_else(|| format!("Go to {}", path));
+                let copy_item = gio::MenuItem::new(Some(&label), None);
+                copy_item.set_action_and_target_value(Some("win.select-path"), Some(&path.to_variant()));
+                copies.append_item(&copy_item);
+            }
+            menu.append_section(Some("Other copies"), &copies);
+        }
+
+        let context_menu = imp.context_menu.get().unwrap();
+        context_menu.set_menu_model(Some(&menu));
+        context_menu.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+        context_menu.popup();
+    }
 }
@@ -0,0 +1,275 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+use gtk::gio;
+use gtk::glib;
+use gtk::prelude::*;
+use tracing::{info, warn};
+
+use crate::shelfignore::IgnoreRules;
+use crate::utils::WalkOptions;
+
+// Downloads and copies come in bursts of events, they're handed over once
+// the folders have been quiet for this long
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+/// What changed under the watched folders since the last hand over. Removed
+/// paths can be folders, everything under them is gone too.
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub added: HashSet<PathBuf>,
+    pub removed: HashSet<PathBuf>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    fn add(&mut self, path: PathBuf) {
+        self.removed.remove(&path);
+        self.added.insert(path);
+    }
+
+    fn remove(&mut self, path: PathBuf) {
+        self.added.remove(&path);
+        self.removed.insert(path);
+    }
+}
+
+fn is_pdf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+fn is_symlink(path: &Path) -> bool {
+    path.symlink_metadata().is_ok_and(|metadata| metadata.is_symlink())
+}
+
+/// Whether the scan leaves `path` out, see `scan_pdfs_rayon`
+fn left_out(path: &Path, is_dir: bool, options: WalkOptions, ignores: &[IgnoreRules]) -> bool {
+    path.file_name().is_some_and(|name| options.skips_name(name))
+        || (!options.follow_symlinks && is_symlink(path))
+        || ignores.iter().any(|ignore| ignore.is_ignored(path, is_dir))
+}
+
+/// `root` and every folder under it with their canonical paths, except the
+/// ones the scan leaves out. A folder reachable through several links is
+/// listed once.
+fn folders_under(root: &Path, options: WalkOptions, ignores: &[IgnoreRules]) -> Vec<(PathBuf, PathBuf)> {
+    let Ok(canonical_root) = root.canonicalize() else { return Vec::new(); };
+    let mut visited = HashSet::from([canonical_root.clone()]);
+    let mut folders = vec![(root.to_path_buf(), canonical_root)];
+    let mut index = 0;
+    while let Some((folder, _)) = folders.get(index).cloned() {
+        index += 1;
+        let Ok(entries) = std::fs::read_dir(&folder) else { continue; };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if !path.is_dir() || left_out(&path, true, options, ignores) { continue; }
+            let Ok(canonical) = path.canonicalize() else { continue; };
+            if visited.insert(canonical.clone()) { folders.push((path, canonical)); }
+        }
+    }
+    folders
+}
+
+/// Keeps an eye on the scan directories, so PDFs that are downloaded, copied
+/// or deleted show up in the library without a rescan. Inotify only reports
+/// on the direct children of a folder, so every folder gets a monitor.
+pub struct LibraryWatcher {
+    // Keyed by path, with the canonical path a monitor watches
+    monitors: RefCell<HashMap<PathBuf, (PathBuf, gio::FileMonitor)>>,
+    // Canonical paths of the watched folders, a link to one isn't watched again
+    watched: RefCell<HashSet<PathBuf>>,
+    options: Cell<WalkOptions>,
+    // The .shelfignore of each scan directory that has one
+    ignores: RefCell<Vec<IgnoreRules>>,
+    changes: RefCell<Changes>,
+    // Bumped by every event, a timer only hands over if nothing came since
+    generation: Cell<u64>,
+    // Bumped by watch() and stop(), folders listed for older roots are dropped
+    listing: Cell<u64>,
+    on_changes: Box<dyn Fn(Changes)>,
+}
+
+impl LibraryWatcher {
+    pub fn new(on_changes: impl Fn(Changes) + 'static) -> Rc<Self> {
+        Rc::new(Self {
+            monitors: RefCell::default(),
+            watched: RefCell::default(),
+            options: Cell::new(WalkOptions { follow_symlinks: true, skip_hidden: true }),
+            ignores: RefCell::default(),
+            changes: RefCell::default(),
+            generation: Cell::new(0),
+            listing: Cell::new(0),
+            on_changes: Box::new(on_changes),
+        })
+    }
+
+    /// Watch `roots` instead of whatever was watched before. Folders are
+    /// listed on a thread of their own, a large library has thousands.
+    pub fn watch(self: &Rc<Self>, roots: Vec<PathBuf>, options: WalkOptions) {
+        self.stop();
+        self.options.set(options);
+        if roots.is_empty() { return; }
+        let (tx, rx) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            let ignores: Vec<IgnoreRules> = roots.iter().filter_map(|root| IgnoreRules::load(root)).collect();
+            let folders: Vec<(PathBuf, PathBuf)> = roots.iter()
+                .flat_map(|root| folders_under(root, options, &ignores))
+                .collect();
+            let _ = tx.send_blocking((folders, ignores));
+        });
+        let watcher = Rc::downgrade(self);
+        let listing = self.listing.get();
+        glib::spawn_future_local(async move {
+            let Ok((folders, ignores)) = rx.recv().await else { return; };
+            let Some(watcher) = watcher.upgrade() else { return; };
+            if watcher.listing.get() != listing { return; }
+            watcher.ignores.replace(ignores);
+            for (folder, canonical) in folders {
+                watcher.watch_folder(folder, canonical);
+            }
+            info!("Watching {} folders for new and deleted PDFs", watcher.monitors.borrow().len());
+        });
+    }
+
+    pub fn stop(&self) {
+        self.listing.set(self.listing.get() + 1);
+        for (_, (_, monitor)) in self.monitors.take() {
+            monitor.cancel();
+        }
+        self.watched.take();
+        self.ignores.take();
+    }
+
+    /// Give changes back that couldn't be dealt with yet, to be handed over
+    /// again later along with anything new
+    pub fn defer(self: &Rc<Self>, changes: Changes) {
+        let mut pending = self.changes.borrow_mut();
+        for path in changes.removed {
+            if !pending.added.contains(&path) { pending.removed.insert(path); }
+        }
+        for path in changes.added {
+            if !pending.removed.contains(&path) { pending.added.insert(path); }
+        }
+        drop(pending);
+        self.schedule();
+    }
+
+    /// False if the folder was already watched, under this path or another
+    fn watch_folder(self: &Rc<Self>, folder: PathBuf, canonical: PathBuf) -> bool {
+        if self.monitors.borrow().contains_key(&folder) || self.watched.borrow().contains(&canonical) { return false; }
+        let file = gio::File::for_path(&folder);
+        let monitor = match file.monitor_directory(gio::FileMonitorFlags::WATCH_MOVES, None::<&gio::Cancellable>) {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                warn!("Failed to watch {}: {}", folder.display(), e);
+                return false;
+            }
+        };
+        let watcher: Weak<Self> = Rc::downgrade(self);
+        monitor.connect_changed(move |_, file, other, event| {
+            let Some(watcher) = watcher.upgrade() else { return; };
+            watcher.handle(file, other, event);
+        });
+        self.watched.borrow_mut().insert(canonical.clone());
+        self.monitors.borrow_mut().insert(folder, (canonical, monitor));
+        true
+    }
+
+    fn handle(self: &Rc<Self>, file: &gio::File, other: Option<&gio::File>, event: gio::FileMonitorEvent) {
+        use gio::FileMonitorEvent as Event;
+        let Some(path) = file.path() else { return; };
+        match event {
+            Event::Created | Event::MovedIn | Event::Changed | Event::ChangesDoneHint => self.appeared(path),
+            Event::Deleted | Event::MovedOut => self.disappeared(path),
+            // Within a watched folder, e.g. a finished download losing its .part
+            Event::Renamed => {
+                self.disappeared(path);
+                if let Some(path) = other.and_then(|other| other.path()) { self.appeared(path); }
+            }
+            _ => return,
+        }
+        self.schedule();
+    }
+
+    fn appeared(self: &Rc<Self>, path: PathBuf) {
+        let options = self.options.get();
+        let ignores = self.ignores.borrow();
+        let is_dir = path.is_dir();
+        if left_out(&path, is_dir, options, &ignores) { return; }
+        if is_dir {
+            self.moved_in(path, options, ignores.clone());
+        } else if is_pdf(&path) {
+            self.changes.borrow_mut().add(path);
+        }
+    }
+
+    /// A folder moved in with its contents, those don't get events of their
+    /// own. Listed on a thread like the roots, it can be a whole library.
+    fn moved_in(self: &Rc<Self>, path: PathBuf, options: WalkOptions, ignores: Vec<IgnoreRules>) {
+        let (tx, rx) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            let folders: Vec<(PathBuf, PathBuf, Vec<PathBuf>)> = folders_under(&path, options, &ignores).into_iter()
+                .map(|(folder, canonical)| {
+                    let pdfs = std::fs::read_dir(&folder).into_iter().flatten().flatten()
+                        .map(|entry| entry.path())
+                        .filter(|path| is_pdf(path) && !left_out(path, false, options, &ignores))
+                        .collect();
+                    (folder, canonical, pdfs)
+                })
+                .collect();
+            let _ = tx.send_blocking(folders);
+        });
+        let watcher = Rc::downgrade(self);
+        let listing = self.listing.get();
+        glib::spawn_future_local(async move {
+            let Ok(folders) = rx.recv().await else { return; };
+            let Some(watcher) = watcher.upgrade() else { return; };
+            if watcher.listing.get() != listing { return; }
+            for (folder, canonical, pdfs) in folders {
+                // Already in the library through another link
+                if !watcher.watch_folder(folder, canonical) { continue; }
+                let mut changes = watcher.changes.borrow_mut();
+                for pdf in pdfs {
+                    changes.add(pdf);
+                }
+            }
+            watcher.schedule();
+        });
+    }
+
+    fn disappeared(&self, path: PathBuf) {
+        let mut monitors = self.monitors.borrow_mut();
+        let mut watched = self.watched.borrow_mut();
+        let folder = monitors.contains_key(&path);
+        monitors.retain(|folder, (canonical, monitor)| {
+            let gone = folder.starts_with(&path);
+            if gone {
+                monitor.cancel();
+                watched.remove(canonical);
+            }
+            !gone
+        });
+        drop(watched);
+        drop(monitors);
+        if folder || is_pdf(&path) {
+            self.changes.borrow_mut().remove(path);
+        }
+    }
+
+    fn schedule(self: &Rc<Self>) {
+        let generation = self.generation.get() + 1;
+        self.generation.set(generation);
+        let watcher = Rc::downgrade(self);
+        glib::timeout_add_local_once(SETTLE_DELAY, move || {
+            let Some(watcher) = watcher.upgrade() else { return; };
+            if watcher.generation.get() != generation { return; }
+            let changes = watcher.changes.take();
+            if !changes.is_empty() { (watcher.on_changes)(changes); }
+        });
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashSet;
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::gio;
+use tracing::{info, warn};
+
+use crate::maintenance;
+use crate::pdf::{rerender_cover, PdfMetadata};
+use crate::ui::settings_window::ShelfSettingsWindow;
+use crate::ui::status_bar::StatusModule;
+use crate::ui::window::ShelfWindow;
+use crate::utils::human_readable_file_size;
+
+// How often to check whether maintenance is due, in seconds
+const MAINTENANCE_CHECK_INTERVAL: u32 = 10 * 60;
+
+impl ShelfWindow {
+    /// Run the daily cache maintenance once the user has looked away and
+    /// nothing is being scanned
+    pub(super) fn schedule_maintenance(&self, model: gio::ListStore) {
+        glib::timeout_add_seconds_local(MAINTENANCE_CHECK_INTERVAL, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[strong] model,
+            #[upgrade_or] glib::ControlFlow::Break,
+            move || {
+                let imp = _self.imp();
+                let Some(cache) = imp.cache.get().cloned() else { return glib::ControlFlow::Break; };
+                let idle = !_self.is_active() && imp.refresh_button.is_sensitive();
+                if idle && maintenance::due(&cache).unwrap_or(false) { _self.run_maintenance(&model); }
+                glib::ControlFlow::Continue
+            }
+        ));
+    }
+
+    /// Run maintenance in the background. Returns false during a scan.
+    pub(super) fn run_maintenance(&self, model: &gio::ListStore) -> bool {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get().cloned() else { return false; };
+        if !imp.refresh_button.is_sensitive() { return false; }
+
+        // Scans wait, new covers would look unused
+        imp.refresh_button.set_sensitive(false);
+        self.set_status(StatusModule::Scan, "Running maintenance...");
+        let (limit, remove_unused) = {
+            let config = imp.config.get().unwrap().read().unwrap();
+            (config.cover_cache_limit_mb * 1024 * 1024, config.remove_unused_covers)
+        };
+        let (tx, rx) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            let _ = tx.send_blocking(maintenance::run(&cache, limit, remove_unused).map_err(|e| format!("{:#}", e)));
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[strong] model,
+            async move {
+                match rx.recv().await {
+                    Ok(Ok(summary)) => {
+                        info!("Maintenance: {}", summary);
+                        _self.set_status(StatusModule::Scan, "Ready");
+                    }
+                    Ok(Err(e)) => {
+                        _self.set_status(StatusModule::Scan, "Ready");
+                        _self.toast_error("Maintenance failed", e);
+                    }
+                    Err(_) => {}
+                }
+                _self.imp().refresh_button.set_sensitive(true);
+                for path in _self.imp().pending_open.take() {
+                    _self.add_document(&model, path);
+                }
+            }
+        ));
+        true
+    }
+
+    /// Delete the covers no document uses in the background and say how
+    /// much space that freed. Returns false during a scan.
+    pub(super) fn remove_unused_covers(&self, model: &gio::ListStore) -> bool {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get().cloned() else { return false; };
+        if !imp.refresh_button.is_sensitive() { return false; }
+
+        imp.refresh_button.set_sensitive(false);
+        self.set_status(StatusModule::Scan, "Removing unused covers...");
+        let (tx, rx) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            let _ = tx.send_blocking(maintenance::remove_unused_covers(&cache).map_err(|e| format!("{:#}", e)));
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[strong] model,
+            async move {
+                let Ok(result) = rx.recv().await else { return; };
+                _self.set_status(StatusModule::Scan, "Ready");
+                match result {
+                    Ok(summary) => {
+                        let freed = human_readable_file_size(summary.freed_bytes);
+                        info!("Removed {} unused covers and {} page thumbnails ({})",
+                            summary.removed_covers, summary.removed_thumbnails, freed);
+                        _self.toast(&match summary.removed_covers + summary.removed_thumbnails {
+                            0 => "No unused covers to remove".to_string(),
+                            removed => format!("Removed {} unused covers and thumbnails, freeing {}", removed, freed),
+                        });
+                    }
+                    Err(e) => _self.toast_error("Failed to remove unused covers", e),
+                }
+                _self.imp().refresh_button.set_sensitive(true);
+                for path in _self.imp().pending_open.take() {
+                    _self.add_document(&model, path);
+                }
+            }
+        ));
+        true
+    }
+
+    /// Render the cover of every document again with the current cover
+    /// settings, without reading the rest of the documents. Returns false
+    /// during a scan.
+    pub(super) fn regenerate_covers(&self, model: &gio::ListStore) -> bool {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get().cloned() else { return false; };
+        if !imp.refresh_button.is_sensitive() { return false; }
+
+        // Once per document, copies share their cover. Custom covers stay.
+        let mut hashes: HashSet<String> = cache.custom_covers().unwrap_or_else(|e| {
+            warn!("Failed to load custom covers: {:#}", e);
+            Default::default()
+        }).into_keys().collect();
+        let documents: Vec<PdfMetadata> = imp.metadata_list.lock().unwrap().iter()
+            .filter(|pdf| !pdf.missing && hashes.insert(pdf.hash.clone()))
+            .cloned()
+            .collect();
+        let total = documents.len();
+        imp.refresh_button.set_sensitive(false);
+        self.set_status(StatusModule::Scan, "Regenerating covers...");
+        imp.status.set_progress(0.0, &format!("0 of {}", total));
+        let (tx, rx) = async_channel::unbounded();
+        std::thread::spawn(move || {
+            for metadata in documents {
+                let (hash, old_cover) = (metadata.hash.clone(), metadata.cover_path.clone());
+                let rendered = rerender_cover(metadata, &cache)
+                    .map(|updated| (old_cover, updated.cover_path))
+                    .map_err(|e| format!("{:#}", e));
+                if tx.send_blocking((hash, rendered)).is_err() { return; }
+            }
+            if let Err(e) = cache.flush() {
+                warn!("Failed to save the regenerated covers: {:#}", e);
+            }
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[strong] model,
+            async move {
+                let (mut done, mut failed) = (0, 0);
+                while let Ok((hash, rendered)) = rx.recv().await {
+                    done += 1;
+                    let imp = _self.imp();
+                    match rendered {
+                        Ok((old_cover, new_cover)) => {
+                            let loader = imp.cover_loader.get().unwrap();
+                            for cover in old_cover.iter().chain(new_cover.iter()) {
+                                loader.forget(cover);
+                            }
+                            for pdf in imp.metadata_list.lock().unwrap().iter_mut().filter(|pdf| pdf.hash == hash) {
+                                pdf.cover_path = new_cover.clone();
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Failed to regenerate the cover of {}: {}", hash, e);
+                            failed += 1;
+                        }
+                    }
+                    imp.status.set_progress(done as f64 / total.max(1) as f64, &format!("{} of {}", done, total));
+                }
+                let imp = _self.imp();
+                imp.status.hide_progress();
+                _self.set_status(StatusModule::Scan, "Ready");
+                _self.refresh_model_in_place(&model);
+                _self.toast(&match failed {
+                    0 => format!("Regenerated {} covers", done),
+                    failed => format!("Regenerated {} covers, {} couldn't be rendered", done - failed, failed),
+                });
+                imp.refresh_button.set_sensitive(true);
+                for path in imp.pending_open.take() {
+                    _self.add_document(&model, path);
+                }
+            }
+        ));
+        true
+    }
+
+    pub(super) fn missing_count(&self) -> usize {
+        self.imp().metadata_list.lock().unwrap().iter().filter(|pdf| pdf.missing).count()
+    }
+
+    /// Forget the documents flagged as missing in the background, then
+    /// update `dialog`. Returns false during a scan, which flags them.
+    pub(super) fn prune_missing(&self, model: &gio::ListStore, dialog: &ShelfSettingsWindow) -> bool {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get().cloned() else { return false; };
+        if !imp.refresh_button.is_sensitive() { return false; }
+
+        imp.refresh_button.set_sensitive(false);
+        self.set_status(StatusModule::Scan, "Forgetting missing documents...");
+        let (tx, rx) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            let _ = tx.send_blocking(maintenance::prune_missing(&cache).map_err(|e| format!("{:#}", e)));
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[weak] dialog,
+            #[strong] model,
+            async move {
+                let Ok(pruned) = rx.recv().await else { return; };
+                let imp = _self.imp();
+                _self.set_status(StatusModule::Scan, "Ready");
+                match pruned {
+                    Ok(gone) => {
+                        let gone: HashSet<String> = gone.into_iter().collect();
+                        imp.metadata_list.lock().unwrap().retain(|pdf| !gone.contains(&pdf.hash));
+                        _self.refresh_model_in_place(&model);
+                        _self.toast(&match gone.len() {
+                            1 => "Forgot 1 missing document".to_string(),
+                            count => format!("Forgot {} missing documents", count),
+                        });
+                    }
+                    Err(e) => _self.toast_error("Failed to forget missing documents", e),
+                }
+                imp.refresh_button.set_sensitive(true);
+                for path in imp.pending_open.take() {
+                    _self.add_document(&model, path);
+                }
+                dialog.set_missing_count(_self.missing_count());
+            }
+        ));
+        true
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::{prelude::*, MultiSelection};
+use gtk::glib;
+use gtk::gio;
+use tracing::{info, warn};
+
+use crate::lookup::{self, LOOKUP_INTERVAL};
+use crate::pdf::{CustomFieldsByHash, reextract_pdf_metadata, save_metadata_into_file, MetadataField, PdfMetadata};
+use crate::ui::batch_editor_window::ShelfBatchEditorWindow;
+use crate::ui::metadata_editor_window::{FieldEdit, ShelfMetadataEditorWindow};
+use crate::ui::metadata_review_window::ShelfMetadataReviewWindow;
+use crate::ui::models::PdfMetadataObject;
+use crate::ui::status_bar::StatusModule;
+use crate::ui::window::ShelfWindow;
+
+impl ShelfWindow {
+    pub(super) fn setup_metadata_actions(&self, model: &gio::ListStore) {
+        let refresh_metadata = gio::ActionEntry::builder("refresh-metadata")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.refresh_metadata(&model)
+            ))
+            .build();
+
+        let edit_metadata = gio::ActionEntry::builder("edit-metadata")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.edit_metadata(&model)
+            ))
+            .build();
+
+        let fill_in_metadata = gio::ActionEntry::builder("fill-in-metadata")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.fill_in_metadata(&model)
+            ))
+            .build();
+
+        let review_metadata = gio::ActionEntry::builder("review-metadata")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.show_metadata_review(&model)
+            ))
+            .build();
+
+        self.add_action_entries([refresh_metadata, edit_metadata, fill_in_metadata, review_metadata]);
+    }
+
+    pub(super) fn refresh_custom_fields(&self) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return; };
+        let fields = cache.all_custom_fields().unwrap_or_else(|e| {
+            warn!("Failed to load custom fields: {}", e);
+            CustomFieldsByHash::new()
+        });
+        imp.custom_fields.replace(fields);
+    }
+
+    /// Correct the title, author, subject and keywords of the selected
+    /// document, the edits win over what rescans read from the file
+    fn edit_metadata(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        let documents = self.selected_documents();
+        if documents.len() > 1 {
+            self.edit_metadata_batch(model, documents);
+            return;
+        }
+        let Some(selected) = imp.selected.lock().unwrap().clone() else { return; };
+        let Some(cache) = imp.cache.get() else { return; };
+        let overrides = cache.overrides(&selected.hash).unwrap_or_else(|e| {
+            warn!("Failed to load the edits of {}: {:#}", selected.path, e);
+            HashMap::new()
+        });
+        let custom_fields = imp.custom_fields.borrow().get(&selected.hash).cloned().unwrap_or_default();
+        let editor = ShelfMetadataEditorWindow::new(self, &selected, &overrides, &custom_fields);
+        editor.connect_local("save", false, glib::clone!(
+            #[weak] model,
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] None,
+            move |args| {
+                let editor: ShelfMetadataEditorWindow = args[0].get().ok()?;
+                let into_file: bool = args[1].get().ok()?;
+                let cache = _self.imp().cache.get()?;
+                let edits = editor.edits();
+                for (field, edit) in &edits {
+                    let field = *field;
+                    let saved = match edit {
+                        FieldEdit::Set(value) => cache.set_field(&selected.hash, field, value.as_deref()),
+                        FieldEdit::Undo => cache.remove_override(&selected.hash, field),
+                    };
+                    if let Err(e) = saved {
+                        _self.toast_error(&format!("Failed to save the {}", field.label().to_lowercase()), format!("{:#}", e));
+                    }
+                }
+                let fields_changed = editor.custom_fields_changed();
+                if fields_changed {
+                    match cache.set_custom_fields(&selected.hash, &editor.custom_fields()) {
+                        Ok(()) => _self.refresh_custom_fields(),
+                        Err(e) => _self.toast_error("Failed to save the custom fields", format!("{:#}", e)),
+                    }
+                }
+                if !edits.is_empty() || fields_changed { _self.show_new_metadata(&model, std::slice::from_ref(&selected.hash)); }
+                if into_file { _self.save_metadata_into_file(&model, &selected.hash); }
+                None
+            }
+        ));
+        editor.present();
+    }
+
+    /// Set one field of every selected document at once, e.g. the author
+    /// of all volumes of a series
+    fn edit_metadata_batch(&self, model: &gio::ListStore, documents: Vec<PdfMetadata>) {
+        let editor = ShelfBatchEditorWindow::new(self, documents);
+        editor.connect_local("apply", false, glib::clone!(
+            #[weak] model,
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] None,
+            move |args| {
+                let editor: ShelfBatchEditorWindow = args[0].get().ok()?;
+                let field = MetadataField::from_column(&args[1].get::<String>().ok()?)?;
+                let value: String = args[2].get().ok()?;
+                let value = Some(value).filter(|value| !value.is_empty());
+                let cache = _self.imp().cache.get()?;
+                let (mut hashes, mut errors) = (Vec::new(), Vec::new());
+                for metadata in editor.changed() {
+                    match cache.set_field(&metadata.hash, field, value.as_deref()) {
+                        Ok(()) => hashes.push(metadata.hash),
+                        Err(e) => errors.push(format!("{}: {:#}", metadata.path, e)),
+                    }
+                }
+                if !hashes.is_empty() { _self.show_new_metadata(&model, &hashes); }
+                let name = field.label().to_lowercase();
+                if errors.is_empty() {
+                    _self.toast(&format!("Changed the {} of {} documents", name, hashes.len()));
+                } else {
+                    _self.toast_error(&format!("Failed to save the {} of {} documents", name, errors.len()), errors.join("\n"));
+                }
+                None
+            }
+        ));
+        editor.present();
+    }
+
+    /// The documents selected in the grid, in grid order
+    fn selected_documents(&self) -> Vec<PdfMetadata> {
+        let Some(selection) = self.imp().grid_view.model().and_downcast::<MultiSelection>() else { return Vec::new(); };
+        let selected = selection.selection();
+        (0..selected.size() as u32)
+            .filter_map(|nth| selection.item(selected.nth(nth)).and_downcast::<PdfMetadataObject>())
+            .filter_map(|object| object.metadata())
+            .collect()
+    }
+
+    /// Write the metadata of the document, edits included, into its file
+    fn save_metadata_into_file(&self, model: &gio::ListStore, hash: &str) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        let Some(metadata) = imp.metadata_list.lock().unwrap().iter().find(|pdf| pdf.hash == hash && !pdf.missing).cloned() else { return; };
+        self.set_status(StatusModule::Scan, &format!("Saving into {}...", metadata.path));
+        let (tx, rx) = async_channel::bounded(1);
+        let old = metadata.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send_blocking(save_metadata_into_file(&metadata, &cache).map_err(|e| format!("{:#}", e)));
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(saved) = rx.recv().await else { return; };
+                _self.set_status(StatusModule::Scan, "Ready");
+                let metadata = match saved {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        _self.toast_error(&format!("Could not save into {}", old.path), e);
+                        return;
+                    }
+                };
+                let imp = _self.imp();
+                let loader = imp.cover_loader.get().unwrap();
+                for cover in old.cover_path.iter().chain(metadata.cover_path.iter()) {
+                    loader.forget(cover);
+                }
+                if let Some(pdf) = imp.metadata_list.lock().unwrap().iter_mut().find(|pdf| pdf.path == metadata.path) {
+                    *pdf = metadata.clone();
+                }
+                imp.pending_selection.replace(Some(metadata.path.clone()));
+                _self.refresh_model_in_place(&model);
+                _self.metadata_changes_changed();
+                _self.toast(&format!("Saved the metadata into {}", metadata.display_title()));
+            }
+        ));
+    }
+
+    /// Look the selected document up online by its arXiv ID or ISBN and fill
+    /// in the fields it has empty, whether or not `fetch_online_metadata` is on
+    fn fill_in_metadata(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        let Some(selected) = imp.selected.lock().unwrap().clone() else { return; };
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        let title = selected.display_title();
+        if lookup::isbn_of(&selected).is_none() && lookup::arxiv_of(&selected).is_none() {
+            self.toast(&format!("No ISBN or arXiv ID found in {}", title));
+            return;
+        }
+        if !lookup::has_missing_details(&selected) {
+            self.toast(&format!("{} has nothing missing to look up", title));
+            return;
+        }
+        self.set_status(StatusModule::Sync, &format!("Looking up {}...", title));
+        let (tx, rx) = async_channel::bounded(1);
+        let hash = selected.hash.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send_blocking(lookup::fill_in_metadata(&selected, &cache).map_err(|e| format!("{:#}", e)));
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(filled) = rx.recv().await else { return; };
+                _self.update_sync_status();
+                match filled {
+                    Ok(filled) if filled.is_empty() => _self.toast(&format!("Nothing found online for {}", title)),
+                    Ok(filled) => {
+                        _self.show_new_metadata(&model, &[hash]);
+                        let fields: Vec<&str> = filled.iter().map(|field| field.label()).collect();
+                        _self.toast(&format!("Filled in {} of {}", fields.join(", ").to_lowercase(), title));
+                    }
+                    Err(e) => _self.toast_error("Failed to look up the metadata", e),
+                }
+            }
+        ));
+    }
+
+    /// With `fetch_online_metadata` on, fill in the missing fields of the
+    /// documents with an ISBN or arXiv ID that haven't been looked up yet, like
+    /// `fetch_online_covers`
+    pub(super) fn fill_in_missing_metadata(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        if !imp.config.get().unwrap().read().unwrap().fetch_online_metadata || !imp.refresh_button.is_sensitive() { return; }
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        if imp.filling_in_metadata.replace(true) { return; }
+
+        let mut skipped = cache.metadata_lookups().unwrap_or_else(|e| {
+            warn!("Failed to load metadata lookups: {:#}", e);
+            HashSet::new()
+        });
+        let books: Vec<PdfMetadata> = imp.metadata_list.lock().unwrap().iter()
+            .filter(|pdf| !pdf.missing && lookup::has_missing_details(pdf) && skipped.insert(pdf.hash.clone()))
+            .cloned()
+            .collect();
+        if books.is_empty() {
+            imp.filling_in_metadata.set(false);
+            return;
+        }
+        info!("Looking up the metadata of {} books online", books.len());
+        let (tx, rx) = async_channel::unbounded();
+        std::thread::spawn(move || {
+            let mut failures = 0;
+            for (index, book) in books.iter().enumerate() {
+                if index > 0 { std::thread::sleep(LOOKUP_INTERVAL); }
+                match lookup::fill_in_metadata(book, &cache) {
+                    Ok(filled) => {
+                        failures = 0;
+                        if filled.is_empty() { continue; }
+                        if tx.send_blocking(book.hash.clone()).is_err() { return; }
+                    }
+                    Err(e) => {
+                        warn!("Failed to look up the metadata of {}: {:#}", book.path, e);
+                        failures += 1;
+                        if failures == 3 { return; }
+                    }
+                }
+            }
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            async move {
+                let mut found = 0;
+                while let Ok(hash) = rx.recv().await {
+                    found += 1;
+                    _self.show_new_metadata(&model, &[hash]);
+                }
+                info!("Filled in the metadata of {} books", found);
+                _self.imp().filling_in_metadata.set(false);
+            }
+        ));
+    }
+
+    /// Take the fields the cache has for `hashes` after a lookup, a review
+    /// or a revert
+    fn show_new_metadata(&self, model: &gio::ListStore, hashes: &[String]) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return; };
+        for hash in hashes {
+            let cached = match cache.get_metadata(hash) {
+                Ok(Some(cached)) => cached,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Failed to load the metadata of {}: {:#}", hash, e);
+                    continue;
+                }
+            };
+            let update = |pdf: &mut PdfMetadata| {
+                for field in MetadataField::ALL {
+                    field.set(pdf, field.get(&cached).map(str::to_string));
+                }
+            };
+            imp.metadata_list.lock().unwrap().iter_mut().filter(|pdf| pdf.hash == *hash).for_each(update);
+            let selected = imp.selected.lock().unwrap().as_mut().filter(|selected| selected.hash == *hash).map(|selected| {
+                update(selected);
+                selected.clone()
+            });
+            if let Some(selected) = selected { self.update_preview_display(&selected); }
+        }
+        // Before the grid, an author edited away no longer filters it
+        self.refresh_author_list();
+        self.refresh_year_list();
+        self.refresh_model_in_place(model);
+        self.metadata_changes_changed();
+    }
+
+    /// Fields filled in by online lookups, to be kept or reverted
+    fn show_metadata_review(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        if let Some(window) = imp.metadata_review_window.upgrade() {
+            window.present();
+            return;
+        }
+        let window = ShelfMetadataReviewWindow::new(self, self.metadata_changes());
+        let review = glib::clone!(
+            #[weak] model,
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] None,
+            move |args: &[glib::Value], revert: bool| {
+                let hash: String = args[1].get().ok()?;
+                let column: String = args[2].get().ok()?;
+                let field = MetadataField::from_column(&column)?;
+                let cache = _self.imp().cache.get()?;
+                let reviewed = if revert { cache.revert_metadata_change(&hash, field) } else { cache.keep_metadata_change(&hash, field) };
+                match reviewed {
+                    Ok(()) if revert => _self.show_new_metadata(&model, &[hash]),
+                    Ok(()) => _self.metadata_changes_changed(),
+                    Err(e) => _self.toast_error("Failed to review the change", format!("{:#}", e)),
+                }
+                None
+            }
+        );
+        let keep = review.clone();
+        window.connect_local("keep", false, move |args| keep(args, false));
+        window.connect_local("revert", false, move |args| review(args, true));
+        window.connect_local("keep-all", false, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] None,
+            move |_| {
+                let cache = _self.imp().cache.get()?;
+                match cache.keep_metadata_changes() {
+                    Ok(()) => _self.metadata_changes_changed(),
+                    Err(e) => _self.toast_error("Failed to review the changes", format!("{:#}", e)),
+                }
+                None
+            }
+        ));
+        imp.metadata_review_window.set(Some(&window));
+        window.present();
+    }
+
+    /// The changes of online lookups with the titles of their documents
+    fn metadata_changes(&self) -> Vec<(String, crate::pdf::MetadataChange)> {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return Vec::new(); };
+        let changes = cache.metadata_changes().unwrap_or_else(|e| {
+            warn!("Failed to load metadata changes: {:#}", e);
+            Vec::new()
+        });
+        let list = imp.metadata_list.lock().unwrap();
+        changes.into_iter()
+            .filter_map(|change| {
+                let title = list.iter().find(|pdf| pdf.hash == change.hash)?.display_title();
+                Some((title, change))
+            })
+            .collect()
+    }
+
+    fn metadata_changes_changed(&self) {
+        if let Some(window) = self.imp().metadata_review_window.upgrade() {
+            window.set_changes(self.metadata_changes());
+        }
+    }
+
+    /// Read the selected document again, ignoring the cache, and render a
+    /// new cover for it
+    fn refresh_metadata(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        let Some(selected) = imp.selected.lock().unwrap().clone() else { return; };
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        self.set_status(StatusModule::Scan, &format!("Reading {} again...", selected.path));
+        let (tx, rx) = async_channel::bounded(1);
+        let (path, old_hash) = (PathBuf::from(&selected.path), selected.hash.clone());
+        std::thread::spawn(move || {
+            let extracted = reextract_pdf_metadata(&path, &cache).and_then(|metadata| {
+                // Changed in place, the old row would stay behind as a copy
+                if metadata.hash != old_hash { cache.remove_metadata(&[old_hash])?; }
+                cache.flush()?;
+                Ok(metadata)
+            });
+            let _ = tx.send_blocking(extracted.map_err(|e| format!("{:#}", e)));
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(extracted) = rx.recv().await else { return; };
+                let metadata = match extracted {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        _self.set_status(StatusModule::Scan, "Ready");
+                        _self.toast_error(&format!("Could not read {} again", selected.path), e);
+                        return;
+                    }
+                };
+                let imp = _self.imp();
+                let loader = imp.cover_loader.get().unwrap();
+                for cover in selected.cover_path.iter().chain(metadata.cover_path.iter()) {
+                    loader.forget(cover);
+                }
+                let title = metadata.display_title();
+                {
+                    let mut list = imp.metadata_list.lock().unwrap();
+                    match list.iter_mut().find(|pdf| pdf.path == metadata.path) {
+                        Some(pdf) => *pdf = metadata.clone(),
+                        None => list.push(metadata.clone()),
+                    }
+                }
+                imp.pending_selection.replace(Some(metadata.path.clone()));
+                _self.refresh_model_in_place(&model);
+                _self.set_status(StatusModule::Scan, &format!("Updated {}", title));
+            }
+        ));
+    }
+}
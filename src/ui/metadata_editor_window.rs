@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+
+use crate::pdf::{MetadataField, PdfMetadata};
+
+/// The fields the dialog edits, in the order it shows them
+pub const EDITABLE_FIELDS: [MetadataField; 4] = [
+    MetadataField::Title,
+    MetadataField::Author,
+    MetadataField::Subject,
+    MetadataField::Keywords,
+];
+
+/// What's to be done to a field once the dialog is saved
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldEdit {
+    // None clears the field
+    Set(Option<String>),
+    // Back to what the file has
+    Undo,
+}
+
+mod imp {
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+    use std::sync::OnceLock;
+
+    use gtk::glib;
+    use gtk::glib::subclass::types::ObjectSubclass;
+    use gtk::glib::subclass::Signal;
+    use gtk::subclass::prelude::*;
+
+    use crate::pdf::MetadataField;
+
+    #[derive(Default, gtk::CompositeTemplate)]
+    #[template(resource = "/org/galib/shelf/ui/metadata_editor_window.xml")]
+    pub struct ShelfMetadataEditorWindow {
+        #[template_child]
+        pub document_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub field_grid: TemplateChild<gtk::Grid>,
+        #[template_child]
+        pub custom_fields_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub add_field_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub cancel_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub save_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub save_into_file_button: TemplateChild<gtk::Button>,
+
+        // With the text they started with
+        pub entries: RefCell<Vec<(MetadataField, gtk::Entry, String)>>,
+        // Edits to undo, unless their entry is changed again
+        pub undone: RefCell<HashSet<MetadataField>>,
+        // Row, key and value of each custom field
+        pub custom_fields: RefCell<Vec<(gtk::Box, gtk::Entry, gtk::Entry)>>,
+        pub initial_custom_fields: RefCell<Vec<(String, String)>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ShelfMetadataEditorWindow {
+        const NAME: &'static str = "ShelfMetadataEditorWindow";
+        type Type = super::ShelfMetadataEditorWindow;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ShelfMetadataEditorWindow {
+        fn constructed(&self) {
+            self.parent_constructed();
+        }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            // The edits are read with edits() before the dialog closes, the
+            // argument is whether they're to be written into the file too
+            SIGNALS.get_or_init(|| vec![Signal::builder("save").param_types([glib::Type::BOOL]).build()])
+        }
+    }
+
+    impl WidgetImpl for ShelfMetadataEditorWindow {}
+    impl WindowImpl for ShelfMetadataEditorWindow {}
+}
+
+glib::wrapper! {
+    pub struct ShelfMetadataEditorWindow(ObjectSubclass<imp::ShelfMetadataEditorWindow>)
+        @extends gtk::Widget, gtk::Window,
+        @implements gio::ActionGroup, gio::ActionMap,
+                    gtk::Accessible, gtk::Buildable,
+                    gtk::ConstraintTarget, gtk::Native,
+                    gtk::Root, gtk::ShortcutManager;
+}
+
+impl ShelfMetadataEditorWindow {
+    /// Edits the fields of `metadata`. `overrides` are the fields already
+    /// edited by hand with what the file has, see `PdfCache::overrides`,
+    /// and `custom_fields` the ones the user made up.
+    pub fn new(
+        parent: &impl IsA<gtk::Window>,
+        metadata: &PdfMetadata,
+        overrides: &HashMap<MetadataField, Option<String>>,
+        custom_fields: &[(String, String)],
+    ) -> Self {
+        let obj: ShelfMetadataEditorWindow = glib::Object::builder()
+            .property("transient-for", parent)
+            .build();
+        let imp = obj.imp();
+        imp.document_label.set_label(&metadata.path);
+        imp.document_label.set_tooltip_text(Some(&metadata.path));
+        for (row, field) in EDITABLE_FIELDS.into_iter().enumerate() {
+            obj.add_field(row as i32, field, field.get(metadata).unwrap_or(""), overrides.get(&field));
+        }
+        for (key, value) in custom_fields {
+            obj.add_custom_field(key, value);
+        }
+        imp.initial_custom_fields.replace(custom_fields.to_vec());
+        obj.set_default_widget(Some(&*imp.save_button));
+
+        imp.add_field_button.connect_clicked(glib::clone!(
+            #[weak] obj,
+            move |_| {
+                obj.add_custom_field("", "").grab_focus();
+            }
+        ));
+        imp.cancel_button.connect_clicked(glib::clone!(
+            #[weak] obj,
+            move |_| obj.close()
+        ));
+        for (button, into_file) in [(&*imp.save_button, false), (&*imp.save_into_file_button, true)] {
+            button.connect_clicked(glib::clone!(
+                #[weak] obj,
+                move |_| {
+                    obj.emit_by_name::<()>("save", &[&into_file]);
+                    obj.close();
+                }
+            ));
+        }
+        obj
+    }
+
+    fn add_field(&self, row: i32, field: MetadataField, value: &str, original: Option<&Option<String>>) {
+        let imp = self.imp();
+        let label = gtk::Label::builder().label(field.label()).xalign(1.0).build();
+        label.add_css_class("dim-label");
+        let entry = gtk::Entry::builder().text(value).hexpand(true).activates_default(true).build();
+        imp.field_grid.attach(&label, 0, row, 1, 1);
+        imp.field_grid.attach(&entry, 1, row, 1, 1);
+        imp.entries.borrow_mut().push((field, entry.clone(), value.to_string()));
+
+        // Only edited fields have something to go back to
+        let Some(original) = original.cloned() else { return; };
+        let original = original.unwrap_or_default();
+        let undo_button = gtk::Button::builder()
+            .icon_name("edit-undo-symbolic")
+            .has_frame(false)
+            .tooltip_text(if original.is_empty() {
+                "Clear the edit, the file has no value".to_string()
+            } else {
+                format!("Clear the edit, the file has “{}”", original)
+            })
+            .build();
+        imp.field_grid.attach(&undo_button, 2, row, 1, 1);
+        undo_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[weak] entry,
+            move |button| {
+                entry.set_text(&original);
+                _self.imp().undone.borrow_mut().insert(field);
+                button.set_sensitive(false);
+            }
+        ));
+        entry.connect_changed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[weak] undo_button,
+            move |_| {
+                // Typed in after undoing, it's an edit again
+                if !undo_button.is_sensitive() && _self.imp().undone.borrow_mut().remove(&field) {
+                    undo_button.set_sensitive(true);
+                }
+            }
+        ));
+    }
+
+    fn add_custom_field(&self, key: &str, value: &str) -> gtk::Entry {
+        let imp = self.imp();
+        let row = gtk::Box::builder().spacing(6).build();
+        let key_entry = gtk::Entry::builder().text(key).placeholder_text("Name, e.g. Course").width_chars(12).build();
+        let value_entry = gtk::Entry::builder().text(value).placeholder_text("Value").hexpand(true).activates_default(true).build();
+        let remove_button = gtk::Button::builder()
+            .icon_name("list-remove-symbolic")
+            .has_frame(false)
+            .tooltip_text("Remove the field")
+            .build();
+        row.append(&key_entry);
+        row.append(&value_entry);
+        row.append(&remove_button);
+        imp.custom_fields_box.append(&row);
+        imp.custom_fields.borrow_mut().push((row.clone(), key_entry.clone(), value_entry));
+        remove_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[weak] row,
+            move |_| {
+                let imp = _self.imp();
+                imp.custom_fields_box.remove(&row);
+                imp.custom_fields.borrow_mut().retain(|(other, _, _)| *other != row);
+            }
+        ));
+        key_entry
+    }
+
+    /// The custom fields as they are in the dialog, fields without a name
+    /// or a value left out
+    pub fn custom_fields(&self) -> Vec<(String, String)> {
+        self.imp().custom_fields.borrow().iter()
+            .map(|(_, key, value)| (key.text().trim().to_string(), value.text().trim().to_string()))
+            .filter(|(key, value)| !key.is_empty() && !value.is_empty())
+            .collect()
+    }
+
+    pub fn custom_fields_changed(&self) -> bool {
+        self.custom_fields() != *self.imp().initial_custom_fields.borrow()
+    }
+
+    /// The fields that were changed or undone
+    pub fn edits(&self) -> Vec<(MetadataField, FieldEdit)> {
+        let imp = self.imp();
+        let undone = imp.undone.borrow();
+        imp.entries.borrow().iter()
+            .filter_map(|(field, entry, initial)| {
+                if undone.contains(field) { return Some((*field, FieldEdit::Undo)); }
+                let text = entry.text().trim().to_string();
+                (text != initial.trim()).then(|| (*field, FieldEdit::Set(Some(text).filter(|text| !text.is_empty()))))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,182 @@
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+
+use crate::pdf::MetadataChange;
+
+mod imp {
+    use std::cell::RefCell;
+    use std::sync::OnceLock;
+
+    use gtk::glib;
+    use gtk::glib::subclass::types::ObjectSubclass;
+    use gtk::glib::subclass::Signal;
+    use gtk::subclass::prelude::*;
+
+    use crate::pdf::MetadataChange;
+
+    #[derive(Default, gtk::CompositeTemplate)]
+    #[template(resource = "/org/galib/shelf/ui/metadata_review_window.xml")]
+    pub struct ShelfMetadataReviewWindow {
+        #[template_child]
+        pub summary_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub change_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub keep_all_button: TemplateChild<gtk::Button>,
+
+        // With the display title of their documents
+        pub changes: RefCell<Vec<(String, MetadataChange)>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ShelfMetadataReviewWindow {
+        const NAME: &'static str = "ShelfMetadataReviewWindow";
+        type Type = super::ShelfMetadataReviewWindow;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ShelfMetadataReviewWindow {
+        fn constructed(&self) {
+            self.parent_constructed();
+        }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            // keep and revert come with the hash of the document and the
+            // column of the field, see MetadataField::column
+            SIGNALS.get_or_init(|| vec![
+                Signal::builder("keep").param_types([glib::Type::STRING, glib::Type::STRING]).build(),
+                Signal::builder("revert").param_types([glib::Type::STRING, glib::Type::STRING]).build(),
+                Signal::builder("keep-all").build(),
+            ])
+        }
+    }
+
+    impl WidgetImpl for ShelfMetadataReviewWindow {}
+    impl WindowImpl for ShelfMetadataReviewWindow {}
+}
+
+glib::wrapper! {
+    pub struct ShelfMetadataReviewWindow(ObjectSubclass<imp::ShelfMetadataReviewWindow>)
+        @extends gtk::Widget, gtk::Window,
+        @implements gio::ActionGroup, gio::ActionMap,
+                    gtk::Accessible, gtk::Buildable,
+                    gtk::ConstraintTarget, gtk::Native,
+                    gtk::Root, gtk::ShortcutManager;
+}
+
+impl ShelfMetadataReviewWindow {
+    /// Lists `changes`, the fields online lookups filled in, each with the
+    /// title of its document
+    pub fn new(parent: &impl IsA<gtk::Window>, changes: Vec<(String, MetadataChange)>) -> Self {
+        let obj: ShelfMetadataReviewWindow = glib::Object::builder()
+            .property("transient-for", parent)
+            .build();
+        obj.imp().keep_all_button.connect_clicked(glib::clone!(
+            #[weak] obj,
+            move |_| obj.emit_by_name::<()>("keep-all", &[])
+        ));
+        obj.set_changes(changes);
+        obj
+    }
+
+    /// Show `changes` instead, e.g. after one was kept or reverted
+    pub fn set_changes(&self, changes: Vec<(String, MetadataChange)>) {
+        let imp = self.imp();
+        imp.changes.replace(changes);
+        self.refresh();
+    }
+
+    fn refresh(&self) {
+        let imp = self.imp();
+        let changes = imp.changes.borrow();
+        let unreviewed = changes.iter().filter(|(_, change)| !change.reviewed).count();
+        imp.summary_label.set_label(&match (changes.len(), unreviewed) {
+            (0, _) => "Nothing was looked up online yet".to_string(),
+            (_, 0) => "Every looked-up field was reviewed".to_string(),
+            (_, 1) => "1 looked-up field to review".to_string(),
+            (_, count) => format!("{} looked-up fields to review", count),
+        });
+        imp.keep_all_button.set_sensitive(unreviewed > 0);
+        imp.change_list.set_visible(!changes.is_empty());
+        imp.change_list.remove_all();
+        for (title, change) in changes.iter() {
+            imp.change_list.append(&self.change_row(title, change));
+        }
+    }
+
+    fn change_row(&self, title: &str, change: &MetadataChange) -> gtk::Box {
+        let row = gtk::Box::builder()
+            .spacing(8)
+            .margin_start(12)
+            .margin_end(12)
+            .margin_top(6)
+            .margin_bottom(6)
+            .build();
+        let text = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(2)
+            .hexpand(true)
+            .build();
+        let title_label = gtk::Label::builder()
+            .label(title)
+            .xalign(0.0)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .tooltip_text(title)
+            .build();
+        title_label.add_css_class("dim-label");
+        let value_label = gtk::Label::builder()
+            .label(format!("{}: {}", change.field.label(), change.value))
+            .xalign(0.0)
+            .wrap(true)
+            // Abstracts run for paragraphs
+            .lines(4)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .build();
+        let was = change.previous.as_deref().filter(|previous| !previous.trim().is_empty()).unwrap_or("empty");
+        let source_label = gtk::Label::builder()
+            .label(format!("From {}, was {}", change.source, was))
+            .xalign(0.0)
+            .wrap(true)
+            .build();
+        source_label.add_css_class("dim-label");
+        text.append(&title_label);
+        text.append(&value_label);
+        text.append(&source_label);
+        row.append(&text);
+
+        let (hash, column) = (change.hash.clone(), change.field.column());
+        if !change.reviewed {
+            let keep_button = gtk::Button::builder()
+                .label("Keep")
+                .valign(gtk::Align::Center)
+                .build();
+            keep_button.connect_clicked(glib::clone!(
+                #[weak(rename_to = _self)] self,
+                #[strong] hash,
+                move |_| _self.emit_by_name::<()>("keep", &[&hash, &column])
+            ));
+            row.append(&keep_button);
+        }
+        let revert_button = gtk::Button::builder()
+            .label("Revert")
+            .has_frame(false)
+            .valign(gtk::Align::Center)
+            .build();
+        revert_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.emit_by_name::<()>("revert", &[&hash, &column])
+        ));
+        row.append(&revert_button);
+        row
+    }
+}
@@ -2,3 +2,31 @@ pub mod window;
 pub mod settings_window;
 pub mod models;
 pub mod grid_item;
+pub mod reader_window;
+pub mod contact_sheet;
+pub mod cover_loader;
+pub mod year_review_window;
+pub mod accessibility;
+pub mod status_bar;
+pub mod usage_window;
+pub mod quick_look_window;
+pub mod about;
+pub mod shortcuts;
+pub mod command_palette;
+pub mod theme;
+pub mod library_watcher;
+pub mod scan_errors_window;
+pub mod metadata_review_window;
+pub mod metadata_editor_window;
+pub mod batch_editor_window;
+pub mod session;
+pub mod maintenance;
+pub mod preview;
+pub mod editions;
+pub mod covers;
+pub mod metadata;
+pub mod sidebar;
+pub mod workspace;
+pub mod collections;
+pub mod scan;
+pub mod commands;
@@ -3,7 +3,7 @@ use gtk::glib::{self, subclass::types::ObjectSubclassIsExt};
 use crate::pdf::PdfMetadata;
 
 mod imp {
-    use std::cell::RefCell;
+    use std::cell::{Cell, RefCell};
     use gtk::glib;
     use gtk::glib::subclass::{object::ObjectImpl, types::ObjectSubclass};
 
@@ -12,6 +12,10 @@ mod imp {
     #[derive(Debug, Default)]
     pub struct PdfMetadataObject {
         pub metadata: RefCell<Option<PdfMetadata>>,
+        // Other paths in the library with the same content hash
+        pub duplicates: RefCell<Vec<String>>,
+        // Page the built-in reader was last left on
+        pub last_page: Cell<Option<u32>>,
     }
 
     #[glib::object_subclass]
@@ -38,4 +42,21 @@ impl PdfMetadataObject {
     pub fn metadata(&self) -> Option<PdfMetadata> {
         self.imp().metadata.borrow().clone()
     }
+
+    pub fn set_duplicates(&self, paths: Vec<String>) {
+        self.imp().duplicates.replace(paths);
+    }
+
+    pub fn duplicates(&self) -> Vec<String> {
+        self.imp().duplicates.borrow().clone()
+    }
+
+    pub fn set_last_page(&self, page: Option<u32>) {
+        self.imp().last_page.set(page);
+    }
+
+    /// The page to resume from, if reading got past the first page
+    pub fn continue_page(&self) -> Option<u32> {
+        self.imp().last_page.get().filter(|&page| page > 0)
+    }
 }
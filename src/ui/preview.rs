@@ -0,0 +1,492 @@
+use std::path::{Path, PathBuf};
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::gio;
+use unicode_segmentation::UnicodeSegmentation;
+use tracing::warn;
+
+use crate::pdf::{extract_annotations, extract_snippet, page_thumbnail, Annotation, PdfMetadata};
+use crate::ui::status_bar::StatusModule;
+use crate::ui::window::ShelfWindow;
+
+// Pages shown in the preview pane's page strip
+const PAGE_STRIP_PAGES: u32 = 8;
+
+impl ShelfWindow {
+    pub(super) fn setup_preview_actions(&self) {
+        let attach_file = gio::ActionEntry::builder("attach-file")
+            .activate(|window: &Self, _, _| window.attach_files())
+            .build();
+
+        self.add_action_entries([attach_file]);
+    }
+
+    // Helper for file size conversion
+    fn format_human_readable_file_size(bytes: u64) -> String {
+        if bytes < 1024 {
+            format!("{} B", bytes)
+        } else if bytes < 1024 * 1024 {
+            format!("{:.2} KB", bytes as f64 / 1024.0)
+        } else if bytes < 1024 * 1024 * 1024 {
+            format!("{:.2} MB", bytes as f64 / (1024.0 * 1024.0))
+        } else {
+            format!("{:.2} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
+        }
+    }
+
+    fn process_option_string(value: &Option<String>) -> String {
+        let mut output = value.as_deref().unwrap_or("(N/A)").to_string();
+        if output.is_empty() { output.push_str("(empty)"); }
+        
+        let max_len = 100;
+        let graphemes: Vec<&str> = output.graphemes(true).collect();
+        if graphemes.len() <= max_len { return output; }
+        return format!("{} ...", graphemes[..max_len].concat());
+    }
+
+    pub(super) fn update_preview_display(&self, metadata: &crate::pdf::PdfMetadata) {
+        let imp = self.imp();
+        imp.preview_content.set_visible(true);
+
+        if metadata.title_is_heuristic() {
+            imp.preview_title.set_markup(&format!(
+                "{} <span size=\"small\" alpha=\"60%\">(guessed)</span>",
+                glib::markup_escape_text(&metadata.display_title())
+            ));
+            imp.preview_title.set_tooltip_text(Some("The file has no usable title, this is the largest text on its first page"));
+        } else {
+            let title_text = metadata.title.as_deref().map_or_else(
+                || "(untitled)".to_string(),
+                |s| if s.trim().is_empty() { "(untitled)".to_string() } else { s.to_string() }
+            );
+            imp.preview_title.set_text(&title_text);
+            imp.preview_title.set_tooltip_text(None);
+        }
+
+        let path_buf = PathBuf::from(&metadata.path);
+        let filename = path_buf.file_name().and_then(|s| s.to_str()).unwrap_or("(N/A)");
+        let filedir = path_buf.parent().and_then(|s| s.to_str()).unwrap_or("(N/A)");
+        imp.preview_filename.set_markup(
+            &format!("<i>{}</i>", glib::markup_escape_text(filename))
+        );
+        imp.preview_filepath.set_markup(&filedir);
+        // Copies of the same file in other places
+        let copies: Vec<String> = imp.cache.get()
+            .and_then(|cache| cache.locations(&metadata.hash).inspect_err(|e| warn!("Failed to load locations: {}", e)).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|path| *path != metadata.path)
+            .collect();
+        imp.preview_locations.set_markup(&format!(
+            "<b>Also at:</b>\n{}",
+            copies.iter().map(|path| glib::markup_escape_text(path).to_string()).collect::<Vec<_>>().join("\n")
+        ));
+        imp.preview_locations.set_visible(!copies.is_empty());
+
+        imp.preview_author.set_markup(&format!("<b>Author:</b> {}", Self::process_option_string(&metadata.author)));
+        imp.preview_subject.set_markup(&format!("<b>Subject:</b> {}", Self::process_option_string(&metadata.subject)));
+        imp.preview_keywords.set_markup(&format!("<b>Keywords:</b> {}", Self::process_option_string(&metadata.keywords)));
+        let summary = metadata.abstract_text.as_deref().filter(|text| !text.trim().is_empty());
+        imp.preview_abstract.set_markup(&format!("<b>Abstract:</b> {}", glib::markup_escape_text(summary.unwrap_or(""))));
+        imp.preview_abstract.set_visible(summary.is_some());
+        let tags = imp.cache.get()
+            .and_then(|cache| cache.get_tags(&metadata.hash).inspect_err(|e| warn!("Failed to load tags: {}", e)).ok())
+            .unwrap_or_default();
+        imp.preview_tags.set_markup(&format!("<b>Tags:</b> {}", glib::markup_escape_text(&tags.join(", "))));
+        imp.preview_tags.set_visible(!tags.is_empty());
+        let custom_fields = imp.custom_fields.borrow().get(&metadata.hash).cloned().unwrap_or_default();
+        imp.preview_custom_fields.set_markup(&custom_fields.iter()
+            .map(|(key, value)| format!("<b>{}:</b> {}", glib::markup_escape_text(key), glib::markup_escape_text(value)))
+            .collect::<Vec<_>>()
+            .join("\n"));
+        imp.preview_custom_fields.set_visible(!custom_fields.is_empty());
+        imp.preview_pages.set_text(&format!("{} pages", &metadata.page_count));
+        
+        let formatted_size = Self::format_human_readable_file_size(metadata.file_size);
+        imp.preview_filesize.set_text(&formatted_size);
+
+        self.load_snippet(metadata);
+        self.load_page_strip(metadata);
+        self.load_annotations(metadata);
+        self.load_bookmarks(&metadata.hash);
+        self.load_editions(&metadata.hash);
+        self.load_attachments(&metadata.hash);
+        self.update_cover_actions();
+    }
+
+    /// Nothing selected, the preview pane stays open but empty
+    pub(super) fn clear_preview(&self) {
+        let imp = self.imp();
+        *imp.selected.lock().unwrap() = None;
+        imp.preview_content.set_visible(false);
+        self.set_status(StatusModule::Path, "");
+        self.update_cover_actions();
+    }
+
+    /// Thumbnails of the first few pages, rendered in the background and
+    /// only once the strip is expanded
+    pub(super) fn load_page_strip(&self, metadata: &PdfMetadata) {
+        let imp = self.imp();
+        if imp.pages_strip_hash.borrow().as_deref() == Some(metadata.hash.as_str()) { return; }
+        while let Some(child) = imp.pages_strip.first_child() {
+            imp.pages_strip.remove(&child);
+        }
+        imp.pages_strip_hash.replace(None);
+        if !imp.pages_expander.is_expanded() { return; }
+        imp.pages_strip_hash.replace(Some(metadata.hash.clone()));
+
+        let (tx, rx) = async_channel::unbounded();
+        let hash = metadata.hash.clone();
+        let path = metadata.path.clone();
+        let pages = metadata.page_count.min(PAGE_STRIP_PAGES) as i32;
+        std::thread::spawn(glib::clone!(
+            #[strong] hash,
+            #[strong] path,
+            move || {
+                let document = match mupdf::Document::open(&path) {
+                    Ok(document) => document,
+                    Err(e) => {
+                        warn!("Failed to open {} for page thumbnails: {}", path, e);
+                        return;
+                    }
+                };
+                for page_no in 0..pages {
+                    match page_thumbnail(&document, &hash, page_no) {
+                        Ok(thumbnail) => if tx.send_blocking((page_no, thumbnail)).is_err() { return; },
+                        Err(e) => warn!("Failed to render page {} of {}: {}", page_no + 1, path, e),
+                    }
+                }
+            }
+        ));
+
+        glib::spawn_future_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            async move {
+                while let Ok((page_no, thumbnail)) = rx.recv().await {
+                    let imp = _self.imp();
+                    // Selection moved on, the strip belongs to another document now
+                    if imp.pages_strip_hash.borrow().as_deref() != Some(hash.as_str()) { break; }
+
+                    let picture = gtk::Picture::for_filename(&thumbnail);
+                    picture.set_can_shrink(true);
+                    picture.set_content_fit(gtk::ContentFit::Contain);
+                    picture.set_size_request(96, 136);
+                    let button = gtk::Button::builder()
+                        .child(&picture)
+                        .tooltip_text(format!("Open at page {}", page_no + 1))
+                        .build();
+                    button.add_css_class("flat");
+                    button.connect_clicked(glib::clone!(
+                        #[weak] _self,
+                        #[strong] path,
+                        move |_| _self.open_document_at(&path, Some(page_no as u32))
+                    ));
+                    imp.pages_strip.append(&button);
+                }
+            }
+        ));
+    }
+
+    pub(super) fn is_selected(&self, hash: &str) -> bool {
+        self.imp().selected.lock().unwrap().as_ref().is_some_and(|selected| selected.hash == hash)
+    }
+
+    fn show_snippet(&self, snippet: &str) {
+        let imp = self.imp();
+        imp.preview_snippet.set_text(snippet);
+        imp.preview_snippet.set_visible(!snippet.is_empty());
+    }
+
+    /// Show the opening text, extracting it in the background for documents
+    /// cached before snippets were
+    fn load_snippet(&self, metadata: &PdfMetadata) {
+        let imp = self.imp();
+        // Grid items keep the metadata from scan time, the cache may know better
+        let snippet = metadata.snippet.clone().or_else(|| {
+            imp.cache.get()
+                .and_then(|cache| cache.get_metadata(&metadata.hash).ok().flatten())
+                .and_then(|cached| cached.snippet)
+        });
+        self.show_snippet(snippet.as_deref().unwrap_or(""));
+        if snippet.is_some() { return; }
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+
+        let (tx, rx) = async_channel::bounded(1);
+        let hash = metadata.hash.clone();
+        let path = metadata.path.clone();
+        std::thread::spawn(glib::clone!(
+            #[strong] hash,
+            move || {
+                let snippet = match mupdf::Document::open(&path).map_err(Into::into).and_then(|document| extract_snippet(&document)) {
+                    Ok(snippet) => snippet,
+                    Err(e) => {
+                        warn!("Failed to extract text from {}: {}", path, e);
+                        return;
+                    }
+                };
+                if let Err(e) = cache.store_snippet(&hash, &snippet) {
+                    warn!("Failed to store text of {}: {}", path, e);
+                }
+                let _ = tx.send_blocking(snippet);
+            }
+        ));
+
+        glib::spawn_future_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(snippet) = rx.recv().await else { return; };
+                if _self.is_selected(&hash) {
+                    _self.show_snippet(&snippet);
+                }
+            }
+        ));
+    }
+
+    pub(super) fn load_bookmarks(&self, hash: &str) {
+        let imp = self.imp();
+        let bookmarks = imp.cache.get()
+            .map(|cache| cache.get_bookmarks(hash).unwrap_or_else(|e| {
+                warn!("Failed to load bookmarks: {}", e);
+                Vec::new()
+            }))
+            .unwrap_or_default();
+
+        imp.bookmarks_list.remove_all();
+        for bookmark in &bookmarks {
+            let row = gtk::Box::builder().spacing(6).build();
+            let page_label = gtk::Label::new(Some(&format!("p. {}", bookmark.page + 1)));
+            page_label.add_css_class("dim-label");
+            let label = gtk::Label::builder()
+                .label(&bookmark.label)
+                .xalign(0.0)
+                .hexpand(true)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .build();
+            let remove_button = gtk::Button::builder()
+                .icon_name("edit-delete-symbolic")
+                .tooltip_text("Remove bookmark")
+                .build();
+            remove_button.add_css_class("flat");
+            let page = bookmark.page;
+            remove_button.connect_clicked(glib::clone!(
+                #[weak(rename_to = _self)] self,
+                #[to_owned] hash,
+                #[to_owned(rename_to = label)] bookmark.label,
+                move |_| {
+                    let Some(cache) = _self.imp().cache.get() else { return; };
+                    if let Err(e) = cache.remove_bookmark(&hash, page) {
+                        _self.toast_error("Failed to remove bookmark", e);
+                        return;
+                    }
+                    _self.load_bookmarks(&hash);
+                    _self.toast_undo(&format!("Removed the bookmark on page {}", page + 1), glib::clone!(
+                        #[weak] _self,
+                        #[strong] hash,
+                        #[strong] label,
+                        move || {
+                            let Some(cache) = _self.imp().cache.get() else { return; };
+                            match cache.store_bookmark(&hash, page, &label) {
+                                Ok(()) => _self.load_bookmarks(&hash),
+                                Err(e) => _self.toast_error("Failed to restore bookmark", e),
+                            }
+                        }
+                    ));
+                }
+            ));
+            row.append(&page_label);
+            row.append(&label);
+            row.append(&remove_button);
+            row.set_tooltip_text(Some("Open at this page"));
+            imp.bookmarks_list.append(&row);
+        }
+
+        let any = !bookmarks.is_empty();
+        imp.bookmarks_heading.set_visible(any);
+        imp.bookmarks_list.set_visible(any);
+        imp.bookmarks.replace(bookmarks);
+    }
+
+    fn load_attachments(&self, hash: &str) {
+        let imp = self.imp();
+        let attachments = imp.cache.get()
+            .map(|cache| cache.get_attachments(hash).unwrap_or_else(|e| {
+                warn!("Failed to load attachments: {}", e);
+                Vec::new()
+            }))
+            .unwrap_or_default();
+
+        imp.attachments_list.remove_all();
+        for path in &attachments {
+            let row = gtk::Box::builder().spacing(6).build();
+            let (content_type, _) = gio::content_type_guess(Some(path), None);
+            let icon = gtk::Image::from_gicon(&gio::content_type_get_symbolic_icon(&content_type));
+            let name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| path.clone());
+            let label = gtk::Label::builder()
+                .label(&name)
+                .xalign(0.0)
+                .hexpand(true)
+                .ellipsize(gtk::pango::EllipsizeMode::Middle)
+                .build();
+            let remove_button = gtk::Button::builder()
+                .icon_name("edit-delete-symbolic")
+                .tooltip_text("Remove attachment")
+                .build();
+            remove_button.add_css_class("flat");
+            remove_button.connect_clicked(glib::clone!(
+                #[weak(rename_to = _self)] self,
+                #[to_owned] hash,
+                #[to_owned] path,
+                move |_| {
+                    let Some(cache) = _self.imp().cache.get() else { return; };
+                    if let Err(e) = cache.remove_attachment(&hash, &path) {
+                        _self.toast_error("Failed to remove attachment", e);
+                        return;
+                    }
+                    _self.load_attachments(&hash);
+                    let name = Path::new(&path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+                    _self.toast_undo(&format!("Removed {}", name), glib::clone!(
+                        #[weak] _self,
+                        #[strong] hash,
+                        #[strong] path,
+                        move || {
+                            let Some(cache) = _self.imp().cache.get() else { return; };
+                            match cache.add_attachment(&hash, &path) {
+                                Ok(_) => _self.load_attachments(&hash),
+                                Err(e) => _self.toast_error("Failed to restore attachment", e),
+                            }
+                        }
+                    ));
+                }
+            ));
+            row.append(&icon);
+            row.append(&label);
+            row.append(&remove_button);
+            if Path::new(path).exists() {
+                row.set_tooltip_text(Some(path));
+            } else {
+                label.add_css_class("dim-label");
+                row.set_tooltip_text(Some(&format!("{} (missing)", path)));
+            }
+            imp.attachments_list.append(&row);
+        }
+
+        let any = !attachments.is_empty();
+        imp.attachments_heading.set_visible(any);
+        imp.attachments_list.set_visible(any);
+        imp.attachments.replace(attachments);
+    }
+
+    /// Pick files to attach to the selected document
+    fn attach_files(&self) {
+        let Some(hash) = self.imp().selected.lock().unwrap().as_ref().map(|selected| selected.hash.clone()) else { return; };
+        let dialog = gtk::FileDialog::builder()
+            .title("Attach Files")
+            .accept_label("Attach")
+            .build();
+        dialog.open_multiple(Some(self), None::<&gio::Cancellable>, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |result| {
+                let Ok(files) = result else { return; };
+                let Some(cache) = _self.imp().cache.get() else { return; };
+                for file in files.iter::<gio::File>().filter_map(Result::ok) {
+                    let Some(path) = file.path() else { continue; };
+                    if let Err(e) = cache.add_attachment(&hash, &path.to_string_lossy()) {
+                        _self.toast_error(&format!("Failed to attach {}", path.display()), e);
+                    }
+                }
+                // The selection may have moved on while the dialog was open
+                let still_selected = _self.imp().selected.lock().unwrap().as_ref().is_some_and(|selected| selected.hash == hash);
+                if still_selected { _self.load_attachments(&hash); }
+            }
+        ));
+    }
+
+    /// Show cached annotations, extracting them in the background the first
+    /// time a document is selected
+    fn load_annotations(&self, metadata: &PdfMetadata) {
+        let imp = self.imp();
+        self.show_annotations(Vec::new());
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+
+        match cache.get_annotations(&metadata.hash) {
+            Ok(Some(annotations)) => {
+                self.show_annotations(annotations);
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to load annotations of {}: {}", metadata.path, e),
+        }
+
+        let (tx, rx) = async_channel::bounded(1);
+        let hash = metadata.hash.clone();
+        let path = metadata.path.clone();
+        std::thread::spawn(glib::clone!(
+            #[strong] hash,
+            move || {
+                let annotations = match extract_annotations(Path::new(&path)) {
+                    Ok(annotations) => annotations,
+                    Err(e) => {
+                        warn!("Failed to extract annotations from {}: {}", path, e);
+                        return;
+                    }
+                };
+                if let Err(e) = cache.store_annotations(&hash, &annotations) {
+                    warn!("Failed to store annotations of {}: {}", path, e);
+                }
+                let _ = tx.send_blocking(annotations);
+            }
+        ));
+
+        glib::spawn_future_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(annotations) = rx.recv().await else { return; };
+                if _self.is_selected(&hash) {
+                    _self.show_annotations(annotations);
+                }
+            }
+        ));
+    }
+
+    fn show_annotations(&self, annotations: Vec<Annotation>) {
+        let imp = self.imp();
+        imp.annotations_list.remove_all();
+        for annotation in &annotations {
+            let mut header = format!("<b>p. {}</b> · {}", annotation.page + 1, annotation.kind_label());
+            if let Some(author) = &annotation.author {
+                header.push_str(&format!(" · {}", glib::markup_escape_text(author)));
+            }
+            let row = gtk::Box::builder()
+                .orientation(gtk::Orientation::Vertical)
+                .spacing(2)
+                .margin_top(4)
+                .margin_bottom(4)
+                .build();
+            let header_label = gtk::Label::builder()
+                .label(header)
+                .use_markup(true)
+                .xalign(0.0)
+                .build();
+            header_label.add_css_class("dim-label");
+            row.append(&header_label);
+            if let Some(contents) = &annotation.contents {
+                let contents_label = gtk::Label::builder()
+                    .label(contents)
+                    .xalign(0.0)
+                    .wrap(true)
+                    .build();
+                row.append(&contents_label);
+            }
+            row.set_tooltip_text(Some("Open at this page"));
+            imp.annotations_list.append(&row);
+        }
+
+        let any = !annotations.is_empty();
+        imp.annotations_heading.set_text(&format!("Annotations ({})", annotations.len()));
+        imp.annotations_heading.set_visible(any);
+        imp.annotations_list.set_visible(any);
+        imp.annotations.replace(annotations);
+    }
+}
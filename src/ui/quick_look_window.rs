@@ -0,0 +1,143 @@
+use anyhow::Result;
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::{gdk, gio, glib};
+use mupdf::{Colorspace, Document, Matrix};
+use tracing::warn;
+
+use crate::pdf::PdfMetadata;
+use crate::utils::human_readable_file_size;
+
+// Long side of the first page render, in pixels
+const PREVIEW_SIZE: f32 = 1200.0;
+
+mod imp {
+    use gtk::glib;
+    use gtk::glib::subclass::types::ObjectSubclass;
+    use gtk::subclass::prelude::*;
+
+    #[derive(Default, gtk::CompositeTemplate)]
+    #[template(resource = "/org/galib/shelf/ui/quick_look_window.xml")]
+    pub struct ShelfQuickLookWindow {
+        #[template_child]
+        pub page_picture: TemplateChild<gtk::Picture>,
+        #[template_child]
+        pub title_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub details_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub path_label: TemplateChild<gtk::Label>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ShelfQuickLookWindow {
+        const NAME: &'static str = "ShelfQuickLookWindow";
+        type Type = super::ShelfQuickLookWindow;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ShelfQuickLookWindow {
+        fn constructed(&self) {
+            self.parent_constructed();
+        }
+    }
+
+    impl WidgetImpl for ShelfQuickLookWindow {}
+    impl WindowImpl for ShelfQuickLookWindow {}
+}
+
+glib::wrapper! {
+    pub struct ShelfQuickLookWindow(ObjectSubclass<imp::ShelfQuickLookWindow>)
+        @extends gtk::Widget, gtk::Window,
+        @implements gio::ActionGroup, gio::ActionMap,
+                    gtk::Accessible, gtk::Buildable,
+                    gtk::ConstraintTarget, gtk::Native,
+                    gtk::Root, gtk::ShortcutManager;
+}
+
+/// RGB samples of the first page, fitted into PREVIEW_SIZE
+fn render_first_page(path: &str) -> Result<(i32, i32, usize, Vec<u8>)> {
+    let document = Document::open(path)?;
+    let page = document.load_page(0)?;
+    let bounds = page.bounds()?;
+    let scale = PREVIEW_SIZE / bounds.width().max(bounds.height()).max(1.0);
+    let pixmap = page.to_pixmap(&Matrix::new_scale(scale, scale), &Colorspace::device_rgb(), false, true)?;
+    Ok((pixmap.width() as i32, pixmap.height() as i32, pixmap.stride() as usize, pixmap.samples().to_vec()))
+}
+
+impl ShelfQuickLookWindow {
+    /// Show `metadata` with `cover` until the first page is rendered
+    pub fn new(metadata: &PdfMetadata, cover: Option<&gdk::Texture>) -> Self {
+        let obj: ShelfQuickLookWindow = glib::Object::builder()
+            .property("title", metadata.display_title())
+            .build();
+        obj.populate(metadata, cover);
+        obj.setup();
+        obj
+    }
+
+    fn setup(&self) {
+        let key_controller = gtk::EventControllerKey::new();
+        key_controller.connect_key_pressed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] glib::Propagation::Proceed,
+            move |_, key, _, _| match key {
+                gdk::Key::space | gdk::Key::Escape => {
+                    _self.close();
+                    glib::Propagation::Stop
+                }
+                _ => glib::Propagation::Proceed,
+            }
+        ));
+        self.add_controller(key_controller);
+    }
+
+    fn populate(&self, metadata: &PdfMetadata, cover: Option<&gdk::Texture>) {
+        let imp = self.imp();
+        imp.title_label.set_text(&metadata.display_title());
+        let mut details = Vec::new();
+        if let Some(author) = metadata.author.as_deref().filter(|a| !a.trim().is_empty()) {
+            details.push(format!("<b>Author:</b> {}", glib::markup_escape_text(author)));
+        }
+        if let Some(subject) = metadata.subject.as_deref().filter(|s| !s.trim().is_empty()) {
+            details.push(format!("<b>Subject:</b> {}", glib::markup_escape_text(subject)));
+        }
+        details.push(format!("{} pages, {}", metadata.page_count, human_readable_file_size(metadata.file_size)));
+        imp.details_label.set_markup(&details.join("\n"));
+        imp.path_label.set_text(&metadata.path);
+
+        imp.page_picture.set_paintable(cover);
+        if metadata.page_count == 0 { return; }
+
+        let (tx, rx) = async_channel::bounded(1);
+        let path = metadata.path.clone();
+        std::thread::spawn(move || {
+            match render_first_page(&path) {
+                Ok(rendered) => { let _ = tx.send_blocking(rendered); }
+                Err(e) => warn!("Failed to render the first page of {}: {}", path, e),
+            }
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok((width, height, stride, samples)) = rx.recv().await else { return; };
+                let texture = gdk::MemoryTexture::new(
+                    width,
+                    height,
+                    gdk::MemoryFormat::R8g8b8,
+                    &glib::Bytes::from_owned(samples),
+                    stride,
+                );
+                _self.imp().page_picture.set_paintable(Some(&texture));
+            }
+        ));
+    }
+}
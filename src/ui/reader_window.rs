@@ -0,0 +1,741 @@
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::{gdk, gio, glib};
+use mupdf::{Colorspace, Document, Matrix, MetadataName, Pixmap, Rect};
+use tracing::warn;
+
+use crate::pdf::PdfCache;
+use crate::ui::accessibility::prefers_reduced_motion;
+
+const DEFAULT_ZOOM: f32 = 1.5;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 5.0;
+const ZOOM_STEP: f32 = 1.25;
+const SEARCH_HIT_MAX: u32 = 512;
+// Pages are searched for this long at a time before the reader handles
+// input again, so a query without matches doesn't freeze it
+const SEARCH_SLICE: Duration = Duration::from_millis(10);
+const SPREAD_SPACING: f32 = 8.0;
+// Highlight colors blended over search matches, RGB
+const MATCH_COLOR: [u8; 3] = [255, 230, 0];
+const CURRENT_MATCH_COLOR: [u8; 3] = [255, 140, 0];
+// Text and background colors of night mode, as 0xRRGGBB
+const NIGHT_TEXT_COLOR: i32 = 0xdcdcdc;
+const NIGHT_BACKGROUND_COLOR: i32 = 0x1e1e1e;
+
+/// How pages are colored after rendering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    #[default]
+    Normal,
+    // Dark background with light text, images keep their tones
+    Night,
+    // Every color inverted, images included
+    Inverted,
+}
+
+impl RenderMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RenderMode::Normal => "normal",
+            RenderMode::Night => "night",
+            RenderMode::Inverted => "inverted",
+        }
+    }
+
+    pub fn from_str(mode: &str) -> Self {
+        match mode {
+            "night" => RenderMode::Night,
+            "inverted" => RenderMode::Inverted,
+            _ => RenderMode::Normal,
+        }
+    }
+
+    fn apply(&self, pixmap: &mut Pixmap) -> Result<()> {
+        match self {
+            RenderMode::Normal => {}
+            RenderMode::Night => pixmap.tint(NIGHT_TEXT_COLOR, NIGHT_BACKGROUND_COLOR)?,
+            RenderMode::Inverted => pixmap.invert()?,
+        }
+        Ok(())
+    }
+}
+
+mod imp {
+    use std::cell::{Cell, OnceCell, RefCell};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use gtk::glib;
+    use gtk::glib::subclass::types::ObjectSubclass;
+    use gtk::subclass::prelude::*;
+    use mupdf::{Document, Rect};
+
+    use crate::pdf::PdfCache;
+    use super::RenderMode;
+
+    #[derive(Default, gtk::CompositeTemplate)]
+    #[template(resource = "/org/galib/shelf/ui/reader_window.xml")]
+    pub struct ShelfReaderWindow {
+        #[template_child]
+        pub prev_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub next_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub page_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub zoom_in_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub zoom_out_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub zoom_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub scrolled_window: TemplateChild<gtk::ScrolledWindow>,
+        #[template_child]
+        pub pages_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub page_picture: TemplateChild<gtk::Picture>,
+        #[template_child]
+        pub second_picture: TemplateChild<gtk::Picture>,
+        #[template_child]
+        pub bookmark_button: TemplateChild<gtk::MenuButton>,
+        #[template_child]
+        pub bookmark_popover: TemplateChild<gtk::Popover>,
+        #[template_child]
+        pub bookmark_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub bookmark_add_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub spread_button: TemplateChild<gtk::ToggleButton>,
+        #[template_child]
+        pub presentation_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub search_button: TemplateChild<gtk::ToggleButton>,
+        #[template_child]
+        pub search_bar: TemplateChild<gtk::SearchBar>,
+        #[template_child]
+        pub search_entry: TemplateChild<gtk::SearchEntry>,
+        #[template_child]
+        pub search_prev_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub search_next_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub search_status: TemplateChild<gtk::Label>,
+
+        pub document: RefCell<Option<Document>>,
+        pub page: Cell<i32>,
+        pub page_count: Cell<i32>,
+        pub zoom: Cell<f32>,
+        // Content hash the reading position is remembered under
+        pub hash: RefCell<Option<String>>,
+        pub cache: OnceCell<Arc<PdfCache>>,
+        // Matches of the current query in page coordinates, filled in page by page
+        pub search_hits: RefCell<HashMap<i32, Vec<Rect>>>,
+        // (page, index into that page's hits)
+        pub current_match: Cell<Option<(i32, usize)>>,
+        // Searching the following pages for the next match, a slice at a time
+        pub search_source: RefCell<Option<glib::SourceId>>,
+        pub render_mode: Cell<RenderMode>,
+        // Show facing pages side by side, the first page stays on its own like a book cover
+        pub spread: Cell<bool>,
+        // Fullscreen with the pages fitted to the screen
+        pub presenting: Cell<bool>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ShelfReaderWindow {
+        const NAME: &'static str = "ShelfReaderWindow";
+        type Type = super::ShelfReaderWindow;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ShelfReaderWindow {
+        fn constructed(&self) {
+            self.parent_constructed();
+        }
+    }
+
+    impl WidgetImpl for ShelfReaderWindow {}
+    impl WindowImpl for ShelfReaderWindow {}
+}
+
+glib::wrapper! {
+    pub struct ShelfReaderWindow(ObjectSubclass<imp::ShelfReaderWindow>)
+        @extends gtk::Widget, gtk::Window,
+        @implements gio::ActionGroup, gio::ActionMap,
+                    gtk::Accessible, gtk::Buildable,
+                    gtk::ConstraintTarget, gtk::Native,
+                    gtk::Root, gtk::ShortcutManager;
+}
+
+/// Wrap an RGB pixmap rendered by MuPDF into a texture GTK can display
+pub fn pixmap_to_texture(pixmap: &Pixmap) -> gdk::MemoryTexture {
+    let format = if pixmap.n() == 4 { gdk::MemoryFormat::R8g8b8a8 } else { gdk::MemoryFormat::R8g8b8 };
+    gdk::MemoryTexture::new(
+        pixmap.width() as i32,
+        pixmap.height() as i32,
+        format,
+        &glib::Bytes::from(pixmap.samples()),
+        pixmap.stride() as usize,
+    )
+}
+
+/// Blend `color` over the pixels covered by `rect`, given in page coordinates
+fn highlight(pixmap: &mut Pixmap, rect: &Rect, zoom: f32, color: [u8; 3]) {
+    let (origin_x, origin_y) = pixmap.origin();
+    let width = pixmap.width() as i32;
+    let height = pixmap.height() as i32;
+    let stride = pixmap.stride() as usize;
+    let n = pixmap.n() as usize;
+
+    let x0 = ((rect.x0 * zoom) as i32 - origin_x).clamp(0, width);
+    let x1 = ((rect.x1 * zoom).ceil() as i32 - origin_x).clamp(0, width);
+    let y0 = ((rect.y0 * zoom) as i32 - origin_y).clamp(0, height);
+    let y1 = ((rect.y1 * zoom).ceil() as i32 - origin_y).clamp(0, height);
+
+    let samples = pixmap.samples_mut();
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let offset = y as usize * stride + x as usize * n;
+            for (channel, tint) in color.iter().enumerate() {
+                // Multiply keeps the text legible under the highlight
+                let value = &mut samples[offset + channel];
+                *value = ((*value as u16 * *tint as u16) / 255) as u8;
+            }
+        }
+    }
+}
+
+impl ShelfReaderWindow {
+    /// Open `path`, resuming from the last page stored for `hash` if there is one
+    pub fn new(path: &Path, cache: Arc<PdfCache>, hash: Option<String>) -> Result<Self> {
+        let document = Document::open(path).context("Failed to open document")?;
+        let page_count = document.page_count()?;
+        let title = document.metadata(MetadataName::Title).ok()
+            .filter(|title| !title.trim().is_empty())
+            .or_else(|| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+
+        let obj: ShelfReaderWindow = glib::Object::builder().property("title", title).build();
+        let imp = obj.imp();
+        imp.document.replace(Some(document));
+        imp.page_count.set(page_count);
+        imp.zoom.set(DEFAULT_ZOOM);
+
+        let last_page = hash.as_deref()
+            .and_then(|hash| cache.get_last_page(hash).unwrap_or_else(|e| {
+                warn!("Failed to read the last page of {}: {}", path.display(), e);
+                None
+            }))
+            .unwrap_or(0);
+        imp.page.set((last_page as i32).clamp(0, (page_count - 1).max(0)));
+
+        let render_mode = hash.as_deref()
+            .and_then(|hash| cache.get_render_mode(hash).unwrap_or_else(|e| {
+                warn!("Failed to read the render mode of {}: {}", path.display(), e);
+                None
+            }))
+            .map_or(RenderMode::Normal, |mode| RenderMode::from_str(&mode));
+        imp.render_mode.set(render_mode);
+        // Bookmarks are stored by content hash too
+        imp.bookmark_button.set_sensitive(hash.is_some());
+        imp.hash.replace(hash);
+        imp.cache.set(cache).ok();
+
+        obj.setup();
+        obj.render_page();
+        Ok(obj)
+    }
+
+    fn setup(&self) {
+        let imp = self.imp();
+        imp.scrolled_window.set_kinetic_scrolling(!prefers_reduced_motion());
+        // Facing pages are in the document's order, a right-to-left locale
+        // would otherwise put the left page on the right
+        imp.pages_box.set_direction(gtk::TextDirection::Ltr);
+
+        imp.prev_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.flip(false)
+        ));
+        imp.next_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.flip(true)
+        ));
+        imp.spread_button.connect_toggled(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |button| _self.set_spread(button.is_active())
+        ));
+        imp.presentation_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.set_presenting(true)
+        ));
+        self.connect_fullscreened_notify(|_self| {
+            // The window manager can leave fullscreen on its own
+            if !_self.is_fullscreen() && _self.imp().presenting.get() {
+                _self.set_presenting(false);
+            }
+        });
+        // Refit the pages once the fullscreen size is known
+        for adjustment in [imp.scrolled_window.hadjustment(), imp.scrolled_window.vadjustment()] {
+            adjustment.connect_notify_local(Some("page-size"), glib::clone!(
+                #[weak(rename_to = _self)] self,
+                move |_, _| {
+                    if _self.imp().presenting.get() {
+                        _self.render_page();
+                    }
+                }
+            ));
+        }
+
+        // Clicking flips pages while presenting, like a slide remote
+        let click = gtk::GestureClick::builder().button(0).build();
+        click.connect_pressed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |gesture, _, _, _| {
+                if !_self.imp().presenting.get() { return; }
+                _self.flip(gesture.current_button() != gdk::BUTTON_SECONDARY);
+            }
+        ));
+        imp.scrolled_window.add_controller(click);
+        imp.bookmark_popover.connect_show(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| {
+                let imp = _self.imp();
+                imp.bookmark_entry.set_text("");
+                imp.bookmark_entry.grab_focus();
+            }
+        ));
+        imp.bookmark_entry.connect_activate(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.add_bookmark()
+        ));
+        imp.bookmark_add_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.add_bookmark()
+        ));
+        imp.zoom_in_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.set_zoom(_self.imp().zoom.get() * ZOOM_STEP)
+        ));
+        imp.zoom_out_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.set_zoom(_self.imp().zoom.get() / ZOOM_STEP)
+        ));
+
+        imp.search_button
+            .bind_property("active", &*imp.search_bar, "search-mode-enabled")
+            .bidirectional()
+            .sync_create()
+            .build();
+        imp.search_bar.connect_entry(&*imp.search_entry);
+        imp.search_bar.connect_search_mode_enabled_notify(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |search_bar| {
+                if !search_bar.is_search_mode() {
+                    _self.clear_search();
+                }
+            }
+        ));
+        imp.search_entry.connect_search_changed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| {
+                _self.clear_search();
+                _self.find_match(true);
+            }
+        ));
+        imp.search_entry.connect_activate(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.find_match(true)
+        ));
+        imp.search_entry.connect_next_match(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.find_match(true)
+        ));
+        imp.search_entry.connect_previous_match(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.find_match(false)
+        ));
+        imp.search_next_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.find_match(true)
+        ));
+        imp.search_prev_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.find_match(false)
+        ));
+
+        let actions = gio::SimpleActionGroup::new();
+        let render_mode = gio::ActionEntry::builder("render-mode")
+            .parameter_type(Some(&String::static_variant_type()))
+            .state(imp.render_mode.get().as_str().to_variant())
+            .activate(glib::clone!(
+                #[weak(rename_to = _self)] self,
+                move |_: &gio::SimpleActionGroup, action, param| {
+                    let Some(mode) = param.and_then(|p| p.get::<String>()) else { return; };
+                    action.set_state(&mode.to_variant());
+                    _self.set_render_mode(RenderMode::from_str(&mode));
+                }
+            ))
+            .build();
+        actions.add_action_entries([render_mode]);
+        self.insert_action_group("reader", Some(&actions));
+        self.update_mode_style();
+
+        let key_controller = gtk::EventControllerKey::new();
+        key_controller.connect_key_pressed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] glib::Propagation::Proceed,
+            move |_, key, _, _| {
+                let imp = _self.imp();
+                match key {
+                    gdk::Key::Right | gdk::Key::Page_Down | gdk::Key::space => _self.flip(true),
+                    gdk::Key::Left | gdk::Key::Page_Up | gdk::Key::BackSpace => _self.flip(false),
+                    // The arrow keys scroll the page unless presenting
+                    gdk::Key::Down if imp.presenting.get() => _self.flip(true),
+                    gdk::Key::Up if imp.presenting.get() => _self.flip(false),
+                    gdk::Key::d => imp.spread_button.set_active(!imp.spread_button.is_active()),
+                    gdk::Key::b if imp.bookmark_button.is_sensitive() => imp.bookmark_button.popup(),
+                    gdk::Key::F5 => _self.set_presenting(!imp.presenting.get()),
+                    gdk::Key::Escape if imp.presenting.get() => _self.set_presenting(false),
+                    gdk::Key::Home => _self.go_to_page(0),
+                    gdk::Key::End => _self.go_to_page(imp.page_count.get() - 1),
+                    gdk::Key::plus | gdk::Key::equal | gdk::Key::KP_Add => _self.set_zoom(imp.zoom.get() * ZOOM_STEP),
+                    gdk::Key::minus | gdk::Key::KP_Subtract => _self.set_zoom(imp.zoom.get() / ZOOM_STEP),
+                    gdk::Key::slash => {
+                        imp.search_bar.set_search_mode(true);
+                        imp.search_entry.grab_focus();
+                    }
+                    gdk::Key::i => {
+                        // Toggle between the document's colors and night mode
+                        let mode = if imp.render_mode.get() == RenderMode::Normal { RenderMode::Night } else { RenderMode::Normal };
+                        let _ = WidgetExt::activate_action(&_self, "reader.render-mode", Some(&mode.as_str().to_variant()));
+                    }
+                    gdk::Key::n => _self.find_match(true),
+                    gdk::Key::N => _self.find_match(false),
+                    _ => return glib::Propagation::Proceed,
+                }
+                glib::Propagation::Stop
+            }
+        ));
+        self.add_controller(key_controller);
+    }
+
+    /// First page of the spread that contains `page`
+    fn spread_start(page: i32) -> i32 {
+        if page <= 0 || page % 2 == 1 { page.max(0) } else { page - 1 }
+    }
+
+    /// Pages currently on screen, one or two in spread mode
+    fn visible_pages(&self) -> Vec<i32> {
+        let imp = self.imp();
+        let page = imp.page.get();
+        if imp.spread.get() && page > 0 && page + 1 < imp.page_count.get() {
+            vec![page, page + 1]
+        } else {
+            vec![page]
+        }
+    }
+
+    fn flip(&self, forward: bool) {
+        let imp = self.imp();
+        let page = imp.page.get();
+        let step = if imp.spread.get() && page > 0 { 2 } else { 1 };
+        if forward {
+            self.go_to_page(page + step);
+        } else {
+            // From the right-hand spread back to the cover is a single step
+            self.go_to_page((page - step).max(0));
+        }
+    }
+
+    fn set_spread(&self, spread: bool) {
+        let imp = self.imp();
+        imp.spread.set(spread);
+        imp.page.set(if spread { Self::spread_start(imp.page.get()) } else { imp.page.get() });
+        self.render_page();
+    }
+
+    fn set_presenting(&self, presenting: bool) {
+        let imp = self.imp();
+        if imp.presenting.get() == presenting { return; }
+        imp.presenting.set(presenting);
+        if let Some(titlebar) = self.titlebar() {
+            titlebar.set_visible(!presenting);
+        }
+        if presenting {
+            imp.search_bar.set_search_mode(false);
+            imp.scrolled_window.add_css_class("reader-presentation");
+            self.fullscreen();
+        } else {
+            imp.scrolled_window.remove_css_class("reader-presentation");
+            self.unfullscreen();
+        }
+        self.render_page();
+    }
+
+    pub fn go_to_page(&self, page: i32) {
+        let imp = self.imp();
+        let mut page = page.clamp(0, (imp.page_count.get() - 1).max(0));
+        if imp.spread.get() { page = Self::spread_start(page); }
+        if page == imp.page.get() { return; }
+        imp.page.set(page);
+        self.render_page();
+        imp.scrolled_window.vadjustment().set_value(0.0);
+        self.save_position();
+    }
+
+    fn add_bookmark(&self) {
+        let imp = self.imp();
+        imp.bookmark_popover.popdown();
+        let Some(hash) = imp.hash.borrow().clone() else { return; };
+        let page = imp.page.get() as u32;
+        let label = imp.bookmark_entry.text();
+        let label = if label.trim().is_empty() { format!("Page {}", page + 1) } else { label.trim().to_string() };
+        if let Err(e) = imp.cache.get().unwrap().store_bookmark(&hash, page, &label) {
+            warn!("Failed to store bookmark: {}", e);
+        }
+    }
+
+    fn set_render_mode(&self, mode: RenderMode) {
+        let imp = self.imp();
+        if imp.render_mode.get() == mode { return; }
+        imp.render_mode.set(mode);
+        self.update_mode_style();
+        self.render_page();
+
+        let (Some(hash), Some(cache)) = (imp.hash.borrow().clone(), imp.cache.get()) else { return; };
+        if let Err(e) = cache.store_render_mode(&hash, mode.as_str()) {
+            warn!("Failed to save render mode: {}", e);
+        }
+    }
+
+    fn update_mode_style(&self) {
+        let scrolled_window = &self.imp().scrolled_window;
+        if self.imp().render_mode.get() == RenderMode::Normal {
+            scrolled_window.remove_css_class("reader-night");
+        } else {
+            scrolled_window.add_css_class("reader-night");
+        }
+    }
+
+    fn save_position(&self) {
+        let imp = self.imp();
+        let (Some(hash), Some(cache)) = (imp.hash.borrow().clone(), imp.cache.get()) else { return; };
+        if let Err(e) = cache.store_last_page(&hash, imp.page.get() as u32) {
+            warn!("Failed to save reading position: {}", e);
+        }
+    }
+
+    fn set_zoom(&self, zoom: f32) {
+        self.imp().zoom.set(zoom.clamp(MIN_ZOOM, MAX_ZOOM));
+        self.render_page();
+    }
+
+    /// The zoom that fits `pages` side by side inside the window
+    fn fit_zoom(&self, pages: &[i32]) -> Result<f32> {
+        let imp = self.imp();
+        let document = imp.document.borrow();
+        let document = document.as_ref().context("No document loaded")?;
+        let mut width = SPREAD_SPACING * (pages.len() - 1) as f32;
+        let mut height: f32 = 0.0;
+        for page in pages {
+            let bounds = document.load_page(*page)?.bounds()?;
+            width += bounds.width();
+            height = height.max(bounds.height());
+        }
+
+        let margin = (imp.pages_box.margin_top() + imp.pages_box.margin_bottom()) as f32;
+        let available_width = imp.scrolled_window.hadjustment().page_size() as f32 - margin;
+        let available_height = imp.scrolled_window.vadjustment().page_size() as f32 - margin;
+        if width <= 0.0 || height <= 0.0 || available_width <= 0.0 || available_height <= 0.0 {
+            return Ok(imp.zoom.get());
+        }
+        Ok((available_width / width).min(available_height / height).clamp(MIN_ZOOM, MAX_ZOOM))
+    }
+
+    fn render_page(&self) {
+        let imp = self.imp();
+        let page = imp.page.get();
+        let page_count = imp.page_count.get();
+        let pages = self.visible_pages();
+        let last = *pages.last().unwrap();
+
+        if pages.len() > 1 {
+            imp.page_label.set_text(&format!("{}\u{2013}{} / {}", page + 1, last + 1, page_count));
+        } else {
+            imp.page_label.set_text(&format!("{} / {}", page + 1, page_count));
+        }
+        imp.prev_button.set_sensitive(page > 0);
+        imp.next_button.set_sensitive(last + 1 < page_count);
+
+        let zoom = if imp.presenting.get() {
+            self.fit_zoom(&pages).unwrap_or_else(|_| imp.zoom.get())
+        } else {
+            imp.zoom.get()
+        };
+        imp.zoom_label.set_text(&format!("{:.0}%", zoom * 100.0));
+
+        let pictures = [&*imp.page_picture, &*imp.second_picture];
+        for (index, picture) in pictures.iter().enumerate() {
+            let Some(&page) = pages.get(index) else {
+                picture.set_visible(false);
+                continue;
+            };
+            picture.set_visible(true);
+            match self.render_texture(page, zoom) {
+                Ok(texture) => picture.set_paintable(Some(&texture)),
+                Err(e) => warn!("Failed to render page {}: {}", page + 1, e),
+            }
+        }
+    }
+
+    fn render_texture(&self, page_no: i32, zoom: f32) -> Result<gdk::MemoryTexture> {
+        let imp = self.imp();
+        let document = imp.document.borrow();
+        let document = document.as_ref().context("No document loaded")?;
+        let page = document.load_page(page_no)?;
+
+        let matrix = Matrix::new_scale(zoom, zoom);
+        let mut pixmap = page.to_pixmap(&matrix, &Colorspace::device_rgb(), false, true)?;
+
+        if let Some(hits) = imp.search_hits.borrow().get(&page_no) {
+            let current = imp.current_match.get()
+                .and_then(|(page, index)| (page == page_no).then_some(index));
+            for (index, hit) in hits.iter().enumerate() {
+                let color = if Some(index) == current { CURRENT_MATCH_COLOR } else { MATCH_COLOR };
+                highlight(&mut pixmap, hit, zoom, color);
+            }
+        }
+        imp.render_mode.get().apply(&mut pixmap)?;
+        Ok(pixmap_to_texture(&pixmap))
+    }
+
+    /// Matches of the search query on `page_no`, searching the page on first use
+    fn page_hits(&self, page_no: i32, query: &str) -> Vec<Rect> {
+        let imp = self.imp();
+        if let Some(hits) = imp.search_hits.borrow().get(&page_no) {
+            return hits.clone();
+        }
+
+        let hits: Vec<Rect> = imp.document.borrow().as_ref()
+            .and_then(|document| document.load_page(page_no).ok())
+            .and_then(|page| page.search(query, SEARCH_HIT_MAX).ok())
+            .map(|quads| quads.iter().map(|quad| Rect {
+                x0: quad.ul.x.min(quad.ll.x),
+                y0: quad.ul.y.min(quad.ur.y),
+                x1: quad.ur.x.max(quad.lr.x),
+                y1: quad.ll.y.max(quad.lr.y),
+            }).collect())
+            .unwrap_or_default();
+        imp.search_hits.borrow_mut().insert(page_no, hits.clone());
+        hits
+    }
+
+    /// Move to the next (or previous) match of the search query, wrapping
+    /// around the end of the document. Pages after the current one are
+    /// searched from an idle source, the next call cancels it.
+    fn find_match(&self, forward: bool) {
+        let imp = self.imp();
+        self.stop_searching();
+        let query = imp.search_entry.text().to_string();
+        if query.is_empty() {
+            imp.search_status.set_text("");
+            return;
+        }
+
+        let page_count = imp.page_count.get();
+        let current = imp.current_match.get();
+        if let Some((page, index)) = current {
+            let hits = self.page_hits(page, &query);
+            let next = if forward { Some(index + 1) } else { index.checked_sub(1) };
+            if let Some(next) = next.filter(|&next| next < hits.len()) {
+                self.show_match(page, next, &hits);
+                return;
+            }
+        }
+
+        // Continue on the following pages; without a current match the page
+        // being viewed is searched first
+        let start_page = current.map_or(imp.page.get(), |(page, _)| page);
+        let mut step = if current.is_some() { 1 } else { 0 };
+        imp.search_status.set_text("Searching…");
+        let source = glib::idle_add_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] glib::ControlFlow::Break,
+            move || {
+                let started = Instant::now();
+                while step <= page_count {
+                    let offset = if forward { step } else { -step };
+                    let page = (start_page + offset).rem_euclid(page_count.max(1));
+                    step += 1;
+                    let hits = _self.page_hits(page, &query);
+                    if !hits.is_empty() {
+                        _self.imp().search_source.take();
+                        let index = if forward { 0 } else { hits.len() - 1 };
+                        _self.show_match(page, index, &hits);
+                        return glib::ControlFlow::Break;
+                    }
+                    if started.elapsed() >= SEARCH_SLICE { return glib::ControlFlow::Continue; }
+                }
+
+                let imp = _self.imp();
+                imp.search_source.take();
+                imp.current_match.set(None);
+                imp.search_status.set_text("No matches");
+                _self.render_page();
+                glib::ControlFlow::Break
+            }
+        ));
+        imp.search_source.replace(Some(source));
+    }
+
+    fn stop_searching(&self) {
+        if let Some(source) = self.imp().search_source.take() {
+            source.remove();
+        }
+    }
+
+    fn show_match(&self, page: i32, index: usize, hits: &[Rect]) {
+        let imp = self.imp();
+        imp.current_match.set(Some((page, index)));
+        imp.search_status.set_text(&format!("{} of {} on page {}", index + 1, hits.len(), page + 1));
+        if self.visible_pages().contains(&page) {
+            self.render_page();
+        } else {
+            self.go_to_page(page);
+        }
+
+        // Bring the match into view, leaving a little context above it
+        let adjustment = imp.scrolled_window.vadjustment();
+        let hit_top = imp.pages_box.margin_top() as f64 + (hits[index].y0 * imp.zoom.get()) as f64;
+        let visible = adjustment.value()..adjustment.value() + adjustment.page_size();
+        if !visible.contains(&hit_top) {
+            adjustment.set_value(hit_top - adjustment.page_size() / 3.0);
+        }
+    }
+
+    fn clear_search(&self) {
+        let imp = self.imp();
+        self.stop_searching();
+        imp.search_hits.borrow_mut().clear();
+        imp.current_match.set(None);
+        imp.search_status.set_text("");
+        self.render_page();
+    }
+}
@@ -0,0 +1,792 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::gio;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use tracing::{debug, error, info, warn};
+
+use crate::config::NetworkMounts;
+use crate::filename_patterns;
+use crate::pdf::{extract_pdf_metadata, reextract_pdf_metadata, ExtractionLimits, PdfMetadata, ScanProgress, ScanReporter, StuckReads, PROGRESS_CHANNEL_CAPACITY, QUARANTINE_AFTER};
+use crate::ui::library_watcher::{Changes, LibraryWatcher};
+use crate::ui::scan_errors_window::ShelfScanErrorsWindow;
+use crate::ui::settings_window::ShelfSettingsWindow;
+use crate::ui::status_bar::StatusModule;
+use crate::ui::window::ShelfWindow;
+use crate::utils::{file_stat, human_readable_duration, scan_pdfs_rayon, unix_now, Visited};
+use crate::volumes;
+
+const LOW_MEMORY_SCAN_THREADS: usize = 2;
+// Search results are refreshed at most this often while a scan is adding documents
+const SCAN_SEARCH_REFRESH: std::time::Duration = std::time::Duration::from_millis(300);
+// How long a scan runs before it guesses at the time remaining
+const ETA_AFTER: std::time::Duration = std::time::Duration::from_secs(2);
+
+impl ShelfWindow {
+    pub(super) fn setup_scan_actions(&self, model: &gio::ListStore) {
+        let Some(app) = self.application() else { return; };
+        // Notifications can only trigger application actions
+        let show_scan_errors = gio::ActionEntry::builder("show-scan-errors")
+            .activate(glib::clone!(
+                #[weak(rename_to = window)] self,
+                #[strong] model,
+                move |_: &gtk::Application, _, _| {
+                    window.present();
+                    window.show_scan_errors(&model);
+                }
+            ))
+            .build();
+        app.add_action_entries([show_scan_errors]);
+    }
+
+    /// Scan just `folder`, one of the scan directories, unless a scan is
+    /// already running. Returns false if one was.
+    pub(super) fn rescan_folder(&self, model: &gio::ListStore, folder: PathBuf) -> bool {
+        if !self.imp().refresh_button.is_sensitive() { return false; }
+        self.start_scan(model, Some(folder));
+        true
+    }
+
+    /// Start a scan, unless one is already running. Returns false if one was.
+    pub fn rescan(&self) -> bool {
+        let refresh_button = &self.imp().refresh_button;
+        if !refresh_button.is_sensitive() { return false; }
+        refresh_button.emit_clicked();
+        true
+    }
+
+    /// Stop the running scan, what it extracted so far stays in the library
+    /// and the cache
+    pub(super) fn cancel_scan(&self) {
+        let imp = self.imp();
+        if imp.refresh_button.is_sensitive() { return; }
+        imp.scan_cancelled.store(true, Ordering::Relaxed);
+        imp.cancel_scan_button.set_sensitive(false);
+        self.set_status(StatusModule::Scan, "Stopping the scan...");
+    }
+
+    /// Scan the scan directories in the background, or only `folder`, and
+    /// replace what the library knew about them once that's done
+    pub(super) fn start_scan(&self, model: &gio::ListStore, folder: Option<PathBuf>) {
+        let imp = self.imp();
+        let model = model.clone();
+        let config = imp.config.get().unwrap().clone();
+        let metadata_list = imp.metadata_list.clone();
+        let refresh_button = imp.refresh_button.get();
+        let search_entry = imp.search_entry.get();
+        // Disable button during scan, searching keeps working on
+        // whatever has been extracted so far
+        refresh_button.set_sensitive(false);
+        imp.cancel_scan_button.set_sensitive(true);
+        imp.cancel_scan_button.set_visible(true);
+        match &folder {
+            Some(folder) => self.set_status(StatusModule::Scan, &format!("Scanning {}...", folder.display())),
+            None => self.set_status(StatusModule::Scan, "Scanning..."),
+        }
+        
+        // The grid keeps showing the last results, new documents are
+        // merged in as they're extracted and the list is replaced once
+        // the scan is complete
+        let (tx, rx) = async_channel::bounded::<ScanProgress>(PROGRESS_CHANNEL_CAPACITY);
+        imp.scan_progress.replace(Some(tx.clone()));
+        let cache = imp.cache.get().cloned();
+        let cancelled = imp.scan_cancelled.clone();
+        cancelled.store(false, Ordering::Relaxed);
+        imp.scan_errors.borrow_mut().clear();
+        self.scan_errors_changed();
+        imp.scan_started_at.set(unix_now());
+        self.warn_network_mounts(folder.as_deref());
+        let only = folder.clone();
+        let scan_thread = std::thread::spawn(glib::clone!(
+            #[strong] config,
+            move || {
+                let start_time = Instant::now(); 
+                let Some(cache) = cache else {
+                    let _ = tx.send_blocking(ScanProgress::Error(
+                        PathBuf::from("cache"),
+                        "Failed to initialize cache".to_string()
+                    ));
+                    return;
+                };
+                let (scan_dirs, extra_files, threads, options, skipped) = {
+                    let config = config.read().unwrap();
+                    // 0 lets rayon pick one thread per core
+                    let threads = match config.scan_threads {
+                        0 if config.low_memory_mode => LOW_MEMORY_SCAN_THREADS,
+                        threads => threads,
+                    };
+                    let mounts = volumes::read_mounts();
+                    let network_dirs: Vec<PathBuf> = config.scan_dirs.iter().chain(&folder)
+                        .filter(|dir| volumes::is_network_path(&mounts, dir))
+                        .cloned()
+                        .collect();
+                    cache.set_extraction_limits(ExtractionLimits {
+                        timeout: (config.extraction_timeout_secs > 0).then(|| std::time::Duration::from_secs(config.extraction_timeout_secs)),
+                        max_file_size: (config.max_file_size_mb > 0).then(|| config.max_file_size_mb * 1024 * 1024),
+                        max_render_dimension: (config.max_render_dimension > 0).then_some(config.max_render_dimension),
+                        max_concurrent_renders: config.max_concurrent_renders,
+                        metadata_only: match config.network_mounts {
+                            NetworkMounts::MetadataOnly => network_dirs.clone(),
+                            _ => Vec::new(),
+                        },
+                        filename_patterns: filename_patterns::compile(&config.filename_patterns),
+                    });
+                    let options = config.walk_options();
+                    match folder {
+                        // Asked for, so scanned even if network mounts are skipped
+                        Some(folder) => (vec![folder], Vec::new(), threads, options, Vec::new()),
+                        None if config.network_mounts == NetworkMounts::Skip => {
+                            let scan_dirs = config.scan_dirs.iter().filter(|dir| !network_dirs.contains(dir)).cloned().collect();
+                            (scan_dirs, config.extra_files.clone(), threads, options, network_dirs)
+                        }
+                        None => (config.scan_dirs.clone(), config.extra_files.clone(), threads, options, Vec::new()),
+                    }
+                };
+                let pool = match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+                    Ok(pool) => pool,
+                    Err(e) => {
+                        let _ = tx.send_blocking(ScanProgress::Error(
+                            PathBuf::from("scan"),
+                            format!("Failed to start scan threads: {}", e)
+                        ));
+                        return;
+                    }
+                };
+
+                let reporter = ScanReporter::new(tx);
+                let quarantined = cache.quarantined().unwrap_or_else(|e| {
+                    warn!("Failed to load quarantined files: {}", e);
+                    HashMap::new()
+                });
+                let mut metadata_list_new: Vec<PdfMetadata> = pool.install(|| {
+                    let mut pdf_paths: Vec<PathBuf> = Vec::new();
+                    let visited = Visited::default();
+                    for dir in &scan_dirs {
+                         pdf_paths.extend(scan_pdfs_rayon(dir, options, &visited, &reporter, &cancelled));
+                    } 
+                    pdf_paths.extend(extra_files.iter().filter(|path| path.is_file()).cloned());
+                    pdf_paths.sort_unstable(); 
+                    pdf_paths.dedup();
+                    reporter.discovered(pdf_paths.len());
+                                
+                    // Process PDFs in parallel
+                    pdf_paths.par_iter().filter_map(|path| {
+                        if cancelled.load(Ordering::Relaxed) { return None; }
+                        // Failed too often, left alone until it changes or is cleared in settings
+                        if let Some(stat) = quarantined.get(path.to_string_lossy().as_ref())
+                            && file_stat(path).is_ok_and(|current| current == *stat) {
+                            reporter.skipped();
+                            return None;
+                        }
+                        reporter.processing(path);
+                        let cache = cache.clone();
+
+                        match extract_pdf_metadata(path, &cache, reporter.sender()) {
+                            Ok(metadata) => {
+                                reporter.extracted(metadata.clone());
+                                Some(metadata)
+                            }
+                            // Reported, but the file may be fine and isn't quarantined
+                            Err(e) if e.downcast_ref::<StuckReads>().is_some() => {
+                                reporter.failed(path, format!("Not read: {}", e));
+                                None
+                            }
+                            Err(e) => {
+                                match cache.record_failure(path, &format!("{:#}", e)) {
+                                    Ok(attempts) if attempts >= QUARANTINE_AFTER => {
+                                        info!("Quarantined {} after {} failed attempts", path.display(), attempts);
+                                    }
+                                    Ok(_) => {}
+                                    Err(e) => warn!("Failed to record the failure of {}: {}", path.display(), e),
+                                }
+                                reporter.failed(path, format!("Extraction failed: {}", e));
+                                None
+                            }
+                        }
+                    })
+                    .collect()
+                });
+
+                if let Err(e) = cache.flush() {
+                    reporter.send(ScanProgress::Error(
+                        PathBuf::from("cache"),
+                        format!("Failed to write cached metadata: {}", e)
+                    ));
+                }
+                if cancelled.load(Ordering::Relaxed) {
+                    reporter.flush();
+                    return;
+                }
+                // Cached documents the scan didn't come across: skipped network
+                // mounts stay in the library as they were last scanned, files
+                // gone from the scanned folders stay flagged as missing, once
+                // however many copies they had
+                match cache.all_metadata() {
+                    Ok(cached) => {
+                        let found: HashSet<String> = metadata_list_new.iter().map(|pdf| pdf.hash.clone()).collect();
+                        let found_paths: HashSet<&str> = metadata_list_new.iter().map(|pdf| pdf.path.as_str()).collect();
+                        let mut missing = Vec::new();
+                        let mut gone = Vec::new();
+                        let mut extra = Vec::new();
+                        for mut pdf in cached {
+                            let path = Path::new(&pdf.path);
+                            let skipped_dir = skipped.iter().any(|dir| path.starts_with(dir));
+                            let scanned = !skipped_dir
+                                && (scan_dirs.iter().any(|dir| path.starts_with(dir)) || extra_files.iter().any(|file| file == path));
+                            if scanned && !found_paths.contains(pdf.path.as_str()) && !path.exists() {
+                                gone.push(pdf.path.clone());
+                            }
+                            if found.contains(&pdf.hash) { continue; }
+                            if skipped_dir {
+                                extra.push(pdf);
+                            } else if scanned && !path.exists() && !missing.contains(&pdf.hash) {
+                                pdf.missing = true;
+                                missing.push(pdf.hash.clone());
+                                extra.push(pdf);
+                            }
+                        }
+                        metadata_list_new.extend(extra);
+                        if let Err(e) = cache.forget_locations(&gone) {
+                            reporter.send(ScanProgress::Error(
+                                PathBuf::from("cache"),
+                                format!("Failed to forget moved copies: {}", e)
+                            ));
+                        }
+                        if let Err(e) = cache.update_missing(&missing, &found) {
+                            reporter.send(ScanProgress::Error(
+                                PathBuf::from("cache"),
+                                format!("Failed to flag missing documents: {}", e)
+                            ));
+                        }
+                    }
+                    Err(e) => reporter.send(ScanProgress::Error(
+                        PathBuf::from("cache"),
+                        format!("Failed to load the cached documents: {}", e)
+                    )),
+                }
+                let duration = start_time.elapsed();
+                reporter.send(ScanProgress::Complete(metadata_list_new, duration));
+            }
+        ));
+        imp.scan_thread.replace(Some(scan_thread));
+
+        gtk::glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[strong] metadata_list,
+            #[weak(rename_to = _self)] self,
+            async move {
+                use std::cell::Cell;
+                // When discovery finished, the time remaining is
+                // worked out from how fast it's gone since
+                let processing_started: Cell<Option<Instant>> = Cell::new(None);
+                let completed = Cell::new(false);
+                // Hash of every document already listed, by path
+                let mut known: HashMap<String, String> = metadata_list.lock().unwrap().iter()
+                    .map(|pdf| (pdf.path.clone(), pdf.hash.clone()))
+                    .collect();
+                _self.imp().status.pulse_progress("Looking for PDFs");
+                
+                while let Ok(msg) = rx.recv().await {
+                    match msg {
+                        ScanProgress::Found(count) => {
+                            _self.set_status(StatusModule::Scan, &format!("Found {} PDFs...", count)); 
+                            _self.imp().status.pulse_progress(&format!("Found {}", count));
+                        }
+                        ScanProgress::Progress(processed, total) => {
+                            let started = processing_started.get().unwrap_or_else(Instant::now);
+                            processing_started.set(Some(started));
+                            let elapsed = started.elapsed();
+                            let mut text = format!("{} of {}", processed, total);
+                            // Too early to tell at first
+                            if processed > 0 && processed < total && elapsed >= ETA_AFTER {
+                                let remaining = elapsed.mul_f64((total - processed) as f64 / processed as f64);
+                                text.push_str(&format!(", about {} left", human_readable_duration(remaining)));
+                            }
+                            let fraction = if total == 0 { 1.0 } else { processed as f64 / total as f64 };
+                            _self.imp().status.set_progress(fraction, &text);
+                        }
+                        ScanProgress::Processing(path) => {
+                            _self.set_status(StatusModule::Scan, &format!("Processing: {}...", path.display()));
+                        }
+                        ScanProgress::Extracted(batch) => {
+                            let mut list = metadata_list.lock().unwrap();
+                            for metadata in batch {
+                                match known.insert(metadata.path.clone(), metadata.hash.clone()) {
+                                    Some(hash) if hash == metadata.hash => {}
+                                    Some(_) => {
+                                        // Changed since it was cached
+                                        if let Some(pdf) = list.iter_mut().find(|pdf| pdf.path == metadata.path) {
+                                            *pdf = metadata;
+                                        }
+                                    }
+                                    None => list.push(metadata),
+                                }
+                            }
+                            drop(list);
+                            _self.update_sync_status();
+                            if !search_entry.text().is_empty() {
+                                _self.schedule_search_refresh(&model);
+                            }
+                        }
+                        ScanProgress::DuplicateDetected(original, duplicate) => {
+                            debug!("Duplicate detected: {} is duplicate of {}", 
+                                duplicate.display(), original.display());
+                        }
+                        ScanProgress::Error(path, error) => {
+                            warn!("Error processing {}: {}", path.display(), error);
+                            _self.imp().scan_errors.borrow_mut().push((path, error));
+                            _self.scan_errors_changed();
+                        }
+                        ScanProgress::Complete(metadata_list_new, duration) => {
+                            let found = metadata_list_new.iter().filter(|pdf| !pdf.missing).count();
+                            // Where the grid shown during the scan was
+                            let previous = _self.imp().selected.lock().unwrap().as_ref()
+                                .map(|selected| selected.hash.clone())
+                                .zip(_self.grid_adjustment().map(|adjustment| adjustment.value()))
+                                .filter(|_| model.n_items() > 0);
+                            let browsing = previous.is_some();
+                            // Store all PDFs for searching
+                            match &only {
+                                // Only what was under the folder is replaced
+                                Some(folder) => {
+                                    let mut list = metadata_list.lock().unwrap();
+                                    list.retain(|pdf| !Path::new(&pdf.path).starts_with(folder));
+                                    list.extend(metadata_list_new);
+                                    list.sort_by(|a, b| a.path.cmp(&b.path));
+                                }
+                                None => *metadata_list.lock().unwrap() = metadata_list_new,
+                            }
+                            _self.refresh_volume_list();
+                            _self.refresh_collections();
+                            _self.refresh_editions();
+                            _self.refresh_custom_fields();
+                            _self.refresh_workspace_list();
+                            _self.populate_model(&model);
+                            if !_self.restore_position(&model) && let Some((hash, scroll)) = previous {
+                                _self.keep_position(&model, &hash, scroll);
+                            }
+                            _self.set_status(StatusModule::Scan, &format!(
+                                "Complete! Found {} PDF files in {:.2?}",
+                                found,
+                                duration
+                            ));
+  
+                            _self.update_sync_status();
+                            refresh_button.set_sensitive(true);
+                            // Don't pull the focus away from the grid shown meanwhile
+                            if !browsing { search_entry.grab_focus(); }
+                            for path in _self.imp().pending_open.take() {
+                                _self.add_document(&model, path);
+                            }
+                            _self.toast(&format!("Scan complete, found {} PDF files", found));
+                            _self.watch_library();
+                            _self.fill_in_missing_metadata(&model);
+                            _self.fetch_online_covers(&model);
+                            _self.notify_scan_complete();
+                            _self.emit_by_name::<()>("scan-complete", &[&(found as u32), &duration.as_secs_f64()]);
+                            completed.set(true);
+                            break;
+                        }
+                    }
+                }
+                // Also when the scan stopped early
+                _self.imp().status.hide_progress();
+                _self.imp().cancel_scan_button.set_visible(false);
+                if !completed.get() {
+                    // Cancelled or failed, keep what was extracted so far
+                    let found = metadata_list.lock().unwrap().len();
+                    _self.populate_model(&model);
+                    _self.refresh_volume_list();
+                    _self.refresh_collections();
+                    _self.update_sync_status();
+                    _self.set_status(StatusModule::Scan, &format!("Scan stopped, {} PDF files so far", found));
+                    refresh_button.set_sensitive(true);
+                }
+            }
+        ));
+    }
+
+    /// Rerun the current search shortly, batching up documents that arrive
+    /// from a running scan in the meantime
+    fn schedule_search_refresh(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        if imp.search_refresh_pending.replace(true) { return; }
+        glib::timeout_add_local_once(SCAN_SEARCH_REFRESH, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[weak] model,
+            move || {
+                _self.imp().search_refresh_pending.set(false);
+                _self.populate_model(&model);
+            }
+        ));
+    }
+
+    /// Tell the user a scan finished if they're looking at something else
+    fn notify_scan_complete(&self) {
+        if self.is_active() { return; }
+        let Some(app) = self.application() else { return; };
+        let imp = self.imp();
+        let added = imp.cache.get()
+            .and_then(|cache| cache.added_between(imp.scan_started_at.get(), unix_now() + 1).ok())
+            .map_or(0, |added| added.len());
+        let errors = imp.scan_errors.borrow().len();
+
+        let mut body = format!("Scan complete: {} new", added);
+        match errors {
+            0 => {}
+            1 => body.push_str(", 1 error"),
+            errors => body.push_str(&format!(", {} errors", errors)),
+        }
+        let notification = gio::Notification::new("Shelf");
+        notification.set_body(Some(&body));
+        if errors > 0 {
+            notification.add_button("Show Errors", "app.show-scan-errors");
+        }
+        app.send_notification(Some("scan-complete"), &notification);
+    }
+
+    /// Files the last scan couldn't read and why, to be read again or let be
+    fn show_scan_errors(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        if let Some(window) = imp.scan_errors_window.upgrade() {
+            window.present();
+            return;
+        }
+        let window = ShelfScanErrorsWindow::new(self, &imp.scan_errors.borrow());
+        window.connect_local("retry", false, glib::clone!(
+            #[weak] model,
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] None,
+            move |args| {
+                let path: String = args[1].get().ok()?;
+                _self.retry_scan_error(&model, PathBuf::from(path));
+                None
+            }
+        ));
+        window.connect_local("ignore", false, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] None,
+            move |args| {
+                let path: String = args[1].get().ok()?;
+                _self.imp().scan_errors.borrow_mut().retain(|(failed, _)| *failed != Path::new(&path));
+                _self.scan_errors_changed();
+                None
+            }
+        ));
+        imp.scan_errors_window.set(Some(&window));
+        window.present();
+    }
+
+    /// Show the number of scan errors, and the errors themselves if they're open
+    fn scan_errors_changed(&self) {
+        let imp = self.imp();
+        let errors = imp.scan_errors.borrow();
+        imp.status.set_error_count(errors.len());
+        if let Some(window) = imp.scan_errors_window.upgrade() {
+            window.set_errors(&errors);
+        }
+    }
+
+    /// List the files scans failed to read in the settings, quarantined
+    /// ones included
+    pub(super) fn show_quarantined(&self, dialog: &ShelfSettingsWindow) {
+        let Some(cache) = self.imp().cache.get() else { return; };
+        match cache.failed_files() {
+            Ok(failed) => dialog.set_quarantined(&failed),
+            Err(e) => warn!("Failed to load quarantined files: {}", e),
+        }
+    }
+
+    /// Let the next scan try `path` again, or every quarantined file if it's empty
+    pub(super) fn clear_quarantine(&self, path: &str) {
+        let Some(cache) = self.imp().cache.get() else { return; };
+        let cleared = if path.is_empty() { cache.clear_failed_files() } else { cache.clear_failure(path) };
+        match cleared {
+            Ok(()) => self.toast(if path.is_empty() {
+                "Quarantined files will be tried again on the next scan"
+            } else {
+                "The file will be tried again on the next scan"
+            }),
+            Err(e) => self.toast_error("Failed to clear quarantined files", e),
+        }
+    }
+
+    /// Read a file that failed in the last scan again, adding it to the
+    /// library if it works this time
+    fn retry_scan_error(&self, model: &gio::ListStore, path: PathBuf) {
+        let Some(cache) = self.imp().cache.get().cloned() else { return; };
+        let (tx, rx) = async_channel::bounded(1);
+        std::thread::spawn(glib::clone!(
+            #[strong] path,
+            move || {
+                let extracted = reextract_pdf_metadata(&path, &cache).and_then(|metadata| {
+                    cache.flush()?;
+                    Ok(metadata)
+                });
+                let _ = tx.send_blocking(extracted.map_err(|e| format!("{:#}", e)));
+            }
+        ));
+        glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(extracted) = rx.recv().await else { return; };
+                let imp = _self.imp();
+                match extracted {
+                    Ok(metadata) => {
+                        imp.scan_errors.borrow_mut().retain(|(failed, _)| *failed != path);
+                        let title = metadata.display_title();
+                        {
+                            let mut list = imp.metadata_list.lock().unwrap();
+                            match list.iter_mut().find(|pdf| pdf.path == metadata.path) {
+                                Some(pdf) => *pdf = metadata,
+                                None => list.push(metadata),
+                            }
+                        }
+                        _self.refresh_model_in_place(&model);
+                        _self.set_status(StatusModule::Scan, &format!("Added {}", title));
+                    }
+                    Err(e) => {
+                        warn!("Error processing {}: {}", path.display(), e);
+                        for (failed, error) in imp.scan_errors.borrow_mut().iter_mut() {
+                            if *failed == path { *error = e.clone(); }
+                        }
+                    }
+                }
+                if let Some(window) = imp.scan_errors_window.upgrade() {
+                    window.finish_retry(&path);
+                }
+                _self.scan_errors_changed();
+            }
+        ));
+    }
+
+    /// Say once per session that scan directories are on a network mount,
+    /// and what network_mounts makes the scans do with them
+    fn warn_network_mounts(&self, folder: Option<&Path>) {
+        let imp = self.imp();
+        let (scan_dirs, mode) = {
+            let config = imp.config.get().unwrap().read().unwrap();
+            (config.scan_dirs.clone(), config.network_mounts)
+        };
+        let mounts = volumes::read_mounts();
+        let new: Vec<PathBuf> = scan_dirs.iter().map(PathBuf::as_path).chain(folder)
+            .filter(|dir| volumes::is_network_path(&mounts, dir))
+            .filter(|dir| imp.network_warned.borrow_mut().insert(dir.to_path_buf()))
+            .map(Path::to_path_buf)
+            .collect();
+        let Some(first) = new.first() else { return; };
+        let name = match new.len() {
+            1 => first.file_name().map_or_else(|| first.display().to_string(), |name| name.to_string_lossy().into_owned()),
+            count => format!("{} scan folders", count),
+        };
+        let verb = if new.len() == 1 { "is" } else { "are" };
+        let message = match mode {
+            NetworkMounts::MetadataOnly => format!("{} {} on a network drive, covers aren't rendered", name, verb),
+            NetworkMounts::Skip if folder.is_none() => format!("{} {} on a network drive and left out of scans", name, verb),
+            _ => format!("{} {} on a network drive, scanning can be slow. Covers can be left out in the settings", name, verb),
+        };
+        for dir in &new {
+            info!("{} is on a network filesystem, network_mounts = {:?}", dir.display(), mode);
+        }
+        self.toast(&message);
+    }
+
+    /// Open a file handed to Shelf from outside, e.g. by a file manager.
+    /// Files the library doesn't know yet are added to it first.
+    pub(super) fn open_file(&self, model: &gio::ListStore, path: PathBuf) {
+        let path = std::fs::canonicalize(&path).unwrap_or(path);
+        if !path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("pdf")) {
+            warn!("Not a PDF, ignoring {}", path.display());
+            return;
+        }
+        let imp = self.imp();
+        // A running scan replaces the library once it's done, add the file after that
+        if !imp.refresh_button.is_sensitive() {
+            imp.pending_open.borrow_mut().push(path);
+            return;
+        }
+        self.add_document(model, path);
+    }
+
+    pub(super) fn add_document(&self, model: &gio::ListStore, path: PathBuf) {
+        let imp = self.imp();
+        let path_str = path.to_string_lossy().to_string();
+        let known = imp.metadata_list.lock().unwrap().iter().any(|pdf| pdf.path == path_str);
+        if known {
+            self.select_path(model, &path_str);
+            self.open_document(&path_str);
+            return;
+        }
+
+        // Keep it in the library across scans unless a scan directory already covers it
+        {
+            let mut config = imp.config.get().unwrap().write().unwrap();
+            let scanned = config.scan_dirs.iter()
+                .any(|dir| path.starts_with(std::fs::canonicalize(dir).unwrap_or_else(|_| dir.clone())));
+            if !scanned && !config.extra_files.contains(&path) {
+                config.extra_files.push(path.clone());
+                if let Err(e) = config.save() {
+                    warn!("Failed to save config: {}", e);
+                }
+            }
+        }
+
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+        self.set_status(StatusModule::Scan, &format!("Adding {}...", path.display()));
+        let (tx, rx) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            // Progress is only interesting during a full scan
+            let (progress, _) = async_channel::unbounded();
+            let extracted = extract_pdf_metadata(&path, &cache, &progress)
+                .and_then(|metadata| cache.flush().map(|()| metadata));
+            let _ = tx.send_blocking(extracted.map_err(|e| format!("{:#}", e)));
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(extracted) = rx.recv().await else { return; };
+                match extracted {
+                    Ok(metadata) => {
+                        let path = metadata.path.clone();
+                        _self.imp().metadata_list.lock().unwrap().push(metadata);
+                        _self.refresh_volume_list();
+                        _self.set_status(StatusModule::Scan, &format!("Added {}", path));
+                        _self.imp().pending_selection.replace(Some(path.clone()));
+                        _self.populate_model(&model);
+                        _self.open_document(&path);
+                    }
+                    Err(e) => {
+                        _self.set_status(StatusModule::Scan, "Ready");
+                        _self.toast_error(&format!("Could not add {}", path_str), e);
+                    }
+                }
+            }
+        ));
+    }
+
+    pub(super) fn setup_library_watcher(&self, model: gio::ListStore) {
+        let watcher = LibraryWatcher::new(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |changes| _self.apply_library_changes(&model, changes)
+        ));
+        let _ = self.imp().library_watcher.set(watcher);
+    }
+
+    /// Watch the scan directories again, they may have changed
+    pub(super) fn watch_library(&self) {
+        let imp = self.imp();
+        let Some(watcher) = imp.library_watcher.get() else { return; };
+        let (watch, scan_dirs, options) = {
+            let config = imp.config.get().unwrap().read().unwrap();
+            (config.watch_scan_dirs, config.scan_dirs.clone(), config.walk_options())
+        };
+        if watch { watcher.watch(scan_dirs, options); } else { watcher.stop(); }
+    }
+
+    /// Add the PDFs that appeared in the scan directories and flag the ones
+    /// that were deleted as missing, without a full scan
+    fn apply_library_changes(&self, model: &gio::ListStore, changes: Changes) {
+        let imp = self.imp();
+        let Some(watcher) = imp.library_watcher.get() else { return; };
+        // Wait for the scan or maintenance to finish, the scan may already
+        // have listed the folder
+        if !imp.refresh_button.is_sensitive() {
+            watcher.defer(changes);
+            return;
+        }
+        let Some(cache) = imp.cache.get().cloned() else { return; };
+
+        let (deleted, missing) = {
+            let mut list = imp.metadata_list.lock().unwrap();
+            let (gone, mut kept): (Vec<PdfMetadata>, Vec<PdfMetadata>) = std::mem::take(&mut *list).into_iter()
+                .partition(|pdf| {
+                    let path = Path::new(&pdf.path);
+                    !pdf.missing && changes.removed.iter().any(|removed| path.starts_with(removed)) && !path.exists()
+                });
+            let deleted = gone.len();
+            // A copy elsewhere keeps the document in the library, the others
+            // stay greyed out like after a scan until they're pruned
+            let mut missing: Vec<String> = Vec::new();
+            for mut pdf in gone {
+                if kept.iter().any(|other| other.hash == pdf.hash) || missing.contains(&pdf.hash) { continue; }
+                pdf.missing = true;
+                missing.push(pdf.hash.clone());
+                kept.push(pdf);
+            }
+            kept.sort_by(|a, b| a.path.cmp(&b.path));
+            *list = kept;
+            (deleted, missing)
+        };
+        if deleted > 0 {
+            info!("{} documents were deleted", deleted);
+            if let Err(e) = cache.mark_missing(&missing) {
+                warn!("Failed to mark deleted documents as missing: {}", e);
+            }
+            self.refresh_model_in_place(model);
+            self.set_status(StatusModule::Scan, &format!("{} PDF files were deleted", deleted));
+        }
+
+        let added: Vec<PathBuf> = changes.added.into_iter().filter(|path| path.is_file()).collect();
+        if added.is_empty() { return; }
+        let (tx, rx) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            // Progress is only interesting during a full scan
+            let (progress, _) = async_channel::unbounded();
+            let extracted: Vec<PdfMetadata> = added.iter()
+                .filter_map(|path| extract_pdf_metadata(path, &cache, &progress)
+                    .inspect_err(|e| warn!("Error processing {}: {:#}", path.display(), e))
+                    .ok())
+                .collect();
+            if let Err(e) = cache.flush() {
+                error!("Failed to write cached metadata: {}", e);
+            }
+            // Deleted files that were put back, or moved in from elsewhere
+            let found: HashSet<String> = extracted.iter().map(|pdf| pdf.hash.clone()).collect();
+            if let Err(e) = cache.update_missing(&[], &found) {
+                warn!("Failed to unflag documents that are back: {}", e);
+            }
+            let _ = tx.send_blocking(extracted);
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(extracted) = rx.recv().await else { return; };
+                if extracted.is_empty() { return; }
+                let count = extracted.len();
+                {
+                    let mut list = _self.imp().metadata_list.lock().unwrap();
+                    for metadata in extracted {
+                        list.retain(|pdf| !(pdf.missing && pdf.hash == metadata.hash && pdf.path != metadata.path));
+                        match list.iter_mut().find(|pdf| pdf.path == metadata.path) {
+                            Some(pdf) => *pdf = metadata,
+                            None => list.push(metadata),
+                        }
+                    }
+                }
+                _self.refresh_volume_list();
+                _self.refresh_model_in_place(&model);
+                _self.update_sync_status();
+                _self.set_status(StatusModule::Scan, &format!("Added {} new PDF files", count));
+            }
+        ));
+    }
+
+    /// Rebuild the grid from the library, keeping the selection and scroll position
+    pub(super) fn refresh_model_in_place(&self, model: &gio::ListStore) {
+        let previous = self.imp().selected.lock().unwrap().as_ref()
+            .map(|selected| selected.hash.clone())
+            .zip(self.grid_adjustment().map(|adjustment| adjustment.value()));
+        self.populate_model(model);
+        if let Some((hash, scroll)) = previous {
+            self.keep_position(model, &hash, scroll);
+        }
+    }
+}
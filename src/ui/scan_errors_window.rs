@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+
+mod imp {
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use std::sync::OnceLock;
+
+    use gtk::glib;
+    use gtk::glib::subclass::types::ObjectSubclass;
+    use gtk::glib::subclass::Signal;
+    use gtk::subclass::prelude::*;
+
+    #[derive(Default, gtk::CompositeTemplate)]
+    #[template(resource = "/org/galib/shelf/ui/scan_errors_window.xml")]
+    pub struct ShelfScanErrorsWindow {
+        #[template_child]
+        pub summary_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub error_list: TemplateChild<gtk::ListBox>,
+
+        pub errors: RefCell<Vec<(PathBuf, String)>>,
+        // Being read again, their Retry buttons wait for the result
+        pub retrying: RefCell<HashSet<PathBuf>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ShelfScanErrorsWindow {
+        const NAME: &'static str = "ShelfScanErrorsWindow";
+        type Type = super::ShelfScanErrorsWindow;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ShelfScanErrorsWindow {
+        fn constructed(&self) {
+            self.parent_constructed();
+        }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            // retry asks the main window to read the file again, ignore to
+            // drop its error, both with the path of the file
+            SIGNALS.get_or_init(|| vec![
+                Signal::builder("retry").param_types([glib::Type::STRING]).build(),
+                Signal::builder("ignore").param_types([glib::Type::STRING]).build(),
+            ])
+        }
+    }
+
+    impl WidgetImpl for ShelfScanErrorsWindow {}
+    impl WindowImpl for ShelfScanErrorsWindow {}
+}
+
+glib::wrapper! {
+    pub struct ShelfScanErrorsWindow(ObjectSubclass<imp::ShelfScanErrorsWindow>)
+        @extends gtk::Widget, gtk::Window,
+        @implements gio::ActionGroup, gio::ActionMap,
+                    gtk::Accessible, gtk::Buildable,
+                    gtk::ConstraintTarget, gtk::Native,
+                    gtk::Root, gtk::ShortcutManager;
+}
+
+impl ShelfScanErrorsWindow {
+    /// Lists `errors`, the files the last scan couldn't read and why
+    pub fn new(parent: &impl IsA<gtk::Window>, errors: &[(PathBuf, String)]) -> Self {
+        let obj: ShelfScanErrorsWindow = glib::Object::builder()
+            .property("transient-for", parent)
+            .build();
+        obj.set_errors(errors);
+        obj
+    }
+
+    /// Show `errors` instead, e.g. after a retry or a new scan
+    pub fn set_errors(&self, errors: &[(PathBuf, String)]) {
+        let imp = self.imp();
+        imp.errors.replace(errors.to_vec());
+        imp.retrying.borrow_mut().retain(|path| errors.iter().any(|(failed, _)| failed == path));
+        self.refresh();
+    }
+
+    /// A retry of `path` is over, whether it worked or not
+    pub fn finish_retry(&self, path: &Path) {
+        self.imp().retrying.borrow_mut().remove(path);
+        self.refresh();
+    }
+
+    fn refresh(&self) {
+        let imp = self.imp();
+        let errors = imp.errors.borrow();
+        imp.summary_label.set_label(&match errors.len() {
+            0 => "The last scan had no errors".to_string(),
+            1 => "1 file couldn't be read".to_string(),
+            count => format!("{} files couldn't be read", count),
+        });
+        imp.error_list.set_visible(!errors.is_empty());
+        imp.error_list.remove_all();
+        for (path, error) in errors.iter() {
+            imp.error_list.append(&self.error_row(path, error));
+        }
+    }
+
+    fn error_row(&self, path: &Path, error: &str) -> gtk::Box {
+        let row = gtk::Box::builder()
+            .spacing(8)
+            .margin_start(12)
+            .margin_end(12)
+            .margin_top(6)
+            .margin_bottom(6)
+            .build();
+        let text = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(2)
+            .hexpand(true)
+            .build();
+        let path_label = gtk::Label::builder()
+            .label(path.to_string_lossy())
+            .xalign(0.0)
+            .ellipsize(gtk::pango::EllipsizeMode::Middle)
+            .tooltip_text(path.to_string_lossy())
+            .build();
+        let error_label = gtk::Label::builder().label(error).xalign(0.0).wrap(true).build();
+        error_label.add_css_class("dim-label");
+        text.append(&path_label);
+        text.append(&error_label);
+        row.append(&text);
+
+        let path = path.to_string_lossy().to_string();
+        // Errors of the cache or the scan itself aren't about a file
+        if Path::new(&path).is_file() {
+            let retrying = self.imp().retrying.borrow().contains(Path::new(&path));
+            let retry_button = gtk::Button::builder()
+                .label(if retrying { "Retrying…" } else { "Retry" })
+                .sensitive(!retrying)
+                .valign(gtk::Align::Center)
+                .build();
+            retry_button.connect_clicked(glib::clone!(
+                #[weak(rename_to = _self)] self,
+                #[strong] path,
+                move |_| {
+                    _self.imp().retrying.borrow_mut().insert(PathBuf::from(&path));
+                    _self.refresh();
+                    _self.emit_by_name::<()>("retry", &[&path]);
+                }
+            ));
+            row.append(&retry_button);
+        }
+        let ignore_button = gtk::Button::builder()
+            .label("Ignore")
+            .has_frame(false)
+            .valign(gtk::Align::Center)
+            .build();
+        ignore_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.emit_by_name::<()>("ignore", &[&path])
+        ));
+        row.append(&ignore_button);
+        row
+    }
+}
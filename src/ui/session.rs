@@ -0,0 +1,165 @@
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::{prelude::*, MultiSelection};
+use gtk::glib;
+use gtk::gio;
+use tracing::{info, warn};
+
+use crate::state::{Session, UiState};
+use crate::ui::models::PdfMetadataObject;
+use crate::ui::window::ShelfWindow;
+
+// How often the session is written out for crash recovery, in seconds
+const SESSION_SAVE_INTERVAL: u32 = 5;
+
+impl ShelfWindow {
+    pub(super) fn restore_state(&self, state: &UiState) {
+        let imp = self.imp();
+        self.change_action_state("sort", &state.sort.to_variant());
+        self.change_action_state("show-missing", &state.show_missing.to_variant());
+        self.set_default_size(state.window_width, state.window_height);
+        if state.maximized { self.maximize(); }
+        imp.right_pane.set_visible(state.show_preview);
+
+        *imp.volume_filter.borrow_mut() = state.volume_filter.clone();
+        imp.collection_filter.set(state.collection_filter);
+        imp.language_filter.replace(state.language_filter.clone());
+        imp.author_filter.replace(state.author_filter.clone());
+        imp.year_filter.set(state.year_filter);
+        self.refresh_volume_list();
+        if !state.search.is_empty() {
+            imp.search_entry.set_visible(true);
+            imp.search_entry.set_text(&state.search);
+        }
+        // The grid is only filled by the startup scan
+        if state.scroll_position > 0.0 {
+            imp.restored_scroll.set(Some(state.scroll_position));
+        }
+        imp.last_document.replace(state.last_document.clone());
+        imp.restored_document.replace(state.last_document.clone());
+    }
+
+    pub(super) fn current_state(&self) -> UiState {
+        let imp = self.imp();
+        let (window_width, window_height) = self.default_size();
+        let scroll_position = self.grid_adjustment()
+            .filter(|adjustment| adjustment.upper() > 0.0)
+            .map_or(0.0, |adjustment| adjustment.value() / adjustment.upper());
+        UiState {
+            window_width,
+            window_height,
+            maximized: self.is_maximized(),
+            show_preview: imp.right_pane.is_visible(),
+            sort: self.action_state("sort").and_then(|state| state.get::<String>()).unwrap_or_default(),
+            show_missing: self.shows_missing(),
+            search: imp.search_entry.text().to_string(),
+            volume_filter: imp.volume_filter.borrow().clone(),
+            collection_filter: imp.collection_filter.get(),
+            language_filter: imp.language_filter.borrow().clone(),
+            author_filter: imp.author_filter.borrow().clone(),
+            year_filter: imp.year_filter.get(),
+            scroll_position,
+            last_document: imp.last_document.borrow().clone(),
+        }
+    }
+
+    /// Select what was selected when Shelf last closed, or scroll back to
+    /// where the grid was. Done once, false when there was nothing to restore.
+    pub(super) fn restore_position(&self, model: &gio::ListStore) -> bool {
+        let imp = self.imp();
+        // A crashed session wins over the last document
+        let restored_document = imp.restored_document.take();
+        if let Some(path) = imp.restored_selection.take() {
+            return self.scroll_to_path(model, &path);
+        }
+        if restored_document.is_some_and(|hash| self.scroll_to_hash(model, &hash)) {
+            return true;
+        }
+        let restoring = imp.restored_scroll.get().is_some();
+        self.restore_scroll();
+        restoring
+    }
+
+    /// Select `hash` again after the grid was refilled, without moving it
+    /// away from `scroll`
+    pub(super) fn keep_position(&self, model: &gio::ListStore, hash: &str, scroll: f64) {
+        let Some(selection) = self.imp().grid_view.model().and_downcast::<MultiSelection>() else { return; };
+        let position = (0..model.n_items()).find(|&i| {
+            model.item(i)
+                .and_downcast::<PdfMetadataObject>()
+                .and_then(|obj| obj.metadata())
+                .is_some_and(|metadata| metadata.hash == hash)
+        });
+        if let Some(position) = position { selection.select_item(position, true); }
+        glib::idle_add_local_once(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move || {
+                let Some(adjustment) = _self.grid_adjustment() else { return; };
+                adjustment.set_value(scroll);
+            }
+        ));
+    }
+
+    /// Scroll back to where the grid was when Shelf last closed, once it has
+    /// been laid out
+    fn restore_scroll(&self) {
+        let Some(position) = self.imp().restored_scroll.take() else { return; };
+        glib::idle_add_local_once(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move || {
+                let Some(adjustment) = _self.grid_adjustment() else { return; };
+                adjustment.set_value(position * adjustment.upper());
+            }
+        ));
+    }
+
+    pub(super) fn restore_session(&self, session: Session) {
+        let imp = self.imp();
+        info!("Restoring the session of a previous run that didn't exit cleanly");
+        *imp.volume_filter.borrow_mut() = session.volume_filter.clone();
+        imp.collection_filter.set(session.collection_filter);
+        imp.language_filter.replace(session.language_filter.clone());
+        imp.author_filter.replace(session.author_filter.clone());
+        imp.year_filter.set(session.year_filter);
+        self.refresh_volume_list();
+        if !session.search.is_empty() {
+            imp.search_entry.set_visible(true);
+            imp.search_entry.set_text(&session.search);
+        }
+        imp.restored_selection.replace(session.selected_path.clone());
+        imp.workspace.replace(session.workspace.clone());
+        self.refresh_workspace_list();
+        imp.saved_session.replace(session);
+    }
+
+    fn current_session(&self) -> Session {
+        let imp = self.imp();
+        Session {
+            search: imp.search_entry.text().to_string(),
+            volume_filter: imp.volume_filter.borrow().clone(),
+            collection_filter: imp.collection_filter.get(),
+            language_filter: imp.language_filter.borrow().clone(),
+            author_filter: imp.author_filter.borrow().clone(),
+            year_filter: imp.year_filter.get(),
+            selected_path: imp.selected.lock().unwrap().as_ref().map(|selected| selected.path.clone()),
+            workspace: imp.workspace.borrow().clone(),
+        }
+    }
+
+    pub(super) fn start_session_autosave(&self) {
+        let timer = glib::timeout_add_seconds_local(SESSION_SAVE_INTERVAL, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] glib::ControlFlow::Break,
+            move || {
+                let session = _self.current_session();
+                if *_self.imp().saved_session.borrow() != session {
+                    match session.save() {
+                        Ok(()) => { _self.imp().saved_session.replace(session); }
+                        Err(e) => warn!("Failed to save session: {}", e),
+                    }
+                }
+                glib::ControlFlow::Continue
+            }
+        ));
+        self.imp().session_timer.replace(Some(timer));
+    }
+}
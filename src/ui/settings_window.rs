@@ -5,17 +5,30 @@ use gtk::gio;
 use gtk::pango::AttrList;
 use gtk::pango::AttrSize;
 use gtk::prelude::*;
-use std::path::PathBuf;
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::Arc;
 use std::sync::RwLock;
-use crate::config::Config;
+use tracing::warn;
+use crate::config::{Config, CoverFormat, NetworkMounts, Theme};
+use crate::filename_patterns::FilenamePattern;
+use crate::pdf::{FailedFile, QUARANTINE_AFTER};
+use crate::portal;
+use crate::utils::{check_viewer_command, viewer_command};
+
+// In the order of the style dropdown
+const THEMES: [Theme; 3] = [Theme::System, Theme::Light, Theme::Dark];
+const NETWORK_MOUNTS: [NetworkMounts; 3] = [NetworkMounts::Full, NetworkMounts::MetadataOnly, NetworkMounts::Skip];
+const COVER_FORMATS: [CoverFormat; 2] = [CoverFormat::Jpeg, CoverFormat::Webp];
 
 mod imp {
     use gtk::glib;
     use gtk::glib::subclass::types::ObjectSubclass;
     use gtk::subclass::prelude::*;
     use std::cell::OnceCell;
-    use std::sync::{Arc, RwLock};
+    use std::sync::{Arc, OnceLock, RwLock};
+    use gtk::glib::subclass::Signal;
 
     use crate::config::Config;
     
@@ -35,7 +48,65 @@ mod imp {
         #[template_child]
         pub command_entry: TemplateChild<gtk::Entry>,
         #[template_child]
+        pub test_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub command_feedback: TemplateChild<gtk::Label>,
+        #[template_child]
         pub file_dialog: TemplateChild<gtk::FileDialog>,
+        #[template_child]
+        pub badges_check: TemplateChild<gtk::CheckButton>,
+        #[template_child]
+        pub vim_check: TemplateChild<gtk::CheckButton>,
+        #[template_child]
+        pub theme_dropdown: TemplateChild<gtk::DropDown>,
+        #[template_child]
+        pub internal_reader_check: TemplateChild<gtk::CheckButton>,
+        #[template_child]
+        pub low_memory_check: TemplateChild<gtk::CheckButton>,
+        #[template_child]
+        pub cover_limit_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub scan_threads_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub renders_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub network_dropdown: TemplateChild<gtk::DropDown>,
+        #[template_child]
+        pub cover_format_dropdown: TemplateChild<gtk::DropDown>,
+        #[template_child]
+        pub cover_quality_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub render_scale_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub online_covers_check: TemplateChild<gtk::CheckButton>,
+        #[template_child]
+        pub online_metadata_check: TemplateChild<gtk::CheckButton>,
+        #[template_child]
+        pub patterns_view: TemplateChild<gtk::TextView>,
+        #[template_child]
+        pub patterns_feedback: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub pattern_test_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub pattern_test_result: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub maintenance_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub remove_unused_check: TemplateChild<gtk::CheckButton>,
+        #[template_child]
+        pub remove_covers_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub regenerate_covers_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub missing_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub prune_missing_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub quarantine_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub quarantine_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub clear_quarantine_button: TemplateChild<gtk::Button>,
 
         // Store the current directories
         pub config: OnceCell<Arc<RwLock<Config>>>,
@@ -60,6 +131,27 @@ mod imp {
         fn constructed(&self) {
             self.parent_constructed();
         }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            // config-changed follows every successful save so the main window
+            // can refresh, run-maintenance asks it to run maintenance now,
+            // remove-unused-covers to delete the covers no document uses,
+            // regenerate-covers to render every cover again,
+            // rescan-folder to scan just the directory it's given,
+            // prune-missing to forget the documents a scan found missing and
+            // clear-quarantine to forget the failures of a file, or of all
+            // of them if the path is empty
+            SIGNALS.get_or_init(|| vec![
+                Signal::builder("config-changed").build(),
+                Signal::builder("run-maintenance").build(),
+                Signal::builder("remove-unused-covers").build(),
+                Signal::builder("regenerate-covers").build(),
+                Signal::builder("rescan-folder").param_types([glib::Type::STRING]).build(),
+                Signal::builder("prune-missing").build(),
+                Signal::builder("clear-quarantine").param_types([glib::Type::STRING]).build(),
+            ])
+        }
     }
     
     impl WidgetImpl for ShelfSettingsWindow {}
@@ -91,13 +183,218 @@ impl ShelfSettingsWindow {
         // *imp.dirs.borrow_mut() = config.scan_dirs.clone();
 
         imp.command_entry.set_sensitive(false);
+        // A shell command reads left to right whatever the locale
+        imp.command_entry.set_direction(gtk::TextDirection::Ltr);
 
         let config = imp.config.get().unwrap();
         {
             let config_reader = config.read().unwrap();
             imp.command_entry.set_text(&config_reader.pdf_viewer_command);
+            imp.badges_check.set_active(config_reader.show_cover_badges);
+            imp.vim_check.set_active(config_reader.vim_navigation);
+            imp.theme_dropdown.set_selected(THEMES.iter().position(|theme| *theme == config_reader.theme).unwrap_or(0) as u32);
+            imp.internal_reader_check.set_active(config_reader.use_internal_reader);
+            imp.low_memory_check.set_active(config_reader.low_memory_mode);
+            imp.cover_limit_spin.set_value(config_reader.cover_cache_limit_mb as f64);
+            imp.remove_unused_check.set_active(config_reader.remove_unused_covers);
+            imp.scan_threads_spin.set_value(config_reader.scan_threads as f64);
+            imp.renders_spin.set_value(config_reader.max_concurrent_renders as f64);
+            imp.network_dropdown.set_selected(NETWORK_MOUNTS.iter().position(|mode| *mode == config_reader.network_mounts).unwrap_or(0) as u32);
+            imp.cover_format_dropdown.set_selected(COVER_FORMATS.iter().position(|format| *format == config_reader.cover_format).unwrap_or(0) as u32);
+            imp.cover_quality_spin.set_value(config_reader.cover_quality as f64);
+            imp.render_scale_spin.set_value(config_reader.cover_render_scale as f64);
+            imp.online_covers_check.set_active(config_reader.fetch_online_covers);
+            imp.online_metadata_check.set_active(config_reader.fetch_online_metadata);
+            imp.patterns_view.buffer().set_text(&config_reader.filename_patterns.join("\n"));
         }
 
+        imp.cover_limit_spin.connect_value_changed(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |spin| {
+                config.write().unwrap().cover_cache_limit_mb = spin.value() as u64;
+                _self.save_config();
+            }
+        ));
+        imp.scan_threads_spin.connect_value_changed(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |spin| {
+                config.write().unwrap().scan_threads = spin.value() as usize;
+                _self.save_config();
+            }
+        ));
+        imp.renders_spin.connect_value_changed(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |spin| {
+                config.write().unwrap().max_concurrent_renders = spin.value() as usize;
+                _self.save_config();
+            }
+        ));
+
+        imp.remove_unused_check.connect_toggled(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |check| {
+                config.write().unwrap().remove_unused_covers = check.is_active();
+                _self.save_config();
+            }
+        ));
+        imp.online_covers_check.connect_toggled(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |check| {
+                config.write().unwrap().fetch_online_covers = check.is_active();
+                _self.save_config();
+            }
+        ));
+        imp.online_metadata_check.connect_toggled(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |check| {
+                config.write().unwrap().fetch_online_metadata = check.is_active();
+                _self.save_config();
+            }
+        ));
+
+        // Tried as they're typed, saved when done with
+        imp.patterns_view.buffer().connect_changed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.test_filename_patterns()
+        ));
+        imp.pattern_test_entry.connect_changed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.test_filename_patterns()
+        ));
+        self.test_filename_patterns();
+        let focus = gtk::EventControllerFocus::new();
+        focus.connect_leave(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.save_filename_patterns()
+        ));
+        imp.patterns_view.add_controller(focus);
+        self.connect_close_request(|_self| {
+            _self.save_filename_patterns();
+            glib::Propagation::Proceed
+        });
+
+        imp.maintenance_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.emit_by_name::<()>("run-maintenance", &[])
+        ));
+        imp.remove_covers_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.emit_by_name::<()>("remove-unused-covers", &[])
+        ));
+        imp.regenerate_covers_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.emit_by_name::<()>("regenerate-covers", &[])
+        ));
+        imp.prune_missing_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.emit_by_name::<()>("prune-missing", &[])
+        ));
+        self.set_missing_count(0);
+        imp.clear_quarantine_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.emit_by_name::<()>("clear-quarantine", &[&""])
+        ));
+        self.set_quarantined(&[]);
+
+        // Check the command as it's typed, the way it would be run
+        imp.command_entry.connect_changed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |entry| _self.show_command_feedback(check_viewer_command(&entry.text()).as_deref(), None)
+        ));
+        self.show_command_feedback(check_viewer_command(&imp.command_entry.text()).as_deref(), None);
+        imp.test_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.test_command()
+        ));
+
+        imp.low_memory_check.connect_toggled(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |check| {
+                config.write().unwrap().low_memory_mode = check.is_active();
+                _self.save_config();
+            }
+        ));
+
+        imp.internal_reader_check.connect_toggled(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |check| {
+                config.write().unwrap().use_internal_reader = check.is_active();
+                _self.save_config();
+            }
+        ));
+
+        imp.badges_check.connect_toggled(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |check| {
+                config.write().unwrap().show_cover_badges = check.is_active();
+                _self.save_config();
+            }
+        ));
+
+        imp.network_dropdown.connect_selected_notify(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |dropdown| {
+                let Some(mode) = NETWORK_MOUNTS.get(dropdown.selected() as usize) else { return; };
+                config.write().unwrap().network_mounts = *mode;
+                _self.save_config();
+            }
+        ));
+
+        imp.cover_format_dropdown.connect_selected_notify(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |dropdown| {
+                let Some(format) = COVER_FORMATS.get(dropdown.selected() as usize) else { return; };
+                config.write().unwrap().cover_format = *format;
+                _self.save_config();
+            }
+        ));
+        imp.cover_quality_spin.connect_value_changed(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |spin| {
+                config.write().unwrap().cover_quality = spin.value() as u8;
+                _self.save_config();
+            }
+        ));
+        imp.render_scale_spin.connect_value_changed(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |spin| {
+                config.write().unwrap().cover_render_scale = spin.value() as f32;
+                _self.save_config();
+            }
+        ));
+
+        imp.theme_dropdown.connect_selected_notify(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |dropdown| {
+                let Some(theme) = THEMES.get(dropdown.selected() as usize) else { return; };
+                config.write().unwrap().theme = *theme;
+                _self.save_config();
+            }
+        ));
+
+        imp.vim_check.connect_toggled(glib::clone!(
+            #[strong] config,
+            #[weak(rename_to = _self)] self,
+            move |check| {
+                config.write().unwrap().vim_navigation = check.is_active();
+                _self.save_config();
+            }
+        ));
+
         imp.edit_button.connect_clicked(glib::clone!(
             #[strong] config,
             #[weak(rename_to = _self)] self,
@@ -135,8 +432,11 @@ impl ShelfSettingsWindow {
                     #[weak] _self,
                     move |result| {
                         if let Ok(file) = result {
-                            if let Some(path) = file.path() {
-                                _self.add_directory(path);
+                            // In a sandbox this is the portal's copy of the
+                            // folder, which stays readable across restarts
+                            match file.path() {
+                                Some(path) => _self.add_directory(path),
+                                None => warn!("Only local folders can be scanned, not {}", file.uri()),
                             }
                         }
                     }
@@ -148,6 +448,99 @@ impl ShelfSettingsWindow {
         self.refresh_directory_list();
     }
     
+    /// An error under the command entry, or else `note`
+    fn show_command_feedback(&self, error: Option<&str>, note: Option<&str>) {
+        let label = &self.imp().command_feedback;
+        match error.or(note) {
+            Some(message) => {
+                label.set_text(message);
+                label.set_visible(true);
+            }
+            None => label.set_visible(false),
+        }
+        if error.is_some() {
+            label.remove_css_class("dim-label");
+            label.add_css_class("error");
+        } else {
+            label.remove_css_class("error");
+            label.add_css_class("dim-label");
+        }
+    }
+
+    /// Open the bundled sample document with the command in the entry,
+    /// saved or not
+    fn test_command(&self) {
+        let template = self.imp().command_entry.text().to_string();
+        if let Some(error) = check_viewer_command(&template) {
+            self.show_command_feedback(Some(&error), None);
+            return;
+        }
+        let sample = std::env::temp_dir().join("shelf-sample.pdf");
+        let launched = gio::resources_lookup_data("/org/galib/shelf/sample.pdf", gio::ResourceLookupFlags::NONE)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| Ok(std::fs::write(&sample, &data)?))
+            .and_then(|()| viewer_command(&template, &sample.to_string_lossy(), Some(0)))
+            .and_then(|command| {
+                let mut child = Command::new(&command[0]).args(&command[1..]).spawn()
+                    .with_context(|| format!("Could not run {}", command[0]))?;
+                std::thread::spawn(move || { let _ = child.wait(); });
+                Ok(command[0].clone())
+            });
+        match launched {
+            Ok(program) => self.show_command_feedback(None, Some(&format!("Opened a sample document with {}", program))),
+            Err(e) => self.show_command_feedback(Some(&format!("{:#}", e)), None),
+        }
+    }
+
+    fn filename_pattern_lines(&self) -> Vec<String> {
+        let buffer = self.imp().patterns_view.buffer();
+        buffer.text(&buffer.start_iter(), &buffer.end_iter(), false)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Check the patterns as typed and show what they read from the test
+    /// file name
+    fn test_filename_patterns(&self) {
+        let imp = self.imp();
+        let mut errors = Vec::new();
+        let mut patterns = Vec::new();
+        for (line, pattern) in self.filename_pattern_lines().iter().enumerate() {
+            match FilenamePattern::parse(pattern) {
+                Ok(pattern) => patterns.push((line + 1, pattern)),
+                Err(e) => errors.push(format!("Line {}: {:#}", line + 1, e)),
+            }
+        }
+        imp.patterns_feedback.set_text(&errors.join("\n"));
+        imp.patterns_feedback.set_visible(!errors.is_empty());
+
+        let name = imp.pattern_test_entry.text().trim().to_string();
+        imp.pattern_test_result.set_visible(!name.is_empty());
+        if name.is_empty() { return; }
+        // Without an extension a dot in the name would be taken for one
+        let name = if name.to_lowercase().ends_with(".pdf") { name } else { format!("{}.pdf", name) };
+        let result = match patterns.iter().find_map(|(line, pattern)| Some((line, pattern.read(Path::new(&name))?))) {
+            None if patterns.is_empty() => "No patterns to try".to_string(),
+            None => "No pattern matches this name".to_string(),
+            Some((line, fields)) => format!("Line {} matches and reads {}", line, fields.iter()
+                .map(|(field, value)| format!("{} “{}”", field.label().to_lowercase(), value.trim_start_matches("D:")))
+                .collect::<Vec<_>>()
+                .join(", ")),
+        };
+        imp.pattern_test_result.set_text(&result);
+    }
+
+    fn save_filename_patterns(&self) {
+        let patterns = self.filename_pattern_lines();
+        let config = self.imp().config.get().unwrap();
+        if config.read().unwrap().filename_patterns == patterns { return; }
+        config.write().unwrap().filename_patterns = patterns;
+        self.save_config();
+    }
+
     fn refresh_directory_list(&self) {
         let imp = self.imp();
         
@@ -168,7 +561,8 @@ impl ShelfSettingsWindow {
     fn create_directory_row(&self, path: &PathBuf, index: usize) -> gtk::Box {
         let hbox = gtk::Box::builder().build();
         
-        let label = gtk::Label::new(Some(&path.display().to_string()));
+        let label = gtk::Label::new(Some(&portal::host_path(path).display().to_string()));
+        label.set_tooltip_text(Some(&path.display().to_string()));
         label.set_halign(gtk::Align::Start);
         label.set_hexpand(true);
         label.set_ellipsize(gtk::pango::EllipsizeMode::Middle);
@@ -176,6 +570,19 @@ impl ShelfSettingsWindow {
         attr_list.insert(AttrSize::new(10480));
         label.set_attributes(Some(&attr_list));
         
+        let rescan_button = gtk::Button::builder()
+            .icon_name("view-refresh-symbolic")
+            .tooltip_text("Rescan this folder")
+            .has_frame(false)
+            .valign(gtk::Align::Center)
+            .build();
+        rescan_button.update_property(&[gtk::accessible::Property::Label("Rescan this folder")]);
+        let folder = path.to_string_lossy().to_string();
+        rescan_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.emit_by_name::<()>("rescan-folder", &[&folder])
+        ));
+
         let button = gtk::Button::builder()
             .icon_name("user-trash-symbolic")
             .has_frame(false)
@@ -190,28 +597,101 @@ impl ShelfSettingsWindow {
         });
         
         hbox.append(&label);
+        hbox.append(&rescan_button);
         hbox.append(&button);
         
         hbox
     } 
 
+    /// How many documents in the library a scan found missing
+    pub fn set_missing_count(&self, count: usize) {
+        let imp = self.imp();
+        imp.missing_label.set_label(&match count {
+            0 => "Documents whose files a scan doesn't find stay in the library, greyed out, until they're forgotten here. None are missing.".to_string(),
+            1 => "1 document is missing. Forgetting it deletes its cached metadata, unless its folder is gone too, e.g. on a drive that isn't plugged in.".to_string(),
+            count => format!("{} documents are missing. Forgetting them deletes their cached metadata, except for ones whose folder is gone too, e.g. on a drive that isn't plugged in.", count),
+        });
+        imp.prune_missing_button.set_sensitive(count > 0);
+    }
+
+    /// List the files scans skip out of `failed`, the ones that failed
+    /// `QUARANTINE_AFTER` times
+    pub fn set_quarantined(&self, failed: &[FailedFile]) {
+        let imp = self.imp();
+        let quarantined: Vec<&FailedFile> = failed.iter().filter(|file| file.attempts >= QUARANTINE_AFTER).collect();
+        imp.quarantine_label.set_label(&if quarantined.is_empty() {
+            format!("Files a scan fails to read {} times in a row are skipped until they change. None are so far.", QUARANTINE_AFTER)
+        } else {
+            format!("Files a scan fails to read {} times in a row are skipped until they change. Clear one to try it again on the next scan.", QUARANTINE_AFTER)
+        });
+        imp.quarantine_list.set_visible(!quarantined.is_empty());
+        imp.clear_quarantine_button.set_visible(quarantined.len() > 1);
+        imp.quarantine_list.remove_all();
+        for file in quarantined {
+            imp.quarantine_list.append(&self.create_quarantine_row(file));
+        }
+    }
+
+    fn create_quarantine_row(&self, file: &FailedFile) -> gtk::Box {
+        let row = gtk::Box::builder()
+            .spacing(8)
+            .margin_start(12)
+            .margin_end(12)
+            .margin_top(6)
+            .margin_bottom(6)
+            .build();
+        let text = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(2)
+            .hexpand(true)
+            .build();
+        let path_label = gtk::Label::builder()
+            .label(portal::host_path(std::path::Path::new(&file.path)).display().to_string())
+            .xalign(0.0)
+            .ellipsize(gtk::pango::EllipsizeMode::Middle)
+            .tooltip_text(&file.path)
+            .build();
+        let error_label = gtk::Label::builder()
+            .label(format!("Failed {} times: {}", file.attempts, file.error))
+            .xalign(0.0)
+            .wrap(true)
+            .build();
+        error_label.add_css_class("dim-label");
+        text.append(&path_label);
+        text.append(&error_label);
+        row.append(&text);
+
+        let clear_button = gtk::Button::builder()
+            .label("Clear")
+            .tooltip_text("Try this file again on the next scan")
+            .valign(gtk::Align::Center)
+            .build();
+        let path = file.path.clone();
+        clear_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.emit_by_name::<()>("clear-quarantine", &[&path])
+        ));
+        row.append(&clear_button);
+        row
+    }
+
     fn add_directory(&self, path: PathBuf) {
         let imp = self.imp();
         let config = imp.config.get().unwrap();
         let mut config_writer = config.write().unwrap();
-        let canon = path.canonicalize().unwrap();
+        let canon = path.canonicalize().unwrap_or_else(|_| path.clone());
         
         // Check if directory already exists
         if !config_writer.scan_dirs.iter().any(|p| canon.starts_with(p)) {
         // if !dirs.contains(&path) {
-            config_writer.scan_dirs.push(path.canonicalize().unwrap());
+            config_writer.scan_dirs.push(canon);
             drop(config_writer); // Release the borrow
             
             // Save and refresh
             self.save_config();
             self.refresh_directory_list();
         } else {
-            eprintln!("The path {} or one of its ancestors is already added", path.display());
+            warn!("The path {} or one of its ancestors is already added", path.display());
         }
     }
     
@@ -239,8 +719,11 @@ impl ShelfSettingsWindow {
         // config.scan_dirs = imp.dirs.borrow().clone();
         
         if let Err(e) = config_writer.save() {
-            eprintln!("Failed to save config: {}", e);
+            warn!("Failed to save config: {}", e);
             // Optionally show an error dialog to the user
+            return;
         }
+        drop(config_writer);
+        self.emit_by_name::<()>("config-changed", &[]);
     }
 }
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use gtk::prelude::*;
+use tracing::warn;
+
+/// A shortcut that can be remapped in the `[keybindings]` section of
+/// config.toml, keyed by its action, e.g. `"win.add-to-workspace" = ["<Control>d"]`
+pub struct Binding {
+    pub action: &'static str,
+    pub title: &'static str,
+    pub group: &'static str,
+    pub default: &'static [&'static str],
+}
+
+pub const BINDINGS: &[Binding] = &[
+    Binding { action: "win.refresh", title: "Rescan library", group: "General", default: &["<Control>r", "F5"] },
+    Binding { action: "win.toggle-search", title: "Search", group: "General", default: &["<Control>f"] },
+    Binding { action: "win.settings", title: "Open settings", group: "General", default: &["<Control>comma"] },
+    Binding { action: "win.toggle-preview", title: "Show or hide the preview", group: "General", default: &["F9"] },
+    Binding { action: "win.command-palette", title: "Command palette", group: "General", default: &["<Control>k"] },
+    Binding { action: "win.show-shortcuts", title: "Keyboard shortcuts", group: "General", default: &["<Control>question"] },
+    Binding { action: "app.about", title: "About Shelf", group: "General", default: &[] },
+    Binding { action: "app.quit", title: "Quit", group: "General", default: &["<Control>q"] },
+    Binding { action: "win.open-selected", title: "Open the selected document", group: "Library", default: &["Return", "KP_Enter"] },
+    Binding { action: "win.add-to-workspace", title: "Add to workspace", group: "Library", default: &["<Control>d"] },
+    Binding { action: "win.attach-file", title: "Attach a file", group: "Library", default: &[] },
+    Binding { action: "win.edit-metadata", title: "Edit metadata", group: "Library", default: &["<Control>e"] },
+    Binding { action: "win.refresh-metadata", title: "Refresh metadata and cover", group: "Library", default: &[] },
+    Binding { action: "win.set-custom-cover", title: "Set a custom cover", group: "Library", default: &[] },
+    Binding { action: "win.fetch-online-cover", title: "Fetch the cover online", group: "Library", default: &[] },
+    Binding { action: "win.fill-in-metadata", title: "Look up the metadata online", group: "Library", default: &[] },
+    Binding { action: "win.find-editions", title: "Find editions", group: "Library", default: &[] },
+    Binding { action: "win.remove-from-collection", title: "Remove from collection", group: "Library", default: &[] },
+    Binding { action: "win.new-collection", title: "New collection", group: "Collections", default: &[] },
+    Binding { action: "win.export-contact-sheet", title: "Export a contact sheet", group: "Views", default: &[] },
+    Binding { action: "win.year-review", title: "Year in review", group: "Views", default: &[] },
+    Binding { action: "win.usage", title: "Most opened", group: "Views", default: &[] },
+    Binding { action: "win.review-metadata", title: "Review looked-up metadata", group: "Views", default: &[] },
+];
+
+// Handled by key controllers of their own, listed but not remappable
+const FIXED: &[(&str, &str, &str)] = &[
+    ("Library", "Quick look", "space"),
+    ("Library", "Clear and close the search", "Escape"),
+    ("Reader", "Next page", "Right Page_Down space"),
+    ("Reader", "Previous page", "Left Page_Up BackSpace"),
+    ("Reader", "First and last page", "Home End"),
+    ("Reader", "Zoom in and out", "plus minus"),
+    ("Reader", "Two page spread", "d"),
+    ("Reader", "Bookmarks", "b"),
+    ("Reader", "Search", "slash"),
+    ("Reader", "Next and previous match", "n <Shift>n"),
+    ("Reader", "Night mode", "i"),
+    ("Reader", "Present", "F5"),
+];
+
+fn warn_invalid(remapped: &BTreeMap<String, Vec<String>>) {
+    for (action, accels) in remapped {
+        if !BINDINGS.iter().any(|binding| binding.action == action) {
+            warn!("Unknown action {} in [keybindings]", action);
+        }
+        for accel in accels {
+            if gtk::accelerator_parse(accel).is_none() {
+                warn!("Invalid shortcut {:?} for {} in [keybindings]", accel, action);
+            }
+        }
+    }
+}
+
+/// The accelerators of every binding, remapped ones replacing the defaults.
+/// An empty list in the config unbinds an action.
+pub fn resolve(remapped: &BTreeMap<String, Vec<String>>) -> Vec<(&'static Binding, Vec<String>)> {
+    BINDINGS.iter()
+        .map(|binding| {
+            let accels = match remapped.get(binding.action) {
+                Some(accels) => accels.iter().filter(|accel| gtk::accelerator_parse(accel.as_str()).is_some()).cloned().collect(),
+                None => binding.default.iter().map(|accel| accel.to_string()).collect(),
+            };
+            (binding, accels)
+        })
+        .collect()
+}
+
+/// Register the accelerators of every binding with the application, which
+/// also shows them next to the menu items
+pub fn apply(app: &gtk::Application, remapped: &BTreeMap<String, Vec<String>>) {
+    warn_invalid(remapped);
+    for (binding, accels) in resolve(remapped) {
+        let accels: Vec<&str> = accels.iter().map(String::as_str).collect();
+        app.set_accels_for_action(binding.action, &accels);
+    }
+}
+
+/// Every shortcut, as currently bound
+// GtkShortcutsWindow is deprecated since GTK 4.18, its replacement
+// AdwShortcutsDialog needs libadwaita 1.8
+#[allow(deprecated)]
+pub fn window(parent: &impl IsA<gtk::Window>, remapped: &BTreeMap<String, Vec<String>>) -> gtk::ShortcutsWindow {
+    let mut groups: Vec<(&str, Vec<(&str, String)>)> = Vec::new();
+    let mut add = |group: &'static str, title: &'static str, accels: String| {
+        match groups.iter_mut().find(|(name, _)| *name == group) {
+            Some((_, shortcuts)) => shortcuts.push((title, accels)),
+            None => groups.push((group, vec![(title, accels)])),
+        }
+    };
+    for (binding, accels) in resolve(remapped) {
+        if accels.is_empty() { continue; }
+        add(binding.group, binding.title, accels.join(" "));
+    }
+    for (group, title, accels) in FIXED {
+        add(group, title, accels.to_string());
+    }
+
+    let section = gtk::ShortcutsSection::builder().section_name("shortcuts").build();
+    for (name, shortcuts) in groups {
+        let group = gtk::ShortcutsGroup::builder().title(name).build();
+        for (title, accels) in shortcuts {
+            group.add_shortcut(&gtk::ShortcutsShortcut::builder().title(title).accelerator(accels).build());
+        }
+        section.add_group(&group);
+    }
+    let window = gtk::ShortcutsWindow::builder()
+        .transient_for(parent)
+        .modal(true)
+        .build();
+    window.add_section(&section);
+    window
+}
@@ -0,0 +1,371 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::gio;
+
+use crate::language;
+use crate::pdf::PdfMetadata;
+use crate::ui::window::ShelfWindow;
+use crate::volumes;
+
+impl ShelfWindow {
+    pub(super) fn setup_sidebar(&self, model: gio::ListStore) {
+        let imp = self.imp();
+        imp.volume_list.connect_row_selected(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                // Rows are rebuilt after every scan, which briefly unselects everything
+                let Some(row) = row else { return; };
+                let imp = _self.imp();
+                let filter = match row.index() {
+                    0 => None,
+                    index => imp.volumes.borrow().get(index as usize - 1).map(|v| v.root.clone()),
+                };
+                if *imp.volume_filter.borrow() == filter && imp.collection_filter.get().is_none() { return; }
+                *imp.volume_filter.borrow_mut() = filter;
+                if imp.collection_filter.take().is_some() {
+                    imp.collection_list.unselect_all();
+                    _self.update_collection_actions();
+                }
+                _self.populate_model(&model);
+            }
+        ));
+        self.refresh_volume_list();
+        self.setup_collections(model.clone());
+        imp.decade_list.connect_row_selected(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                let Some(row) = row else { return; };
+                let imp = _self.imp();
+                let filter = match row.index() {
+                    0 => None,
+                    index => imp.decade_rows.borrow().get(index as usize - 1).map(|decade| (*decade, decade + 9)),
+                };
+                _self.set_year_filter(&model, filter);
+                imp.year_popover.popdown();
+            }
+        ));
+        imp.year_range_button.connect_clicked(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            move |_| {
+                let imp = _self.imp();
+                let (from, to) = (imp.year_from_spin.value_as_int(), imp.year_to_spin.value_as_int());
+                _self.set_year_filter(&model, Some((from.min(to), from.max(to))));
+                // A range rather than a decade, unless it is one
+                _self.refresh_year_list();
+                imp.year_popover.popdown();
+            }
+        ));
+        imp.author_list.connect_row_selected(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                let Some(row) = row else { return; };
+                let imp = _self.imp();
+                let filter = match row.index() {
+                    0 => None,
+                    index => imp.author_rows.borrow().get(index as usize - 1).cloned(),
+                };
+                if *imp.author_filter.borrow() == filter { return; }
+                imp.author_filter.replace(filter);
+                _self.populate_model(&model);
+            }
+        ));
+        imp.author_list.set_filter_func(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] true,
+            move |row| {
+                let imp = _self.imp();
+                let query = imp.author_search.text().to_lowercase();
+                // "All authors" stays to go back to
+                row.index() == 0 || imp.author_rows.borrow().get(row.index() as usize - 1)
+                    .is_some_and(|author| author.to_lowercase().contains(query.trim()))
+            }
+        ));
+        imp.author_search.connect_search_changed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.imp().author_list.invalidate_filter()
+        ));
+        imp.language_list.connect_row_selected(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                // Rows are rebuilt along with the volumes
+                let Some(row) = row else { return; };
+                let imp = _self.imp();
+                let filter = match row.index() {
+                    0 => None,
+                    index => imp.language_rows.borrow().get(index as usize - 1).cloned(),
+                };
+                if *imp.language_filter.borrow() == filter { return; }
+                imp.language_filter.replace(filter);
+                _self.populate_model(&model);
+            }
+        ));
+
+        imp.workspace_list.connect_row_activated(glib::clone!(
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                let path = _self.imp().workspace.borrow().get(row.index() as usize).cloned();
+                if let Some(path) = path {
+                    _self.select_path(&model, &path);
+                }
+            }
+        ));
+
+        let drop_target = gtk::DropTarget::new(String::static_type(), gtk::gdk::DragAction::COPY);
+        drop_target.connect_drop(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] false,
+            move |_, value, _, _| {
+                let Ok(path) = value.get::<String>() else { return false; };
+                _self.add_to_workspace(&path);
+                true
+            }
+        ));
+        imp.workspace_box.add_controller(drop_target);
+        self.refresh_workspace_list();
+    }
+
+    pub(super) fn create_volume_row(icon_name: &str, name: &str, count: usize) -> gtk::Box {
+        let hbox = gtk::Box::builder().spacing(8).build();
+        let icon = gtk::Image::from_icon_name(icon_name);
+        let label = gtk::Label::builder()
+            .label(name)
+            .xalign(0.0)
+            .hexpand(true)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .build();
+        let count_label = gtk::Label::new(Some(&count.to_string()));
+        count_label.add_css_class("dim-label");
+        hbox.append(&icon);
+        hbox.append(&label);
+        hbox.append(&count_label);
+        hbox
+    }
+
+    pub(super) fn refresh_volume_list(&self) {
+        let imp = self.imp();
+        let scan_dirs = imp.config.get().unwrap().read().unwrap().scan_dirs.clone();
+        let volumes = volumes::library_volumes(&scan_dirs);
+
+        let mut counts = vec![0usize; volumes.len()];
+        let total = {
+            let files = match imp.metadata_list.lock() {
+                Ok(files) => files,
+                Err(poisoned) => poisoned.into_inner()
+            };
+            for pdf in files.iter() {
+                if let Some(index) = volumes::find_volume(&volumes, Path::new(&pdf.path))
+                    .and_then(|v| volumes.iter().position(|other| other == v)) {
+                    counts[index] += 1;
+                }
+            }
+            files.len()
+        };
+
+        imp.volume_list.remove_all();
+        imp.volume_list.append(&Self::create_volume_row("folder-documents-symbolic", "All documents", total));
+        for (volume, count) in volumes.iter().zip(counts) {
+            let row = Self::create_volume_row(volume.kind.icon_name(), &volume.name, count);
+            if volume.online {
+                row.set_tooltip_text(Some(&volume.root.display().to_string()));
+            } else {
+                row.add_css_class("dim-label");
+                row.set_tooltip_text(Some(&format!("{} (offline)", volume.root.display())));
+            }
+            imp.volume_list.append(&row);
+        }
+
+        let selected_index = imp.volume_filter.borrow().as_ref()
+            .and_then(|root| volumes.iter().position(|v| &v.root == root))
+            .map_or(0, |index| index + 1);
+        *imp.volumes.borrow_mut() = volumes;
+        // A selected collection takes the place of a volume
+        if imp.collection_filter.get().is_none() {
+            imp.volume_list.select_row(imp.volume_list.row_at_index(selected_index as i32).as_ref());
+        }
+        // Counted from the same list
+        self.refresh_language_list();
+        self.refresh_author_list();
+        self.refresh_year_list();
+    }
+
+    /// The documents the grid lists before any sidebar filter or search,
+    /// grouped by `key`. Each group is counted the way the grid shows it when
+    /// filtered to it, with editions hidden among its documents. Also the
+    /// count of them all.
+    fn shown_counts<K: Ord>(&self, key: impl Fn(&PdfMetadata) -> Option<K>) -> (BTreeMap<K, usize>, usize) {
+        let imp = self.imp();
+        let show_missing = self.shows_missing();
+        let files = match imp.metadata_list.lock() {
+            Ok(files) => files,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        let editions = imp.editions.borrow();
+        let count = |documents: Vec<&PdfMetadata>| editions.without_hidden(documents, |pdf| pdf.hash.as_str()).len();
+
+        let listed: Vec<&PdfMetadata> = files.iter().filter(|pdf| show_missing || !pdf.missing).collect();
+        let mut groups: BTreeMap<K, Vec<&PdfMetadata>> = BTreeMap::new();
+        for pdf in listed.iter().copied() {
+            if let Some(key) = key(pdf) { groups.entry(key).or_default().push(pdf); }
+        }
+        let counts = groups.into_iter().map(|(key, documents)| (key, count(documents))).collect();
+        (counts, count(listed))
+    }
+
+    pub(super) fn refresh_year_list(&self) {
+        let imp = self.imp();
+        let (years, _) = self.shown_counts(|pdf| pdf.year());
+        let (decades, total) = self.shown_counts(|pdf| pdf.year().map(|year| year - year % 10));
+        let years: Vec<i32> = years.into_keys().collect();
+
+        imp.decade_list.remove_all();
+        imp.decade_list.append(&Self::create_volume_row("x-office-calendar-symbolic", "Any year", total));
+        // Newest first, like the grid sorted by date
+        for (decade, count) in decades.iter().rev() {
+            let row = Self::create_volume_row("x-office-calendar-symbolic", &format!("{}s", decade), *count);
+            imp.decade_list.append(&row);
+        }
+        // Filtering needs something to tell apart
+        imp.year_button.set_visible(!years.is_empty() || imp.year_filter.get().is_some());
+        if let (Some(first), Some(last)) = (years.first(), years.last()) && imp.year_filter.get().is_none() {
+            imp.year_from_spin.set_value(*first as f64);
+            imp.year_to_spin.set_value(*last as f64);
+        }
+        let decades: Vec<i32> = decades.into_iter().rev().map(|(decade, _)| decade).collect();
+        let selected_index = match imp.year_filter.get() {
+            None => Some(0),
+            Some((from, to)) => decades.iter().position(|decade| from == *decade && to == decade + 9).map(|index| index + 1),
+        };
+        imp.decade_rows.replace(decades);
+        match selected_index {
+            Some(index) => imp.decade_list.select_row(imp.decade_list.row_at_index(index as i32).as_ref()),
+            None => imp.decade_list.unselect_all(),
+        }
+        self.update_year_button();
+    }
+
+    pub(super) fn update_year_button(&self) {
+        let imp = self.imp();
+        match imp.year_filter.get() {
+            None => {
+                imp.year_button.set_icon_name("x-office-calendar-symbolic");
+                imp.year_button.set_tooltip_text(Some("Filter by year"));
+            }
+            Some((from, to)) => {
+                let label = if from % 10 == 0 && to == from + 9 {
+                    format!("{}s", from)
+                } else if from == to {
+                    from.to_string()
+                } else {
+                    format!("{}\u{2013}{}", from, to)
+                };
+                imp.year_button.set_label(&label);
+                imp.year_button.set_tooltip_text(Some(&format!("Showing documents from {}", label)));
+            }
+        }
+    }
+
+    fn set_year_filter(&self, model: &gio::ListStore, filter: Option<(i32, i32)>) {
+        let imp = self.imp();
+        if imp.year_filter.get() == filter { return; }
+        imp.year_filter.set(filter);
+        if let Some((from, to)) = filter {
+            imp.year_from_spin.set_value(from as f64);
+            imp.year_to_spin.set_value(to as f64);
+        }
+        self.update_year_button();
+        self.populate_model(model);
+    }
+
+    pub(super) fn refresh_author_list(&self) {
+        let imp = self.imp();
+        // Authors differing only in case or surrounding spaces are the same,
+        // the filter matches them that way too
+        let written = |pdf: &PdfMetadata| pdf.author.as_deref().map(str::trim).filter(|author| !author.is_empty()).map(str::to_string);
+        let (counts, total) = self.shown_counts(|pdf| written(pdf).map(|author| author.to_ascii_lowercase()));
+        let names: HashMap<String, String> = match imp.metadata_list.lock() {
+            Ok(files) => files,
+            Err(poisoned) => poisoned.into_inner()
+        }.iter().filter_map(written).map(|author| (author.to_ascii_lowercase(), author)).collect();
+        // The most written first
+        let mut authors: Vec<(String, usize)> = counts.into_iter()
+            .filter_map(|(key, count)| Some((names.get(&key)?.clone(), count)))
+            .collect();
+        authors.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.to_lowercase().cmp(&b.to_lowercase())));
+
+        imp.author_list.remove_all();
+        imp.author_list.append(&Self::create_volume_row("system-users-symbolic", "All authors", total));
+        for (author, count) in &authors {
+            let row = Self::create_volume_row("avatar-default-symbolic", author, *count);
+            row.set_tooltip_text(Some(author));
+            imp.author_list.append(&row);
+        }
+        imp.authors_box.set_visible(!authors.is_empty());
+        let names: Vec<String> = authors.into_iter().map(|(author, _)| author).collect();
+        // Edited away, or the last document by them is gone
+        let filter = imp.author_filter.borrow().clone();
+        let selected_index = match filter.and_then(|author| names.iter().position(|other| other.eq_ignore_ascii_case(&author))) {
+            Some(index) => index + 1,
+            None => {
+                imp.author_filter.replace(None);
+                0
+            }
+        };
+        imp.author_rows.replace(names);
+        imp.author_list.select_row(imp.author_list.row_at_index(selected_index as i32).as_ref());
+    }
+
+    fn refresh_language_list(&self) {
+        let imp = self.imp();
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let total = {
+            let files = match imp.metadata_list.lock() {
+                Ok(files) => files,
+                Err(poisoned) => poisoned.into_inner()
+            };
+            for pdf in files.iter() {
+                *counts.entry(pdf.language.clone().unwrap_or_default()).or_default() += 1;
+            }
+            files.len()
+        };
+        // Most documents first, the ones without a language last
+        let mut languages: Vec<(String, usize)> = counts.into_iter().collect();
+        languages.sort_by(|(a, a_count), (b, b_count)| a.is_empty().cmp(&b.is_empty())
+            .then(b_count.cmp(a_count))
+            .then_with(|| language::name(a).cmp(language::name(b))));
+
+        imp.language_list.remove_all();
+        imp.language_list.append(&Self::create_volume_row("preferences-desktop-locale-symbolic", "All languages", total));
+        for (code, count) in &languages {
+            let name = if code.is_empty() { "Unknown" } else { language::name(code) };
+            let row = Self::create_volume_row("preferences-desktop-locale-symbolic", name, *count);
+            if code.is_empty() {
+                row.add_css_class("dim-label");
+                row.set_tooltip_text(Some("No text to tell the language by, e.g. scanned books"));
+            }
+            imp.language_list.append(&row);
+        }
+        imp.languages_box.set_visible(languages.iter().any(|(code, _)| !code.is_empty()));
+        let codes: Vec<String> = languages.into_iter().map(|(code, _)| code).collect();
+        // A language no document is in any more shows nothing
+        let filter = imp.language_filter.borrow().clone();
+        let selected_index = match filter.and_then(|code| codes.iter().position(|other| *other == code)) {
+            Some(index) => index + 1,
+            None => {
+                imp.language_filter.replace(None);
+                0
+            }
+        };
+        imp.language_rows.replace(codes);
+        imp.language_list.select_row(imp.language_list.row_at_index(selected_index as i32).as_ref());
+    }
+}
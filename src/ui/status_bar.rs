@@ -0,0 +1,207 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use gtk::glib;
+use gtk::prelude::*;
+use tracing::warn;
+
+// Scan progress changes many times a second, screen readers get the latest
+// text at most this often
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatusModule {
+    // Path of the hovered or selected document
+    Path,
+    // Position of the selection and how much of the library is shown
+    Selection,
+    // Scan progress and results of other long running actions
+    Scan,
+    // Whether everything extracted has been written to the cache
+    Sync,
+}
+
+impl StatusModule {
+    pub fn from_str(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "path" => Some(StatusModule::Path),
+            "selection" => Some(StatusModule::Selection),
+            "scan" => Some(StatusModule::Scan),
+            "sync" => Some(StatusModule::Sync),
+            _ => None,
+        }
+    }
+}
+
+/// The labels along the bottom of the main window, one per configured module.
+/// Text set for a hidden module is kept in case it gets turned on later.
+#[derive(Default)]
+pub struct StatusBar {
+    labels: RefCell<HashMap<StatusModule, gtk::Label>>,
+    texts: RefCell<HashMap<StatusModule, String>>,
+    container: glib::WeakRef<gtk::Box>,
+    // Shown next to the scan module while a scan runs
+    progress_bar: RefCell<Option<gtk::ProgressBar>>,
+    progress: RefCell<Option<(f64, String)>>,
+    // Also next to the scan module, opens the files the last scan couldn't read
+    errors_button: RefCell<Option<(gtk::Button, gtk::Label)>>,
+    error_count: Cell<usize>,
+    pending_announcement: Rc<RefCell<Option<String>>>,
+    last_announcement: Rc<Cell<Option<Instant>>>,
+}
+
+impl StatusBar {
+    /// Fill `container` with the modules named in `modules`, in that order
+    pub fn build(&self, container: &gtk::Box, modules: &[String]) {
+        // For restyling in style.css
+        container.add_css_class("status-bar");
+        self.container.set(Some(container));
+        while let Some(child) = container.first_child() {
+            container.remove(&child);
+        }
+
+        let mut labels = self.labels.borrow_mut();
+        labels.clear();
+        self.progress_bar.replace(None);
+        self.errors_button.replace(None);
+        for name in modules {
+            let Some(module) = StatusModule::from_str(name) else {
+                warn!("Unknown status bar module: {}", name);
+                continue;
+            };
+            if labels.contains_key(&module) { continue; }
+
+            let label = gtk::Label::builder()
+                .label(self.texts.borrow().get(&module).map(String::as_str).unwrap_or(""))
+                .xalign(0.0)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .build();
+            label.add_css_class("dim-label");
+            label.add_css_class(&format!("status-{}", name.trim().to_lowercase()));
+            // The path is the longest, let it take the spare room
+            label.set_hexpand(module == StatusModule::Path);
+            // Empty modules take up no room
+            if label.label().is_empty() { label.set_visible(false); }
+            container.append(&label);
+            labels.insert(module, label);
+
+            if module == StatusModule::Scan {
+                let progress_bar = gtk::ProgressBar::builder()
+                    .show_text(true)
+                    .valign(gtk::Align::Center)
+                    .width_request(220)
+                    .build();
+                progress_bar.add_css_class("status-progress");
+                container.append(&progress_bar);
+                self.progress_bar.replace(Some(progress_bar));
+
+                let icon = gtk::Image::from_icon_name("dialog-warning-symbolic");
+                let count_label = gtk::Label::new(None);
+                let content = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+                content.append(&icon);
+                content.append(&count_label);
+                let errors_button = gtk::Button::builder()
+                    .child(&content)
+                    .action_name("app.show-scan-errors")
+                    .has_frame(false)
+                    .valign(gtk::Align::Center)
+                    .tooltip_text("Show the files the last scan couldn't read")
+                    .build();
+                errors_button.add_css_class("status-errors");
+                errors_button.add_css_class("warning");
+                container.append(&errors_button);
+                self.errors_button.replace(Some((errors_button, count_label)));
+            }
+        }
+        drop(labels);
+        self.show_progress();
+        self.show_error_count();
+    }
+
+    /// How many files the last scan couldn't read, hidden at 0
+    pub fn set_error_count(&self, count: usize) {
+        self.error_count.set(count);
+        self.show_error_count();
+    }
+
+    fn show_error_count(&self) {
+        let Some((button, label)) = self.errors_button.borrow().clone() else { return; };
+        let count = self.error_count.get();
+        label.set_label(&if count == 1 { "1 error".to_string() } else { format!("{} errors", count) });
+        button.set_visible(count > 0);
+    }
+
+    /// Fill the scan progress bar to `fraction`, with `text` on it
+    pub fn set_progress(&self, fraction: f64, text: &str) {
+        self.progress.replace(Some((fraction.clamp(0.0, 1.0), text.to_string())));
+        self.show_progress();
+    }
+
+    /// Bounce the progress bar while there's no telling how much is left
+    pub fn pulse_progress(&self, text: &str) {
+        self.progress.replace(None);
+        if let Some(progress_bar) = self.progress_bar.borrow().as_ref() {
+            progress_bar.set_text(Some(text));
+            progress_bar.set_visible(true);
+            progress_bar.pulse();
+        }
+    }
+
+    pub fn hide_progress(&self) {
+        self.progress.replace(None);
+        if let Some(progress_bar) = self.progress_bar.borrow().as_ref() {
+            progress_bar.set_visible(false);
+        }
+    }
+
+    fn show_progress(&self) {
+        let Some(progress_bar) = self.progress_bar.borrow().clone() else { return; };
+        match self.progress.borrow().as_ref() {
+            Some((fraction, text)) => {
+                progress_bar.set_fraction(*fraction);
+                progress_bar.set_text(Some(text));
+                progress_bar.set_visible(true);
+            }
+            None => progress_bar.set_visible(false),
+        }
+    }
+
+    pub fn set(&self, module: StatusModule, text: &str) {
+        let previous = self.texts.borrow_mut().insert(module, text.to_string());
+        if let Some(label) = self.labels.borrow().get(&module) {
+            label.set_text(text);
+            label.set_visible(!text.is_empty());
+        }
+        if !text.is_empty() && previous.as_deref() != Some(text) {
+            self.announce(module, text);
+        }
+    }
+
+    /// Tell screen readers about scan progress and selection changes, the
+    /// way a live region would, whether the module is shown or not
+    fn announce(&self, module: StatusModule, text: &str) {
+        let Some(container) = self.container.upgrade() else { return; };
+        match module {
+            StatusModule::Selection => container.announce(text, gtk::AccessibleAnnouncementPriority::Low),
+            StatusModule::Scan => {
+                // A timer is already waiting to announce the latest text
+                if self.pending_announcement.replace(Some(text.to_string())).is_some() { return; }
+                let wait = self.last_announcement.get()
+                    .map_or(Duration::ZERO, |last| ANNOUNCE_INTERVAL.saturating_sub(last.elapsed()));
+                let pending = self.pending_announcement.clone();
+                let last = self.last_announcement.clone();
+                let container = container.downgrade();
+                glib::timeout_add_local_once(wait, move || {
+                    let Some(text) = pending.take() else { return; };
+                    if let Some(container) = container.upgrade() {
+                        container.announce(&text, gtk::AccessibleAnnouncementPriority::Medium);
+                    }
+                    last.set(Some(Instant::now()));
+                });
+            }
+            StatusModule::Path | StatusModule::Sync => {}
+        }
+    }
+}
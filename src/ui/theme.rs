@@ -0,0 +1,63 @@
+use std::cell::RefCell;
+
+use gtk::gio;
+use gtk::prelude::*;
+use tracing::warn;
+
+use crate::config::{Config, Theme};
+
+thread_local! {
+    // Kept so the stylesheet can be swapped out when it changes
+    static USER_CSS: RefCell<Option<gtk::CssProvider>> = const { RefCell::new(None) };
+    static USER_CSS_MONITOR: RefCell<Option<gio::FileMonitor>> = const { RefCell::new(None) };
+}
+
+/// Light, dark or whatever the desktop prefers
+pub fn apply(theme: Theme) {
+    let scheme = match theme {
+        Theme::System => adw::ColorScheme::Default,
+        Theme::Light => adw::ColorScheme::ForceLight,
+        Theme::Dark => adw::ColorScheme::ForceDark,
+    };
+    adw::StyleManager::default().set_color_scheme(scheme);
+}
+
+/// Load `style.css` from the config directory on top of Shelf's own style,
+/// and again whenever it's saved. Grid items are `.grid-item`, the status
+/// bar is `.status-bar` with a `.status-<module>` label per module.
+pub fn load_user_css() {
+    let Ok(path) = Config::config_path().map(|config| config.with_file_name("style.css")) else { return; };
+    let file = gio::File::for_path(&path);
+    reload(&file);
+
+    match file.monitor_file(gio::FileMonitorFlags::WATCH_MOVES, None::<&gio::Cancellable>) {
+        Ok(monitor) => {
+            monitor.connect_changed(|_, file, _, event| {
+                if matches!(event, gio::FileMonitorEvent::ChangesDoneHint | gio::FileMonitorEvent::Deleted
+                    | gio::FileMonitorEvent::Created | gio::FileMonitorEvent::MovedIn | gio::FileMonitorEvent::Renamed) {
+                    reload(file);
+                }
+            });
+            USER_CSS_MONITOR.with(|cell| cell.replace(Some(monitor)));
+        }
+        Err(e) => warn!("Failed to watch {}: {}", path.display(), e),
+    }
+}
+
+fn reload(file: &gio::File) {
+    let Some(display) = gtk::gdk::Display::default() else { return; };
+    USER_CSS.with(|cell| {
+        if let Some(old) = cell.take() {
+            gtk::style_context_remove_provider_for_display(&display, &old);
+        }
+        // Deleted, only the built-in style is left
+        if !file.query_exists(None::<&gio::Cancellable>) { return; }
+        let provider = gtk::CssProvider::new();
+        provider.connect_parsing_error(|_, section, error| {
+            warn!("style.css:{}: {}", section.start_location().lines() + 1, error);
+        });
+        provider.load_from_file(file);
+        gtk::style_context_add_provider_for_display(&display, &provider, gtk::STYLE_PROVIDER_PRIORITY_USER);
+        cell.replace(Some(provider));
+    });
+}
@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+
+use crate::pdf::PdfMetadata;
+use crate::volumes::{self, Volume};
+
+const USAGE_LIMIT: usize = 50;
+
+mod imp {
+    use std::cell::RefCell;
+
+    use gtk::glib;
+    use gtk::glib::subclass::types::ObjectSubclass;
+    use gtk::subclass::prelude::*;
+
+    #[derive(Default, gtk::CompositeTemplate)]
+    #[template(resource = "/org/galib/shelf/ui/usage_window.xml")]
+    pub struct ShelfUsageWindow {
+        #[template_child]
+        pub summary_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub usage_list: TemplateChild<gtk::ListBox>,
+
+        // Path of each row in usage_list
+        pub paths: RefCell<Vec<String>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ShelfUsageWindow {
+        const NAME: &'static str = "ShelfUsageWindow";
+        type Type = super::ShelfUsageWindow;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ShelfUsageWindow {
+        fn constructed(&self) {
+            self.parent_constructed();
+        }
+    }
+
+    impl WidgetImpl for ShelfUsageWindow {}
+    impl WindowImpl for ShelfUsageWindow {}
+}
+
+glib::wrapper! {
+    pub struct ShelfUsageWindow(ObjectSubclass<imp::ShelfUsageWindow>)
+        @extends gtk::Widget, gtk::Window,
+        @implements gio::ActionGroup, gio::ActionMap,
+                    gtk::Accessible, gtk::Buildable,
+                    gtk::ConstraintTarget, gtk::Native,
+                    gtk::Root, gtk::ShortcutManager;
+}
+
+fn usage_row(rank: usize, title: &str, volume: &str, count: u32) -> gtk::Box {
+    let row = gtk::Box::builder().spacing(8).margin_top(4).margin_bottom(4).build();
+    let rank_label = gtk::Label::new(Some(&format!("{}.", rank)));
+    rank_label.add_css_class("dim-label");
+    let text = gtk::Box::builder().orientation(gtk::Orientation::Vertical).hexpand(true).build();
+    let title_label = gtk::Label::builder()
+        .label(title)
+        .xalign(0.0)
+        .ellipsize(gtk::pango::EllipsizeMode::End)
+        .build();
+    let volume_label = gtk::Label::builder().label(volume).xalign(0.0).build();
+    volume_label.add_css_class("dim-label");
+    text.append(&title_label);
+    text.append(&volume_label);
+    let count_label = gtk::Label::new(Some(&if count == 1 { "1 open".to_string() } else { format!("{} opens", count) }));
+    count_label.add_css_class("dim-label");
+    row.append(&rank_label);
+    row.append(&text);
+    row.append(&count_label);
+    row
+}
+
+impl ShelfUsageWindow {
+    /// Rank `library` by `open_counts`, which are keyed by content hash
+    pub fn new(library: &[PdfMetadata], open_counts: &HashMap<String, u32>, volumes: &[Volume]) -> Self {
+        let obj: ShelfUsageWindow = glib::Object::builder().build();
+        obj.populate(library, open_counts, volumes);
+        obj.setup();
+        obj
+    }
+
+    fn setup(&self) {
+        self.imp().usage_list.connect_row_activated(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                let Some(path) = _self.imp().paths.borrow().get(row.index() as usize).cloned() else { return; };
+                // Selecting happens in the main window, which owns the action
+                if let Some(parent) = _self.transient_for() {
+                    let _ = WidgetExt::activate_action(&parent, "win.select-path", Some(&path.to_variant()));
+                }
+            }
+        ));
+    }
+
+    fn populate(&self, library: &[PdfMetadata], open_counts: &HashMap<String, u32>, volumes: &[Volume]) {
+        let imp = self.imp();
+        // Copies share a hash and so a count, list each document once
+        let mut seen = HashSet::new();
+        let mut ranked: Vec<(&PdfMetadata, u32)> = library.iter()
+            .filter(|pdf| seen.insert(pdf.hash.as_str()))
+            .filter_map(|pdf| open_counts.get(&pdf.hash).map(|count| (pdf, *count)))
+            .collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.path.cmp(&b.0.path)));
+
+        let mut opens_by_volume: HashMap<&str, u32> = HashMap::new();
+        for (pdf, count) in &ranked {
+            let volume = volumes::find_volume(volumes, Path::new(&pdf.path)).map_or("Other", |v| v.name.as_str());
+            *opens_by_volume.entry(volume).or_default() += count;
+        }
+        let mut by_volume: Vec<(&str, u32)> = opens_by_volume.into_iter().collect();
+        by_volume.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        let summary = if by_volume.is_empty() {
+            "Nothing has been opened yet".to_string()
+        } else {
+            let volumes: Vec<String> = by_volume.iter().map(|(name, count)| format!("{} {}", name, count)).collect();
+            format!("Opens by volume: {}", volumes.join(", "))
+        };
+        imp.summary_label.set_text(&summary);
+
+        ranked.truncate(USAGE_LIMIT);
+        let mut paths = Vec::new();
+        for (rank, (pdf, count)) in ranked.iter().enumerate() {
+            let volume = volumes::find_volume(volumes, Path::new(&pdf.path)).map_or("", |v| v.name.as_str());
+            let row = usage_row(rank + 1, &pdf.display_title(), volume, *count);
+            row.set_tooltip_text(Some(&pdf.path));
+            imp.usage_list.append(&row);
+            paths.push(pdf.path.clone());
+        }
+        imp.paths.replace(paths);
+    }
+}
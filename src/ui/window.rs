@@ -1,37 +1,93 @@
 #![allow(dead_code)]
 
-use std::path::PathBuf;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, RwLock};
-use std::time::Instant;
 
+use anyhow::Context;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 use gtk::glib::subclass::types::ObjectSubclassIsExt;
-use gtk::{prelude::*, SignalListItemFactory, SingleSelection};
+use gtk::{prelude::*, MultiSelection, SignalListItemFactory};
 use gtk::glib;
 use gtk::gio;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-use unicode_segmentation::UnicodeSegmentation;
+use tracing::{debug, error, info, warn};
 
-use crate::config::Config;
-use crate::pdf::{extract_pdf_metadata, PdfCache, PdfMetadata, ScanProgress};
+use crate::collections::CollectionTree;
+use crate::config::{Backend, Config};
+use crate::gsettings;
+use crate::logging;
+use crate::portal;
+use crate::pdf::{CustomFieldsByHash, PdfCache, PdfMetadata};
+use crate::selector::Filter;
+use crate::state::{Session, UiState};
+use crate::ui::accessibility::{self, prefers_high_contrast, prefers_reduced_motion};
+use crate::ui::cover_loader::{self, CoverLoader};
 use crate::ui::grid_item::ShelfGridItem;
 use crate::ui::models::PdfMetadataObject;
+use crate::ui::quick_look_window::ShelfQuickLookWindow;
+use crate::ui::reader_window::ShelfReaderWindow;
 use crate::ui::settings_window::ShelfSettingsWindow;
-use crate::utils::scan_pdfs_rayon;
+use crate::ui::status_bar::StatusModule;
+use crate::ui::theme;
+use crate::utils::viewer_command;
+use crate::volumes;
 use super::models;
 
+const SEARCH_RESULT_LIMIT: usize = 10;
+// Quiet time after config.toml changes before it's read again
+const CONFIG_RELOAD_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Grid order while not searching, search results are ordered by relevance
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum SortOrder {
+    // Scan order, which is sorted by path
+    #[default]
+    Path,
+    MostUsed,
+}
+
+impl SortOrder {
+    fn from_str(order: &str) -> Self {
+        match order {
+            "most-used" => SortOrder::MostUsed,
+            _ => SortOrder::Path,
+        }
+    }
+}
+
 mod imp {
-    use std::cell::{OnceCell, RefCell};
-    use std::sync::{Arc, Mutex, RwLock};
+    use std::cell::{Cell, OnceCell, RefCell};
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Mutex, OnceLock, RwLock};
+    use std::thread::JoinHandle;
 
     use gtk::glib;
     use gtk::glib::subclass::types::ObjectSubclass;
+    use gtk::glib::subclass::Signal;
     use gtk::subclass::prelude::*;
+    use adw::subclass::prelude::AdwApplicationWindowImpl;
 
+    use crate::collections::CollectionTree;
     use crate::config::Config;
-    use crate::pdf::PdfMetadata;
+    use crate::editions::Editions;
+    use crate::pdf::{Annotation, Bookmark, CustomFieldsByHash, PdfCache, PdfMetadata, ScanProgress};
+    use crate::state::Session;
+    use crate::ui::cover_loader::CoverLoader;
+    use crate::ui::library_watcher::LibraryWatcher;
+    use crate::ui::metadata_review_window::ShelfMetadataReviewWindow;
+    use crate::ui::quick_look_window::ShelfQuickLookWindow;
+    use crate::ui::scan_errors_window::ShelfScanErrorsWindow;
+    use crate::ui::status_bar::StatusBar;
+    use crate::volumes::Volume;
 
     #[derive(Default, gtk::CompositeTemplate)]
     #[template(resource = "/org/galib/shelf/ui/window.xml")]
@@ -39,37 +95,111 @@ mod imp {
         #[template_child]
         pub refresh_button: TemplateChild<gtk::Button>,
         #[template_child]
+        pub cancel_scan_button: TemplateChild<gtk::Button>,
+        #[template_child]
         pub settings_button: TemplateChild<gtk::Button>,
         #[template_child]
         pub search_button: TemplateChild<gtk::Button>,
         #[template_child]
         pub search_entry: TemplateChild<gtk::SearchEntry>,
         #[template_child]
-        pub status_label: TemplateChild<gtk::Label>,
+        pub status_bar: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub toast_overlay: TemplateChild<adw::ToastOverlay>,
         #[template_child]
         pub grid_view: TemplateChild<gtk::GridView>,
         #[template_child]
         pub preview_title: TemplateChild<gtk::Label>,
         #[template_child]
+        pub preview_abstract: TemplateChild<gtk::Label>,
+        #[template_child]
         pub preview_filename: TemplateChild<gtk::Label>,
         #[template_child]
         pub preview_filepath: TemplateChild<gtk::Label>,
         #[template_child]
+        pub preview_locations: TemplateChild<gtk::Label>,
+        #[template_child]
         pub preview_author: TemplateChild<gtk::Label>,
         #[template_child]
         pub preview_subject: TemplateChild<gtk::Label>,
         #[template_child]
         pub preview_keywords: TemplateChild<gtk::Label>,
         #[template_child]
+        pub preview_tags: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub year_button: TemplateChild<gtk::MenuButton>,
+        #[template_child]
+        pub year_popover: TemplateChild<gtk::Popover>,
+        #[template_child]
+        pub decade_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub year_from_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub year_to_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub year_range_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub preview_custom_fields: TemplateChild<gtk::Label>,
+        #[template_child]
         pub preview_pages: TemplateChild<gtk::Label>,
         #[template_child]
         pub preview_filesize: TemplateChild<gtk::Label>,
         #[template_child]
         pub preview_toggle_button: TemplateChild<gtk::Button>,
         #[template_child]
+        pub preview_snippet: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub pages_expander: TemplateChild<gtk::Expander>,
+        #[template_child]
+        pub pages_strip: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub annotations_heading: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub annotations_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub bookmarks_heading: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub bookmarks_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub editions_heading: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub editions_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub attachments_heading: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub attachments_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
         pub right_pane: TemplateChild<gtk::Box>,
         #[template_child]
+        pub preview_content: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub grid_stack: TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub add_folder_button: TemplateChild<gtk::Button>,
+        #[template_child]
         pub paned: TemplateChild<gtk::Paned>,
+        #[template_child]
+        pub volume_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub collections_header: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub collection_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub languages_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub language_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub authors_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub author_search: TemplateChild<gtk::SearchEntry>,
+        #[template_child]
+        pub author_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub workspace_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub workspace_heading: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub workspace_list: TemplateChild<gtk::ListBox>,
         // author,
         // subject,
         // keywords,
@@ -84,15 +214,92 @@ mod imp {
         // Store for PDF files
         pub metadata_list: Arc<Mutex<Vec<PdfMetadata>>>, 
         pub selected: Arc<Mutex<Option<PdfMetadata>>>,
+        // Annotations listed in the preview pane, in row order
+        pub annotations: RefCell<Vec<Annotation>>,
+        pub bookmarks: RefCell<Vec<Bookmark>>,
+        pub attachments: RefCell<Vec<String>>,
+        pub editions: RefCell<Editions>,
+        // For the preview pane and searching
+        pub custom_fields: RefCell<CustomFieldsByHash>,
+        // Path of the edition in each row of the preview pane
+        pub edition_rows: RefCell<Vec<String>>,
+        // Document the page strip was filled for, it's only filled while expanded
+        pub pages_strip_hash: RefCell<Option<String>>,
         pub config: OnceCell<Arc<RwLock<Config>>>,
+        pub cache: OnceCell<Arc<PdfCache>>,
+        pub cover_loader: OnceCell<Rc<CoverLoader>>,
+        pub quick_look: glib::WeakRef<ShelfQuickLookWindow>,
+        // Last grid scroll offset, to tell which way the user is scrolling
+        pub scroll_value: Cell<f64>,
+        pub scrolling_up: Cell<bool>,
+        pub search_refresh_pending: Cell<bool>,
+        // Covers are being looked up online, see fetch_online_covers
+        pub fetching_covers: Cell<bool>,
+        // Same for metadata, see fill_in_missing_metadata
+        pub filling_in_metadata: Cell<bool>,
+        pub metadata_review_window: glib::WeakRef<ShelfMetadataReviewWindow>,
+        // Set on close so a running scan stops picking up new files
+        pub scan_cancelled: Arc<AtomicBool>,
+        pub scan_thread: RefCell<Option<JoinHandle<()>>>,
+        pub scan_progress: RefCell<Option<async_channel::Sender<ScanProgress>>>,
+        // Unix time the running or last scan started, and what failed in it
+        pub scan_started_at: Cell<i64>,
+        pub scan_errors: RefCell<Vec<(PathBuf, String)>>,
+        // Scan directories on network mounts already warned about this session
+        pub network_warned: RefCell<HashSet<PathBuf>>,
+        pub scan_errors_window: glib::WeakRef<ShelfScanErrorsWindow>,
+        // Last session written to disk and the timer writing it
+        pub saved_session: RefCell<Session>,
+        pub session_timer: RefCell<Option<glib::SourceId>>,
+        // Selection from a crashed session, applied once the first scan is done
+        pub restored_selection: RefCell<Option<String>>,
+        pub restored_scroll: Cell<Option<f64>>,
+        // Hash of the document last selected or opened
+        pub last_document: RefCell<Option<String>>,
+        pub restored_document: RefCell<Option<String>>,
+        // First g of gg in vim navigation
+        pub pending_g: Cell<bool>,
+        pub status: StatusBar,
         pub width: RefCell<i32>,
+        pub volumes: RefCell<Vec<Volume>>,
+        // Root of the volume selected in the sidebar, None shows everything
+        pub volume_filter: RefCell<Option<PathBuf>>,
+        // Path to select once the model is next rebuilt
+        pub pending_selection: RefCell<Option<String>>,
+        // Documents gathered for this session only, in the order they were added
+        pub workspace: RefCell<Vec<String>>,
+        // Files passed on the command line while a scan was running
+        pub pending_open: RefCell<Vec<PathBuf>>,
+        pub collection_tree: RefCell<CollectionTree>,
+        // Collection of each row in the sidebar, in row order
+        pub collection_rows: RefCell<Vec<i64>>,
+        // Collection selected in the sidebar, shown instead of a volume
+        pub collection_filter: Cell<Option<i64>>,
+        // Language code of each row in the sidebar after "All languages",
+        // empty for documents whose language wasn't told
+        pub language_rows: RefCell<Vec<String>>,
+        // Narrows down whatever volume or collection is shown
+        pub language_filter: RefCell<Option<String>>,
+        // Author of each row in the sidebar after "All authors"
+        pub author_rows: RefCell<Vec<String>>,
+        // Like the language, matched ignoring case
+        pub author_filter: RefCell<Option<String>>,
+        // First year of each decade in the year popover after "Any year"
+        pub decade_rows: RefCell<Vec<i32>>,
+        // First and last year, both included
+        pub year_filter: Cell<Option<(i32, i32)>>,
+        // Report preferences changed outside Shelf, in GSettings or config.toml
+        pub settings_watch: RefCell<Option<gtk::gio::Settings>>,
+        pub config_monitor: RefCell<Option<gtk::gio::FileMonitor>>,
+        // New and deleted PDFs in the scan directories
+        pub library_watcher: OnceCell<Rc<LibraryWatcher>>,
     }
 
     #[glib::object_subclass]
     impl ObjectSubclass for ShelfWindow {
         const NAME: &'static str = "ShelfWindow";
         type Type = super::ShelfWindow;
-        type ParentType = gtk::ApplicationWindow;
+        type ParentType = adw::ApplicationWindow;
 
         fn class_init(klass: &mut Self::Class) {
             klass.bind_template();
@@ -107,15 +314,24 @@ mod imp {
         fn constructed(&self) {
             self.parent_constructed();
         }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: OnceLock<Vec<Signal>> = OnceLock::new();
+            // Documents found and seconds taken, after every finished scan
+            SIGNALS.get_or_init(|| vec![
+                Signal::builder("scan-complete").param_types([glib::Type::U32, glib::Type::F64]).build()
+            ])
+        }
     }
     impl WidgetImpl for ShelfWindow {}
     impl WindowImpl for ShelfWindow {}
     impl ApplicationWindowImpl for ShelfWindow {}
+    impl AdwApplicationWindowImpl for ShelfWindow {}
 }
 
 glib::wrapper! {
     pub struct ShelfWindow(ObjectSubclass<imp::ShelfWindow>)
-        @extends gtk::Widget, gtk::Window, gtk::ApplicationWindow,
+        @extends gtk::Widget, gtk::Window, gtk::ApplicationWindow, adw::ApplicationWindow,
         @implements gio::ActionGroup, gio::ActionMap,
                     gtk::Accessible, gtk::Buildable,
                     gtk::ConstraintTarget, gtk::Native,
@@ -126,57 +342,208 @@ impl ShelfWindow {
     pub fn new(app: &gtk::Application, config: Arc<RwLock<Config>>) -> Self {
         let obj: ShelfWindow = glib::Object::builder().property("application", app).build();
         obj.imp().config.set(config).unwrap();
+        let low_memory = obj.imp().config.get().unwrap().read().unwrap().low_memory_mode;
+        match PdfCache::new(low_memory) {
+            Ok(cache) => { obj.imp().cache.set(Arc::new(cache)).ok(); }
+            Err(e) => error!("Failed to initialize cache: {}", e),
+        }
+        obj.imp().cover_loader.set(CoverLoader::new(low_memory)).ok();
+        obj.restore_evicted_covers();
+        obj.apply_cover_encoding();
+        // Sharp covers on HiDPI screens, without decoding more than needed
+        obj.connect_scale_factor_notify(|window| {
+            let pixels = cover_loader::DISPLAY_SIZE * window.scale_factor().max(1) as u32;
+            window.imp().cover_loader.get().unwrap().set_display_size(pixels);
+        });
+        theme::apply(obj.imp().config.get().unwrap().read().unwrap().theme);
         obj.setup();
+        obj.restore_state(&UiState::load());
+        if let Some(session) = Session::load() {
+            obj.restore_session(session);
+        }
+        obj.start_session_autosave();
+        if obj.imp().cache.get().is_some_and(|cache| cache.has_shares().unwrap_or(false))
+            && let Err(e) = obj.start_share_server() {
+            warn!("Failed to start sharing: {:#}", e);
+        }
         obj
     }
 
-    // Helper for file size conversion
-    fn format_human_readable_file_size(bytes: u64) -> String {
-        if bytes < 1024 {
-            format!("{} B", bytes)
-        } else if bytes < 1024 * 1024 {
-            format!("{:.2} KB", bytes as f64 / 1024.0)
-        } else if bytes < 1024 * 1024 * 1024 {
-            format!("{:.2} MB", bytes as f64 / (1024.0 * 1024.0))
-        } else {
-            format!("{:.2} GB", bytes as f64 / (1024.0 * 1024.0 * 1024.0))
+    /// Documents matching a filter as used by `shelf --filter`, in library order
+    pub fn search_documents(&self, query: &str) -> anyhow::Result<Vec<PdfMetadata>> {
+        let imp = self.imp();
+        let filter = Filter::parse(query)?;
+        let (tags, collections) = match imp.cache.get() {
+            Some(cache) => (cache.all_tags()?, CollectionTree::load(cache)?.names_by_hash()),
+            None => Default::default(),
+        };
+        let fields = imp.custom_fields.borrow();
+        Ok(imp.metadata_list.lock().unwrap().iter()
+            .filter(|metadata| filter.matches(
+                metadata,
+                tags.get(&metadata.hash).map(Vec::as_slice).unwrap_or(&[]),
+                collections.get(&metadata.hash).map(Vec::as_slice).unwrap_or(&[]),
+                fields.get(&metadata.hash).map(Vec::as_slice).unwrap_or(&[]),
+            ))
+            .cloned()
+            .collect())
+    }
+
+    /// Select and open the document with this hash, or a hash starting with it.
+    /// Returns false if there is no such document.
+    pub fn open_hash(&self, hash: &str) -> bool {
+        let hash = hash.trim().to_lowercase();
+        if hash.is_empty() { return false; }
+        let path = {
+            let metadata_list = self.imp().metadata_list.lock().unwrap();
+            metadata_list.iter()
+                .find(|metadata| metadata.hash == hash)
+                .or_else(|| metadata_list.iter().find(|metadata| metadata.hash.starts_with(&hash)))
+                .map(|metadata| metadata.path.clone())
+        };
+        let Some(path) = path else { return false; };
+        ActionGroupExt::activate_action(self, "open-file", Some(&path.to_variant()));
+        true
+    }
+
+    /// Show the search bar with this query
+    pub fn search(&self, query: &str) {
+        let search_entry = &self.imp().search_entry;
+        search_entry.set_visible(true);
+        search_entry.set_text(query);
+    }
+
+    /// Select the collection or volume with this name, collections first.
+    /// Returns false if there is neither.
+    pub fn show_shelf(&self, name: &str) -> bool {
+        let imp = self.imp();
+        let name = name.trim().to_lowercase();
+        let collection = imp.collection_rows.borrow().iter().position(|id| {
+            imp.collection_tree.borrow().get(*id).is_some_and(|collection| collection.name.to_lowercase() == name)
+        });
+        if let Some(index) = collection {
+            imp.collection_list.select_row(imp.collection_list.row_at_index(index as i32).as_ref());
+            return true;
+        }
+        let volume = imp.volumes.borrow().iter().position(|volume| volume.name.to_lowercase() == name);
+        if let Some(index) = volume {
+            // The first row is "All documents"
+            imp.volume_list.select_row(imp.volume_list.row_at_index(index as i32 + 1).as_ref());
+            return true;
         }
+        false
     }
 
-    fn process_option_string(value: &Option<String>) -> String {
-        let mut output = value.as_deref().unwrap_or("(N/A)").to_string();
-        if output.is_empty() { output.push_str("(empty)"); }
-        
-        let max_len = 100;
-        let graphemes: Vec<&str> = output.graphemes(true).collect();
-        if graphemes.len() <= max_len { return output; }
-        return format!("{} ...", graphemes[..max_len].concat());
+    pub(super) fn grid_adjustment(&self) -> Option<gtk::Adjustment> {
+        self.imp().grid_view.parent().and_downcast::<gtk::ScrolledWindow>().map(|scrolled| scrolled.vadjustment())
     }
 
-    fn update_preview_display(&self, metadata: &crate::pdf::PdfMetadata) {
+    /// Fill the grid with what the last scan found, straight from the cache,
+    /// so there's something to browse while the startup scan runs
+    fn load_cached_library(&self, model: &gio::ListStore) {
         let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return; };
+        let documents = match cache.all_metadata() {
+            Ok(documents) => documents,
+            Err(e) => {
+                warn!("Failed to load the cached library: {}", e);
+                return;
+            }
+        };
+        let (scan_dirs, extra_files) = {
+            let config = imp.config.get().unwrap().read().unwrap();
+            (config.scan_dirs.clone(), config.extra_files.clone())
+        };
+        // Leave out folders that have been removed since
+        let documents: Vec<PdfMetadata> = documents.into_iter()
+            .filter(|pdf| {
+                let path = Path::new(&pdf.path);
+                scan_dirs.iter().any(|dir| path.starts_with(dir)) || extra_files.iter().any(|file| file == path)
+            })
+            .collect();
+        if documents.is_empty() { return; }
+        info!("Showing {} cached documents until the scan is done", documents.len());
 
-        let title_text = metadata.title.as_deref().map_or_else(
-            || "(untitled)".to_string(),
-            |s| if s.trim().is_empty() { "(untitled)".to_string() } else { s.to_string() }
-        );
-        imp.preview_title.set_text(&title_text);
-
-        let path_buf = PathBuf::from(&metadata.path);
-        let filename = path_buf.file_name().and_then(|s| s.to_str()).unwrap_or("(N/A)");
-        let filedir = path_buf.parent().and_then(|s| s.to_str()).unwrap_or("(N/A)");
-        imp.preview_filename.set_markup(
-            &format!("<i>{}</i>", glib::markup_escape_text(filename))
-        );
-        imp.preview_filepath.set_markup(&filedir);
-
-        imp.preview_author.set_markup(&format!("<b>Author:</b> {}", Self::process_option_string(&metadata.author)));
-        imp.preview_subject.set_markup(&format!("<b>Subject:</b> {}", Self::process_option_string(&metadata.subject)));
-        imp.preview_keywords.set_markup(&format!("<b>Keywords:</b> {}", Self::process_option_string(&metadata.keywords)));
-        imp.preview_pages.set_text(&format!("{} pages", &metadata.page_count));
-        
-        let formatted_size = Self::format_human_readable_file_size(metadata.file_size);
-        imp.preview_filesize.set_text(&formatted_size);
+        *imp.metadata_list.lock().unwrap() = documents;
+        self.refresh_volume_list();
+        self.refresh_collections();
+        self.refresh_editions();
+        self.refresh_custom_fields();
+        self.refresh_workspace_list();
+        self.populate_model(model);
+        self.restore_position(model);
+    }
+
+    /// Let a running scan wind down and write out everything it extracted,
+    /// so closing never leaves the cache half updated
+    fn shutdown(&self) {
+        let imp = self.imp();
+        imp.scan_cancelled.store(true, Ordering::Relaxed);
+        // Nothing reads the progress anymore, don't leave the scan waiting on it
+        if let Some(progress) = imp.scan_progress.take() {
+            progress.close();
+        }
+        if let Some(scan_thread) = imp.scan_thread.take() {
+            info!("Waiting for the scan to stop...");
+            if scan_thread.join().is_err() {
+                error!("Scan thread panicked");
+            }
+        }
+        if let Some(cache) = imp.cache.get() && let Err(e) = cache.flush() {
+            error!("Failed to write cached metadata: {}", e);
+        }
+        if let Err(e) = self.current_state().save() {
+            warn!("Failed to save window state: {}", e);
+        }
+
+        // A clean exit has nothing to recover
+        if let Some(timer) = imp.session_timer.take() {
+            timer.remove();
+        }
+        if let Err(e) = Session::clear() {
+            warn!("Failed to remove session file: {}", e);
+        }
+    }
+
+    /// The grid, or a page saying why it's empty: no folders scanned yet, or
+    /// nothing matching the search and filters
+    fn update_empty_state(&self) {
+        let imp = self.imp();
+        let shown = imp.grid_view.model().map_or(0, |model| model.n_items());
+        let library_empty = imp.metadata_list.lock().unwrap().is_empty();
+        let scanning = !imp.refresh_button.is_sensitive();
+        let page = match (shown, library_empty) {
+            (0, true) if !scanning => "empty",
+            (0, false) => "no-results",
+            _ => "grid",
+        };
+        imp.grid_stack.set_visible_child_name(page);
+    }
+
+    /// Pick a folder to scan, from the empty library page
+    fn add_first_folder(&self) {
+        let dialog = gtk::FileDialog::builder().title("Add a Folder").modal(true).build();
+        dialog.select_folder(Some(self), None::<&gio::Cancellable>, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |result| {
+                let Ok(file) = result else { return; };
+                let Some(path) = file.path() else {
+                    _self.toast_error("Only local folders can be scanned", file.uri());
+                    return;
+                };
+                let path = path.canonicalize().unwrap_or(path);
+                let shared = _self.imp().config.get().unwrap();
+                let saved = {
+                    let mut config = shared.write().unwrap();
+                    if !config.scan_dirs.contains(&path) { config.scan_dirs.push(path); }
+                    config.save()
+                };
+                if let Err(e) = saved {
+                    _self.toast_error("Failed to save the config", format!("{:#}", e));
+                }
+                _self.rescan();
+            }
+        ));
     }
 
     fn setup(&self) {
@@ -195,98 +562,981 @@ impl ShelfWindow {
                 ));
             }
         });
+        self.connect_close_request(|_self| {
+            _self.shutdown();
+            glib::Propagation::Proceed
+        });
         let model = gio::ListStore::new::<models::PdfMetadataObject>();
+        self.setup_actions(model.clone());
+        self.setup_sidebar(model.clone());
         self.setup_grid_view(model.clone());
+        self.setup_cover_drop(model.clone());
         self.setup_buttons(model.clone());
         self.setup_search_entry(model.clone());
+        self.setup_accessibility(model.clone());
+        self.schedule_maintenance(model.clone());
+        self.watch_config(model.clone());
+        self.setup_library_watcher(model.clone());
+        self.build_status_bar();
+        self.set_status(StatusModule::Scan, "Ready");
+
+        imp.annotations_list.connect_row_activated(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                let imp = _self.imp();
+                let Some(page) = imp.annotations.borrow().get(row.index() as usize).map(|a| a.page) else { return; };
+                let Some(path) = imp.selected.lock().unwrap().as_ref().map(|selected| selected.path.clone()) else { return; };
+                if let Some(reader) = _self.open_in_reader(&path) {
+                    reader.go_to_page(page as i32);
+                }
+            }
+        ));
+        imp.pages_expander.connect_expanded_notify(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| {
+                let selected = _self.imp().selected.lock().unwrap().clone();
+                if let Some(metadata) = selected {
+                    _self.load_page_strip(&metadata);
+                }
+            }
+        ));
+        imp.editions_list.connect_row_activated(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                let Some(path) = _self.imp().edition_rows.borrow().get(row.index() as usize).cloned() else { return; };
+                _self.open_document(&path);
+            }
+        ));
+        imp.attachments_list.connect_row_activated(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                let Some(path) = _self.imp().attachments.borrow().get(row.index() as usize).cloned() else { return; };
+                let launcher = gtk::FileLauncher::new(Some(&gio::File::for_path(&path)));
+                launcher.launch(Some(&_self), None::<&gio::Cancellable>, glib::clone!(
+                    #[weak] _self,
+                    move |result| {
+                        if let Err(e) = result {
+                            _self.toast_error(&format!("Failed to open attachment {}", path), e);
+                        }
+                    }
+                ));
+            }
+        ));
+        imp.bookmarks_list.connect_row_activated(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_, row| {
+                let imp = _self.imp();
+                let Some(page) = imp.bookmarks.borrow().get(row.index() as usize).map(|b| b.page) else { return; };
+                let Some(path) = imp.selected.lock().unwrap().as_ref().map(|selected| selected.path.clone()) else { return; };
+                _self.open_document_at(&path, Some(page));
+            }
+        ));
+        self.load_cached_library(&model);
         imp.refresh_button.emit_clicked();
     }
     
+    fn build_status_bar(&self) {
+        let imp = self.imp();
+        let modules = imp.config.get().unwrap().read().unwrap().status_bar_modules.clone();
+        imp.status.build(&imp.status_bar, &modules);
+    }
+
+    pub(super) fn set_status(&self, module: StatusModule, text: &str) {
+        self.imp().status.set(module, text);
+    }
+
+    fn update_selection_status(&self) {
+        let imp = self.imp();
+        let Some(selection) = imp.grid_view.model().and_downcast::<MultiSelection>() else { return; };
+        let shown = selection.n_items();
+        let total = imp.metadata_list.lock().unwrap().len() as u32;
+        let selected = selection.selection();
+        let mut text = match selected.minimum() {
+            _ if shown == 0 => "No documents".to_string(),
+            gtk::INVALID_LIST_POSITION => format!("{} shown", shown),
+            _ if selected.size() > 1 => format!("{} selected of {}", selected.size(), shown),
+            position => format!("{} of {}", position + 1, shown),
+        };
+        if shown < total {
+            text.push_str(&format!(" ({} in library)", total));
+        }
+        self.set_status(StatusModule::Selection, &text);
+    }
+
+    pub(super) fn update_sync_status(&self) {
+        let Some(cache) = self.imp().cache.get() else { return; };
+        let text = match cache.pending_writes() {
+            0 => "All changes saved".to_string(),
+            pending => format!("{} waiting to be saved", pending),
+        };
+        self.set_status(StatusModule::Sync, &text);
+    }
+
+    fn setup_accessibility(&self, model: gio::ListStore) {
+        self.apply_accessibility();
+        let window = self.downgrade();
+        accessibility::connect_changed(move || {
+            let Some(window) = window.upgrade() else { return; };
+            window.apply_accessibility();
+            // Rebind so grid items pick up the title labels
+            let n_items = model.n_items();
+            model.items_changed(0, n_items, n_items);
+        });
+    }
+
+    fn apply_accessibility(&self) {
+        let imp = self.imp();
+        if prefers_high_contrast() {
+            self.add_css_class("high-contrast");
+        } else {
+            self.remove_css_class("high-contrast");
+        }
+        if let Some(scrolled_window) = imp.grid_view.parent().and_downcast::<gtk::ScrolledWindow>() {
+            scrolled_window.set_kinetic_scrolling(!prefers_reduced_motion());
+        }
+    }
+
     fn setup_search_entry(&self, model: gio::ListStore) {
         let imp = self.imp();
         imp.search_entry.connect_search_changed(glib::clone!(
             #[strong] model,
-            #[strong(rename_to = selected)] imp.selected,
-            #[strong(rename_to = metadata_list)] imp.metadata_list,
-            #[weak(rename_to = status_label)] imp.status_label,
             #[weak(rename_to = _self)] self,
-            move |entry| {
-                let query = entry.text();
-                
-                let pdf_files = match metadata_list.lock() {
-                    Ok(files) => files,
-                    Err(poisoned) => poisoned.into_inner()
-                };
-                
-                model.remove_all();
-                
-                if query.is_empty() {
-                    for item in pdf_files.iter() {
-                        model.append(&PdfMetadataObject::new(item.clone()));
+            move |_| {
+                _self.populate_model(&model);
+            }
+        ));
+        // Escape
+        imp.search_entry.connect_stop_search(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.close_search()
+        ));
+    }
+
+    /// Show the search entry and move the focus there, typing `text` after
+    /// the current query
+    fn focus_search(&self, text: &str) {
+        let search_entry = &self.imp().search_entry;
+        search_entry.set_visible(true);
+        if !text.is_empty() {
+            search_entry.set_text(&format!("{}{}", search_entry.text(), text));
+        }
+        search_entry.grab_focus();
+        search_entry.set_position(-1);
+    }
+
+    /// Clear and hide the search entry, the grid shows everything again and
+    /// gets the focus back
+    fn close_search(&self) {
+        let imp = self.imp();
+        if !imp.search_entry.text().is_empty() {
+            imp.search_entry.set_text("");
+        }
+        imp.search_entry.set_visible(false);
+        imp.grid_view.grab_focus();
+    }
+
+    /// Whether documents a scan found missing are in the grid, greyed out
+    pub(super) fn shows_missing(&self) -> bool {
+        self.action_state("show-missing").and_then(|state| state.get::<bool>()).unwrap_or(true)
+    }
+
+    /// Rebuild the grid from the scanned documents, applying the sidebar volume
+    /// and the search query
+    pub(super) fn populate_model(&self, model: &gio::ListStore) {
+        let imp = self.imp();
+        let query = imp.search_entry.text();
+        let show_missing = self.shows_missing();
+        let pdf_files = match imp.metadata_list.lock() {
+            Ok(files) => files,
+            Err(poisoned) => poisoned.into_inner()
+        };
+
+        let volumes = imp.volumes.borrow();
+        let volume_filter = imp.volume_filter.borrow();
+        let language_filter = imp.language_filter.borrow();
+        let author_filter = imp.author_filter.borrow();
+        let year_filter = imp.year_filter.get();
+        let collection_documents = imp.collection_filter.get().map(|id| imp.collection_tree.borrow().documents(id));
+        let visible: Vec<&PdfMetadata> = pdf_files.iter()
+            .filter(|pdf| match volume_filter.as_ref() {
+                None => true,
+                Some(root) => volumes::find_volume(&volumes, Path::new(&pdf.path))
+                    .is_some_and(|v| &v.root == root),
+            })
+            .filter(|pdf| collection_documents.as_ref().is_none_or(|documents| documents.contains(&pdf.hash)))
+            .filter(|pdf| language_filter.as_ref().is_none_or(|code| pdf.language.as_deref().unwrap_or("") == code))
+            .filter(|pdf| author_filter.as_ref().is_none_or(|author| pdf.author.as_deref()
+                .is_some_and(|other| other.trim().eq_ignore_ascii_case(author))))
+            .filter(|pdf| year_filter.is_none_or(|(from, to)| pdf.year().is_some_and(|year| (from..=to).contains(&year))))
+            .filter(|pdf| show_missing || !pdf.missing)
+            .collect();
+        // Editions are hidden among the documents left after filtering and
+        // searching, so a work whose preferred edition is filtered out still
+        // shows one of the others
+        let editions = imp.editions.borrow();
+
+        let sort_order = self.action_state("sort")
+            .and_then(|state| state.get::<String>())
+            .map_or(SortOrder::Path, |order| SortOrder::from_str(&order));
+        let results: Vec<&PdfMetadata> = if query.is_empty() && sort_order == SortOrder::MostUsed {
+            let open_counts = imp.cache.get()
+                .map(|cache| cache.open_counts().unwrap_or_else(|e| {
+                    warn!("Failed to load open counts: {}", e);
+                    HashMap::new()
+                }))
+                .unwrap_or_default();
+            let mut visible = editions.without_hidden(visible, |pdf| pdf.hash.as_str());
+            // Stable, so documents opened equally often stay in path order
+            visible.sort_by_key(|pdf| std::cmp::Reverse(open_counts.get(&pdf.hash).copied().unwrap_or(0)));
+            visible
+        } else if query.is_empty() {
+            editions.without_hidden(visible, |pdf| pdf.hash.as_str())
+        } else {
+            let matcher = SkimMatcherV2::default();
+            let custom_fields = imp.custom_fields.borrow();
+            // Scored on every core, the map is shared but not the RefCell
+            let custom_fields: &CustomFieldsByHash = &custom_fields;
+            let query_str = query.as_str();
+            let query_lower = query.to_lowercase();
+            let score = |pdf: &PdfMetadata| {
+                // Extract filename from path
+                let filename = std::path::Path::new(&pdf.path)
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("");
+
+                let searchable = format!(
+                    "{} {} {}",
+                    filename,
+                    pdf.title.as_deref().or(pdf.heuristic_title.as_deref()).unwrap_or(""),
+                    pdf.author.as_deref().unwrap_or("")
+                );
+
+                // Every abstract fuzzily matches most queries, words from
+                // it rank after the title and author, as do custom fields
+                matcher.fuzzy_match(&searchable, query_str).or_else(|| {
+                    let in_fields = custom_fields.get(&pdf.hash)
+                        .is_some_and(|fields| fields.iter().any(|(_, value)| value.to_lowercase().contains(&query_lower)));
+                    (in_fields || pdf.abstract_text.as_deref()
+                        .is_some_and(|text| text.to_lowercase().contains(&query_lower)))
+                        .then_some(0)
+                })
+            };
+
+            let low_memory = imp.config.get().unwrap().read().unwrap().low_memory_mode;
+            let scored: Vec<(&PdfMetadata, i64)> = if low_memory {
+                // Stream through the library keeping only the best matches,
+                // instead of scoring everything up front on every core
+                let mut best: Vec<(&PdfMetadata, i64)> = Vec::with_capacity(SEARCH_RESULT_LIMIT + 1);
+                for pdf in &visible {
+                    let Some(score) = score(pdf) else { continue; };
+                    let position = best.partition_point(|(_, other)| *other >= score);
+                    if position < SEARCH_RESULT_LIMIT {
+                        best.insert(position, (*pdf, score));
+                        best.truncate(SEARCH_RESULT_LIMIT);
                     }
-                    {
-                        let mut selected = selected.lock().unwrap();
-                        *selected = Some(pdf_files[0].clone());
-                        status_label.set_text(&pdf_files[0].path); 
-                        _self.update_preview_display(&pdf_files[0]);
+                }
+                best
+            } else {
+                visible
+                    .par_iter()
+                    .filter_map(|pdf| score(pdf).map(|score| (*pdf, score)))
+                    .collect()
+            };
+
+            let mut scored = editions.without_hidden(scored, |(pdf, _)| pdf.hash.as_str());
+            scored.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+            scored.truncate(SEARCH_RESULT_LIMIT);
+            scored.into_iter().map(|(pdf, _)| pdf).collect()
+        };
+
+        let last_pages = imp.cache.get()
+            .map(|cache| cache.all_last_pages().unwrap_or_else(|e| {
+                warn!("Failed to load reading progress: {}", e);
+                HashMap::new()
+            }))
+            .unwrap_or_default();
+
+        // Copies are detected across the whole library, not just the visible part
+        let mut paths_by_hash: HashMap<&str, Vec<&str>> = HashMap::new();
+        for pdf in pdf_files.iter() {
+            paths_by_hash.entry(pdf.hash.as_str()).or_default().push(pdf.path.as_str());
+        }
+
+        imp.cover_loader.get().unwrap().cancel_prefetch();
+        model.remove_all();
+        let objects: Vec<PdfMetadataObject> = results.iter()
+            .map(|metadata| {
+                let object = PdfMetadataObject::new((*metadata).clone());
+                let duplicates = paths_by_hash.get(metadata.hash.as_str())
+                    .map(|paths| paths.iter()
+                        .filter(|path| **path != metadata.path)
+                        .map(|path| path.to_string())
+                        .collect())
+                    .unwrap_or_default();
+                object.set_duplicates(duplicates);
+                object.set_last_page(last_pages.get(&metadata.hash).copied());
+                object
+            })
+            .collect();
+        model.extend_from_slice(&objects);
+
+        if let Some(first) = results.first() {
+            let mut selected = imp.selected.lock().unwrap();
+            *selected = Some((*first).clone());
+            self.set_status(StatusModule::Path, &first.path);
+            self.update_preview_display(first);
+        } else {
+            self.clear_preview();
+        }
+        drop(pdf_files);
+        drop(volumes);
+        drop(volume_filter);
+
+        self.update_selection_status();
+        self.update_empty_state();
+
+        if let Some(path) = imp.pending_selection.take() {
+            self.select_path(model, &path);
+        }
+    }
+
+    fn setup_actions(&self, model: gio::ListStore) {
+        let open_path = gio::ActionEntry::builder("open-path")
+            .parameter_type(Some(&String::static_variant_type()))
+            .activate(|window: &Self, _, param| {
+                if let Some(path) = param.and_then(|p| p.get::<String>()) {
+                    window.open_document(&path);
+                }
+            })
+            .build();
+
+        let read_path = gio::ActionEntry::builder("read-path")
+            .parameter_type(Some(&String::static_variant_type()))
+            .activate(|window: &Self, _, param| {
+                if let Some(path) = param.and_then(|p| p.get::<String>()) {
+                    window.open_in_reader(&path);
+                }
+            })
+            .build();
+
+        let select_path = gio::ActionEntry::builder("select-path")
+            .parameter_type(Some(&String::static_variant_type()))
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, param| {
+                    if let Some(path) = param.and_then(|p| p.get::<String>()) {
+                        window.select_path(&model, &path);
                     }
-                     
-                } else {
-                    let matcher = SkimMatcherV2::default();
-                    let query_str = query.as_str();
-                    
-                    let mut scored: Vec<(&PdfMetadata, i64)> = pdf_files
-                        .par_iter()
-                        .filter_map(|pdf| {
-                            // Extract filename from path
-                            let filename = std::path::Path::new(&pdf.path)
-                                .file_name()
-                                .and_then(|n| n.to_str())
-                                .unwrap_or("");
-                            
-                            let searchable = format!(
-                                "{} {} {}",
-                                filename, 
-                                pdf.title.as_deref().unwrap_or(""),
-                                pdf.author.as_deref().unwrap_or("")
-                            );
-                            
-                            matcher.fuzzy_match(&searchable, query_str)
-                                .map(|score| (pdf, score))
-                        })
-                        .collect();
-                    
-                    scored.sort_unstable_by(|a, b| b.1.cmp(&a.1));
-                    scored.truncate(10);
-                    {
-                        let mut selected = selected.lock().unwrap();
-                        *selected = Some(scored[0].0.clone());
-                        status_label.set_text(&scored[0].0.path); 
-                        _self.update_preview_display(&scored[0].0);
+                }
+            ))
+            .build();
+
+        let export_contact_sheet = gio::ActionEntry::builder("export-contact-sheet")
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, _| window.export_contact_sheet(&model)
+            ))
+            .build();
+
+        let year_review = gio::ActionEntry::builder("year-review")
+            .activate(|window: &Self, _, _| window.show_year_review())
+            .build();
+
+        let usage = gio::ActionEntry::builder("usage")
+            .activate(|window: &Self, _, _| window.show_usage())
+            .build();
+
+        // The buttons in the header bar do the same
+        let refresh = gio::ActionEntry::builder("refresh")
+            .activate(|window: &Self, _, _| {
+                if !window.rescan() { window.toast("A scan is already running"); }
+            })
+            .build();
+
+        let toggle_search = gio::ActionEntry::builder("toggle-search")
+            .activate(|window: &Self, _, _| window.imp().search_button.emit_clicked())
+            .build();
+
+        let settings = gio::ActionEntry::builder("settings")
+            .activate(|window: &Self, _, _| window.imp().settings_button.emit_clicked())
+            .build();
+
+        let toggle_preview = gio::ActionEntry::builder("toggle-preview")
+            .activate(|window: &Self, _, _| window.imp().preview_toggle_button.emit_clicked())
+            .build();
+
+        let open_selected = gio::ActionEntry::builder("open-selected")
+            .activate(|window: &Self, _, _| {
+                let path = window.imp().selected.lock().unwrap().as_ref().map(|selected| selected.path.clone());
+                if let Some(path) = path {
+                    window.open_document(&path);
+                }
+            })
+            .build();
+
+        let command_palette = gio::ActionEntry::builder("command-palette")
+            .activate(|window: &Self, _, _| window.show_command_palette())
+            .build();
+
+        let show_shortcuts = gio::ActionEntry::builder("show-shortcuts")
+            .activate(|window: &Self, _, _| window.show_shortcuts())
+            .build();
+
+        let sort = gio::ActionEntry::builder("sort")
+            .parameter_type(Some(&String::static_variant_type()))
+            .state("path".to_variant())
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, action, param| {
+                    let Some(order) = param.and_then(|p| p.get::<String>()) else { return; };
+                    action.set_state(&order.to_variant());
+                    window.populate_model(&model);
+                }
+            ))
+            .build();
+
+        let show_missing = gio::ActionEntry::builder("show-missing")
+            .state(true.to_variant())
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, action, _| {
+                    let show = !action.state().and_then(|state| state.get::<bool>()).unwrap_or(true);
+                    action.set_state(&show.to_variant());
+                    window.refresh_author_list();
+                    window.refresh_year_list();
+                    window.populate_model(&model);
+                }
+            ))
+            .build();
+
+        let open_file = gio::ActionEntry::builder("open-file")
+            .parameter_type(Some(&String::static_variant_type()))
+            .activate(glib::clone!(
+                #[strong] model,
+                move |window: &Self, _, param| {
+                    if let Some(path) = param.and_then(|p| p.get::<String>()) {
+                        window.open_file(&model, PathBuf::from(path));
                     }
-                    for (metadata, _) in scored {
-                        model.append(&PdfMetadataObject::new(metadata.clone()));
+                }
+            ))
+            .build();
+
+        self.add_action_entries([
+            refresh, toggle_search, settings, toggle_preview, open_selected,
+            open_path, read_path, select_path, open_file, export_contact_sheet, year_review, usage, command_palette, show_shortcuts, sort,
+            show_missing,
+        ]);
+        self.setup_preview_actions();
+        self.setup_metadata_actions(&model);
+        self.setup_covers_actions(&model);
+        self.setup_workspace_actions();
+        self.setup_collections_actions(&model);
+        self.setup_editions_actions(&model);
+        self.setup_scan_actions(&model);
+        self.load_shortcuts();
+    }
+
+    /// Count an open of the document at `path` towards its usage
+    fn record_open(&self, path: &str) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return; };
+        let hash = imp.metadata_list.lock().unwrap().iter()
+            .find(|pdf| pdf.path == path)
+            .map(|pdf| pdf.hash.clone());
+        if let Some(hash) = hash && let Err(e) = cache.record_open(&hash) {
+            warn!("Failed to record open of {}: {}", path, e);
+        }
+    }
+
+    /// Select `path` if it is currently shown in the grid
+    pub(super) fn scroll_to_path(&self, model: &gio::ListStore, path: &str) -> bool {
+        let position = (0..model.n_items()).find(|&i| {
+            model.item(i)
+                .and_downcast::<PdfMetadataObject>()
+                .and_then(|obj| obj.metadata())
+                .is_some_and(|metadata| metadata.path == path)
+        });
+
+        let Some(position) = position else { return false; };
+        self.imp().grid_view.scroll_to(position, gtk::ListScrollFlags::SELECT | gtk::ListScrollFlags::FOCUS, None);
+        true
+    }
+
+    /// h/j/k/l, gg, G and o in the grid, when `vim_navigation` is on
+    fn vim_key(&self, key: gtk::gdk::Key, modifiers: gtk::gdk::ModifierType) -> glib::Propagation {
+        use gtk::gdk::Key;
+        let imp = self.imp();
+        if !imp.config.get().unwrap().read().unwrap().vim_navigation
+            || !(modifiers - gtk::gdk::ModifierType::SHIFT_MASK).is_empty() {
+            return glib::Propagation::Proceed;
+        }
+        let Some(selection) = imp.grid_view.model().and_downcast::<MultiSelection>() else {
+            return glib::Propagation::Proceed;
+        };
+        let n_items = selection.n_items();
+        if n_items == 0 { return glib::Propagation::Proceed; }
+        let current = selection.selection().minimum().min(n_items - 1);
+        let pending_g = imp.pending_g.replace(false);
+
+        let position = match key {
+            Key::h => current.saturating_sub(1),
+            Key::l => (current + 1).min(n_items - 1),
+            Key::k => current.saturating_sub(self.grid_columns()),
+            Key::j => (current + self.grid_columns()).min(n_items - 1),
+            Key::g if pending_g => 0,
+            Key::g => {
+                imp.pending_g.set(true);
+                return glib::Propagation::Stop;
+            }
+            Key::G => n_items - 1,
+            Key::o => {
+                imp.grid_view.emit_by_name::<()>("activate", &[&current]);
+                return glib::Propagation::Stop;
+            }
+            _ => return glib::Propagation::Proceed,
+        };
+        imp.grid_view.scroll_to(position, gtk::ListScrollFlags::SELECT | gtk::ListScrollFlags::FOCUS, None);
+        glib::Propagation::Stop
+    }
+
+    /// Columns the grid currently shows, going by the width of an item
+    fn grid_columns(&self) -> u32 {
+        let grid_view = &self.imp().grid_view;
+        let item_width = grid_view.first_child().map_or(0, |child| child.width());
+        if item_width <= 0 { return 1; }
+        (grid_view.width() / item_width).clamp(1, grid_view.max_columns() as i32) as u32
+    }
+
+    /// Select the document with `hash` if it is currently shown in the grid
+    pub(super) fn scroll_to_hash(&self, model: &gio::ListStore, hash: &str) -> bool {
+        let position = (0..model.n_items()).find(|&i| {
+            model.item(i)
+                .and_downcast::<PdfMetadataObject>()
+                .and_then(|obj| obj.metadata())
+                .is_some_and(|metadata| metadata.hash == hash)
+        });
+        let Some(position) = position else { return false; };
+        self.imp().grid_view.scroll_to(position, gtk::ListScrollFlags::SELECT | gtk::ListScrollFlags::FOCUS, None);
+        true
+    }
+
+    /// Select and scroll to the document at `path`, clearing the search and
+    /// sidebar filters first if they hide it
+    pub(super) fn select_path(&self, model: &gio::ListStore, path: &str) {
+        let imp = self.imp();
+        if self.scroll_to_path(model, path) { return; }
+
+        let filtered = imp.volume_filter.borrow().is_some() || imp.collection_filter.get().is_some()
+            || imp.language_filter.borrow().is_some() || imp.author_filter.borrow().is_some()
+            || imp.year_filter.get().is_some() || !imp.search_entry.text().is_empty();
+        if !filtered { return; }
+
+        // The model is rebuilt asynchronously once the search entry settles
+        imp.pending_selection.replace(Some(path.to_string()));
+        *imp.volume_filter.borrow_mut() = None;
+        if imp.collection_filter.take().is_some() {
+            imp.collection_list.unselect_all();
+            self.update_collection_actions();
+        }
+        imp.volume_list.select_row(imp.volume_list.row_at_index(0).as_ref());
+        if imp.language_filter.take().is_some() {
+            imp.language_list.select_row(imp.language_list.row_at_index(0).as_ref());
+        }
+        if imp.author_filter.take().is_some() {
+            imp.author_list.select_row(imp.author_list.row_at_index(0).as_ref());
+        }
+        if imp.year_filter.take().is_some() {
+            imp.decade_list.select_row(imp.decade_list.row_at_index(0).as_ref());
+            self.update_year_button();
+        }
+        if imp.search_entry.text().is_empty() {
+            self.populate_model(model);
+        } else {
+            imp.search_entry.set_text("");
+        }
+    }
+
+    fn toggle_quick_look(&self) {
+        let imp = self.imp();
+        if let Some(quick_look) = imp.quick_look.upgrade() {
+            quick_look.close();
+            return;
+        }
+        let Some(metadata) = imp.selected.lock().unwrap().clone() else { return; };
+        let cover = metadata.cover_path.as_deref().and_then(|name| {
+            imp.cover_loader.get().unwrap().cached(name)
+                .or_else(|| cover_loader::load_full_cover(name))
+        });
+        let quick_look = ShelfQuickLookWindow::new(&metadata, cover.as_ref());
+        quick_look.set_transient_for(Some(self));
+        quick_look.present();
+        imp.quick_look.set(Some(&quick_look));
+    }
+
+    fn open_in_reader(&self, path: &str) -> Option<ShelfReaderWindow> {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get().cloned() else {
+            warn!("Failed to open {} in the reader: cache unavailable", path);
+            return None;
+        };
+        let hash = imp.metadata_list.lock().unwrap().iter()
+            .find(|pdf| pdf.path == path)
+            .map(|pdf| pdf.hash.clone());
+
+        match ShelfReaderWindow::new(Path::new(path), cache, hash.clone()) {
+            Ok(reader) => {
+                self.record_open(path);
+                reader.set_application(self.application().as_ref());
+                reader.connect_close_request(glib::clone!(
+                    #[weak(rename_to = _self)] self,
+                    #[upgrade_or] glib::Propagation::Proceed,
+                    move |_| {
+                        if let Some(hash) = hash.as_deref() {
+                            _self.refresh_last_page(hash);
+                            // Bookmarks may have been added in the reader
+                            if _self.is_selected(hash) {
+                                _self.load_bookmarks(hash);
+                            }
+                        }
+                        glib::Propagation::Proceed
                     }
+                ));
+                reader.present();
+                Some(reader)
+            }
+            Err(e) => {
+                self.toast_error(&format!("Failed to open {} in the reader", path), e);
+                None
+            }
+        }
+    }
+
+    /// Update the "Continue reading" badge of every grid item showing `hash`
+    fn refresh_last_page(&self, hash: &str) {
+        let imp = self.imp();
+        let Some(cache) = imp.cache.get() else { return; };
+        let Some(model) = imp.grid_view.model()
+            .and_downcast::<MultiSelection>()
+            .and_then(|selection| selection.model()) else { return; };
+        let last_page = cache.get_last_page(hash).unwrap_or_default();
+
+        for position in 0..model.n_items() {
+            let Some(object) = model.item(position).and_downcast::<PdfMetadataObject>() else { continue; };
+            if object.metadata().is_some_and(|metadata| metadata.hash == hash) {
+                object.set_last_page(last_page);
+                model.items_changed(position, 1, 1);
+            }
+        }
+    }
+
+    pub(super) fn open_document(&self, path: &str) {
+        self.open_document_at(path, None);
+    }
+
+    /// Open `path` in the configured viewer, at `page` (zero-based) if given.
+    /// External viewers get the page through `%p` in the command, e.g.
+    /// `evince --page-index=%p %`, zathura through `--page` when the command
+    /// doesn't say.
+    pub(super) fn open_document_at(&self, path: &str, page: Option<u32>) {
+        let imp = self.imp();
+        let hash = imp.metadata_list.lock().unwrap().iter()
+            .find(|metadata| metadata.path == path)
+            .map(|metadata| metadata.hash.clone());
+        if hash.is_some() { imp.last_document.replace(hash); }
+        let config_reader = imp.config.get().unwrap().read().unwrap();
+        if config_reader.use_internal_reader {
+            drop(config_reader);
+            if let Some(reader) = self.open_in_reader(path) && let Some(page) = page {
+                reader.go_to_page(page as i32);
+            }
+            return;
+        }
+
+        let template = config_reader.pdf_viewer_command.clone();
+        drop(config_reader);
+
+        let spawned = viewer_command(&template, path, page).and_then(|command| {
+            let child = Command::new(&command[0])
+                .args(&command[1..])
+                .spawn()
+                .with_context(|| format!("Could not run {}", command[0]))?;
+            Ok((command[0].clone(), child))
+        });
+        match spawned {
+            Ok((program, mut child)) => {
+                debug!("Opened {} with {}", path, program);
+                self.record_open(path);
+                // Reap the viewer once it exits
+                std::thread::spawn(move || { let _ = child.wait(); });
+            }
+            // Only what's inside the sandbox can be run, the portal can
+            // still hand the file to the default viewer outside it
+            Err(e) if portal::is_sandboxed() => {
+                warn!("Failed to open {}: {:#}, asking the portal instead", path, e);
+                self.record_open(path);
+                gtk::FileLauncher::new(Some(&gio::File::for_path(path))).launch(
+                    Some(self), None::<&gio::Cancellable>, |result| {
+                        if let Err(e) = result { warn!("Failed to open the document: {}", e); }
+                    }
+                );
+            }
+            Err(e) => {
+                warn!("Failed to open {}: {:#}", path, e);
+                self.show_error("Could not open the document", &format!(
+                    "{:#}\n\nCheck pdf_viewer_command in the settings, it is currently:\n{}",
+                    e, template
+                ));
+            }
+        }
+    }
+
+    /// A short message over the window, instead of only in the log
+    pub(super) fn toast(&self, title: &str) {
+        let toast = adw::Toast::builder().title(title).use_markup(false).build();
+        self.imp().toast_overlay.add_toast(toast);
+    }
+
+    /// An action that failed: the details go to the log, the gist on screen
+    /// with a button for the rest
+    pub(super) fn toast_error(&self, message: &str, error: impl std::fmt::Display) {
+        warn!("{}: {}", message, error);
+        let toast = adw::Toast::builder()
+            .title(message)
+            .use_markup(false)
+            .button_label("Details")
+            .build();
+        let (message, detail) = (message.to_string(), error.to_string());
+        toast.connect_button_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.show_error(&message, &detail)
+        ));
+        self.imp().toast_overlay.add_toast(toast);
+    }
+
+    /// A dialog for something that went wrong, which can copy the end of
+    /// the log for a bug report
+    pub(super) fn show_error(&self, message: &str, detail: &str) {
+        let dialog = gtk::AlertDialog::builder()
+            .message(message)
+            .detail(detail)
+            .buttons(["Close", "Copy Debug Log"])
+            .cancel_button(0)
+            .default_button(0)
+            .build();
+        dialog.choose(Some(self), None::<&gio::Cancellable>, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |response| {
+                if response != Ok(1) { return; }
+                match logging::recent_log() {
+                    Ok(log) => {
+                        _self.clipboard().set_text(&log);
+                        _self.toast("Copied the debug log");
+                    }
+                    Err(e) => _self.toast(&format!("Failed to read the log: {:#}", e)),
                 }
             }
         ));
     }
 
+    /// A toast with an Undo button for something just done
+    pub(super) fn toast_undo(&self, title: &str, undo: impl Fn() + 'static) {
+        let toast = adw::Toast::builder()
+            .title(title)
+            .use_markup(false)
+            .button_label("Undo")
+            .priority(adw::ToastPriority::High)
+            .build();
+        toast.connect_button_clicked(move |_| undo());
+        self.imp().toast_overlay.add_toast(toast);
+    }
+
+    fn apply_config(&self, model: &gio::ListStore) {
+        theme::apply(self.imp().config.get().unwrap().read().unwrap().theme);
+        self.apply_cover_encoding();
+        // Rebind every grid item so cover options apply immediately
+        let n_items = model.n_items();
+        model.items_changed(0, n_items, n_items);
+        self.refresh_volume_list();
+        self.build_status_bar();
+        self.load_shortcuts();
+        self.watch_library();
+        self.fill_in_missing_metadata(model);
+        self.fetch_online_covers(model);
+    }
+
+    /// Load the config again after it changed outside Shelf, e.g. in a text
+    /// editor or with `gsettings set`
+    fn reload_config(&self, model: &gio::ListStore) {
+        let config = match Config::load() {
+            Ok(config) => config,
+            Err(e) => {
+                // Most likely saved halfway through an edit, the next save fixes it
+                self.toast_error("config.toml has an error, see the log", format!("{:#}", e));
+                return;
+            }
+        };
+        let shared = self.imp().config.get().unwrap();
+        let old = shared.read().unwrap().clone();
+        if toml::to_string(&old).ok() == toml::to_string(&config).ok() { return; }
+
+        info!("Config changed, reloaded");
+        let rescan = old.scan_dirs != config.scan_dirs || old.extra_files != config.extra_files;
+        if old.low_memory_mode != config.low_memory_mode {
+            info!("low_memory_mode applies after a restart");
+        }
+        *shared.write().unwrap() = config;
+        self.apply_config(model);
+        if rescan { self.rescan(); }
+    }
+
+    /// Pick up preferences changed outside Shelf while it runs
+    fn watch_config(&self, model: gio::ListStore) {
+        let imp = self.imp();
+        if imp.config.get().unwrap().read().unwrap().backend == Backend::Gsettings {
+            let watch = gsettings::watch(glib::clone!(
+                #[weak(rename_to = _self)] self,
+                #[strong] model,
+                move || _self.reload_config(&model)
+            ));
+            imp.settings_watch.replace(watch);
+            if imp.settings_watch.borrow().is_some() { return; }
+        }
+
+        let Ok(path) = Config::config_path() else { return; };
+        let monitor = match gio::File::for_path(&path).monitor_file(gio::FileMonitorFlags::WATCH_MOVES, None::<&gio::Cancellable>) {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                warn!("Failed to watch {}: {}", path.display(), e);
+                return;
+            }
+        };
+        // Editors write in several steps, wait for them to settle
+        let pending = Rc::new(Cell::new(false));
+        monitor.connect_changed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_, _, _, event| {
+                if matches!(event, gio::FileMonitorEvent::Deleted | gio::FileMonitorEvent::AttributeChanged) { return; }
+                if pending.replace(true) { return; }
+                glib::timeout_add_local_once(CONFIG_RELOAD_DELAY, glib::clone!(
+                    #[weak] _self,
+                    #[strong] pending,
+                    #[strong] model,
+                    move || {
+                        pending.set(false);
+                        _self.reload_config(&model);
+                    }
+                ));
+            }
+        ));
+        imp.config_monitor.replace(Some(monitor));
+    }
+
     fn setup_buttons(&self, model: gio::ListStore) {
         let imp = self.imp();
+        imp.add_folder_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.add_first_folder()
+        ));
+        // A scan that found nothing only counts once it's over
+        imp.refresh_button.connect_sensitive_notify(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.update_empty_state()
+        ));
+
         imp.search_button.connect_clicked(glib::clone!(
-            #[weak(rename_to = search_entry)] imp.search_entry,
+            #[weak(rename_to = _self)] self,
             move |_| {
-                search_entry.set_visible(!search_entry.is_visible());
-                search_entry.grab_focus();
+                if _self.imp().search_entry.is_visible() { _self.close_search(); } else { _self.focus_search(""); }
             }
         ));
 
         let config = imp.config.get().unwrap();
         imp.settings_button.connect_clicked(glib::clone!(
             #[strong] config,
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
             move |_| {
                 let dialog = ShelfSettingsWindow::new(config.clone());
+                dialog.connect_local("config-changed", false, glib::clone!(
+                    #[weak] model,
+                    #[weak] _self,
+                    #[upgrade_or] None,
+                    move |_| {
+                        _self.apply_config(&model);
+                        None
+                    }
+                ));
+                dialog.connect_local("rescan-folder", false, glib::clone!(
+                    #[weak] model,
+                    #[weak] _self,
+                    #[upgrade_or] None,
+                    move |args| {
+                        let folder: String = args[1].get().ok()?;
+                        if !_self.rescan_folder(&model, PathBuf::from(folder)) {
+                            _self.toast("A scan is already running");
+                        }
+                        None
+                    }
+                ));
+                dialog.connect_local("run-maintenance", false, glib::clone!(
+                    #[weak] model,
+                    #[weak] _self,
+                    #[upgrade_or] None,
+                    move |_| {
+                        if !_self.run_maintenance(&model) {
+                            _self.toast("Maintenance waits until the scan is done");
+                        }
+                        None
+                    }
+                ));
+                dialog.connect_local("remove-unused-covers", false, glib::clone!(
+                    #[weak] model,
+                    #[weak] _self,
+                    #[upgrade_or] None,
+                    move |_| {
+                        if !_self.remove_unused_covers(&model) {
+                            _self.toast("Unused covers can be removed once the scan is done");
+                        }
+                        None
+                    }
+                ));
+                dialog.connect_local("regenerate-covers", false, glib::clone!(
+                    #[weak] model,
+                    #[weak] _self,
+                    #[upgrade_or] None,
+                    move |_| {
+                        if !_self.regenerate_covers(&model) {
+                            _self.toast("Covers can be regenerated once the scan is done");
+                        }
+                        None
+                    }
+                ));
+                dialog.set_missing_count(_self.missing_count());
+                dialog.connect_local("prune-missing", false, glib::clone!(
+                    #[weak] model,
+                    #[weak] _self,
+                    #[upgrade_or] None,
+                    move |args| {
+                        let dialog: ShelfSettingsWindow = args[0].get().ok()?;
+                        if !_self.prune_missing(&model, &dialog) {
+                            _self.toast("Missing documents can be forgotten once the scan is done");
+                        }
+                        None
+                    }
+                ));
+                _self.show_quarantined(&dialog);
+                dialog.connect_local("clear-quarantine", false, glib::clone!(
+                    #[weak] _self,
+                    #[upgrade_or] None,
+                    move |args| {
+                        let dialog: ShelfSettingsWindow = args[0].get().ok()?;
+                        let path: String = args[1].get().ok()?;
+                        _self.clear_quarantine(&path);
+                        _self.show_quarantined(&dialog);
+                        None
+                    }
+                ));
                 dialog.present();
             }
         ));
@@ -299,165 +1549,48 @@ impl ShelfWindow {
             }
         ));
 
+        imp.cancel_scan_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.cancel_scan()
+        ));
+
         imp.refresh_button.connect_clicked(glib::clone!(
             #[strong] model,
-            #[strong] config,
-            #[strong(rename_to = metadata_list)] imp.metadata_list,
-            #[strong(rename_to = selected)] imp.selected,
-            #[weak(rename_to = refresh_button)] imp.refresh_button,
-            #[weak(rename_to = search_button)] imp.search_button,
-            #[weak(rename_to = search_entry)] imp.search_entry,
-            #[weak(rename_to = status_label)] imp.status_label,
             #[weak(rename_to = _self)] self,
-            move |_| {
-                // Disable button during scan
-                refresh_button.set_sensitive(false);
-                search_button.set_sensitive(false);
-                search_entry.set_sensitive(false);
-                status_label.set_text("Scanning...");
-                
-                // Clear previous results
-                model.remove_all();
-                search_entry.set_text("");
-                let (tx, rx) = async_channel::unbounded::<ScanProgress>();
-                std::thread::spawn(glib::clone!(
-                    #[strong] config,
-                    move || {
-                        let start_time = Instant::now(); 
-                        let cache = match PdfCache::new() {
-                            Ok(c) => Arc::new(c),
-                            Err(e) => {
-                                let _ = tx.send_blocking(ScanProgress::Error(
-                                    PathBuf::from("cache"),
-                                    format!("Failed to initialize cache: {}", e)
-                                ));
-                                return;
-                            }
-                        };
-                        let mut pdf_paths: Vec<PathBuf> = Vec::new();
-                        for dir in &config.read().unwrap().scan_dirs {
-                             pdf_paths.extend(scan_pdfs_rayon(dir, tx.clone()));
-                        } 
-                        pdf_paths.sort_unstable(); 
-                                        
-                        // Process PDFs in parallel
-                        // Replace the parallel processing section with:
-                        let metadata_list_new: Vec<PdfMetadata> = pdf_paths.par_iter().filter_map(|path| {
-                            let _ = tx.send_blocking(ScanProgress::Processing(path.clone()));
-                            let cache = cache.clone();
-
-                            match extract_pdf_metadata(path, &cache, &tx) {
-                                Ok(metadata) => Some(metadata),
-                                Err(e) => {
-                                    let _ = tx.send_blocking(ScanProgress::Error(
-                                        path.clone(),
-                                        format!("Extraction failed: {}", e),
-                                    ));
-                                    None
-                                }
-                            }
-                        })
-                        .collect();
-
-                        let duration = start_time.elapsed();
-                        let _ = tx.send_blocking(ScanProgress::Complete(metadata_list_new, duration));
-                    }
-                ));
-
-                gtk::glib::spawn_future_local(glib::clone!(
-                    #[strong] model,
-                    #[strong] selected,
-                    #[strong] metadata_list,
-                    #[weak] _self,
-                    async move {
-                        use std::cell::Cell;
-                        let count = Cell::new(0);
-                        
-                        while let Ok(msg) = rx.recv().await {
-                            match msg {
-                                ScanProgress::Found(_path) => {
-                                    count.set(count.get() + 1);
-                                    status_label.set_text(&format!("Found {} PDFs...", count.get())); 
-                                }
-                                ScanProgress::Processing(path) => {
-                                    status_label.set_text(&format!("Processing: {}...", path.display()));
-                                }
-                                ScanProgress::Extracted(_hash, metadata) => {
-                                    status_label.set_text(&format!("Extracted: {}...", 
-                                        metadata.title.as_deref().unwrap_or("Untitled")));
-                                }
-                                ScanProgress::DuplicateDetected(original, duplicate) => {
-                                    println!("Duplicate detected: {} is duplicate of {}", 
-                                        duplicate.display(), original.display());
-                                }
-                                ScanProgress::Error(path, error) => {
-                                    eprintln!("Error processing {}: {}", path.display(), error);
-                                }
-                                ScanProgress::Complete(metadata_list_new, duration) => {
-                                    for item in &metadata_list_new {
-                                        model.append(&PdfMetadataObject::new(item.to_owned()));
-                                    }
-                                    status_label.set_text(&format!(
-                                        "Complete! Found {} PDF files in {:.2?}",
-                                        metadata_list_new.len(),
-                                        duration
-                                    ));
-                                    // Store all PDFs for searching
-                                    {
-                                        let mut selected = selected.lock().unwrap();
-                                        let mut files = metadata_list.lock().unwrap();
-                                        *files = metadata_list_new;
-                                        *selected = Some(files[0].clone());
-                                        _self.update_preview_display(&files[0]); 
-                                    }
-          
-                                    refresh_button.set_sensitive(true);
-                                    search_button.set_sensitive(true);
-                                    search_entry.set_sensitive(true);
-                                    search_entry.grab_focus();
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                ));
-            }
+            move |_| _self.start_scan(&model, None)
         ));
     }
 
     fn setup_grid_view(&self, model: gio::ListStore) {
         let imp = self.imp();
-        let selection_model = SingleSelection::new(Some(model.clone()));
-        selection_model.set_selected(0);
+        let selection_model = MultiSelection::new(Some(model.clone()));
+        selection_model.select_item(0, true);
         let factory = SignalListItemFactory::new();
 
         selection_model.connect_selection_changed(glib::clone!(
             #[strong(rename_to = selected)] imp.selected,
             #[weak(rename_to = _self)] self,
-            // #[weak(rename_to = preview_title)] imp.preview_title,
-            // #[weak(rename_to = preview_filename)] imp.preview_filename,
-            // #[weak(rename_to = preview_filepath)] imp.preview_filepath,
-            // #[weak(rename_to = preview_author)] imp.preview_author,
-            // #[weak(rename_to = preview_subject)] imp.preview_subject,
-            // #[weak(rename_to = preview_keywords)] imp.preview_keywords,
-            // #[weak(rename_to = preview_pages)] imp.preview_pages,
-            // #[weak(rename_to = preview_filesize)] imp.preview_filesize,
             move |sel_model, _, _| {
-                let item = sel_model.selected_item().unwrap();
-                let metadata_object = item.downcast_ref::<PdfMetadataObject>().unwrap();
-                {
-                    let mut selected = selected.lock().unwrap();
-                    *selected = metadata_object.metadata();
-                    let metadata = selected.as_ref().unwrap().to_owned();
-
-                    _self.update_preview_display(&metadata);
+                // Nothing left to select, e.g. a search without results.
+                // Of several selected the first one is previewed.
+                let metadata = sel_model.item(sel_model.selection().minimum())
+                    .and_downcast::<PdfMetadataObject>()
+                    .and_then(|object| object.metadata());
+                *selected.lock().unwrap() = metadata.clone();
+                match metadata {
+                    Some(metadata) => {
+                        _self.imp().last_document.replace(Some(metadata.hash.clone()));
+                        _self.update_preview_display(&metadata);
+                    }
+                    None => _self.clear_preview(),
                 }
+                _self.update_selection_status();
             }
         ));
 
         factory.connect_setup(glib::clone!(
             #[strong(rename_to = selected)] imp.selected,
-            #[weak(rename_to = status_label)] imp.status_label,
+            #[weak(rename_to = _self)] self,
             move |_, item| {
                 let grid_item = ShelfGridItem::new();
                 let list_item = item.downcast_ref::<gtk::ListItem>().unwrap();
@@ -468,13 +1601,13 @@ impl ShelfWindow {
                 
                 let list_item_weak = list_item.downgrade();
                 motion_controller.connect_enter(glib::clone!(
-                    #[weak] status_label,
+                    #[weak] _self,
                     move |_, _, _| {
                         if let Some(list_item) = list_item_weak.upgrade() {
                             if let Some(obj) = list_item.item() {
                                 if let Some(pdf_obj) = obj.downcast_ref::<PdfMetadataObject>() {
                                     if let Some(metadata) = pdf_obj.metadata() {
-                                        status_label.set_text(&metadata.path);
+                                        _self.set_status(StatusModule::Path, &metadata.path);
                                     }
                                 }
                             }
@@ -484,25 +1617,70 @@ impl ShelfWindow {
     
                 motion_controller.connect_leave(glib::clone!(
                     #[strong] selected,
-                    #[weak] status_label,
+                    #[weak] _self,
                     move |_| {
                         let selected = selected.lock().unwrap();
                         if let Some(metadata) = selected.as_ref() {
-                            status_label.set_text(&metadata.path);
+                            _self.set_status(StatusModule::Path, &metadata.path);
                         }
                     }
                 ));
                 
                 grid_item.add_controller(motion_controller);
+
+                // Drag onto the workspace pane to add the document to it
+                let drag_source = gtk::DragSource::new();
+                drag_source.set_actions(gtk::gdk::DragAction::COPY);
+                let list_item_weak = list_item.downgrade();
+                drag_source.connect_prepare(move |_, _, _| {
+                    let path = list_item_weak.upgrade()?
+                        .item()
+                        .and_downcast::<PdfMetadataObject>()?
+                        .metadata()?
+                        .path;
+                    Some(gtk::gdk::ContentProvider::for_value(&path.to_value()))
+                });
+                grid_item.add_controller(drag_source);
             }
         ));
         
-        factory.connect_bind(move |_, item| {
-            let item = item.downcast_ref::<gtk::ListItem>().unwrap();
-            let pdf_metadata_object = item.item().and_downcast::<PdfMetadataObject>().unwrap();
-            let grid_item = item.child().and_downcast::<ShelfGridItem>().unwrap();
-            grid_item.bind(&pdf_metadata_object);
-        });
+        let config = imp.config.get().unwrap();
+        let cover_loader = imp.cover_loader.get().unwrap();
+        factory.connect_bind(glib::clone!(
+            #[strong] config,
+            #[strong] cover_loader,
+            #[strong] model,
+            #[weak(rename_to = _self)] self,
+            move |_, item| {
+                let item = item.downcast_ref::<gtk::ListItem>().unwrap();
+                let pdf_metadata_object = item.item().and_downcast::<PdfMetadataObject>().unwrap();
+                let grid_item = item.child().and_downcast::<ShelfGridItem>().unwrap();
+                grid_item.bind(&pdf_metadata_object, &config.read().unwrap(), &cover_loader);
+                _self.prefetch_covers(&model, item.position());
+            }
+        ));
+        factory.connect_unbind(glib::clone!(
+            #[strong] cover_loader,
+            move |_, item| {
+                let item = item.downcast_ref::<gtk::ListItem>().unwrap();
+                if let Some(grid_item) = item.child().and_downcast::<ShelfGridItem>() {
+                    grid_item.unbind(&cover_loader);
+                }
+            }
+        ));
+
+        if let Some(adjustment) = imp.grid_view.vadjustment() {
+            adjustment.connect_value_changed(glib::clone!(
+                #[weak(rename_to = imp)] self.imp(),
+                move |adjustment| {
+                    let value = adjustment.value();
+                    if value != imp.scroll_value.get() {
+                        imp.scrolling_up.set(value < imp.scroll_value.get());
+                        imp.scroll_value.set(value);
+                    }
+                }
+            ));
+        }
 
         imp.grid_view.set_model(Some(&selection_model));
         imp.grid_view.set_factory(Some(&factory));
@@ -510,40 +1688,42 @@ impl ShelfWindow {
         imp.grid_view.set_max_columns(6);
         imp.grid_view.set_single_click_activate(false);
 
-        let config = imp.config.get().unwrap();
+        // Capture phase, the grid view would otherwise take Space to activate the item
+        let key_controller = gtk::EventControllerKey::new();
+        key_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+        key_controller.connect_key_pressed(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[upgrade_or] glib::Propagation::Proceed,
+            move |_, key, _, modifiers| {
+                if key == gtk::gdk::Key::space && modifiers.is_empty() {
+                    _self.toggle_quick_look();
+                    return glib::Propagation::Stop;
+                }
+                if _self.vim_key(key, modifiers) == glib::Propagation::Stop {
+                    return glib::Propagation::Stop;
+                }
+                // Typing anywhere in the grid starts a search
+                let shortcut_modifiers = gtk::gdk::ModifierType::CONTROL_MASK | gtk::gdk::ModifierType::ALT_MASK
+                    | gtk::gdk::ModifierType::SUPER_MASK;
+                match key.to_unicode() {
+                    Some(c) if !c.is_control() && !modifiers.intersects(shortcut_modifiers) => {
+                        _self.focus_search(&c.to_string());
+                        glib::Propagation::Stop
+                    }
+                    _ => glib::Propagation::Proceed,
+                }
+            }
+        ));
+        imp.grid_view.add_controller(key_controller);
+
         imp.grid_view.connect_activate(glib::clone!(
             #[strong] model,
-            #[strong] config,
+            #[weak(rename_to = _self)] self,
             move |_, position| {
-                let item = model.item(position).unwrap();
-                let metadata_object = item.downcast_ref::<PdfMetadataObject>().unwrap(); 
-                if let Some(metadata) = metadata_object.metadata() {
-                    let path = metadata.path.clone();
-                    // Spawn Zathura in a separate process
-                    let config_reader = config.read().unwrap();
-                    let mut cmd = config_reader.pdf_viewer_command.clone();
-                    if !cmd.contains("%") { cmd.push_str(" %"); }
-
-                    std::thread::spawn(glib::clone!(
-                        #[strong] cmd,
-                        move || {
-                            let mut parts: Vec<String> = cmd.split_whitespace().map(|s| s.to_string()).collect();
-                            if let Some((program, args)) = parts.split_first_mut() {
-                                for arg in args.into_iter() {
-                                    if arg == "%" {
-                                        *arg = path.clone();
-                                    }
-                                }
-                                match Command::new(program)
-                                    .args(args)
-                                    .spawn() {
-                                    Ok(_) => println!("Opened {} with Zathura", path),
-                                    Err(e) => eprintln!("Failed to open {}: {}", path, e),
-                                }
-                            }
-                        }
-                    ));
-                } 
+                let Some(object) = model.item(position).and_downcast::<PdfMetadataObject>() else { return; };
+                if let Some(metadata) = object.metadata() {
+                    _self.open_document(&metadata.path);
+                }
             }
         ));
     }
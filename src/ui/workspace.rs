@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::glib;
+use gtk::gio;
+use tracing::warn;
+
+use crate::citations::{send_to_zotero, CitationFormat};
+use crate::pdf::{AttachmentsByHash, PdfMetadata};
+use crate::ui::status_bar::StatusModule;
+use crate::ui::window::ShelfWindow;
+
+impl ShelfWindow {
+    pub(super) fn setup_workspace_actions(&self) {
+        let add_to_workspace = gio::ActionEntry::builder("add-to-workspace")
+            .activate(|window: &Self, _, _| {
+                let path = window.imp().selected.lock().unwrap().as_ref().map(|selected| selected.path.clone());
+                if let Some(path) = path {
+                    window.add_to_workspace(&path);
+                }
+            })
+            .build();
+
+        let clear_workspace = gio::ActionEntry::builder("clear-workspace")
+            .activate(|window: &Self, _, _| {
+                window.imp().workspace.borrow_mut().clear();
+                window.refresh_workspace_list();
+            })
+            .build();
+
+        let export_workspace = gio::ActionEntry::builder("export-workspace")
+            .parameter_type(Some(&String::static_variant_type()))
+            .activate(|window: &Self, _, param| {
+                let format = param.and_then(|p| p.get::<String>()).and_then(|name| CitationFormat::from_str(&name));
+                if let Some(format) = format {
+                    window.export_workspace(format);
+                }
+            })
+            .build();
+
+        let send_workspace_to_zotero = gio::ActionEntry::builder("send-to-zotero")
+            .activate(|window: &Self, _, _| window.send_workspace_to_zotero())
+            .build();
+
+        self.add_action_entries([add_to_workspace, clear_workspace, export_workspace, send_workspace_to_zotero]);
+    }
+
+    pub(super) fn add_to_workspace(&self, path: &str) {
+        {
+            let mut workspace = self.imp().workspace.borrow_mut();
+            if workspace.iter().any(|other| other == path) { return; }
+            workspace.push(path.to_string());
+        }
+        self.refresh_workspace_list();
+    }
+
+    fn remove_from_workspace(&self, path: &str) {
+        let imp = self.imp();
+        let Some(position) = imp.workspace.borrow().iter().position(|other| other == path) else { return; };
+        imp.workspace.borrow_mut().remove(position);
+        self.refresh_workspace_list();
+        self.toast_undo("Removed from the workspace", glib::clone!(
+            #[weak(rename_to = _self)] self,
+            #[to_owned] path,
+            move || {
+                let mut workspace = _self.imp().workspace.borrow_mut();
+                if workspace.contains(&path) { return; }
+                let position = position.min(workspace.len());
+                workspace.insert(position, path.clone());
+                drop(workspace);
+                _self.refresh_workspace_list();
+            }
+        ));
+    }
+
+    pub(super) fn refresh_workspace_list(&self) {
+        let imp = self.imp();
+        let workspace = imp.workspace.borrow();
+        let titles: HashMap<String, String> = imp.metadata_list.lock().unwrap().iter()
+            .filter(|pdf| workspace.contains(&pdf.path))
+            .map(|pdf| (pdf.path.clone(), pdf.display_title()))
+            .collect();
+
+        imp.workspace_list.remove_all();
+        for path in workspace.iter() {
+            let title = titles.get(path).cloned().unwrap_or_else(|| {
+                Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| path.clone())
+            });
+            let hbox = gtk::Box::builder().spacing(8).build();
+            let label = gtk::Label::builder()
+                .label(title)
+                .tooltip_text(path)
+                .xalign(0.0)
+                .hexpand(true)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .build();
+            let remove_button = gtk::Button::builder()
+                .icon_name("list-remove-symbolic")
+                .tooltip_text("Remove from workspace")
+                .build();
+            remove_button.add_css_class("flat");
+            remove_button.connect_clicked(glib::clone!(
+                #[weak(rename_to = _self)] self,
+                #[strong] path,
+                move |_| _self.remove_from_workspace(&path)
+            ));
+            hbox.append(&label);
+            hbox.append(&remove_button);
+            imp.workspace_list.append(&hbox);
+        }
+        imp.workspace_heading.set_text(&match workspace.len() {
+            0 => "Workspace".to_string(),
+            count => format!("Workspace ({})", count),
+        });
+        for name in ["clear-workspace", "export-workspace", "send-to-zotero"] {
+            if let Some(action) = self.lookup_action(name).and_downcast::<gio::SimpleAction>() {
+                action.set_enabled(!workspace.is_empty());
+            }
+        }
+    }
+
+    /// Metadata of the workspace documents that are still in the library
+    pub(super) fn workspace_documents(&self) -> Vec<PdfMetadata> {
+        let imp = self.imp();
+        let files = imp.metadata_list.lock().unwrap();
+        imp.workspace.borrow().iter()
+            .filter_map(|path| files.iter().find(|pdf| &pdf.path == path).cloned())
+            .collect()
+    }
+
+    fn all_attachments(&self) -> AttachmentsByHash {
+        self.imp().cache.get()
+            .and_then(|cache| cache.all_attachments().inspect_err(|e| warn!("Failed to load attachments: {}", e)).ok())
+            .unwrap_or_default()
+    }
+
+    fn export_workspace(&self, format: CitationFormat) {
+        let documents = self.workspace_documents();
+        if documents.is_empty() { return; }
+        let attachments = self.all_attachments();
+        let dialog = gtk::FileDialog::builder()
+            .title("Export Workspace")
+            .initial_name(format!("workspace.{}", format.extension()))
+            .build();
+        dialog.save(Some(self), None::<&gio::Cancellable>, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |result| {
+                let Ok(file) = result else { return; };
+                let Some(path) = file.path() else { return; };
+                match std::fs::write(&path, format.format(&documents, &attachments)) {
+                    Ok(()) => _self.toast(&format!("Exported {} documents to {}", documents.len(), path.display())),
+                    Err(e) => _self.toast_error("Failed to export workspace", e),
+                }
+            }
+        ));
+    }
+
+    fn send_workspace_to_zotero(&self) {
+        let documents = self.workspace_documents();
+        if documents.is_empty() { return; }
+        let attachments = self.all_attachments();
+        self.set_status(StatusModule::Sync, "Sending to Zotero...");
+        let (tx, rx) = async_channel::bounded(1);
+        std::thread::spawn(move || {
+            let sent = send_to_zotero(&documents, &attachments).map(|()| documents.len());
+            let _ = tx.send_blocking(sent.map_err(|e| e.to_string()));
+        });
+        glib::spawn_future_local(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            async move {
+                let Ok(sent) = rx.recv().await else { return; };
+                _self.set_status(StatusModule::Sync, "");
+                match sent {
+                    Ok(count) => _self.toast(&format!("Sent {} documents to Zotero", count)),
+                    Err(e) => _self.toast_error("Failed to send the workspace to Zotero", e),
+                }
+            }
+        ));
+    }
+}
@@ -0,0 +1,258 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use gtk::glib::subclass::types::ObjectSubclassIsExt;
+use gtk::prelude::*;
+use gtk::{gdk, gio, glib, graphene};
+use tracing::{info, warn};
+
+use crate::pdf::{PdfCache, PdfMetadata};
+use crate::review::{current_year, year_review, YearReview};
+
+mod imp {
+    use std::cell::{Cell, OnceCell, RefCell};
+    use std::sync::Arc;
+
+    use gtk::glib;
+    use gtk::glib::subclass::types::ObjectSubclass;
+    use gtk::subclass::prelude::*;
+
+    use crate::pdf::{PdfCache, PdfMetadata};
+    use crate::review::YearReview;
+
+    #[derive(Default, gtk::CompositeTemplate)]
+    #[template(resource = "/org/galib/shelf/ui/year_review_window.xml")]
+    pub struct ShelfYearReviewWindow {
+        #[template_child]
+        pub prev_year_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub next_year_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub export_html_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub export_image_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub report_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub year_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub stats_grid: TemplateChild<gtk::Grid>,
+        #[template_child]
+        pub authors_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub tags_box: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub finished_box: TemplateChild<gtk::Box>,
+
+        pub cache: OnceCell<Arc<PdfCache>>,
+        pub library: RefCell<Vec<PdfMetadata>>,
+        pub year: Cell<i32>,
+        pub review: RefCell<Option<YearReview>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for ShelfYearReviewWindow {
+        const NAME: &'static str = "ShelfYearReviewWindow";
+        type Type = super::ShelfYearReviewWindow;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.bind_template();
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for ShelfYearReviewWindow {
+        fn constructed(&self) {
+            self.parent_constructed();
+        }
+    }
+
+    impl WidgetImpl for ShelfYearReviewWindow {}
+    impl WindowImpl for ShelfYearReviewWindow {}
+}
+
+glib::wrapper! {
+    pub struct ShelfYearReviewWindow(ObjectSubclass<imp::ShelfYearReviewWindow>)
+        @extends gtk::Widget, gtk::Window,
+        @implements gio::ActionGroup, gio::ActionMap,
+                    gtk::Accessible, gtk::Buildable,
+                    gtk::ConstraintTarget, gtk::Native,
+                    gtk::Root, gtk::ShortcutManager;
+}
+
+fn clear(container: &impl IsA<gtk::Widget>) {
+    while let Some(child) = container.first_child() {
+        child.unparent();
+    }
+}
+
+fn ranked_row(rank: usize, name: &str, count: usize) -> gtk::Box {
+    let row = gtk::Box::builder().spacing(8).build();
+    let rank_label = gtk::Label::new(Some(&format!("{}.", rank)));
+    rank_label.add_css_class("dim-label");
+    let name_label = gtk::Label::builder()
+        .label(name)
+        .xalign(0.0)
+        .hexpand(true)
+        .ellipsize(gtk::pango::EllipsizeMode::End)
+        .build();
+    let count_label = gtk::Label::new(Some(&count.to_string()));
+    count_label.add_css_class("dim-label");
+    row.append(&rank_label);
+    row.append(&name_label);
+    row.append(&count_label);
+    row
+}
+
+fn empty_label() -> gtk::Label {
+    let label = gtk::Label::builder().label("Nothing yet").xalign(0.0).build();
+    label.add_css_class("dim-label");
+    label
+}
+
+impl ShelfYearReviewWindow {
+    pub fn new(cache: Arc<PdfCache>, library: Vec<PdfMetadata>) -> Self {
+        let obj: ShelfYearReviewWindow = glib::Object::builder().build();
+        let imp = obj.imp();
+        imp.cache.set(cache).ok();
+        imp.library.replace(library);
+        obj.setup();
+        obj.show_year(current_year());
+        obj
+    }
+
+    fn setup(&self) {
+        let imp = self.imp();
+        imp.prev_year_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.show_year(_self.imp().year.get() - 1)
+        ));
+        imp.next_year_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.show_year(_self.imp().year.get() + 1)
+        ));
+        imp.export_html_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.export(false)
+        ));
+        imp.export_image_button.connect_clicked(glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |_| _self.export(true)
+        ));
+    }
+
+    fn show_year(&self, year: i32) {
+        let imp = self.imp();
+        imp.year.set(year);
+        imp.next_year_button.set_sensitive(year < current_year());
+        imp.year_label.set_text(&format!("{} in Review", year));
+
+        let review = match year_review(imp.cache.get().unwrap(), &imp.library.borrow(), year) {
+            Ok(review) => review,
+            Err(e) => {
+                warn!("Failed to build the {} review: {}", year, e);
+                YearReview { year, ..Default::default() }
+            }
+        };
+
+        clear(&*imp.stats_grid);
+        let stats = [
+            (review.added, "added"),
+            (review.opened, "opened"),
+            (review.finished.len(), "finished"),
+            (review.pages_read, "pages read"),
+        ];
+        for (column, (value, caption)) in stats.iter().enumerate() {
+            let value_label = gtk::Label::new(Some(&value.to_string()));
+            value_label.add_css_class("title-2");
+            let caption_label = gtk::Label::new(Some(caption));
+            caption_label.add_css_class("dim-label");
+            imp.stats_grid.attach(&value_label, column as i32, 0, 1, 1);
+            imp.stats_grid.attach(&caption_label, column as i32, 1, 1, 1);
+        }
+
+        for (container, items) in [(&imp.authors_box, &review.top_authors), (&imp.tags_box, &review.top_tags)] {
+            clear(&**container);
+            if items.is_empty() {
+                container.append(&empty_label());
+            }
+            for (rank, (name, count)) in items.iter().enumerate() {
+                container.append(&ranked_row(rank + 1, name, *count));
+            }
+        }
+
+        clear(&*imp.finished_box);
+        if review.finished.is_empty() {
+            imp.finished_box.append(&empty_label());
+        }
+        for title in &review.finished {
+            let label = gtk::Label::builder()
+                .label(title)
+                .xalign(0.0)
+                .ellipsize(gtk::pango::EllipsizeMode::End)
+                .build();
+            imp.finished_box.append(&label);
+        }
+
+        imp.review.replace(Some(review));
+    }
+
+    /// Render the report the way it is currently laid out on screen
+    fn render_report(&self) -> Result<gdk::Texture> {
+        let report_box = &*self.imp().report_box;
+        let width = report_box.width() as f32;
+        let height = report_box.height() as f32;
+
+        // Pick a background that keeps the theme's text color legible
+        let foreground = report_box.color();
+        let luminance = 0.299 * foreground.red() + 0.587 * foreground.green() + 0.114 * foreground.blue();
+        let background = if luminance > 0.5 {
+            gdk::RGBA::new(0.14, 0.14, 0.14, 1.0)
+        } else {
+            gdk::RGBA::new(0.98, 0.98, 0.97, 1.0)
+        };
+
+        let snapshot = gtk::Snapshot::new();
+        let bounds = graphene::Rect::new(0.0, 0.0, width, height);
+        snapshot.append_color(&background, &bounds);
+        gtk::WidgetPaintable::new(Some(report_box)).snapshot(&snapshot, width as f64, height as f64);
+
+        let node = snapshot.to_node().context("Nothing to render")?;
+        let renderer = self.renderer().context("Window has no renderer")?;
+        Ok(renderer.render_texture(&node, Some(&bounds)))
+    }
+
+    fn export(&self, as_image: bool) {
+        let year = self.imp().year.get();
+        let (title, name) = if as_image {
+            ("Export Review as Image", format!("shelf-{}.png", year))
+        } else {
+            ("Export Review as HTML", format!("shelf-{}.html", year))
+        };
+        let dialog = gtk::FileDialog::builder()
+            .title(title)
+            .initial_name(name)
+            .build();
+        dialog.save(Some(self), None::<&gio::Cancellable>, glib::clone!(
+            #[weak(rename_to = _self)] self,
+            move |result| {
+                let Ok(file) = result else { return; };
+                let Some(path) = file.path() else { return; };
+                let saved = if as_image {
+                    _self.render_report().and_then(|texture| texture.save_to_png(&path).map_err(Into::into))
+                } else {
+                    let html = _self.imp().review.borrow().as_ref().map(YearReview::to_html).unwrap_or_default();
+                    std::fs::write(&path, html).map_err(Into::into)
+                };
+                match saved {
+                    Ok(()) => info!("Exported review to {}", path.display()),
+                    Err(e) => warn!("Failed to export review: {}", e),
+                }
+            }
+        ));
+    }
+}
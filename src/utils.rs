@@ -1,42 +1,118 @@
 #![allow(dead_code)]
 
 use std::{
+    collections::HashSet,
+    ffi::OsStr,
     fs::{read_dir, File}, 
-    io::{Read, Seek, SeekFrom}, path::{Path, PathBuf}
+    io::{Read, Seek, SeekFrom}, path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, Mutex}
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use blake3::Hasher;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use tracing::warn;
 
-use crate::pdf::ScanProgress;
+use crate::pdf::ScanReporter;
+use crate::shelfignore::IgnoreRules;
 
-// https://docs.rs/globmatch/latest/src/globmatch/utils.rs.html#133-145
-pub fn is_hidden_path<P>(path: P) -> bool
-where
-    P: AsRef<Path>,
-{
-    let has_hidden = path.as_ref().components().find(|c| {
-        c.as_os_str()
-            .to_str()
-            .map(|s| s.starts_with('.'))
-            .unwrap_or(false)
-    });
+/// Where a scan has been, by canonical path, so a folder linked to from
+/// inside itself isn't scanned for ever and a file reachable through
+/// several links or overlapping scan directories is found once
+#[derive(Default)]
+pub struct Visited {
+    folders: Mutex<HashSet<PathBuf>>,
+    files: Mutex<HashSet<PathBuf>>,
+}
+
+impl Visited {
+    fn first_visit(set: &Mutex<HashSet<PathBuf>>, canonical: PathBuf) -> bool {
+        set.lock().unwrap().insert(canonical)
+    }
+}
 
-    has_hidden.is_some()
+/// What a scan leaves out on its way through the scan directories
+#[derive(Debug, Clone, Copy)]
+pub struct WalkOptions {
+    pub follow_symlinks: bool,
+    // Files and folders whose names start with a dot
+    pub skip_hidden: bool,
 }
 
-pub fn scan_pdfs_rayon(dir: &PathBuf, tx: async_channel::Sender<ScanProgress>) -> Vec<PathBuf> {
+impl WalkOptions {
+    pub fn skips_name(&self, name: &OsStr) -> bool {
+        self.skip_hidden && name.to_str().is_some_and(|name| name.starts_with('.'))
+    }
+}
+
+/// Every PDF under `root` not left out by `options` or its `.shelfignore`,
+/// giving up on whatever is left once `cancelled` is set
+pub fn scan_pdfs_rayon(
+    root: &PathBuf,
+    options: WalkOptions,
+    visited: &Visited,
+    reporter: &ScanReporter,
+    cancelled: &AtomicBool,
+) -> Vec<PathBuf> {
+    let ignore = IgnoreRules::load(root);
+    walk(root, options, ignore.as_ref(), visited, reporter, cancelled)
+}
+
+fn walk(
+    dir: &PathBuf,
+    options: WalkOptions,
+    ignore: Option<&IgnoreRules>,
+    visited: &Visited,
+    reporter: &ScanReporter,
+    cancelled: &AtomicBool,
+) -> Vec<PathBuf> {
     let mut pdfs = Vec::new();
     let mut subdirs = Vec::new();
-    let entries = read_dir(&dir).unwrap();
+    if cancelled.load(Ordering::Relaxed) { return pdfs; }
+    let canonical_dir = match dir.canonicalize() {
+        Ok(canonical_dir) => canonical_dir,
+        Err(e) => {
+            warn!("Failed to read {}: {}", dir.display(), e);
+            return pdfs;
+        }
+    };
+    if !Visited::first_visit(&visited.folders, canonical_dir.clone()) { return pdfs; }
+    // A folder can go away mid scan, or the portal can stop exporting it
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to read {}: {}", dir.display(), e);
+            return pdfs;
+        }
+    };
 
     for entry in entries.flatten() {
+        // Huge folders on slow drives take a while on their own
+        if cancelled.load(Ordering::Relaxed) { return pdfs; }
+        if options.skips_name(&entry.file_name()) { continue; }
         let path = entry.path();
-        if is_hidden_path(&path) { continue; }
-        if path.is_file() && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf")) {
-            pdfs.push(path.clone());
-            let _ = tx.send_blocking(ScanProgress::Found(path));
-        } else if path.is_dir() {
+        let Ok(file_type) = entry.file_type() else { continue; };
+        let symlink = file_type.is_symlink();
+        if symlink && !options.follow_symlinks { continue; }
+        // Where a link points, broken ones are left out
+        let (is_file, is_dir) = if symlink {
+            let Ok(target) = std::fs::metadata(&path) else { continue; };
+            (target.is_file(), target.is_dir())
+        } else {
+            (file_type.is_file(), file_type.is_dir())
+        };
+        if ignore.is_some_and(|ignore| ignore.is_ignored(&path, is_dir)) { continue; }
+
+        if is_file && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf")) {
+            let canonical = if symlink {
+                let Ok(canonical) = path.canonicalize() else { continue; };
+                canonical
+            } else {
+                canonical_dir.join(entry.file_name())
+            };
+            if !Visited::first_visit(&visited.files, canonical) { continue; }
+            pdfs.push(path);
+            reporter.found();
+        } else if is_dir {
             subdirs.push(path);
         }
     }
@@ -44,13 +120,22 @@ pub fn scan_pdfs_rayon(dir: &PathBuf, tx: async_channel::Sender<ScanProgress>) -
     // Process subdirectories recursively in parallel
     let sub_pdfs: Vec<PathBuf> = subdirs
         .par_iter()
-        .flat_map(|subdir| scan_pdfs_rayon(subdir, tx.clone()))
+        .flat_map(|subdir| walk(subdir, options, ignore, visited, reporter, cancelled))
         .collect();
 
     pdfs.extend(sub_pdfs);
     pdfs
 }
 
+/// Size and modification time of a file, in seconds since the epoch
+pub fn file_stat(path: &Path) -> Result<(u64, i64)> {
+    let metadata = std::fs::metadata(path)?;
+    let mtime = metadata.modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |mtime| mtime.as_secs() as i64);
+    Ok((metadata.len(), mtime))
+}
+
 pub fn compute_partial_hash(path: &Path) -> Result<(String, u64)> {
     let mut file = File::open(path)?;
     let file_size = file.metadata()?.len();
@@ -91,7 +176,7 @@ pub fn compute_full_hash(path: &Path) -> Result<String> {
     Ok(hasher.finalize().to_hex().to_string())
 }
 
-fn human_readable_file_size(bytes: u64) -> String {
+pub fn human_readable_file_size(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
     const GB: u64 = MB * 1024;
@@ -106,3 +191,169 @@ fn human_readable_file_size(bytes: u64) -> String {
         format!("{} B", bytes)
     }
 }
+
+/// Roughly how long something will take, e.g. `45s` or `3 min`
+pub fn human_readable_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{}s", secs.max(1))
+    } else if secs < 3600 {
+        format!("{} min", secs.div_ceil(60))
+    } else {
+        format!("{}h {} min", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+/// Seconds since the epoch, as stored in the cache
+pub fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64)
+}
+
+fn expand_placeholders(arg: &str, path: &str, page: Option<u32>) -> (String, bool) {
+    let mut expanded = String::with_capacity(arg.len());
+    let mut has_path = false;
+    let mut chars = arg.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('%') => { chars.next(); expanded.push('%'); }
+            Some('p') => {
+                chars.next();
+                if let Some(page) = page { expanded.push_str(&(page + 1).to_string()); }
+            }
+            _ => { expanded.push_str(path); has_path = true; }
+        }
+    }
+    (expanded, has_path)
+}
+
+/// Build the command line for an external viewer from a template like
+/// `okular --page %p "%"`. It is split the way a shell would split it, `%`
+/// becomes the file, `%p` the one-based page and `%%` a literal percent sign.
+/// The file is appended when the template has no `%`.
+pub fn viewer_command(template: &str, path: &str, page: Option<u32>) -> Result<Vec<String>> {
+    let Some(mut parts) = shlex::split(template) else {
+        bail!("Unbalanced quotes in viewer command: {}", template);
+    };
+    if parts.is_empty() { bail!("No viewer command configured"); }
+
+    let asks_for_page = |arg: &String| arg.replace("%%", "").contains("%p");
+    match page {
+        // Without a page to go to, drop the argument asking for one, and
+        // the flag it's the value of, e.g. the -P of "-P %p"
+        None => {
+            let is_flag = |arg: &String| arg.starts_with('-') && !arg.contains(['=', '%']);
+            let mut kept = vec![parts.remove(0)];
+            for arg in parts {
+                if !asks_for_page(&arg) {
+                    kept.push(arg);
+                    continue;
+                }
+                if arg == "%p" && kept.len() > 1 && kept.last().is_some_and(is_flag) {
+                    kept.pop();
+                }
+            }
+            parts = kept;
+        }
+        Some(page) if !parts.iter().any(asks_for_page)
+            && Path::new(&parts[0]).file_name().is_some_and(|name| name == "zathura") => {
+            parts.insert(1, format!("--page={}", page + 1));
+        }
+        Some(_) => {}
+    }
+
+    let mut has_path = false;
+    let mut command = vec![parts[0].clone()];
+    for arg in &parts[1..] {
+        let (arg, with_path) = expand_placeholders(arg, path, page);
+        has_path |= with_path;
+        command.push(arg);
+    }
+    if !has_path { command.push(path.to_string()); }
+    Ok(command)
+}
+
+/// The executable `program` runs, looked up on the PATH unless it's a path
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    let is_executable = |path: &Path| path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0);
+    if program.contains('/') {
+        let path = PathBuf::from(shellexpand::tilde(program).into_owned());
+        return is_executable(&path).then_some(path);
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+/// Why `template` can't open anything, None if it looks usable
+pub fn check_viewer_command(template: &str) -> Option<String> {
+    let command = match viewer_command(template, "", None) {
+        Ok(command) => command,
+        Err(e) => return Some(e.to_string()),
+    };
+    if find_program(&command[0]).is_none() {
+        return Some(format!("{} isn't installed, or isn't on the PATH", command[0]));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::viewer_command;
+
+    fn command(template: &str, page: Option<u32>) -> Vec<String> {
+        viewer_command(template, "/books/a b.pdf", page).unwrap()
+    }
+
+    #[test]
+    fn page_and_path_placeholders() {
+        assert_eq!(command("evince --page-index=%p %", Some(4)), ["evince", "--page-index=5", "/books/a b.pdf"]);
+    }
+
+    #[test]
+    fn page_argument_dropped_without_a_page() {
+        assert_eq!(command("evince --page-index=%p %", None), ["evince", "/books/a b.pdf"]);
+        assert_eq!(command("okular --page %p --unique %", None), ["okular", "--unique", "/books/a b.pdf"]);
+    }
+
+    #[test]
+    fn double_percent_is_a_literal_percent() {
+        assert_eq!(command("viewer --zoom=100%% %", None), ["viewer", "--zoom=100%", "/books/a b.pdf"]);
+        // Not a %p either
+        assert_eq!(command("viewer --label=%%p %", None), ["viewer", "--label=%p", "/books/a b.pdf"]);
+    }
+
+    #[test]
+    fn quoted_program() {
+        assert_eq!(command("\"my viewer\" %", Some(0)), ["my viewer", "/books/a b.pdf"]);
+    }
+
+    #[test]
+    fn path_appended_without_placeholder() {
+        assert_eq!(command("okular", None), ["okular", "/books/a b.pdf"]);
+        assert_eq!(command("okular", Some(2)), ["okular", "/books/a b.pdf"]);
+    }
+
+    #[test]
+    fn zathura_page_fallback() {
+        assert_eq!(command("zathura %", Some(9)), ["zathura", "--page=10", "/books/a b.pdf"]);
+        assert_eq!(command("zathura %", None), ["zathura", "/books/a b.pdf"]);
+        assert_eq!(command("/usr/bin/zathura", Some(0)), ["/usr/bin/zathura", "--page=1", "/books/a b.pdf"]);
+        // Its own page argument wins
+        assert_eq!(command("zathura -P %p %", Some(1)), ["zathura", "-P", "2", "/books/a b.pdf"]);
+        // Its flag goes too without a page
+        assert_eq!(command("zathura -P %p %", None), ["zathura", "/books/a b.pdf"]);
+    }
+
+    #[test]
+    fn unbalanced_quotes_fail() {
+        assert!(viewer_command("\"my viewer %", "/books/a.pdf", None).is_err());
+        assert!(viewer_command("", "/books/a.pdf", None).is_err());
+    }
+}
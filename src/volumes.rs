@@ -0,0 +1,157 @@
+#![allow(dead_code)]
+
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+
+use crate::portal;
+
+const NETWORK_FS_TYPES: &[&str] = &[
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "9p", "afs", "ceph",
+    "fuse.sshfs", "fuse.rclone", "fuse.davfs2", "davfs", "fuse.gvfsd-fuse",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeKind {
+    Internal,
+    Removable,
+    Network,
+}
+
+impl VolumeKind {
+    pub fn icon_name(&self) -> &'static str {
+        match self {
+            VolumeKind::Internal => "drive-harddisk-symbolic",
+            VolumeKind::Removable => "drive-removable-media-symbolic",
+            VolumeKind::Network => "network-server-symbolic",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MountEntry {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Volume {
+    pub name: String,
+    pub root: PathBuf,
+    pub kind: VolumeKind,
+    pub online: bool,
+}
+
+pub fn read_mounts() -> Vec<MountEntry> {
+    let contents = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    contents.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let mount_point = unescape_mount_field(fields.next()?);
+            let fs_type = fields.next()?.to_string();
+            Some(MountEntry { mount_point, fs_type })
+        })
+        .collect()
+}
+
+// The kernel escapes spaces, tabs and backslashes in mount points as octal (e.g. `\040`)
+fn unescape_mount_field(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4)
+            .filter(|digits| digits.iter().all(|d| (b'0'..=b'7').contains(d)));
+        if bytes[i] == b'\\' && let Some(digits) = octal {
+            out.push(digits.iter().fold(0u8, |acc, d| acc.wrapping_mul(8).wrapping_add(d - b'0')));
+            i += 4;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    PathBuf::from(OsString::from_vec(out))
+}
+
+/// Find the mount that contains `path` (the longest matching mount point wins,
+/// later entries shadow earlier ones on the same mount point)
+pub fn mount_for<'a>(mounts: &'a [MountEntry], path: &Path) -> Option<&'a MountEntry> {
+    mounts.iter()
+        .filter(|m| path.starts_with(&m.mount_point))
+        .max_by_key(|m| m.mount_point.components().count())
+}
+
+/// Whether `path` is on NFS, SMB, sshfs and the like
+pub fn is_network_path(mounts: &[MountEntry], path: &Path) -> bool {
+    !portal::is_portal_path(path) && mount_for(mounts, path).is_some_and(|mount| kind_for_mount(mount) == VolumeKind::Network)
+}
+
+fn is_removable_path(path: &Path) -> bool {
+    path.starts_with("/media") || path.starts_with("/run/media")
+}
+
+fn kind_for_mount(mount: &MountEntry) -> VolumeKind {
+    if NETWORK_FS_TYPES.contains(&mount.fs_type.as_str()) {
+        VolumeKind::Network
+    } else if is_removable_path(&mount.mount_point) {
+        VolumeKind::Removable
+    } else {
+        VolumeKind::Internal
+    }
+}
+
+fn volume_name(root: &Path) -> String {
+    if root == Path::new("/") { return "Computer".to_string(); }
+    root.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| root.display().to_string())
+}
+
+/// Volumes backing the configured scan directories, one entry per distinct mount.
+/// Scan directories that can't be reached are reported as offline volumes of their own.
+pub fn library_volumes(scan_dirs: &[PathBuf]) -> Vec<Volume> {
+    let mounts = read_mounts();
+    let mut volumes: Vec<Volume> = Vec::new();
+
+    for dir in scan_dirs {
+        let volume = match mount_for(&mounts, dir) {
+            // Every folder exported by the document portal is on the same
+            // mount in a sandbox, so each one is a volume of its own
+            Some(_) if portal::is_portal_path(dir) => {
+                let host = portal::host_path(dir);
+                Volume {
+                    name: volume_name(&host),
+                    root: dir.clone(),
+                    kind: if is_removable_path(&host) { VolumeKind::Removable } else { VolumeKind::Internal },
+                    online: dir.exists(),
+                }
+            }
+            Some(mount) if dir.exists() => Volume {
+                name: volume_name(&mount.mount_point),
+                root: mount.mount_point.clone(),
+                kind: kind_for_mount(mount),
+                online: true,
+            },
+            _ => Volume {
+                name: volume_name(dir),
+                root: dir.clone(),
+                kind: if is_removable_path(dir) { VolumeKind::Removable } else { VolumeKind::Internal },
+                online: false,
+            },
+        };
+        if !volumes.iter().any(|v| v.root == volume.root) {
+            volumes.push(volume);
+        }
+    }
+
+    volumes
+}
+
+/// The volume a document belongs to, by longest root prefix
+pub fn find_volume<'a>(volumes: &'a [Volume], path: &Path) -> Option<&'a Volume> {
+    volumes.iter()
+        .filter(|v| path.starts_with(&v.root))
+        .max_by_key(|v| v.root.components().count())
+}
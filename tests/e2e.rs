@@ -0,0 +1,298 @@
+//! Runs the real app on a headless display against a throwaway library and
+//! drives it over its D-Bus interface: scan, search, open and rescan.
+//!
+//! Needs `dbus-daemon` and either `weston` (Wayland) or `Xvfb` (X11) on the
+//! PATH, so the tests are ignored by default. Run them with
+//! `cargo test -- --ignored`, they fail when the programs are missing.
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use gtk::gio;
+use gtk::glib;
+use gtk::prelude::*;
+
+const BUS_NAME: &str = "org.galib.shelf";
+const OBJECT_PATH: &str = "/org/galib/shelf";
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A minimal single page PDF with a title and author in its info dictionary
+fn fixture_pdf(title: &str, author: &str) -> Vec<u8> {
+    let content = format!("BT /F1 18 Tf 20 100 Td ({}) Tj ET", title);
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Contents 4 0 R /Resources << /Font << /F1 5 0 R >> >> >>".to_string(),
+        format!("<< /Length {} >>\nstream\n{}\nendstream", content.len(), content),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        format!("<< /Title ({}) /Author ({}) >>", title, author),
+    ];
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).into_bytes());
+    }
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+    for offset in offsets {
+        pdf.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+    }
+    pdf.extend(format!(
+        "trailer\n<< /Size {} /Root 1 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1, objects.len(), xref
+    ).into_bytes());
+    pdf
+}
+
+fn find_program(name: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+fn wait_until(what: &str, mut done: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !done() {
+        if start.elapsed() > TIMEOUT { panic!("Timed out waiting for {}", what); }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// Spawn a program that prints something once it's ready and return the line
+fn spawn_reporting(command: &mut Command) -> std::io::Result<(Child, String)> {
+    let mut child = command.stdout(Stdio::piped()).stderr(Stdio::null()).spawn()?;
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut line)?;
+    Ok((child, line.trim().to_string()))
+}
+
+/// A running app with its own home, session bus and display, torn down on drop
+struct Session {
+    root: PathBuf,
+    library: PathBuf,
+    opened: PathBuf,
+    children: Vec<Child>,
+    bus: gio::DBusConnection,
+}
+
+impl Session {
+    fn start(name: &str, titles: &[(&str, &str)]) -> Self {
+        let Some(dbus_daemon) = find_program("dbus-daemon") else {
+            panic!("Can't run {}: dbus-daemon isn't installed", name);
+        };
+        let (weston, xvfb) = (find_program("weston"), find_program("Xvfb"));
+        if weston.is_none() && xvfb.is_none() {
+            panic!("Can't run {}: neither weston nor Xvfb is installed", name);
+        }
+
+        let root = std::env::temp_dir().join(format!("shelf-e2e-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let (home, library, runtime) = (root.join("home"), root.join("library"), root.join("runtime"));
+        for dir in [&home, &library, &runtime] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            // Wayland compositors refuse a runtime directory others can read
+            fs::set_permissions(&runtime, fs::Permissions::from_mode(0o700)).unwrap();
+        }
+        for (title, author) in titles {
+            write_fixture(&library, title, author);
+        }
+
+        // The viewer just records what it was asked to open
+        let opened = root.join("opened");
+        let viewer = root.join("viewer.sh");
+        fs::write(&viewer, format!("#!/bin/sh\nprintf '%s\\n' \"$1\" >> '{}'\n", opened.display())).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&viewer, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let config_dir = home.join(".config").join("shelf");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(config_dir.join("config.toml"), format!(
+            "scan_dirs = [{:?}]\npdf_viewer_command = \"{} %\"\nuse_internal_reader = false\n",
+            library.display().to_string(), viewer.display()
+        )).unwrap();
+
+        let mut children = Vec::new();
+        let (daemon, address) = spawn_reporting(
+            Command::new(dbus_daemon).args(["--session", "--nofork", "--print-address=1"])
+        ).expect("Failed to start dbus-daemon");
+        children.push(daemon);
+
+        let mut app = Command::new(env!("CARGO_BIN_EXE_shelf"));
+        if let Some(weston) = weston {
+            let socket = "shelf-e2e";
+            let compositor = Command::new(weston)
+                .args(["--backend=headless", "--socket", socket, "--idle-time=0"])
+                .env("XDG_RUNTIME_DIR", &runtime)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("Failed to start weston");
+            children.push(compositor);
+            wait_until("the Wayland socket", || runtime.join(socket).exists());
+            app.env("GDK_BACKEND", "wayland").env("WAYLAND_DISPLAY", socket).env_remove("DISPLAY");
+        } else if let Some(xvfb) = xvfb {
+            let (server, display) = spawn_reporting(
+                Command::new(xvfb).args(["-displayfd", "1", "-screen", "0", "1280x800x24", "-nolisten", "tcp"])
+            ).expect("Failed to start Xvfb");
+            children.push(server);
+            app.env("GDK_BACKEND", "x11").env("DISPLAY", format!(":{}", display)).env_remove("WAYLAND_DISPLAY");
+        }
+
+        let log = fs::File::create(root.join("shelf.log")).unwrap();
+        let shelf = app
+            .env("HOME", &home)
+            .env("XDG_CONFIG_HOME", home.join(".config"))
+            .env("XDG_CACHE_HOME", home.join(".cache"))
+            .env("XDG_DATA_HOME", home.join(".local").join("share"))
+            .env("XDG_RUNTIME_DIR", &runtime)
+            .env("DBUS_SESSION_BUS_ADDRESS", &address)
+            .env("GSK_RENDERER", "cairo")
+            .env("NO_AT_BRIDGE", "1")
+            .stdout(log.try_clone().unwrap())
+            .stderr(log)
+            .spawn()
+            .expect("Failed to start shelf");
+        children.push(shelf);
+
+        let bus = gio::DBusConnection::for_address_sync(
+            &address,
+            gio::DBusConnectionFlags::AUTHENTICATION_CLIENT | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+            None,
+            None::<&gio::Cancellable>,
+        ).expect("Failed to connect to the test bus");
+
+        let session = Self { root, library, opened, children, bus };
+        // The interface is there once the window is, until then calls fail
+        wait_until("the library window", || session.call("Search", Some(&("",).to_variant())).is_ok());
+        session
+    }
+
+    fn call(&self, method: &str, args: Option<&glib::Variant>) -> Result<glib::Variant, glib::Error> {
+        self.bus.call_sync(
+            Some(BUS_NAME), OBJECT_PATH, BUS_NAME, method, args, None,
+            gio::DBusCallFlags::NONE, 5000, None::<&gio::Cancellable>,
+        )
+    }
+
+    /// (hash, title, path) of every match
+    fn search(&self, query: &str) -> Vec<(String, String, String)> {
+        let reply = self.call("Search", Some(&(query,).to_variant()))
+            .unwrap_or_else(|e| panic!("Search {:?} failed: {}\n{}", query, e, self.log()));
+        reply.get::<(Vec<(String, String, String)>,)>().expect("Unexpected Search reply").0
+    }
+
+    fn wait_for_documents(&self, count: usize) {
+        wait_until(&format!("{} documents", count), || self.search("").len() == count);
+    }
+
+    fn log(&self) -> String {
+        fs::read_to_string(self.root.join("shelf.log")).unwrap_or_default()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // The app goes first so it doesn't outlive its bus and display
+        for child in self.children.iter_mut().rev() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+fn write_fixture(library: &Path, title: &str, author: &str) -> PathBuf {
+    let path = library.join(format!("{}.pdf", title.to_lowercase().replace(' ', "-")));
+    fs::write(&path, fixture_pdf(title, author)).unwrap();
+    path
+}
+
+const FIXTURES: [(&str, &str); 3] = [
+    ("The Rust Programming Language", "Steve Klabnik"),
+    ("Structure and Interpretation of Computer Programs", "Harold Abelson"),
+    ("A Philosophy of Software Design", "John Ousterhout"),
+];
+
+#[test]
+#[ignore = "needs dbus-daemon and weston or Xvfb"]
+fn scan_search_open() {
+    let session = Session::start("open", &FIXTURES);
+    session.wait_for_documents(FIXTURES.len());
+
+    let rust = session.search("title:rust");
+    assert_eq!(rust.len(), 1, "{:?}", rust);
+    let (hash, title, path) = &rust[0];
+    assert_eq!(title, "The Rust Programming Language");
+    assert!(path.starts_with(&session.library.display().to_string()), "{}", path);
+    assert_eq!(session.search("author:ousterhout").len(), 1);
+    assert!(session.search("nothing-matches-this").is_empty());
+    assert!(session.call("Search", Some(&("author:",).to_variant())).is_err());
+
+    // A shortened hash is enough
+    session.call("Open", Some(&(&hash[..12],).to_variant())).expect("Open failed");
+    wait_until("the viewer", || {
+        fs::read_to_string(&session.opened).is_ok_and(|opened| opened.lines().any(|line| line == path))
+    });
+    assert!(session.call("Open", Some(&("0000000000000000",).to_variant())).is_err());
+}
+
+#[test]
+#[ignore = "needs dbus-daemon and weston or Xvfb"]
+fn rescan_picks_up_new_files() {
+    let session = Session::start("rescan", &FIXTURES[..2]);
+    session.wait_for_documents(2);
+
+    write_fixture(&session.library, FIXTURES[2].0, FIXTURES[2].1);
+    // False while the startup scan is still going
+    wait_until("a rescan to start", || {
+        session.call("Rescan", None).ok().and_then(|reply| reply.get::<(bool,)>()).is_some_and(|(started,)| started)
+    });
+    session.wait_for_documents(3);
+    assert_eq!(session.search("design").len(), 1);
+}
+
+#[test]
+#[ignore = "needs dbus-daemon and weston or Xvfb"]
+fn watched_folders_update_without_rescan() {
+    let session = Session::start("watch", &FIXTURES[..2]);
+    session.wait_for_documents(2);
+
+    // Folders are watched once the startup scan is done
+    wait_until("the folders to be watched", || session.log().contains("for new and deleted PDFs"));
+    let added = write_fixture(&session.library, FIXTURES[2].0, FIXTURES[2].1);
+    session.wait_for_documents(3);
+    assert_eq!(session.search("design").len(), 1);
+
+    fs::remove_file(&added).unwrap();
+    session.wait_for_documents(2);
+    assert!(session.search("design").is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+#[ignore = "needs dbus-daemon and weston or Xvfb"]
+fn symlink_loops_are_scanned_once() {
+    let session = Session::start("symlinks", &FIXTURES[..2]);
+    session.wait_for_documents(2);
+
+    // Points back at the library, following it naively never ends
+    std::os::unix::fs::symlink(&session.library, session.library.join("loop")).unwrap();
+    let rescan = || session.call("Rescan", None).ok().and_then(|reply| reply.get::<(bool,)>()).is_some_and(|(started,)| started);
+    wait_until("a rescan to start", rescan);
+    // Only starts again once the first one is done
+    wait_until("the rescan to finish", rescan);
+    let documents = session.search("");
+    assert_eq!(documents.len(), 2, "{:?}", documents);
+    assert!(documents.iter().all(|(_, _, path)| !path.contains("/loop/")), "{:?}", documents);
+}